@@ -0,0 +1,203 @@
+//! The [rvm_asm!] macro, which assembles programs for the virtual machine
+//! at compile time.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Assemble a program at compile time into a `&'static [u8]`, with the
+/// syntax of [interpreter::assemble]:
+///
+/// ```
+/// use rvm_asm_macro::rvm_asm;
+///
+/// let program: &[u8] = rvm_asm! {
+///     loadimm r1, 42 // The exit code
+///     halt r1
+/// };
+/// assert_eq!(&[4, 1, 42, 0, 44, 1], program);
+/// ```
+///
+/// The lines of the program are those of the macro invocation. As the
+/// program is read as Rust tokens, its comments must be Rust ones. The
+/// program can also be given as a string literal, which is required for the
+/// syntax that isn't made of Rust tokens, such as the `\param` of the macros:
+///
+/// ```
+/// use rvm_asm_macro::rvm_asm;
+///
+/// let program = rvm_asm!(r"
+///     .macro twice insn
+///     \insn
+///     \insn
+///     .endm
+///     twice exit
+/// ");
+/// assert_eq!(&[7, 7], program);
+/// ```
+///
+/// Assembler errors are reported as compile errors at the line they are
+/// found at.
+#[proc_macro]
+pub fn rvm_asm(input: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let (source, lines) = match &tokens[..] {
+        [TokenTree::Literal(literal)] => match parse_string(&literal.to_string()) {
+            Some(source) => (source, vec![literal.span()]),
+            None => return error("expected a string literal", literal.span()),
+        },
+        _ => source_of(tokens),
+    };
+    match interpreter::assemble(&source) {
+        Ok(program) => {
+            // (b"..." as &[u8])
+            let expression: TokenStream = [
+                TokenTree::Literal(Literal::byte_string(&program)),
+                TokenTree::Ident(Ident::new("as", Span::call_site())),
+                TokenTree::Punct(Punct::new('&', Spacing::Alone)),
+                TokenTree::Group(Group::new(
+                    Delimiter::Bracket,
+                    TokenTree::Ident(Ident::new("u8", Span::call_site())).into(),
+                )),
+            ]
+            .into_iter()
+            .collect();
+            return TokenTree::Group(Group::new(Delimiter::Parenthesis, expression)).into();
+        }
+        Err(asm_error) => {
+            let span = lines
+                .get(asm_error.line.saturating_sub(1))
+                .copied()
+                .unwrap_or_else(|| lines.first().copied().unwrap_or(Span::call_site()));
+            return error(&format!("{}", asm_error), span);
+        }
+    }
+}
+
+// The assembler source written by `tokens`, laid out at the position of
+// the tokens, and a span on every one of its lines
+fn source_of(tokens: Vec<TokenTree>) -> (String, Vec<Span>) {
+    let mut writer = Writer {
+        source: String::new(),
+        lines: Vec::new(),
+        first_line: tokens.first().map_or(1, |token| token.span().line()),
+        line: 0,
+        column: 1,
+    };
+    for token in tokens {
+        writer.token(token);
+    }
+    return (writer.source, writer.lines);
+}
+
+// Write the source text of tokens at their position
+struct Writer {
+    source: String,
+    lines: Vec<Span>, // A span on every line, the first token when there is one
+    first_line: usize,
+    line: usize, // The current position, relative to the first line
+    column: usize,
+}
+
+impl Writer {
+    fn token(&mut self, token: TokenTree) {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                self.text(open, group.span_open());
+                for token in group.stream() {
+                    self.token(token);
+                }
+                self.text(close, group.span_close());
+            }
+            _ => self.text(&token.to_string(), token.span()),
+        }
+    }
+
+    fn text(&mut self, text: &str, span: Span) {
+        let line = span.line().saturating_sub(self.first_line);
+        if line > self.line || self.lines.is_empty() {
+            while self.lines.len() <= line {
+                self.lines.push(span);
+            }
+            for _ in self.line..line {
+                self.source.push('\n');
+            }
+            self.line = line;
+            self.column = 1;
+        }
+        while self.column < span.column() {
+            self.source.push(' ');
+            self.column += 1;
+        }
+        self.source.push_str(text);
+        self.column += text.chars().count();
+    }
+}
+
+// The content of a Rust string literal, if `literal` is one
+fn parse_string(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let content = raw[hashes..].strip_prefix('"')?;
+        return Some(content[..content.len().checked_sub(hashes + 1)?].to_string());
+    }
+    let content = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut string = String::new();
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => string.push('\n'),
+            't' => string.push('\t'),
+            'r' => string.push('\r'),
+            '0' => string.push('\0'),
+            '\\' => string.push('\\'),
+            '"' => string.push('"'),
+            '\'' => string.push('\''),
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                string.push(u8::from_str_radix(&digits, 16).ok()? as char);
+            }
+            'u' => {
+                let digits: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let code = u32::from_str_radix(digits.strip_prefix('{')?, 16).ok()?;
+                string.push(char::from_u32(code)?);
+            }
+            '\n' => {
+                // A line continuation skips the leading whitespace
+                let rest: String = chars.collect();
+                string.push_str(&parse_string(&format!("\"{}\"", rest.trim_start()))?);
+                return Some(string);
+            }
+            _ => return None,
+        }
+    }
+    return Some(string);
+}
+
+// compile_error!("message"), reported at `span`
+fn error(message: &str, span: Span) -> TokenStream {
+    let mut literal = Literal::string(&format!("rvm_asm: {}", message));
+    literal.set_span(span);
+    let tokens: Vec<TokenTree> = vec![
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct({
+            let mut bang = Punct::new('!', Spacing::Alone);
+            bang.set_span(span);
+            bang
+        }),
+        TokenTree::Group({
+            let mut group = Group::new(Delimiter::Parenthesis, TokenTree::Literal(literal).into());
+            group.set_span(span);
+            group
+        }),
+    ];
+    return tokens.into_iter().collect();
+}
@@ -0,0 +1,1403 @@
+use crate::debuginfo::{DebugInfo, SourceLocation};
+use crate::isa::{instruction_by_mnemonic, InstructionInfo, Operand, REGISTER_ALIASES};
+use crate::object::{Object, Relocation, RelocationTarget, Section};
+use crate::symbols::SymbolTable;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+
+// An error found while assembling a program, with the line it comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize, // it's numbered from 1
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),         // No instruction has this name
+    InvalidRegister(String),         // Not a register name
+    InvalidImmediate(String),        // Not a number
+    ImmediateOutOfRange(i64),        // Number too large for the operand
+    WrongOperandCount(usize),        // Expected number of operands
+    InvalidLabel(String),            // Not a valid label name
+    DuplicateLabel(String),          // Label defined twice
+    UndefinedLabel(String),          // Label used but never defined
+    UnknownDirective(String),        // No directive has this name
+    InvalidString(String),           // Not a valid quoted string
+    InvalidMacroName(String),        // Not a valid name for a macro
+    DuplicateMacro(String),          // Macro defined twice
+    UnterminatedMacro(String),       // Macro without `.endm`
+    UnexpectedDirective(String),     // Directive not allowed here
+    RecursiveMacro(String),          // Macro expanded within itself
+    InvalidExpression(String),       // Not a valid constant expression
+    DivisionByZero,                  // Constant expression divided by zero
+    UnrelocatableExpression(String), // Address expression the linker cannot compute
+    IncludeFailed(String, String),   // File that can't be included, and why
+    IncludeCycle(String),            // File included within itself
+    Included(String, Box<AsmError>), // Error found in an included file
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic `{}`", m),
+            AsmErrorKind::InvalidRegister(r) => write!(f, "invalid register `{}`", r),
+            AsmErrorKind::InvalidImmediate(i) => write!(f, "invalid immediate value `{}`", i),
+            AsmErrorKind::ImmediateOutOfRange(v) => {
+                write!(f, "immediate value {} does not fit in the operand", v)
+            }
+            AsmErrorKind::WrongOperandCount(n) => write!(f, "expected {} operand(s)", n),
+            AsmErrorKind::InvalidLabel(l) => write!(f, "invalid label name `{}`", l),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "label `{}` is already defined", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "label `{}` is not defined", l),
+            AsmErrorKind::UnknownDirective(d) => write!(f, "unknown directive `{}`", d),
+            AsmErrorKind::InvalidString(t) => write!(f, "invalid string `{}`", t),
+            AsmErrorKind::InvalidMacroName(m) => write!(f, "invalid macro name `{}`", m),
+            AsmErrorKind::DuplicateMacro(m) => write!(f, "macro `{}` is already defined", m),
+            AsmErrorKind::UnterminatedMacro(m) => write!(f, "macro `{}` has no `.endm`", m),
+            AsmErrorKind::UnexpectedDirective(d) => write!(f, "unexpected `{}`", d),
+            AsmErrorKind::RecursiveMacro(m) => write!(f, "macro `{}` expands itself", m),
+            AsmErrorKind::InvalidExpression(e) => write!(f, "invalid expression `{}`", e),
+            AsmErrorKind::DivisionByZero => write!(f, "division by zero"),
+            AsmErrorKind::UnrelocatableExpression(e) => {
+                write!(f, "expression `{}` cannot be relocated", e)
+            }
+            AsmErrorKind::IncludeFailed(n, m) => write!(f, "cannot include `{}`: {}", n, m),
+            AsmErrorKind::IncludeCycle(n) => write!(f, "`{}` includes itself", n),
+            AsmErrorKind::Included(n, e) => write!(f, "in `{}`, {}", n, e),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.kind);
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+impl AsmError {
+    /// The column, numbered from 1, of the text the error is about within
+    /// its line of `source`. When this text can't be found, such as for
+    /// errors found in an expanded macro, it is the column of the first
+    /// character of the line.
+    pub fn column(&self, source: &str) -> usize {
+        let line = source.lines().nth(self.line.wrapping_sub(1)).unwrap_or("");
+        let code = strip_comment(line);
+        let start = code.len() - code.trim_start().len();
+        let position = self
+            .kind
+            .text()
+            .filter(|text| !text.is_empty())
+            .and_then(|text| find_token(code, text))
+            .unwrap_or(start);
+        return line[..position].chars().count() + 1;
+    }
+}
+
+// Find the first occurrence of `text` in `code` that is not part of a longer
+// word, or else its first occurrence
+fn find_token(code: &str, text: &str) -> Option<usize> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let mut occurrences = code.match_indices(text).map(|(i, _)| i);
+    let first = occurrences.clone().next()?;
+    let token = occurrences.find(|i| {
+        let before = code[..*i].chars().next_back();
+        let after = code[i + text.len()..].chars().next();
+        return !before.is_some_and(is_word) && !after.is_some_and(is_word);
+    });
+    return Some(token.unwrap_or(first));
+}
+
+impl AsmErrorKind {
+    // The source text the error is about, if it is known
+    fn text(&self) -> Option<&str> {
+        match self {
+            AsmErrorKind::UnknownMnemonic(t)
+            | AsmErrorKind::InvalidRegister(t)
+            | AsmErrorKind::InvalidImmediate(t)
+            | AsmErrorKind::InvalidLabel(t)
+            | AsmErrorKind::DuplicateLabel(t)
+            | AsmErrorKind::UndefinedLabel(t)
+            | AsmErrorKind::UnknownDirective(t)
+            | AsmErrorKind::InvalidString(t)
+            | AsmErrorKind::InvalidMacroName(t)
+            | AsmErrorKind::DuplicateMacro(t)
+            | AsmErrorKind::UnterminatedMacro(t)
+            | AsmErrorKind::UnexpectedDirective(t)
+            | AsmErrorKind::RecursiveMacro(t)
+            | AsmErrorKind::InvalidExpression(t)
+            | AsmErrorKind::UnrelocatableExpression(t)
+            | AsmErrorKind::IncludeFailed(t, _)
+            | AsmErrorKind::IncludeCycle(t) => return Some(t),
+            AsmErrorKind::ImmediateOutOfRange(_)
+            | AsmErrorKind::WrongOperandCount(_)
+            | AsmErrorKind::DivisionByZero
+            | AsmErrorKind::Included(_, _) => return None,
+        }
+    }
+}
+
+/// Assemble `source` into a program image ready to be given to
+/// [Machine::try_new](crate::Machine::try_new).
+///
+/// Each line contains at most one instruction, written as its mnemonic
+/// followed by its comma-separated operands in encoding order, such as
+/// `sub r2, r2, r3`. Registers are named `r0` to `r15` (`f0` to `f7`
+/// for floating-point ones), or by one of the [REGISTER_ALIASES] such as
+/// `sp`, immediate values are written in decimal,
+/// hexadecimal (`0x`), binary (`0b`) or as a character (`'A'`), and
+/// everything following a `;` is a comment.
+///
+/// A line may start with a `label:` definition. Labels can be used in
+/// place of any immediate value and stand for the address of the
+/// instruction following them, even when defined later in the source.
+/// For the relative jump `jrel`, the offset to the label is computed.
+///
+/// Immediate values can also be constant expressions made of numbers,
+/// labels, parentheses and the `+`, `-`, `*`, `/`, `<<` and `>>`
+/// operators, such as `(BUFFER + 4*INDEX)`. `.equ NAME, expression`
+/// defines a symbol usable like a label, whose expression may only
+/// refer to the labels and symbols defined before it. Label addresses can
+/// only be added to or subtracted from constants, or subtracted from
+/// another address of the same section.
+///
+/// `.section name` continues the program in the named section, `.text`
+/// being the initial one. Sections are laid out one after the other, in
+/// the order they first appear in the source.
+///
+/// Data can be embedded in the program with the following directives:
+///   - `.byte v, ...`: one byte per value
+///   - `.word v, ...`: one 32-bit little-endian word per value
+///   - `.ascii "text"`: the bytes of the string
+///   - `.asciiz "text"`: the bytes of the string followed by a NUL byte
+///   - `.space n[, v]`: `n` bytes equal to `v` (0 by default)
+///
+/// Strings and characters accept the `\n`, `\t`, `\r`, `\0`, `\\`,
+/// `\'`, `\"` and `\xNN` escape sequences.
+///
+/// A sequence of lines can be defined once as a macro between
+/// `.macro name param, ...` and `.endm`, then used like an instruction
+/// with `name arg, ...`. Within the body, `\param` is replaced by the
+/// matching argument and `\@` by a number unique to each expansion,
+/// which is useful to define local labels. Errors found in an expanded
+/// macro are reported at the line of its invocation.
+///
+/// `.include "name"` is replaced by the content of the named file, which
+/// requires an [Assembler] with an [IncludeLoader]. The errors found in
+/// an included file are reported at the line of the `.include`, with an
+/// [AsmErrorKind::Included] error giving their location in the file.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    return Assembler::new().assemble(source);
+}
+
+/// Assemble `source` like [assemble], and also return the address of
+/// every label it defines.
+pub fn assemble_with_symbols(source: &str) -> Result<(Vec<u8>, SymbolTable), AsmError> {
+    return Assembler::new().assemble_with_symbols(source);
+}
+
+/// Assemble `source` like [assemble], and also return its listing: every
+/// source line along with the address and the bytes it was assembled to.
+pub fn assemble_with_listing(source: &str) -> Result<(Vec<u8>, String), AsmError> {
+    return Assembler::new().assemble_with_listing(source);
+}
+
+/// Assemble `source` like [assemble], and also return its debug info: the
+/// source line every instruction and data comes from, and the labels. The
+/// lines of `source` are attributed to the file `name`, and those of the
+/// included files to the file returned by the [IncludeLoader].
+pub fn assemble_with_debug_info(
+    name: &str,
+    source: &str,
+) -> Result<(Vec<u8>, DebugInfo), AsmError> {
+    return Assembler::new().assemble_with_debug_info(name, source);
+}
+
+/// Assemble `source` into an object that can be [linked](crate::link) with
+/// other ones. The addresses of the labels are relative to their section,
+/// and the labels used but not defined in `source` are left to the linker.
+///
+/// Besides the syntax of [assemble], `.global name, ...` makes labels
+/// visible to the other objects.
+pub fn assemble_object(source: &str) -> Result<Object, AsmError> {
+    return Assembler::new().assemble_object(source);
+}
+
+/// Loads the files included with the `.include` directive.
+pub trait IncludeLoader {
+    /// Return a name identifying the file `name`, such as its path, along
+    /// with its content. A file including a file with the same identity
+    /// as itself or as one of the files including it is an include cycle.
+    fn load(&mut self, name: &str) -> std::io::Result<(String, String)>;
+}
+
+impl<F: FnMut(&str) -> std::io::Result<String>> IncludeLoader for F {
+    fn load(&mut self, name: &str) -> std::io::Result<(String, String)> {
+        return Ok((name.to_string(), self(name)?));
+    }
+}
+
+/// Loads the included files from the first of a list of directories that
+/// contains them. The files are identified by their canonical path.
+#[derive(Debug, Clone, Default)]
+pub struct FileLoader {
+    directories: Vec<PathBuf>,
+}
+
+impl FileLoader {
+    pub fn new(directories: Vec<PathBuf>) -> Self {
+        return FileLoader { directories };
+    }
+}
+
+impl IncludeLoader for FileLoader {
+    fn load(&mut self, name: &str) -> std::io::Result<(String, String)> {
+        for directory in &self.directories {
+            let path = directory.join(name);
+            if path.is_file() {
+                let path = path.canonicalize()?;
+                let content = std::fs::read_to_string(&path)?;
+                return Ok((path.display().to_string(), content));
+            }
+        }
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "not found in the include directories",
+        ));
+    }
+}
+
+/// An assembler, which can load the files included by `.include` with an
+/// [IncludeLoader]. Its methods behave like the functions of the same name
+/// such as [assemble], which use an assembler without loader.
+#[derive(Default)]
+pub struct Assembler {
+    loader: Option<Box<dyn IncludeLoader>>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Use `loader` to load the files included by the sources.
+    pub fn set_include_loader<L: IncludeLoader + 'static>(&mut self, loader: L) {
+        self.loader = Some(Box::new(loader));
+    }
+
+    pub fn assemble(&mut self, source: &str) -> Result<Vec<u8>, AsmError> {
+        return Ok(self.assemble_flat(source)?.program);
+    }
+
+    pub fn assemble_with_symbols(
+        &mut self,
+        source: &str,
+    ) -> Result<(Vec<u8>, SymbolTable), AsmError> {
+        let assembly = self.assemble_flat(source)?;
+        return Ok((assembly.program, assembly.symbols));
+    }
+
+    pub fn assemble_with_listing(&mut self, source: &str) -> Result<(Vec<u8>, String), AsmError> {
+        let assembly = self.assemble_flat(source)?;
+        let mut spans = assembly.spans.iter().peekable();
+        let mut listing = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let mut rows: Vec<(usize, &[u8])> = Vec::new();
+            while let Some((_, address, len)) =
+                spans.next_if(|(location, _, _)| location.line == index + 1)
+            {
+                let bytes = &assembly.program[*address..*address + *len];
+                for (i, chunk) in bytes.chunks(LISTING_BYTES).enumerate() {
+                    rows.push((address + i * LISTING_BYTES, chunk));
+                }
+            }
+            let mut rows = rows.into_iter();
+            let code = match rows.next() {
+                Some((address, bytes)) => format!("{:04x}  {}", address, hex_bytes(bytes)),
+                None => String::new(),
+            };
+            listing.push_str(format!("{:<29}{:>5}  {}", code, index + 1, text).trim_end());
+            listing.push('\n');
+            for (address, bytes) in rows {
+                listing.push_str(&format!("{:04x}  {}\n", address, hex_bytes(bytes)));
+            }
+        }
+        return Ok((assembly.program, listing));
+    }
+
+    pub fn assemble_with_debug_info(
+        &mut self,
+        name: &str,
+        source: &str,
+    ) -> Result<(Vec<u8>, DebugInfo), AsmError> {
+        let assembly = self.assemble_flat(source)?;
+        let mut info = DebugInfo::new();
+        for (location, address, size) in &assembly.spans {
+            if *size == 0 {
+                continue;
+            }
+            let (file, line) = match location.includes.last() {
+                Some((file, line)) => (file.as_str(), *line),
+                None => (name, location.line),
+            };
+            let location = SourceLocation {
+                file: file.to_string(),
+                line,
+            };
+            info.insert_line(*address as u32, *size as u32, location);
+        }
+        for (label, address) in assembly.symbols.iter() {
+            info.insert_label(label, address);
+        }
+        return Ok((assembly.program, info));
+    }
+
+    pub fn assemble_object(&mut self, source: &str) -> Result<Object, AsmError> {
+        let lines = self.preprocess(source)?;
+        let layout = first_pass(&lines)?;
+        let (emitters, _) = second_pass(&layout, true)?;
+        let mut sections: Vec<Section> = layout
+            .sections
+            .iter()
+            .zip(emitters)
+            .map(|((name, _), emitter)| Section {
+                name: name.clone(),
+                data: emitter.data,
+                symbols: Default::default(),
+                relocations: emitter.relocations,
+            })
+            .collect();
+        for (name, value) in &layout.symbols {
+            if let Some(Base::Section(section)) = value.base {
+                sections[section]
+                    .symbols
+                    .insert(name.clone(), value.value as u32);
+            }
+        }
+        let mut globals: BTreeSet<String> = BTreeSet::new();
+        for (location, name) in &layout.globals {
+            match layout.symbols.get(*name) {
+                Some(Value {
+                    base: Some(Base::Section(_)),
+                    ..
+                }) => globals.insert(name.to_string()),
+                _ => return Err(location.error(AsmErrorKind::UndefinedLabel(name.to_string()))),
+            };
+        }
+        return Ok(Object { sections, globals });
+    }
+
+    fn assemble_flat(&mut self, source: &str) -> Result<Assembly, AsmError> {
+        let lines = self.preprocess(source)?;
+        let mut layout = first_pass(&lines)?;
+
+        // Lay out the sections one after the other
+        let mut bases: Vec<usize> = Vec::new();
+        let mut address: usize = 0;
+        for (_, size) in &layout.sections {
+            bases.push(address);
+            address += size;
+        }
+        for value in layout.symbols.values_mut() {
+            if let Some(Base::Section(section)) = value.base {
+                value.value += bases[section] as i64;
+            }
+        }
+        for statement in &mut layout.statements {
+            statement.address += bases[statement.section];
+        }
+
+        let (emitters, sizes) = second_pass(&layout, false)?;
+        let program = emitters.into_iter().flat_map(|e| e.data).collect();
+        let spans = layout
+            .statements
+            .iter()
+            .zip(sizes)
+            .map(|(statement, size)| (statement.location.clone(), statement.address, size))
+            .collect();
+        let mut names: Vec<&String> = layout.symbols.keys().collect();
+        names.sort();
+        let mut table = SymbolTable::new();
+        for name in names {
+            if let Some(Base::Section(_)) = layout.symbols[name].base {
+                table.insert(name, layout.symbols[name].value as u32);
+            }
+        }
+        return Ok(Assembly {
+            program,
+            symbols: table,
+            spans,
+        });
+    }
+
+    // Remove the comments, the macro definitions and the includes from
+    // `source`, and expand the macro invocations and the included files
+    fn preprocess(&mut self, source: &str) -> Result<Vec<(Location, String)>, AsmError> {
+        let mut preprocessor = Preprocessor {
+            macros: HashMap::new(),
+            expansions: 0,
+            lines: Vec::new(),
+            loader: self.loader.as_mut(),
+            files: Vec::new(),
+        };
+        preprocessor.process(source, None)?;
+        return Ok(preprocessor.lines);
+    }
+}
+
+// The number of bytes shown on every row of a listing
+const LISTING_BYTES: usize = 8;
+
+// Format `bytes` in hexadecimal, separated by spaces
+fn hex_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    return bytes.join(" ");
+}
+
+// A program assembled at its final address
+struct Assembly {
+    program: Vec<u8>,
+    symbols: SymbolTable,
+    spans: Vec<(Location, usize, usize)>, // The location, address and size of every statement, sorted by line
+}
+
+// Where a line comes from: its line in the source given to the assembler,
+// then the name and line of every file included from there
+#[derive(Clone)]
+struct Location {
+    line: usize,
+    includes: Vec<(String, usize)>,
+}
+
+impl Location {
+    // An error found at this location
+    fn error(&self, kind: AsmErrorKind) -> AsmError {
+        // The line in the source, then in every included file
+        let lines: Vec<usize> = std::iter::once(self.line)
+            .chain(self.includes.iter().map(|(_, line)| *line))
+            .collect();
+        let mut error = AsmError {
+            line: lines[lines.len() - 1],
+            kind,
+        };
+        for (index, (file, _)) in self.includes.iter().enumerate().rev() {
+            error = AsmError {
+                line: lines[index],
+                kind: AsmErrorKind::Included(file.clone(), Box::new(error)),
+            };
+        }
+        return error;
+    }
+}
+
+// The statements, symbols and sections found by the first pass over a source
+struct Layout<'a> {
+    statements: Vec<Statement<'a>>,
+    symbols: HashMap<String, Value>,
+    sections: Vec<(String, usize)>, // The name and size of every section
+    globals: Vec<(Location, &'a str)>, // The location and name of every `.global` label
+}
+
+// First pass: find the address of every statement and label, relative to
+// their section
+fn first_pass(lines: &[(Location, String)]) -> Result<Layout<'_>, AsmError> {
+    let mut layout = Layout {
+        statements: Vec::new(),
+        symbols: HashMap::new(),
+        sections: vec![(".text".to_string(), 0)],
+        globals: Vec::new(),
+    };
+    let mut section: usize = 0;
+    for (location, text) in lines {
+        let error = |kind| location.error(kind);
+        let (label, text) = split_label(text).map_err(error)?;
+        if let Some(label) = label {
+            let value = Value {
+                value: layout.sections[section].1 as i64,
+                base: Some(Base::Section(section)),
+            };
+            define_symbol(label, value, &mut layout.symbols).map_err(error)?;
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (name, operands) = split_statement(text);
+        match statement_kind(name).map_err(error)? {
+            StatementKind::Directive(Directive::Equ) => {
+                define_constant(&operands, &mut layout.symbols).map_err(error)?;
+            }
+            StatementKind::Directive(Directive::Section) => {
+                let [name] = operands[..] else {
+                    return Err(error(AsmErrorKind::WrongOperandCount(1)));
+                };
+                if !is_identifier(name) {
+                    return Err(error(AsmErrorKind::InvalidLabel(name.to_string())));
+                }
+                section = match layout.sections.iter().position(|(n, _)| n == name) {
+                    Some(index) => index,
+                    None => {
+                        layout.sections.push((name.to_string(), 0));
+                        layout.sections.len() - 1
+                    }
+                };
+            }
+            StatementKind::Directive(Directive::Global) => {
+                if operands.is_empty() {
+                    return Err(error(AsmErrorKind::WrongOperandCount(1)));
+                }
+                for name in operands {
+                    if !is_identifier(name) {
+                        return Err(error(AsmErrorKind::InvalidLabel(name.to_string())));
+                    }
+                    layout.globals.push((location.clone(), name));
+                }
+            }
+            kind => {
+                let statement = Statement {
+                    location: location.clone(),
+                    section,
+                    address: layout.sections[section].1,
+                    kind,
+                    operands,
+                };
+                let scope = Scope {
+                    symbols: &layout.symbols,
+                    externals: false,
+                };
+                layout.sections[section].1 += statement.size(&scope).map_err(error)?;
+                layout.statements.push(statement);
+            }
+        }
+    }
+    return Ok(layout);
+}
+
+// Second pass: encode every statement now that all labels are known. The
+// addresses that are not known yet are relocations when `relocatable`.
+// Return the encoded sections and the size of every statement.
+fn second_pass(layout: &Layout, relocatable: bool) -> Result<(Vec<Emitter>, Vec<usize>), AsmError> {
+    let scope = Scope {
+        symbols: &layout.symbols,
+        externals: relocatable,
+    };
+    let mut emitters: Vec<Emitter> = (0..layout.sections.len())
+        .map(|section| Emitter {
+            section,
+            relocatable,
+            data: Vec::new(),
+            relocations: Vec::new(),
+        })
+        .collect();
+    let mut sizes: Vec<usize> = Vec::new();
+    for statement in &layout.statements {
+        let emitter = &mut emitters[statement.section];
+        let start = emitter.data.len();
+        let result = match statement.kind {
+            StatementKind::Instruction(info) => {
+                encode_instruction(statement, info, &scope, emitter)
+            }
+            StatementKind::Directive(directive) => {
+                encode_directive(statement, directive, &scope, emitter)
+            }
+        };
+        result.map_err(|kind| statement.location.error(kind))?;
+        sizes.push(emitter.data.len() - start);
+    }
+    return Ok((emitters, sizes));
+}
+
+// The bytes and relocations of a section being encoded
+struct Emitter {
+    section: usize,
+    relocatable: bool,
+    data: Vec<u8>,
+    relocations: Vec<Relocation>,
+}
+
+impl Emitter {
+    // Append `value` encoded as `kind`. Relative offsets are computed from
+    // `next_address`, the address of the next instruction.
+    fn emit(
+        &mut self,
+        kind: Operand,
+        value: Value,
+        next_address: usize,
+    ) -> Result<(), AsmErrorKind> {
+        let target = match value.base {
+            None => return encode_immediate(kind, value.value, &mut self.data),
+            Some(Base::Section(section))
+                if !self.relocatable || (kind == Operand::Rel16 && section == self.section) =>
+            {
+                let mut address = value.value;
+                if kind == Operand::Rel16 {
+                    address -= next_address as i64;
+                }
+                return encode_immediate(kind, address, &mut self.data);
+            }
+            Some(Base::Section(section)) => RelocationTarget::Section(section),
+            Some(Base::External(name)) => RelocationTarget::Symbol(name),
+        };
+
+        // The address will only be known once linked
+        let offset = self.data.len();
+        let mut addend = value.value;
+        if kind == Operand::Rel16 {
+            addend -= (next_address - offset) as i64;
+        }
+        self.relocations.push(Relocation {
+            offset: offset as u32,
+            kind,
+            target,
+            addend,
+        });
+        self.data.extend(std::iter::repeat_n(0, kind.size()));
+        return Ok(());
+    }
+}
+
+// The maximum nesting of macro expansions
+const MAX_MACRO_DEPTH: usize = 16;
+
+// A macro defined between `.macro` and `.endm`
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<String>,
+}
+
+// The macro definitions, the included files and the state of their
+// expansion
+struct Preprocessor<'a> {
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    lines: Vec<(Location, String)>,
+    loader: Option<&'a mut Box<dyn IncludeLoader>>,
+    files: Vec<String>, // The files being included
+}
+
+impl Preprocessor<'_> {
+    // Add the lines of `source` to the expanded lines. `include` is the
+    // location of the `.include` directive of `source` and its file name,
+    // unless it is the source given to the assembler.
+    fn process(
+        &mut self,
+        source: &str,
+        include: Option<(&Location, &str)>,
+    ) -> Result<(), AsmError> {
+        let locate = |line: usize| match include {
+            Some((location, file)) => {
+                let mut location = location.clone();
+                location.includes.push((file.to_string(), line));
+                location
+            }
+            None => Location {
+                line,
+                includes: Vec::new(),
+            },
+        };
+        let mut definition: Option<(usize, &str, Macro)> = None;
+        for (index, line) in source.lines().enumerate() {
+            let location = locate(index + 1);
+            let error = |kind| location.error(kind);
+            let text = strip_comment(line).trim();
+            let name = split_statement(text).0;
+            match (name.to_lowercase().as_str(), &mut definition) {
+                (".macro", None) => {
+                    // The parameters are separated from the name by a space
+                    let (name, parameters) = split_statement(text[name.len()..].trim());
+                    if !is_identifier(name)
+                        || instruction_by_mnemonic(&name.to_lowercase()).is_some()
+                    {
+                        return Err(error(AsmErrorKind::InvalidMacroName(name.to_string())));
+                    }
+                    if self.macros.contains_key(name) {
+                        return Err(error(AsmErrorKind::DuplicateMacro(name.to_string())));
+                    }
+                    let parameters = parameters.into_iter().map(String::from).collect();
+                    let body = Vec::new();
+                    definition = Some((index + 1, name, Macro { parameters, body }));
+                }
+                (".endm", Some(_)) => {
+                    let (_, name, definition) = definition.take().unwrap();
+                    self.macros.insert(name.to_string(), definition);
+                }
+                (".macro", Some(_)) | (".endm", None) => {
+                    return Err(error(AsmErrorKind::UnexpectedDirective(name.to_string())));
+                }
+                (_, Some((_, _, definition))) => definition.body.push(text.to_string()),
+                (".include", None) => self.include(text[name.len()..].trim(), &location)?,
+                (_, None) => self.expand(&location, text, 0)?,
+            }
+        }
+        if let Some((line, name, _)) = definition {
+            return Err(locate(line).error(AsmErrorKind::UnterminatedMacro(name.to_string())));
+        }
+        return Ok(());
+    }
+
+    // Add the lines of the file included by `.include operand` at `location`
+    fn include(&mut self, operand: &str, location: &Location) -> Result<(), AsmError> {
+        let name = parse_string(operand).map_err(|kind| location.error(kind))?;
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let failed = |message: String| {
+            return location.error(AsmErrorKind::IncludeFailed(name.clone(), message));
+        };
+        let loaded = match self.loader.as_mut() {
+            Some(loader) => loader.load(&name).map_err(|e| failed(e.to_string()))?,
+            None => return Err(failed("no include loader".to_string())),
+        };
+        let (file, content) = loaded;
+        if self.files.contains(&file) {
+            return Err(location.error(AsmErrorKind::IncludeCycle(file)));
+        }
+        self.files.push(file.clone());
+        self.process(&content, Some((location, &file)))?;
+        self.files.pop();
+        return Ok(());
+    }
+
+    // Add the line `text` to the expanded lines, expanding it first if it is
+    // an invocation
+    fn expand(&mut self, location: &Location, text: &str, depth: usize) -> Result<(), AsmError> {
+        let error = |kind| location.error(kind);
+        let (label, rest) = split_label(text).map_err(error)?;
+        let (name, arguments) = split_statement(rest);
+        let Some(definition) = self.macros.get(name) else {
+            self.lines.push((location.clone(), text.to_string()));
+            return Ok(());
+        };
+        if depth == MAX_MACRO_DEPTH {
+            return Err(error(AsmErrorKind::RecursiveMacro(name.to_string())));
+        }
+        if arguments.len() != definition.parameters.len() {
+            let count = definition.parameters.len();
+            return Err(error(AsmErrorKind::WrongOperandCount(count)));
+        }
+        if let Some(label) = label {
+            self.lines.push((location.clone(), format!("{}:", label)));
+        }
+        self.expansions += 1;
+        let body: Vec<String> = definition
+            .body
+            .iter()
+            .map(|text| substitute(text, &definition.parameters, &arguments, self.expansions))
+            .collect();
+        for text in body {
+            self.expand(location, &text, depth + 1)?;
+        }
+        return Ok(());
+    }
+}
+
+// Replace in `text` the `\parameter` by their argument and `\@` by `unique`
+fn substitute(text: &str, parameters: &[String], arguments: &[&str], unique: usize) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('\\') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('@') {
+            result.push_str(&unique.to_string());
+            rest = after;
+            continue;
+        }
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        match parameters.iter().position(|p| *p == rest[..end]) {
+            Some(index) => {
+                result.push_str(arguments[index]);
+                rest = &rest[end..];
+            }
+            None => result.push('\\'),
+        }
+    }
+    result.push_str(rest);
+    return result;
+}
+
+// The data directives
+#[derive(Clone, Copy)]
+enum Directive {
+    Byte,
+    Word,
+    Ascii,
+    Asciiz,
+    Space,
+    Equ,
+    Section,
+    Global,
+}
+
+#[derive(Clone, Copy)]
+enum StatementKind {
+    Instruction(&'static InstructionInfo),
+    Directive(Directive),
+}
+
+// An instruction or a directive found in the source during the first pass
+struct Statement<'a> {
+    location: Location,
+    section: usize,
+    address: usize,
+    kind: StatementKind,
+    operands: Vec<&'a str>,
+}
+
+impl Statement<'_> {
+    // The number of bytes produced by the statement
+    fn size(&self, scope: &Scope) -> Result<usize, AsmErrorKind> {
+        match self.kind {
+            StatementKind::Instruction(info) => return Ok(info.size()),
+            StatementKind::Directive(Directive::Byte) => return Ok(self.operands.len()),
+            StatementKind::Directive(Directive::Word) => return Ok(4 * self.operands.len()),
+            StatementKind::Directive(Directive::Ascii) => return Ok(self.string()?.len()),
+            StatementKind::Directive(Directive::Asciiz) => return Ok(self.string()?.len() + 1),
+            StatementKind::Directive(Directive::Space) => {
+                let count = self.operands.first();
+                match count.map(|n| evaluate_constant(n, scope)).transpose()? {
+                    Some(n) if (0..=u16::MAX as i64).contains(&n) => return Ok(n as usize),
+                    Some(n) => return Err(AsmErrorKind::ImmediateOutOfRange(n)),
+                    None => return Err(AsmErrorKind::WrongOperandCount(1)),
+                }
+            }
+            StatementKind::Directive(Directive::Equ)
+            | StatementKind::Directive(Directive::Section)
+            | StatementKind::Directive(Directive::Global) => return Ok(0),
+        }
+    }
+
+    // The bytes of the single string operand of `.ascii` and `.asciiz`
+    fn string(&self) -> Result<Vec<u8>, AsmErrorKind> {
+        match self.operands[..] {
+            [text] => return parse_string(text),
+            _ => return Err(AsmErrorKind::WrongOperandCount(1)),
+        }
+    }
+}
+
+// Find whether `name` is an instruction mnemonic or a directive
+fn statement_kind(name: &str) -> Result<StatementKind, AsmErrorKind> {
+    let lowercase = name.to_lowercase();
+    let directive = match lowercase.as_str() {
+        ".byte" => Directive::Byte,
+        ".word" => Directive::Word,
+        ".ascii" => Directive::Ascii,
+        ".asciiz" => Directive::Asciiz,
+        ".space" => Directive::Space,
+        ".equ" => Directive::Equ,
+        ".section" => Directive::Section,
+        ".global" => Directive::Global,
+        _ if lowercase.starts_with('.') => {
+            return Err(AsmErrorKind::UnknownDirective(name.to_string()))
+        }
+        _ => match instruction_by_mnemonic(&lowercase) {
+            Some(info) => return Ok(StatementKind::Instruction(info)),
+            None => return Err(AsmErrorKind::UnknownMnemonic(name.to_string())),
+        },
+    };
+    return Ok(StatementKind::Directive(directive));
+}
+
+// Remove the comment at the end of `line`, if any
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (in_quotes, c) {
+            (None, ';') => return &line[..i],
+            (None, '\'') | (None, '"') => in_quotes = Some(c),
+            (Some(_), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), _) if q == c && !escaped => in_quotes = None,
+            _ => (),
+        }
+        escaped = false;
+    }
+    return line;
+}
+
+// Split a line into its optional label definition and the rest of the line
+fn split_label(text: &str) -> Result<(Option<&str>, &str), AsmErrorKind> {
+    match text.find(':') {
+        Some(i) if !text[..i].contains(['\'', '"']) => {
+            let label = text[..i].trim();
+            if !is_identifier(label) {
+                return Err(AsmErrorKind::InvalidLabel(label.to_string()));
+            }
+            return Ok((Some(label), text[i + 1..].trim()));
+        }
+        _ => return Ok((None, text)),
+    }
+}
+
+// Check that `text` can be used as a label name
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            return chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        }
+        _ => return false,
+    }
+}
+
+// Split a statement into its mnemonic or directive name and its operands
+fn split_statement(text: &str) -> (&str, Vec<&str>) {
+    let (name, rest) = match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    };
+    if rest.is_empty() {
+        return (name, Vec::new());
+    }
+    return (name, split_operands(rest));
+}
+
+// Split comma-separated operands, ignoring the commas within quotes
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands: Vec<&str> = Vec::new();
+    let mut in_quotes: Option<char> = None;
+    let mut escaped = false;
+    let mut start: usize = 0;
+    for (i, c) in text.char_indices() {
+        match (in_quotes, c) {
+            (None, ',') => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            (None, '\'') | (None, '"') => in_quotes = Some(c),
+            (Some(_), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), _) if q == c && !escaped => in_quotes = None,
+            _ => (),
+        }
+        escaped = false;
+    }
+    operands.push(text[start..].trim());
+    return operands;
+}
+
+// Encode the instruction `statement` with `emitter`
+fn encode_instruction(
+    statement: &Statement,
+    info: &InstructionInfo,
+    scope: &Scope,
+    emitter: &mut Emitter,
+) -> Result<(), AsmErrorKind> {
+    if statement.operands.len() != info.operands.len() {
+        return Err(AsmErrorKind::WrongOperandCount(info.operands.len()));
+    }
+
+    let next_address = statement.address + info.size();
+    emitter.data.push(info.opcode);
+    for (kind, operand) in info.operands.iter().zip(&statement.operands) {
+        match kind {
+            Operand::Reg => emitter.data.push(parse_register(operand, 'r', 16)?),
+            Operand::FReg => emitter.data.push(parse_register(operand, 'f', 8)?),
+            _ => emitter.emit(*kind, evaluate(operand, scope)?, next_address)?,
+        }
+    }
+    return Ok(());
+}
+
+// Encode the data directive `statement` with `emitter`
+fn encode_directive(
+    statement: &Statement,
+    directive: Directive,
+    scope: &Scope,
+    emitter: &mut Emitter,
+) -> Result<(), AsmErrorKind> {
+    match directive {
+        Directive::Byte => {
+            for operand in &statement.operands {
+                let value = evaluate(operand, scope)?;
+                emitter.emit(Operand::Imm8, value, statement.address)?;
+            }
+        }
+        Directive::Word => {
+            for operand in &statement.operands {
+                let value = evaluate(operand, scope)?;
+                emitter.emit(Operand::Imm32, value, statement.address)?;
+            }
+        }
+        Directive::Ascii => emitter.data.extend(statement.string()?),
+        Directive::Asciiz => {
+            emitter.data.extend(statement.string()?);
+            emitter.data.push(0);
+        }
+        Directive::Space => {
+            let fill = match statement.operands[..] {
+                [_] => 0,
+                [_, fill] => evaluate_constant(fill, scope)?,
+                _ => return Err(AsmErrorKind::WrongOperandCount(2)),
+            };
+            let count = statement.size(scope)?;
+            for _ in 0..count {
+                encode_immediate(Operand::Imm8, fill, &mut emitter.data)?;
+            }
+        }
+        Directive::Equ | Directive::Section | Directive::Global => (),
+    }
+    return Ok(());
+}
+
+// The value of a symbol or of a constant expression
+#[derive(Clone)]
+struct Value {
+    value: i64,
+    base: Option<Base>, // What the value is relative to, if it is not a constant
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Base {
+    Section(usize),   // An address within a section of the source
+    External(String), // The address of a label defined in another object
+}
+
+// The symbols that can be used in an expression
+struct Scope<'a> {
+    symbols: &'a HashMap<String, Value>,
+    externals: bool, // True if the undefined labels are left to the linker
+}
+
+// Add the symbol `name` to `symbols`
+fn define_symbol(
+    name: &str,
+    value: Value,
+    symbols: &mut HashMap<String, Value>,
+) -> Result<(), AsmErrorKind> {
+    if symbols.insert(name.to_string(), value).is_some() {
+        return Err(AsmErrorKind::DuplicateLabel(name.to_string()));
+    }
+    return Ok(());
+}
+
+// Define the symbol of the `.equ name, expression` directive
+fn define_constant(
+    operands: &[&str],
+    symbols: &mut HashMap<String, Value>,
+) -> Result<(), AsmErrorKind> {
+    let [name, expression] = operands[..] else {
+        return Err(AsmErrorKind::WrongOperandCount(2));
+    };
+    if !is_identifier(name) {
+        return Err(AsmErrorKind::InvalidLabel(name.to_string()));
+    }
+    let scope = Scope {
+        symbols,
+        externals: false,
+    };
+    let value = evaluate(expression, &scope)?;
+    return define_symbol(name, value, symbols);
+}
+
+// Compute the value of the constant expression `operand`
+fn evaluate(operand: &str, scope: &Scope) -> Result<Value, AsmErrorKind> {
+    let mut parser = ExpressionParser {
+        text: operand,
+        rest: operand,
+        scope,
+    };
+    let value = parser.shift()?;
+    if !parser.rest.trim().is_empty() {
+        return Err(parser.invalid());
+    }
+    return Ok(value);
+}
+
+// Compute the value of `operand`, which must not depend on a label address
+fn evaluate_constant(operand: &str, scope: &Scope) -> Result<i64, AsmErrorKind> {
+    let value = evaluate(operand, scope)?;
+    if value.base.is_some() {
+        return Err(AsmErrorKind::UnrelocatableExpression(operand.to_string()));
+    }
+    return Ok(value.value);
+}
+
+// A recursive descent parser evaluating a constant expression, with the
+// usual precedence of the operators
+struct ExpressionParser<'a> {
+    text: &'a str,
+    rest: &'a str,
+    scope: &'a Scope<'a>,
+}
+
+impl ExpressionParser<'_> {
+    fn invalid(&self) -> AsmErrorKind {
+        return AsmErrorKind::InvalidExpression(self.text.to_string());
+    }
+
+    fn unrelocatable(&self) -> AsmErrorKind {
+        return AsmErrorKind::UnrelocatableExpression(self.text.to_string());
+    }
+
+    // Check that the operands of an operator are constants
+    fn constants(&self, left: &Value, right: &Value) -> Result<(), AsmErrorKind> {
+        if left.base.is_some() || right.base.is_some() {
+            return Err(self.unrelocatable());
+        }
+        return Ok(());
+    }
+
+    // Consume `operator` if the remaining text starts with it
+    fn eat(&mut self, operator: &str) -> bool {
+        match self.rest.trim_start().strip_prefix(operator) {
+            Some(rest) => {
+                self.rest = rest;
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    // The `<<` and `>>` operators, which have the lowest precedence
+    fn shift(&mut self) -> Result<Value, AsmErrorKind> {
+        let mut left = self.additive()?;
+        loop {
+            let shift: fn(i64, u32) -> Option<i64> = if self.eat("<<") {
+                i64::checked_shl
+            } else if self.eat(">>") {
+                i64::checked_shr
+            } else {
+                return Ok(left);
+            };
+            let right = self.additive()?;
+            self.constants(&left, &right)?;
+            let amount = u32::try_from(right.value).map_err(|_| self.invalid())?;
+            left.value = shift(left.value, amount).ok_or_else(|| self.invalid())?;
+        }
+    }
+
+    // The `+` and `-` operators
+    fn additive(&mut self) -> Result<Value, AsmErrorKind> {
+        let mut left = self.term()?;
+        loop {
+            if self.eat("+") {
+                let right = self.term()?;
+                left.value = left
+                    .value
+                    .checked_add(right.value)
+                    .ok_or_else(|| self.invalid())?;
+                left.base = match (left.base, right.base) {
+                    (base, None) | (None, base) => base,
+                    _ => return Err(self.unrelocatable()),
+                };
+            } else if self.eat("-") {
+                let right = self.term()?;
+                left.value = left
+                    .value
+                    .checked_sub(right.value)
+                    .ok_or_else(|| self.invalid())?;
+                left.base = match (left.base, right.base) {
+                    (base, None) => base,
+                    // The distance between two labels of a section is a constant
+                    (Some(a), Some(b)) if a == b && matches!(a, Base::Section(_)) => None,
+                    _ => return Err(self.unrelocatable()),
+                };
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    // The `*` and `/` operators
+    fn term(&mut self) -> Result<Value, AsmErrorKind> {
+        let mut left = self.unary()?;
+        loop {
+            if self.eat("*") {
+                let right = self.unary()?;
+                self.constants(&left, &right)?;
+                left.value = left
+                    .value
+                    .checked_mul(right.value)
+                    .ok_or_else(|| self.invalid())?;
+            } else if self.eat("/") {
+                let right = self.unary()?;
+                self.constants(&left, &right)?;
+                if right.value == 0 {
+                    return Err(AsmErrorKind::DivisionByZero);
+                }
+                left.value = left
+                    .value
+                    .checked_div(right.value)
+                    .ok_or_else(|| self.invalid())?;
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    // The unary `-` operator
+    fn unary(&mut self) -> Result<Value, AsmErrorKind> {
+        if self.eat("-") {
+            let mut value = self.unary()?;
+            if value.base.is_some() {
+                return Err(self.unrelocatable());
+            }
+            value.value = value.value.checked_neg().ok_or_else(|| self.invalid())?;
+            return Ok(value);
+        }
+        return self.primary();
+    }
+
+    // A number, a symbol or an expression within parentheses
+    fn primary(&mut self) -> Result<Value, AsmErrorKind> {
+        if self.eat("(") {
+            let value = self.shift()?;
+            if !self.eat(")") {
+                return Err(self.invalid());
+            }
+            return Ok(value);
+        }
+        self.rest = self.rest.trim_start();
+        let length = match self.rest.strip_prefix('\'') {
+            // A character, whose quote may be escaped
+            Some(after) => match after.strip_prefix('\\') {
+                Some(escaped) => escaped.get(1..).and_then(|t| t.find('\'')).map(|i| i + 4),
+                None => after.find('\'').map(|i| i + 2),
+            },
+            None => self
+                .rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.'),
+        };
+        let length = length.unwrap_or(self.rest.len());
+        let (token, rest) = self.rest.split_at(length);
+        if token.is_empty() {
+            return Err(self.invalid());
+        }
+        self.rest = rest;
+        if is_identifier(token) {
+            return resolve_symbol(token, self.scope);
+        }
+        let value = parse_number(token)?;
+        return Ok(Value { value, base: None });
+    }
+}
+
+// Find the value of the symbol `name`
+fn resolve_symbol(name: &str, scope: &Scope) -> Result<Value, AsmErrorKind> {
+    match scope.symbols.get(name) {
+        Some(value) => return Ok(value.clone()),
+        None if scope.externals => {
+            return Ok(Value {
+                value: 0,
+                base: Some(Base::External(name.to_string())),
+            })
+        }
+        None => return Err(AsmErrorKind::UndefinedLabel(name.to_string())),
+    }
+}
+
+// Append the immediate `value` encoded as `kind` to `program`
+pub(crate) fn encode_immediate(
+    kind: Operand,
+    value: i64,
+    program: &mut Vec<u8>,
+) -> Result<(), AsmErrorKind> {
+    let (min, max): (i64, i64) = match kind {
+        Operand::Imm8 => (i8::MIN as i64, u8::MAX as i64),
+        Operand::Imm16 => (i16::MIN as i64, u16::MAX as i64),
+        Operand::Addr16 => (0, u16::MAX as i64),
+        Operand::Rel16 => (i16::MIN as i64, i16::MAX as i64),
+        _ => (i32::MIN as i64, u32::MAX as i64),
+    };
+    if value < min || value > max {
+        return Err(AsmErrorKind::ImmediateOutOfRange(value));
+    }
+    let bytes = (value as u32).to_le_bytes();
+    program.extend_from_slice(&bytes[..kind.size()]);
+    return Ok(());
+}
+
+// Parse a register name made of `prefix` followed by a number below `count`,
+// or the alias of a general-purpose register
+pub(crate) fn parse_register(text: &str, prefix: char, count: u8) -> Result<u8, AsmErrorKind> {
+    let lowercase = text.to_lowercase();
+    if prefix == 'r' {
+        if let Some((_, number)) = REGISTER_ALIASES.iter().find(|(a, _)| *a == lowercase) {
+            return Ok(*number);
+        }
+    }
+    let number = lowercase
+        .strip_prefix(prefix)
+        .and_then(|n| n.parse::<u8>().ok())
+        .filter(|n| *n < count);
+    return number.ok_or_else(|| AsmErrorKind::InvalidRegister(text.to_string()));
+}
+
+// Parse a decimal, hexadecimal, binary or character number
+pub(crate) fn parse_number(text: &str) -> Result<i64, AsmErrorKind> {
+    let invalid = || AsmErrorKind::InvalidImmediate(text.to_string());
+    if let Some(c) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        match parse_escaped(c).map_err(|_| invalid())?[..] {
+            [byte] => return Ok(byte as i64),
+            _ => return Err(invalid()),
+        }
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else {
+        digits.parse::<i64>()
+    };
+    let value = value.map_err(|_| invalid())?;
+    if negative {
+        return Ok(-value);
+    }
+    return Ok(value);
+}
+
+// Parse a double-quoted string into its UTF-8 bytes
+fn parse_string(text: &str) -> Result<Vec<u8>, AsmErrorKind> {
+    let invalid = || AsmErrorKind::InvalidString(text.to_string());
+    let content = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .filter(|t| !t.ends_with('\\') || t.ends_with("\\\\"))
+        .ok_or_else(invalid)?;
+    return parse_escaped(content).map_err(|_| invalid());
+}
+
+// Replace the escape sequences of `text` and return its UTF-8 bytes
+fn parse_escaped(text: &str) -> Result<Vec<u8>, ()> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let byte = match chars.next().ok_or(())? {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            '0' => 0,
+            '\\' => b'\\',
+            '\'' => b'\'',
+            '"' => b'"',
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                if digits.len() != 2 {
+                    return Err(());
+                }
+                u8::from_str_radix(&digits, 16).map_err(|_| ())?
+            }
+            _ => return Err(()),
+        };
+        bytes.push(byte);
+    }
+    return Ok(bytes);
+}
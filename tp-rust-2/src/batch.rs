@@ -0,0 +1,16 @@
+// The runs of many independent machines at once, on the threads of `rayon`
+use crate::machine::{Machine, MachineError, RunOutcome};
+use rayon::prelude::*;
+
+/// Run every machine until it stops, like
+/// [run_capture](Machine::run_capture), spreading the machines over the
+/// threads of the global `rayon` pool, and return their outcomes with what
+/// they printed, in the order of the machines. Their input is empty, and
+/// the machines which may not terminate must be limited with their fuel
+/// (see [set_fuel](Machine::set_fuel)).
+pub fn run_all(machines: &mut [Machine]) -> Vec<Result<(RunOutcome, Vec<u8>), MachineError>> {
+    return machines
+        .par_iter_mut()
+        .map(|machine| return machine.run_capture())
+        .collect();
+}
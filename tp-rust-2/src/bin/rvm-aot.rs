@@ -0,0 +1,91 @@
+use interpreter::{transpile, Image};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+// The exit codes of sysexits.h
+const EX_USAGE: u8 = 64; // Wrong command line
+const EX_DATAERR: u8 = 65; // Invalid program
+const EX_NOINPUT: u8 = 66; // Program can't be read
+const EX_CANTCREAT: u8 = 73; // Output can't be written
+
+const USAGE: &str = "\
+usage: rvm-aot [options] <program>
+
+Transpile a flat program, loaded and started at address 0, or a program
+image to a Rust module, to compile it with a host program.
+
+options:
+  -o <file>  write the module to <file> (default: the program with the .rs
+             extension)
+  -h         print this help";
+
+// The command line options
+struct Options {
+    program: PathBuf,
+    output: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_arguments(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("rvm-aot: {}\n\n{}", message, USAGE);
+            return ExitCode::from(EX_USAGE);
+        }
+    };
+    match run(&options) {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(code) => return ExitCode::from(code),
+    }
+}
+
+// Parse the command line, or return `None` if the help is requested
+fn parse_arguments(mut arguments: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut program = None;
+    let mut output = None;
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" => {
+                let value = arguments.next().ok_or("missing value for -o")?;
+                output = Some(PathBuf::from(value));
+            }
+            _ if argument.starts_with('-') => return Err(format!("unknown option {}", argument)),
+            _ if program.is_some() => return Err("more than one program".to_string()),
+            _ => program = Some(PathBuf::from(argument)),
+        }
+    }
+    let program = program.ok_or("no program file")?;
+    return Ok(Some(Options { program, output }));
+}
+
+// Transpile the program, reporting the errors on the standard error
+fn run(options: &Options) -> Result<(), u8> {
+    let path = &options.program;
+    let bytes = std::fs::read(path).map_err(|error| {
+        eprintln!("rvm-aot: cannot read {}: {}", path.display(), error);
+        return EX_NOINPUT;
+    })?;
+    let image = if Image::is_image(&bytes) {
+        Image::parse(&bytes)
+    } else {
+        Ok(Image::from_program(&bytes))
+    };
+    let source = image.and_then(|image| transpile(&image)).map_err(|error| {
+        eprintln!("rvm-aot: {}: {}", path.display(), error);
+        return EX_DATAERR;
+    })?;
+
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => path.with_extension("rs"),
+    };
+    return std::fs::write(&output, source).map_err(|error| {
+        eprintln!("rvm-aot: cannot write {}: {}", output.display(), error);
+        return EX_CANTCREAT;
+    });
+}
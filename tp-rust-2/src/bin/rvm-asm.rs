@@ -0,0 +1,217 @@
+use interpreter::{link, optimize, AsmError, AsmErrorKind, Assembler, FileLoader, Image};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+// The exit codes of sysexits.h
+const EX_USAGE: u8 = 64; // Wrong command line
+const EX_DATAERR: u8 = 65; // Invalid source
+const EX_NOINPUT: u8 = 66; // Source can't be read
+const EX_CANTCREAT: u8 = 73; // Output can't be written
+
+const USAGE: &str = "\
+usage: rvm-asm [options] <source.s>...
+
+Assemble the sources, linking them together if there are several ones.
+
+options:
+  -o <file>  write the program to <file> (default: the first source with
+             the .bin extension)
+  -l <file>  also write the listing of the program to <file>, which
+             requires a single source
+  -g <file>  also write the debug info of the program to <file>, which
+             requires a single source
+  -i         write a program image started at address 0, with a CRC32,
+             instead of a flat program
+  -O         optimize the program, which can't be combined with -l or -g
+  -I <dir>   search the files included with .include in <dir>, after the
+             directory of the source including them (can be repeated)
+  -h         print this help";
+
+// The command line options
+struct Options {
+    sources: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    listing: Option<PathBuf>,
+    debug_info: Option<PathBuf>,
+    optimize: bool,
+    image: bool,
+    include_directories: Vec<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_arguments(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("rvm-asm: {}\n\n{}", message, USAGE);
+            return ExitCode::from(EX_USAGE);
+        }
+    };
+    match run(&options) {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(code) => return ExitCode::from(code),
+    }
+}
+
+// Parse the command line, or return `None` if the help is requested
+fn parse_arguments(mut arguments: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        sources: Vec::new(),
+        output: None,
+        listing: None,
+        debug_info: None,
+        optimize: false,
+        image: false,
+        include_directories: Vec::new(),
+    };
+    while let Some(argument) = arguments.next() {
+        let mut value = |option: &str| {
+            return arguments
+                .next()
+                .map(PathBuf::from)
+                .ok_or(format!("missing value for {}", option));
+        };
+        match argument.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" => options.output = Some(value("-o")?),
+            "-l" => options.listing = Some(value("-l")?),
+            "-g" => options.debug_info = Some(value("-g")?),
+            "-O" => options.optimize = true,
+            "-i" => options.image = true,
+            "-I" => options.include_directories.push(value("-I")?),
+            _ if argument.starts_with('-') && argument != "-" => {
+                return Err(format!("unknown option {}", argument))
+            }
+            _ => options.sources.push(PathBuf::from(argument)),
+        }
+    }
+    if options.sources.is_empty() {
+        return Err("no source file".to_string());
+    }
+    if options.listing.is_some() && options.sources.len() > 1 {
+        return Err("a listing requires a single source".to_string());
+    }
+    if options.debug_info.is_some() && options.sources.len() > 1 {
+        return Err("debug info requires a single source".to_string());
+    }
+    if options.optimize && (options.listing.is_some() || options.debug_info.is_some()) {
+        return Err("an optimized program has no listing or debug info".to_string());
+    }
+    return Ok(Some(options));
+}
+
+// Assemble the program, reporting the errors on the standard error
+fn run(options: &Options) -> Result<(), u8> {
+    let mut sources: Vec<(&Path, String)> = Vec::new();
+    for path in &options.sources {
+        match std::fs::read_to_string(path) {
+            Ok(source) => sources.push((path, source)),
+            Err(error) => {
+                eprintln!("rvm-asm: cannot read {}: {}", path.display(), error);
+                return Err(EX_NOINPUT);
+            }
+        }
+    }
+
+    // The included files are searched in the directory of the source first
+    let assembler = |path: &Path| {
+        let mut directories = vec![path.parent().unwrap_or(Path::new("")).to_path_buf()];
+        directories.extend(options.include_directories.iter().cloned());
+        let mut assembler = Assembler::new();
+        assembler.set_include_loader(FileLoader::new(directories));
+        return assembler;
+    };
+
+    let program = match &sources[..] {
+        [(path, source)] => {
+            let (program, listing) = assembler(path)
+                .assemble_with_listing(source)
+                .map_err(|error| report(path, source, &error))?;
+            if let Some(listing_path) = &options.listing {
+                write(listing_path, listing.as_bytes())?;
+            }
+            if let Some(debug_info_path) = &options.debug_info {
+                let name = path.display().to_string();
+                let (_, info) = assembler(path)
+                    .assemble_with_debug_info(&name, source)
+                    .map_err(|error| report(path, source, &error))?;
+                write(debug_info_path, info.to_string().as_bytes())?;
+            }
+            program
+        }
+        _ => {
+            let mut objects = Vec::new();
+            for (path, source) in &sources {
+                let object = assembler(path)
+                    .assemble_object(source)
+                    .map_err(|error| report(path, source, &error))?;
+                objects.push(object);
+            }
+            link(&objects).map_err(|error| {
+                eprintln!("rvm-asm: error: {}", error);
+                return EX_DATAERR;
+            })?
+        }
+    };
+
+    let program = if options.optimize {
+        optimize(program)
+    } else {
+        program
+    };
+
+    let program = if options.image {
+        let mut image = Image::from_program(&program);
+        image.crc = true;
+        image.to_bytes()
+    } else {
+        program
+    };
+
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => options.sources[0].with_extension("bin"),
+    };
+    return write(&output, &program);
+}
+
+// Print `error` along with the line of `source` it comes from. For an error
+// found in an included file, it is the line of this file.
+fn report(path: &Path, source: &str, error: &AsmError) -> u8 {
+    let mut path = path.to_path_buf();
+    let mut source = source.to_string();
+    let mut error = error;
+    let mut includes: Vec<String> = Vec::new();
+    while let AsmErrorKind::Included(file, included) = &error.kind {
+        includes.push(format!("{}:{}", path.display(), error.line));
+        path = PathBuf::from(file);
+        source = std::fs::read_to_string(&path).unwrap_or_default();
+        error = included;
+    }
+
+    let column = error.column(&source);
+    let line = source.lines().nth(error.line - 1).unwrap_or("");
+    eprintln!(
+        "{}:{}:{}: error: {}",
+        path.display(),
+        error.line,
+        column,
+        error.kind
+    );
+    eprintln!("{:>5} | {}", error.line, line);
+    eprintln!("      | {:>1$}", "^", column);
+    for include in includes.iter().rev() {
+        eprintln!("note: included from {}", include);
+    }
+    return EX_DATAERR;
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<(), u8> {
+    return std::fs::write(path, content).map_err(|error| {
+        eprintln!("rvm-asm: cannot write {}: {}", path.display(), error);
+        return EX_CANTCREAT;
+    });
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+
+// The JSON values of the messages of the Debug Adapter Protocol
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // The members, in order
+}
+
+impl Json {
+    // Parse a JSON text, ignoring the whitespace around it
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != text.len() {
+            return Err(parser.error("end of text"));
+        }
+        return Ok(value);
+    }
+
+    // An object made of `members`
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        let members = members.into_iter().map(|(k, v)| (k.to_string(), v));
+        return Json::Object(members.collect());
+    }
+
+    // The member `key` of an object, `Null` if there is none
+    pub fn get(&self, key: &str) -> &Json {
+        if let Json::Object(members) = self {
+            if let Some((_, value)) = members.iter().find(|(k, _)| k == key) {
+                return value;
+            }
+        }
+        return &Json::Null;
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => return Some(s),
+            _ => return None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => return Some(*n as u64),
+            _ => return None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) if n.fract() == 0.0 => return Some(*n as i64),
+            _ => return None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => return Some(*b),
+            _ => return None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => return values,
+            _ => return &[],
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        return Json::String(s.to_string());
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        return Json::String(s);
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        return Json::Bool(b);
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Self {
+        return Json::Number(n as f64);
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Self {
+        return Json::Number(n as f64);
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        return Json::Number(n as f64);
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        return Json::Array(values);
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => return write!(f, "null"),
+            Json::Bool(b) => return write!(f, "{}", b),
+            // The integers are written without a fraction
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                return write!(f, "{}", *n as i64)
+            }
+            Json::Number(n) => return write!(f, "{}", n),
+            Json::String(s) => return write_string(f, s),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                return write!(f, "]");
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                return write!(f, "}}");
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    return write!(f, "\"");
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        match self.text.get(self.position) {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members: Vec<(String, Json)> = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        members.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                return Ok(Json::Object(members));
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut values: Vec<Json> = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        values.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                return Ok(Json::Array(values));
+            }
+            Some(b'"') => return Ok(Json::String(self.string()?)),
+            Some(b't') => return self.keyword("true", Json::Bool(true)),
+            Some(b'f') => return self.keyword("false", Json::Bool(false)),
+            Some(b'n') => return self.keyword("null", Json::Null),
+            Some(c) if *c == b'-' || c.is_ascii_digit() => {
+                let start = self.position;
+                while self
+                    .text
+                    .get(self.position)
+                    .is_some_and(|c| b"+-.eE".contains(c) || c.is_ascii_digit())
+                {
+                    self.position += 1;
+                }
+                let number = std::str::from_utf8(&self.text[start..self.position]).unwrap();
+                return number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| self.error("a number"));
+            }
+            _ => return Err(self.error("a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat(b'"') {
+            return Err(self.error("a string"));
+        }
+        let mut bytes: Vec<u8> = Vec::new();
+        loop {
+            let Some(c) = self.text.get(self.position).copied() else {
+                return Err(self.error("the end of the string"));
+            };
+            self.position += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escape) = self.text.get(self.position).copied() else {
+                        return Err(self.error("an escape"));
+                    };
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("an escape")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        return String::from_utf8(bytes).map_err(|_| self.error("UTF-8 text"));
+    }
+
+    // The character of the `\uXXXX` escape whose `\u` was just read,
+    // followed by a second one for the characters outside of the BMP
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let first = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&first) {
+            return char::from_u32(first).ok_or_else(|| self.error("a character"));
+        }
+        if !self.text[self.position..].starts_with(b"\\u") {
+            return Err(self.error("a low surrogate"));
+        }
+        self.position += 2;
+        let second = self.hex4()?;
+        let code = 0x10000 + ((first - 0xd800) << 10) + (second.wrapping_sub(0xdc00) & 0x3ff);
+        return char::from_u32(code).ok_or_else(|| self.error("a character"));
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("4 hexadecimal digits"))?;
+        self.position += 4;
+        return Ok(digits);
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.position..].starts_with(keyword.as_bytes()) {
+            return Err(self.error(keyword));
+        }
+        self.position += keyword.len();
+        return Ok(value);
+    }
+
+    fn whitespace(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    // Skip the whitespace and `c` if it follows
+    fn eat(&mut self, c: u8) -> bool {
+        self.whitespace();
+        if self.text.get(self.position) == Some(&c) {
+            self.position += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        return Err(self.error(&format!("`{}`", c as char)));
+    }
+
+    fn error(&self, expected: &str) -> String {
+        return format!("expected {} at offset {}", expected, self.position);
+    }
+}
@@ -0,0 +1,558 @@
+//! A Debug Adapter Protocol server, letting editors such as VS Code run
+//! programs on the virtual machine and debug them. The messages are read on
+//! the standard input and written on the standard output.
+
+mod json;
+
+use interpreter::{register_alias, DebugInfo, Expr, Image, Machine, StepOutcome};
+use json::Json;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+
+// The number of instructions executed between two checks of the requests
+// while the program runs
+const RUN_SLICE: usize = 10_000;
+
+// The number of steps that can be stepped back
+const JOURNAL_CAPACITY: usize = 100_000;
+
+// The program has a single thread
+const THREAD_ID: u32 = 1;
+
+// The variable references of the scopes
+const REGISTERS: u64 = 1;
+const FLAGS: u64 = 2;
+
+fn main() {
+    // The requests are read by their own thread, so that they can be
+    // handled while the program runs
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut input = BufReader::new(io::stdin().lock());
+        while let Some(message) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter::new(io::stdout().lock());
+    while !adapter.done {
+        let message = if adapter.running {
+            match receiver.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => {
+                    adapter.run_slice();
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            }
+        };
+        adapter.handle(&message);
+    }
+}
+
+// Read a message made of headers, among which its `Content-Length`, and
+// of its JSON content. `None` is returned at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> Option<Json> {
+    loop {
+        let mut length: Option<usize> = None;
+        loop {
+            let mut header = String::new();
+            if input.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("Content-Length") {
+                    length = value.trim().parse().ok();
+                }
+            }
+        }
+        let mut content = vec![0; length?];
+        input.read_exact(&mut content).ok()?;
+        // An invalid message is skipped
+        if let Ok(message) = Json::parse(&String::from_utf8_lossy(&content)) {
+            return Some(message);
+        }
+    }
+}
+
+// Why the program stopped
+enum Stop {
+    Breakpoint(&'static str), // The description of the breakpoint
+    Exception(String),
+    Exited, // The exited and terminated events are already sent
+}
+
+struct Adapter<W: Write> {
+    output: W,
+    seq: u64, // The sequence number of the last message sent
+    machine: Machine,
+    debug_info: DebugInfo,
+    source_root: PathBuf, // The directory the relative files of the debug info are in
+    breakpoints: BTreeMap<String, Vec<u32>>, // The addresses of the breakpoints set in every source
+    stop_on_entry: bool,
+    running: bool,  // The program runs until it stops
+    resuming: bool, // The next instruction runs even if there is a breakpoint
+    finished: bool, // The program exited or faulted
+    done: bool,     // The adapter must leave
+}
+
+impl<W: Write> Adapter<W> {
+    fn new(output: W) -> Self {
+        return Adapter {
+            output,
+            seq: 0,
+            machine: Machine::try_new(&[]).unwrap(),
+            debug_info: DebugInfo::new(),
+            source_root: PathBuf::new(),
+            breakpoints: BTreeMap::new(),
+            stop_on_entry: false,
+            running: false,
+            resuming: false,
+            finished: false,
+            done: false,
+        };
+    }
+
+    fn send(&mut self, mut members: Vec<(&str, Json)>) {
+        self.seq += 1;
+        members.insert(0, ("seq", self.seq.into()));
+        let content = Json::object(members).to_string();
+        // The client is gone if the message can't be written
+        let written = write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        );
+        if written.and_then(|_| self.output.flush()).is_err() {
+            self.done = true;
+        }
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) {
+        let mut members = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("command", request.get("command").clone()),
+            ("success", result.is_ok().into()),
+        ];
+        match result {
+            Ok(body) => members.push(("body", body)),
+            Err(message) => members.push(("message", message.into())),
+        }
+        self.send(members);
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(vec![
+            ("type", "event".into()),
+            ("event", event.into()),
+            ("body", body),
+        ]);
+    }
+
+    fn handle(&mut self, request: &Json) {
+        if request.get("type").as_str() != Some("request") {
+            return;
+        }
+        let arguments = request.get("arguments");
+        let command = request.get("command").as_str().unwrap_or("");
+        let result = match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsConditionalBreakpoints", true.into()),
+                ("supportsReadMemoryRequest", true.into()),
+                ("supportsStepBack", true.into()),
+            ])),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setExceptionBreakpoints" | "configurationDone" => Ok(Json::Null),
+            "threads" => {
+                let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+                Ok(Json::object(vec![("threads", vec![thread].into())]))
+            }
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => {
+                let scope = |name: &str, reference: u64| {
+                    return Json::object(vec![
+                        ("name", name.into()),
+                        ("variablesReference", reference.into()),
+                        ("expensive", false.into()),
+                    ]);
+                };
+                let scopes = vec![scope("Registers", REGISTERS), scope("Flags", FLAGS)];
+                Ok(Json::object(vec![("scopes", scopes.into())]))
+            }
+            "variables" => Ok(self.variables(arguments)),
+            "readMemory" => self.read_memory(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "continue" => Ok(Json::object(vec![("allThreadsContinued", true.into())])),
+            "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" | "pause" => {
+                Ok(Json::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Json::Null)
+            }
+            _ => Err(format!("unsupported request `{}`", command)),
+        };
+        let launched = command == "launch" && result.is_ok();
+        self.respond(request, result);
+
+        // What follows the response
+        match command {
+            "launch" if launched => self.event("initialized", Json::Null),
+            "configurationDone" if self.stop_on_entry => {
+                self.stopped("entry", None);
+            }
+            "configurationDone" | "continue" => self.resume(),
+            "next" | "stepIn" | "stepOut" => {
+                if self.finished {
+                    self.event("terminated", Json::Null);
+                } else if let Some(stop) = self.step() {
+                    self.report(stop);
+                } else {
+                    self.stopped("step", None);
+                }
+            }
+            "stepBack" => {
+                if self.machine.step_back() {
+                    self.finished = false;
+                }
+                self.stopped("step", None);
+            }
+            "reverseContinue" => {
+                while self.machine.step_back() {
+                    self.finished = false;
+                    if self.machine.at_breakpoint() {
+                        break;
+                    }
+                }
+                let reason = if self.machine.at_breakpoint() {
+                    "breakpoint"
+                } else {
+                    "step"
+                };
+                self.stopped(reason, None);
+            }
+            "pause" if self.running => {
+                self.running = false;
+                self.stopped("pause", None);
+            }
+            _ => {}
+        }
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let program = arguments.get("program").as_str().ok_or("missing program")?;
+        let bytes = std::fs::read(program).map_err(|e| format!("{}: {}", program, e))?;
+        let machine = if Image::is_image(&bytes) {
+            Machine::load_image(&bytes).map_err(|e| format!("{}: {}", program, e))?
+        } else {
+            Machine::from_reader(&bytes[..]).map_err(|e| format!("{}: {}", program, e))?
+        };
+        self.machine = machine;
+        self.machine.set_journal_capacity(JOURNAL_CAPACITY);
+
+        // The debug info written by `rvm-asm -g`, next to the program by default
+        let debug_info = match arguments.get("debugInfo").as_str() {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(program).with_extension("dbg"),
+        };
+        self.debug_info = DebugInfo::load(&debug_info).unwrap_or_default();
+        self.source_root = match arguments.get("cwd").as_str() {
+            Some(cwd) => PathBuf::from(cwd),
+            None => std::env::current_dir().unwrap_or_default(),
+        };
+        self.stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+        return Ok(Json::Null);
+    }
+
+    // Replace the breakpoints of a source by those at the lines requested
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let path = arguments.get("source").get("path").as_str().unwrap_or("");
+        for address in self.breakpoints.remove(path).unwrap_or_default() {
+            self.machine.remove_breakpoint(address);
+        }
+        let mut addresses: Vec<u32> = Vec::new();
+        let mut breakpoints: Vec<Json> = Vec::new();
+        for requested in arguments.get("breakpoints").as_array() {
+            let line = requested.get("line").as_u64().unwrap_or(0) as usize;
+            let Some((address, line)) = self.address_of_line(path, line) else {
+                breakpoints.push(Json::object(vec![
+                    ("verified", false.into()),
+                    ("message", "no code at this line".into()),
+                ]));
+                continue;
+            };
+            match requested.get("condition").as_str().map(Expr::parse) {
+                Some(Err(error)) => {
+                    breakpoints.push(Json::object(vec![
+                        ("verified", false.into()),
+                        ("message", format!("invalid condition: {}", error).into()),
+                    ]));
+                    continue;
+                }
+                Some(Ok(condition)) => self.machine.add_conditional_breakpoint(address, condition),
+                None => self.machine.add_breakpoint(address),
+            };
+            addresses.push(address);
+            breakpoints.push(Json::object(vec![
+                ("verified", true.into()),
+                ("line", line.into()),
+                ("instructionReference", format!("0x{:04x}", address).into()),
+            ]));
+        }
+        self.breakpoints.insert(path.to_string(), addresses);
+        return Json::object(vec![("breakpoints", breakpoints.into())]);
+    }
+
+    // The address of a line of the source at `path`, whose name in the debug
+    // info is either relative to the source root or one of the final parts
+    // of the path, such as `prog.s` for `/home/me/prog.s`
+    fn address_of_line(&self, path: &str, line: usize) -> Option<(u32, usize)> {
+        let path = Path::new(path);
+        let mut candidates: Vec<PathBuf> = vec![path.to_path_buf()];
+        if let Ok(relative) = path.strip_prefix(&self.source_root) {
+            candidates.push(relative.to_path_buf());
+        }
+        let components: Vec<_> = path.components().collect();
+        for start in 1..components.len() {
+            candidates.push(components[start..].iter().collect());
+        }
+        return candidates.iter().find_map(|file| {
+            return self.debug_info.address_of_line(file.to_str()?, line);
+        });
+    }
+
+    fn stack_trace(&self) -> Json {
+        let ip = self.machine.regs()[0];
+        let name = match self.debug_info.label_for(ip) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+{}", label, offset),
+            None => format!("0x{:04x}", ip),
+        };
+        let mut frame = vec![
+            ("id", 0u32.into()),
+            ("name", name.into()),
+            (
+                "instructionPointerReference",
+                format!("0x{:04x}", ip).into(),
+            ),
+            ("line", 0u32.into()),
+            ("column", 0u32.into()),
+        ];
+        if let Some(location) = self.debug_info.location(ip) {
+            let path = self.source_root.join(&location.file);
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let source = Json::object(vec![
+                ("name", name.to_string().into()),
+                ("path", path.to_string_lossy().to_string().into()),
+            ]);
+            frame[3] = ("line", location.line.into());
+            frame[4] = ("column", 1u32.into());
+            frame.push(("source", source));
+        }
+        return Json::object(vec![
+            ("stackFrames", vec![Json::object(frame)].into()),
+            ("totalFrames", 1u32.into()),
+        ]);
+    }
+
+    fn variables(&self, arguments: &Json) -> Json {
+        let variable = |name: String, value: String, memory: Option<String>| {
+            let mut members = vec![
+                ("name", name.into()),
+                ("value", value.into()),
+                ("variablesReference", 0u32.into()),
+            ];
+            if let Some(memory) = memory {
+                members.push(("memoryReference", memory.into()));
+            }
+            return Json::object(members);
+        };
+        let mut variables: Vec<Json> = Vec::new();
+        match arguments.get("variablesReference").as_u64() {
+            Some(REGISTERS) => {
+                for (number, value) in self.machine.regs().iter().enumerate() {
+                    let name = match register_alias(number as u8) {
+                        Some(alias) => format!("r{} ({})", number, alias),
+                        None => format!("r{}", number),
+                    };
+                    let value_text = format!("0x{:08x} ({})", value, value);
+                    variables.push(variable(name, value_text, Some(format!("0x{:x}", value))));
+                }
+            }
+            Some(FLAGS) => {
+                let flags = self.machine.flags();
+                for (name, value) in [
+                    ("zero", flags.zero),
+                    ("negative", flags.negative),
+                    ("carry", flags.carry),
+                    ("overflow", flags.overflow),
+                ] {
+                    variables.push(variable(name.to_string(), value.to_string(), None));
+                }
+            }
+            _ => {}
+        }
+        return Json::object(vec![("variables", variables.into())]);
+    }
+
+    fn read_memory(&self, arguments: &Json) -> Result<Json, String> {
+        let reference = arguments.get("memoryReference").as_str().unwrap_or("");
+        let start = parse_address(reference).ok_or("invalid memory reference")?;
+        let offset = arguments.get("offset").as_i64().unwrap_or(0);
+        let start = start.checked_add(offset).ok_or("invalid memory range")?;
+        let count = i64::try_from(arguments.get("count").as_u64().unwrap_or(0))
+            .map_err(|_| "invalid memory range")?;
+        let end = start.checked_add(count).ok_or("invalid memory range")?;
+        let memory = self.machine.memory();
+        let readable = start.clamp(0, memory.len() as i64) as usize
+            ..end.clamp(0, memory.len() as i64) as usize;
+        let mut body = vec![("address", format!("0x{:x}", start.max(0)).into())];
+        if !readable.is_empty() {
+            body.push(("data", base64(&memory[readable.clone()]).into()));
+        }
+        let unreadable = count - readable.len() as i64;
+        if unreadable > 0 {
+            body.push(("unreadableBytes", (unreadable as u64).into()));
+        }
+        return Ok(Json::object(body));
+    }
+
+    fn evaluate(&self, arguments: &Json) -> Result<Json, String> {
+        let expression = arguments.get("expression").as_str().unwrap_or("");
+        let expression = Expr::parse(expression).map_err(|e| e.to_string())?;
+        let value = expression.eval(&self.machine).map_err(|e| e.to_string())?;
+        return Ok(Json::object(vec![
+            ("result", format!("0x{:08x} ({})", value, value).into()),
+            ("variablesReference", 0u32.into()),
+        ]));
+    }
+
+    fn resume(&mut self) {
+        if self.finished {
+            self.event("terminated", Json::Null);
+            return;
+        }
+        self.running = true;
+        self.resuming = true;
+    }
+
+    // Run the program a little, until it stops at most
+    fn run_slice(&mut self) {
+        for _ in 0..RUN_SLICE {
+            if !self.resuming && self.machine.at_breakpoint() {
+                self.running = false;
+                self.stopped("breakpoint", None);
+                return;
+            }
+            self.resuming = false;
+            if let Some(stop) = self.step() {
+                self.running = false;
+                self.report(stop);
+                return;
+            }
+        }
+    }
+
+    // Execute an instruction, sending its output. `None` is returned if the
+    // program goes on.
+    fn step(&mut self) -> Option<Stop> {
+        let mut output: Vec<u8> = Vec::new();
+        let ip = self.machine.regs()[0];
+        let outcome = self.machine.step_on(&mut output);
+        if !output.is_empty() {
+            let body = Json::object(vec![
+                ("category", "stdout".into()),
+                (
+                    "output",
+                    String::from_utf8_lossy(&output).to_string().into(),
+                ),
+            ]);
+            self.event("output", body);
+        }
+        match outcome {
+            Ok(StepOutcome::Continue) => return None,
+            Ok(StepOutcome::Breakpoint) => return Some(Stop::Breakpoint("breakpoint instruction")),
+            Ok(StepOutcome::Paused) => return Some(Stop::Breakpoint("hook")),
+            Ok(StepOutcome::OutOfFuel) => return Some(Stop::Breakpoint("out of fuel")),
+            Ok(StepOutcome::Watchpoint(_) | StepOutcome::ReadWatchpoint(_)) => {
+                return Some(Stop::Breakpoint("watchpoint"))
+            }
+            Ok(StepOutcome::Exited) => {
+                self.finished = true;
+                let code = self.machine.exit_code().unwrap_or(0);
+                self.event("exited", Json::object(vec![("exitCode", code.into())]));
+                self.event("terminated", Json::Null);
+                return Some(Stop::Exited);
+            }
+            Err(error) => {
+                self.finished = true;
+                return Some(Stop::Exception(format!("fault at 0x{:04x}: {}", ip, error)));
+            }
+        }
+    }
+
+    fn report(&mut self, stop: Stop) {
+        match stop {
+            Stop::Breakpoint(description) => self.stopped("breakpoint", Some(description.into())),
+            Stop::Exception(text) => self.stopped("exception", Some(text)),
+            Stop::Exited => {}
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(text) = text {
+            body.push(("description", text.clone().into()));
+            body.push(("text", text.into()));
+        }
+        self.event("stopped", Json::object(body));
+    }
+}
+
+// A number, in decimal or in hexadecimal with the `0x` prefix
+fn parse_address(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x") {
+        Some(digits) => return i64::from_str_radix(digits, 16).ok(),
+        None => return text.parse().ok(),
+    }
+}
+
+// Encode `bytes` in base64, as readMemory responses do
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, byte)| {
+            value | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(value >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    return text;
+}
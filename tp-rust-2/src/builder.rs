@@ -0,0 +1,240 @@
+use crate::machine::{
+    LoadError, Machine, MachineError, MaybeSend, MemoryObserver, Register, RunOutcome, StepHook,
+    SyscallHandler, MEMORY_SIZE,
+};
+use std::io::{self, Read, Write};
+
+// The registration of a handler, done once the machine is created
+type Setup = Box<dyn FnOnce(&mut Machine)>;
+
+/// A builder of machines, setting up in one place what is otherwise set
+/// once the machine is created: its memory size, program, registers, random
+/// seed, handlers and fuel.
+///
+/// ```
+/// use interpreter::{MachineBuilder, Register};
+///
+/// // 0x100: loadimm r1, 10
+/// let machine = MachineBuilder::new()
+///     .memory_size(0x2000)
+///     .load_at(0x100, &[4, 1, 10, 0])
+///     .entry(0x100)
+///     .register(Register::SP, 0x2000)
+///     .build()
+///     .unwrap();
+/// assert_eq!(0x2000, machine.memory().len());
+/// assert_eq!(&[0x100, 0x2000], &[machine.regs()[0], machine.regs()[15]]);
+/// ```
+///
+/// The program input and output and the steps of a run can be set too, to
+/// be used by the [Session] created by
+/// [build_session](MachineBuilder::build_session):
+///
+/// ```
+/// use interpreter::{assemble, MachineBuilder, RunOutcome};
+///
+/// let program = assemble("in r1\nout r1\njmp 0").unwrap();
+/// let mut output = Vec::new();
+/// let mut session = MachineBuilder::new()
+///     .program(&program)
+///     .input(&b"hi"[..])
+///     .output(&mut output)
+///     .step_limit(6)
+///     .build_session()
+///     .unwrap();
+/// assert_eq!(RunOutcome::StepLimitReached, session.run().unwrap());
+/// drop(session);
+/// assert_eq!(b"hi", &output[..]);
+/// ```
+pub struct MachineBuilder<'io> {
+    memory_size: usize,
+    segments: Vec<(usize, Vec<u8>)>, // The bytes loaded at an address
+    regs: Vec<(Register, u32)>,      // The registers set, IP included
+    seed: u64,
+    handlers: Vec<Setup>,
+    fuel: Option<u64>,
+    input: Option<Box<dyn Read + 'io>>,
+    output: Option<Box<dyn Write + 'io>>,
+    step_limit: Option<u64>, // The steps of every run of the session
+}
+
+impl Default for MachineBuilder<'_> {
+    fn default() -> Self {
+        return MachineBuilder {
+            memory_size: MEMORY_SIZE,
+            segments: Vec::new(),
+            regs: Vec::new(),
+            seed: 0,
+            handlers: Vec::new(),
+            fuel: None,
+            input: None,
+            output: None,
+            step_limit: None,
+        };
+    }
+}
+
+impl<'io> MachineBuilder<'io> {
+    /// A builder of machines like the ones of [Machine::try_new], with an empty
+    /// memory of 4096 bytes.
+    pub fn new() -> Self {
+        return MachineBuilder::default();
+    }
+
+    /// Give the machine a memory of `size` bytes.
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        return self;
+    }
+
+    /// Copy `program` at the beginning of the memory, like [Machine::try_new].
+    pub fn program(self, program: &[u8]) -> Self {
+        return self.load_at(0, program);
+    }
+
+    /// Copy `bytes` into the memory starting at `address`, after the bytes
+    /// given before.
+    pub fn load_at(mut self, address: usize, bytes: &[u8]) -> Self {
+        self.segments.push((address, bytes.to_vec()));
+        return self;
+    }
+
+    /// Set `register` to `value` instead of 0.
+    pub fn register(mut self, register: Register, value: u32) -> Self {
+        self.regs.push((register, value));
+        return self;
+    }
+
+    /// Start the program at `ip` instead of 0.
+    pub fn entry(self, ip: u32) -> Self {
+        return self.register(Register::IP, ip);
+    }
+
+    /// Seed the pseudo-random number generator, see [Machine::seed_rng].
+    pub fn seed_rng(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        return self;
+    }
+
+    /// Serve the `syscall` instruction with `handler`, see
+    /// [Machine::set_syscall_handler].
+    pub fn syscall_handler<H: SyscallHandler + MaybeSend + 'static>(mut self, handler: H) -> Self {
+        self.handlers
+            .push(Box::new(|machine| machine.set_syscall_handler(handler)));
+        return self;
+    }
+
+    /// Call `hook` around every step, see [Machine::set_hook].
+    pub fn hook<H: StepHook + MaybeSend + 'static>(mut self, hook: H) -> Self {
+        self.handlers
+            .push(Box::new(|machine| machine.set_hook(hook)));
+        return self;
+    }
+
+    /// Tell `observer` about the memory accesses, see
+    /// [Machine::set_memory_observer].
+    pub fn memory_observer<O: MemoryObserver + MaybeSend + 'static>(mut self, observer: O) -> Self {
+        self.handlers
+            .push(Box::new(|machine| machine.set_memory_observer(observer)));
+        return self;
+    }
+
+    /// Limit the fuel of the machine to `fuel` units, see
+    /// [Machine::set_fuel].
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        return self;
+    }
+
+    /// Read the program input from `input` instead of the standard input
+    /// during the runs of the session.
+    pub fn input<R: Read + 'io>(mut self, input: R) -> Self {
+        self.input = Some(Box::new(input));
+        return self;
+    }
+
+    /// Write the program output to `output` instead of the standard output
+    /// during the runs of the session.
+    pub fn output<W: Write + 'io>(mut self, output: W) -> Self {
+        self.output = Some(Box::new(output));
+        return self;
+    }
+
+    /// Stop every run of the session once `steps` steps have been executed,
+    /// like [Machine::run_for_with_io].
+    pub fn step_limit(mut self, steps: u64) -> Self {
+        self.step_limit = Some(steps);
+        return self;
+    }
+
+    /// Create the machine, or return [LoadError::TooLarge] if some bytes
+    /// don't fit in the memory. The input, output and step limit are left
+    /// to the methods running it, see [build_session](MachineBuilder::build_session).
+    pub fn build(self) -> Result<Machine, LoadError> {
+        let mut machine = Machine::with_memory_size(self.memory_size, &[])?;
+        for (address, bytes) in &self.segments {
+            machine
+                .load_at(*address, bytes)
+                .map_err(|_| LoadError::TooLarge)?;
+        }
+        for (register, value) in self.regs {
+            machine.set(register, value);
+        }
+        machine.seed_rng(self.seed);
+        for handler in self.handlers {
+            handler(&mut machine);
+        }
+        machine.set_fuel(self.fuel);
+        machine.keep_initial_state();
+        return Ok(machine);
+    }
+
+    /// Create the machine like [build](MachineBuilder::build), along with
+    /// the input, output and step limit of its runs.
+    pub fn build_session(mut self) -> Result<Session<'io>, LoadError> {
+        let input = self.input.take();
+        let output = self.output.take();
+        let step_limit = self.step_limit;
+        return Ok(Session {
+            machine: self.build()?,
+            input: input.unwrap_or_else(|| Box::new(io::stdin())),
+            output: output.unwrap_or_else(|| Box::new(io::stdout())),
+            step_limit,
+        });
+    }
+}
+
+/// A machine created by [MachineBuilder::build_session], running with the
+/// input, output and step limit given to the builder.
+pub struct Session<'io> {
+    machine: Machine,
+    input: Box<dyn Read + 'io>,
+    output: Box<dyn Write + 'io>,
+    step_limit: Option<u64>,
+}
+
+impl Session<'_> {
+    /// Run like [Machine::run_with_io], or [Machine::run_for_with_io] if
+    /// there is a step limit. Running again resumes the program.
+    pub fn run(&mut self) -> Result<RunOutcome, MachineError> {
+        return match self.step_limit {
+            Some(steps) => self
+                .machine
+                .run_for_with_io(steps, &mut self.input, &mut self.output),
+            None => self.machine.run_with_io(&mut self.input, &mut self.output),
+        };
+    }
+
+    pub fn machine(&self) -> &Machine {
+        return &self.machine;
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        return &mut self.machine;
+    }
+
+    /// The machine, dropping the input and the output.
+    pub fn into_machine(self) -> Machine {
+        return self.machine;
+    }
+}
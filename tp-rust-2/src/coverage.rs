@@ -0,0 +1,83 @@
+use crate::disasm::{DecodedInsn, FormatOptions};
+
+/// The number of times the instruction at every address of the memory
+/// has been executed, kept by a machine once
+/// [enable_coverage](crate::Machine::enable_coverage) has been called.
+/// Only the instructions executed without a fault are counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    hits: Vec<u64>,
+}
+
+impl Coverage {
+    /// The coverage of a memory of `size` bytes where nothing has been
+    /// executed.
+    pub fn new(size: usize) -> Self {
+        return Coverage {
+            hits: vec![0; size],
+        };
+    }
+
+    /// Count an execution of the instruction at `address`.
+    pub fn record(&mut self, address: u32) {
+        if let Some(hits) = self.hits.get_mut(address as usize) {
+            *hits += 1;
+        }
+    }
+
+    /// The number of times the instruction at `address` has been executed.
+    pub fn hits(&self, address: u32) -> u64 {
+        return self.hits.get(address as usize).copied().unwrap_or(0);
+    }
+
+    pub fn is_covered(&self, address: u32) -> bool {
+        return self.hits(address) > 0;
+    }
+
+    /// The addresses of the instructions executed, by increasing address.
+    pub fn covered(&self) -> Vec<u32> {
+        return (0..self.hits.len() as u32)
+            .filter(|&address| self.is_covered(address))
+            .collect();
+    }
+
+    /// A listing of `instructions` with the number of times each of them
+    /// has been executed, `-` marking the ones never executed, followed by
+    /// the share of the instructions executed.
+    pub fn report(&self, instructions: &[DecodedInsn], options: &FormatOptions) -> String {
+        let mut report = String::new();
+        for instruction in instructions {
+            let address = instruction.address as u32;
+            if let Some(symbols) = options.symbols {
+                for label in symbols.labels_at(address) {
+                    report.push_str(&format!("{}:\n", label));
+                }
+            }
+            let hits = match self.hits(address) {
+                0 => String::from("-"),
+                hits => hits.to_string(),
+            };
+            report.push_str(&format!(
+                "{:>8}  {:04x}  {}\n",
+                hits,
+                address,
+                instruction.format(options)
+            ));
+        }
+        let covered = instructions
+            .iter()
+            .filter(|instruction| self.is_covered(instruction.address as u32))
+            .count();
+        let percent = match instructions.len() {
+            0 => 100.0,
+            len => 100.0 * covered as f64 / len as f64,
+        };
+        report.push_str(&format!(
+            "{} of {} instructions executed ({:.1}%)\n",
+            covered,
+            instructions.len(),
+            percent
+        ));
+        return report;
+    }
+}
@@ -0,0 +1,669 @@
+use crate::debuginfo::DebugInfo;
+use crate::disasm::{decode, FormatOptions};
+use crate::expr::Expr;
+use crate::isa::{instruction_by_mnemonic, instruction_info};
+use crate::machine::{Machine, StepOutcome};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+// The number of steps `rstep` can undo
+const JOURNAL_CAPACITY: usize = 100_000;
+
+// The checkpoints `rewind` goes back to, every 1000 instructions
+const CHECKPOINT_INTERVAL: u64 = 1000;
+const CHECKPOINT_CAPACITY: usize = 1000;
+
+const HELP: &str = "\
+step [n]         execute one or n instructions (s)
+rstep [n]        undo the last one or n instructions
+rewind <n>       go back to n instructions ago, replaying them from a
+                 checkpoint
+next [n]         execute one or n instructions, running the functions they
+                 call until they return (n)
+finish           run until the current function returns (fin)
+continue         run until a breakpoint or the end of the program (c)
+regs             print the registers and the flags (r)
+bt               print the calls not returned yet, the latest first
+mem <addr> <len> print len bytes of memory from addr (x)
+break [addr [if cond]]
+                 add a breakpoint at addr, stopping only if cond holds when
+                 given, or list the breakpoints (b)
+break insn <mnemonic>
+                 stop before every instruction named mnemonic
+delete <addr>    remove the breakpoint at addr (d)
+delete insn <mnemonic>
+                 remove the breakpoint on the instructions named mnemonic
+watch [addr [len]]
+                 stop when the program writes len bytes (4 by default) from
+                 addr, or list the watchpoints (w)
+rwatch [addr [len]]
+                 stop when the program reads len bytes (4 by default) from
+                 addr, or list the read watchpoints
+unwatch <addr>   remove the watchpoints and read watchpoints starting at addr
+expect <cond>    report a failure unless cond holds, to check a script
+help             print this help (h)
+quit             leave the debugger (q)
+An address is a number or, with debug info, a label. A condition is an
+expression such as `r3 == 10 && mem[0x100] != 0`. An empty line repeats
+the previous command.
+";
+
+/// An interactive debugger, executing the commands read on a line each, such
+/// as `step`, `continue`, `regs`, `mem <addr> <len>`, `break <addr>` and
+/// `quit`, on a machine. Run `help` for the full list.
+///
+/// The output of the program is written along with the one of the debugger,
+/// unless it is captured. Input instructions always see the end of input.
+///
+/// A script of commands can also be run with
+/// [run_script](Debugger::run_script), its `expect` commands checking the
+/// state of the machine.
+pub struct Debugger {
+    machine: Machine,
+    debug_info: Option<DebugInfo>,
+    finished: bool,                  // The program exited or faulted
+    last_command: String,            // Run again on an empty line
+    program_output: Option<Vec<u8>>, // The output of the program, when kept apart
+    failures: usize,                 // Failed expectations and invalid commands
+}
+
+// A parsed command
+enum Command {
+    Step(usize),
+    StepBack(usize),
+    Rewind(u64),
+    Next(usize),
+    Finish,
+    Continue,
+    Regs,
+    Backtrace,
+    Mem(u32, usize),
+    Break(Option<(u32, Option<Expr>)>),
+    Delete(u32),
+    BreakOpcode(u8),
+    DeleteOpcode(u8),
+    Watch(Option<(u32, u32)>),
+    ReadWatch(Option<(u32, u32)>),
+    Unwatch(u32),
+    Expect(Expr),
+    Help,
+    Quit,
+}
+
+impl Debugger {
+    /// Debug `machine`, recording its steps so that `rstep` can undo them,
+    /// and checkpoints of its state for `rewind`.
+    pub fn new(mut machine: Machine) -> Self {
+        machine.set_journal_capacity(JOURNAL_CAPACITY);
+        machine.set_checkpoints(CHECKPOINT_INTERVAL, CHECKPOINT_CAPACITY);
+        return Debugger {
+            machine,
+            debug_info: None,
+            finished: false,
+            last_command: String::new(),
+            program_output: None,
+            failures: 0,
+        };
+    }
+
+    /// Use the debug info of the program to show source lines and labels,
+    /// and to accept labels as addresses.
+    pub fn set_debug_info(&mut self, debug_info: DebugInfo) {
+        self.debug_info = Some(debug_info);
+    }
+
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        return self.debug_info.as_ref();
+    }
+
+    /// Keep the output of the program apart from the one of the debugger,
+    /// to be read with [program_output](Debugger::program_output).
+    pub fn capture_program_output(&mut self) {
+        self.program_output.get_or_insert_with(Vec::new);
+    }
+
+    /// What the program printed since
+    /// [capture_program_output](Debugger::capture_program_output) was called.
+    pub fn program_output(&self) -> &[u8] {
+        return self.program_output.as_deref().unwrap_or(&[]);
+    }
+
+    pub fn machine(&self) -> &Machine {
+        return &self.machine;
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        return &mut self.machine;
+    }
+
+    /// Read commands from `input` until `quit` or the end of input, writing
+    /// a prompt before every one of them and their result on `output`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "(rvm) ")?;
+            output.flush()?;
+            let Some(line) = lines.next() else {
+                writeln!(output)?;
+                return Ok(());
+            };
+            if !self.execute(&line?, output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute the commands of `script`, one per line, until `quit` or the
+    /// end of the script, writing every command after a prompt and its
+    /// result on `output`. The empty lines and the ones starting with `#`
+    /// are skipped. Whether all of them succeeded is told by
+    /// [failures](Debugger::failures).
+    pub fn run_script<W: Write>(&mut self, script: &str, output: &mut W) -> io::Result<()> {
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            writeln!(output, "(rvm) {}", line)?;
+            if !self.execute(line, output)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    /// The number of `expect` commands whose condition did not hold, and of
+    /// invalid commands, executed so far.
+    pub fn failures(&self) -> usize {
+        return self.failures;
+    }
+
+    /// Execute a command line, writing its result on `output`. It returns
+    /// `false` if the command is `quit`.
+    pub fn execute<W: Write>(&mut self, line: &str, output: &mut W) -> io::Result<bool> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            line.trim().to_string()
+        };
+        if line.is_empty() {
+            return Ok(true);
+        }
+        self.last_command = line.clone();
+        let command = match self.parse(&line) {
+            Ok(command) => command,
+            Err(message) => {
+                writeln!(output, "{}", message)?;
+                self.failures += 1;
+                return Ok(true);
+            }
+        };
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if !self.step(output)? {
+                        return Ok(true);
+                    }
+                }
+                self.show_next(output)?;
+            }
+            Command::StepBack(count) => {
+                for _ in 0..count {
+                    if !self.machine.step_back() {
+                        writeln!(output, "no earlier step recorded")?;
+                        break;
+                    }
+                    self.finished = false;
+                }
+                self.show_next(output)?;
+            }
+            Command::Rewind(count) => {
+                if self.machine.rewind(count) {
+                    self.finished = false;
+                } else {
+                    writeln!(output, "cannot rewind {} instructions", count)?;
+                }
+                self.show_next(output)?;
+            }
+            Command::Next(count) => {
+                for _ in 0..count {
+                    // A call is stepped over by running until the function
+                    // it calls returns
+                    let ip = self.machine.regs()[0];
+                    if self.mnemonic_at(ip) != Some("call") {
+                        if !self.step(output)? {
+                            return Ok(true);
+                        }
+                        continue;
+                    }
+                    if !self.step(output)? || !self.run_until_return(output)? {
+                        return Ok(true);
+                    }
+                    if self.machine.at_breakpoint() {
+                        break;
+                    }
+                }
+                self.show_next(output)?;
+            }
+            Command::Finish => {
+                if self.run_until_return(output)? {
+                    self.show_next(output)?;
+                }
+            }
+            Command::Continue => {
+                // Like run_on, but stepping keeps the address of a faulting instruction
+                if self.step(output)? {
+                    loop {
+                        let ip = self.machine.regs()[0];
+                        if self.machine.at_breakpoint() {
+                            writeln!(output, "breakpoint at {}", self.describe(ip))?;
+                            break;
+                        }
+                        if !self.step(output)? {
+                            return Ok(true);
+                        }
+                    }
+                    self.show_next(output)?;
+                }
+            }
+            Command::Regs => self.regs(output)?,
+            Command::Backtrace => {
+                // The current instruction, then where every call returns
+                let ip = self.machine.regs()[0];
+                writeln!(output, "#0 {}", self.describe(ip))?;
+                for (i, frame) in self.machine.backtrace().iter().enumerate() {
+                    writeln!(output, "#{} {}", i + 1, self.describe(frame.return_address))?;
+                }
+            }
+            Command::Mem(address, len) => self.mem(address, len, output)?,
+            Command::Break(Some((address, condition))) => {
+                match condition {
+                    Some(condition) => self.machine.add_conditional_breakpoint(address, condition),
+                    None => self.machine.add_breakpoint(address),
+                };
+                self.show_breakpoint(address, output)?;
+            }
+            Command::Break(None) => {
+                if self.machine.breakpoints().next().is_none()
+                    && self.machine.opcode_breakpoints().next().is_none()
+                {
+                    writeln!(output, "no breakpoints")?;
+                }
+                for address in self.machine.breakpoints() {
+                    self.show_breakpoint(address, output)?;
+                }
+                for opcode in self.machine.opcode_breakpoints() {
+                    writeln!(output, "breakpoint on {}", mnemonic(opcode))?;
+                }
+            }
+            Command::Delete(address) => {
+                if !self.machine.remove_breakpoint(address) {
+                    writeln!(output, "no breakpoint at 0x{:04x}", address)?;
+                }
+            }
+            Command::BreakOpcode(opcode) => {
+                self.machine.add_opcode_breakpoint(opcode);
+                writeln!(output, "breakpoint on {}", mnemonic(opcode))?;
+            }
+            Command::DeleteOpcode(opcode) => {
+                if !self.machine.remove_opcode_breakpoint(opcode) {
+                    writeln!(output, "no breakpoint on {}", mnemonic(opcode))?;
+                }
+            }
+            Command::Watch(Some((address, len))) => {
+                let range = address..address.saturating_add(len);
+                writeln!(
+                    output,
+                    "watchpoint at 0x{:04x}..0x{:04x}",
+                    range.start, range.end
+                )?;
+                self.machine.add_watchpoint(range);
+            }
+            Command::Watch(None) => {
+                if self.machine.watchpoints().next().is_none() {
+                    writeln!(output, "no watchpoints")?;
+                }
+                for range in self.machine.watchpoints() {
+                    writeln!(
+                        output,
+                        "watchpoint at 0x{:04x}..0x{:04x}",
+                        range.start, range.end
+                    )?;
+                }
+            }
+            Command::ReadWatch(Some((address, len))) => {
+                let range = address..address.saturating_add(len);
+                writeln!(
+                    output,
+                    "read watchpoint at 0x{:04x}..0x{:04x}",
+                    range.start, range.end
+                )?;
+                self.machine.add_read_watchpoint(range);
+            }
+            Command::ReadWatch(None) => {
+                if self.machine.read_watchpoints().next().is_none() {
+                    writeln!(output, "no read watchpoints")?;
+                }
+                for range in self.machine.read_watchpoints() {
+                    writeln!(
+                        output,
+                        "read watchpoint at 0x{:04x}..0x{:04x}",
+                        range.start, range.end
+                    )?;
+                }
+            }
+            Command::Unwatch(address) => {
+                let starting = |range: &Range<u32>| range.start == address;
+                let ranges: Vec<_> = self.machine.watchpoints().filter(starting).collect();
+                let read_ranges: Vec<_> =
+                    self.machine.read_watchpoints().filter(starting).collect();
+                if ranges.is_empty() && read_ranges.is_empty() {
+                    writeln!(output, "no watchpoint at 0x{:04x}", address)?;
+                }
+                for range in ranges {
+                    self.machine.remove_watchpoint(range);
+                }
+                for range in read_ranges {
+                    self.machine.remove_read_watchpoint(range);
+                }
+            }
+            Command::Expect(condition) => match condition.eval(&self.machine) {
+                Ok(0) => {
+                    writeln!(output, "expectation failed: {}", condition)?;
+                    self.failures += 1;
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    writeln!(output, "expectation failed: {}: {}", condition, error)?;
+                    self.failures += 1;
+                }
+            },
+            Command::Help => write!(output, "{}", HELP)?,
+            Command::Quit => return Ok(false),
+        }
+        return Ok(true);
+    }
+
+    fn parse(&self, line: &str) -> Result<Command, String> {
+        // The condition of a breakpoint follows `if`
+        if let Some((command, condition)) = line.split_once(" if ") {
+            let Command::Break(Some((address, None))) = self.parse(command)? else {
+                return Err("only breakpoints take a condition".to_string());
+            };
+            let condition =
+                Expr::parse(condition).map_err(|error| format!("invalid condition: {}", error))?;
+            return Ok(Command::Break(Some((address, Some(condition)))));
+        }
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        let command = match (name, &args[..]) {
+            ("step" | "s", []) => Command::Step(1),
+            ("step" | "s", [count]) => match count.parse() {
+                Ok(count) => Command::Step(count),
+                Err(_) => return Err(format!("invalid count `{}`", count)),
+            },
+            ("rstep", []) => Command::StepBack(1),
+            ("rstep", [count]) => match count.parse() {
+                Ok(count) => Command::StepBack(count),
+                Err(_) => return Err(format!("invalid count `{}`", count)),
+            },
+            ("rewind", [count]) => match count.parse() {
+                Ok(count) => Command::Rewind(count),
+                Err(_) => return Err(format!("invalid count `{}`", count)),
+            },
+            ("next" | "n", []) => Command::Next(1),
+            ("next" | "n", [count]) => match count.parse() {
+                Ok(count) => Command::Next(count),
+                Err(_) => return Err(format!("invalid count `{}`", count)),
+            },
+            ("finish" | "fin", []) => Command::Finish,
+            ("continue" | "c", []) => Command::Continue,
+            ("regs" | "r", []) => Command::Regs,
+            ("bt", []) => Command::Backtrace,
+            ("mem" | "x", [address, len]) => {
+                let len = parse_number(len).ok_or(format!("invalid length `{}`", len))?;
+                Command::Mem(self.address(address)?, len as usize)
+            }
+            ("break" | "b", ["insn", name]) => Command::BreakOpcode(opcode(name)?),
+            ("delete" | "d", ["insn", name]) => Command::DeleteOpcode(opcode(name)?),
+            ("break" | "b", []) => Command::Break(None),
+            ("break" | "b", [address]) => Command::Break(Some((self.address(address)?, None))),
+            ("delete" | "d", [address]) => Command::Delete(self.address(address)?),
+            ("watch" | "w", []) => Command::Watch(None),
+            ("watch" | "w", [address]) => Command::Watch(Some((self.address(address)?, 4))),
+            ("watch" | "w", [address, len]) => {
+                let len = parse_number(len).ok_or(format!("invalid length `{}`", len))?;
+                Command::Watch(Some((self.address(address)?, len)))
+            }
+            ("rwatch", []) => Command::ReadWatch(None),
+            ("rwatch", [address]) => Command::ReadWatch(Some((self.address(address)?, 4))),
+            ("rwatch", [address, len]) => {
+                let len = parse_number(len).ok_or(format!("invalid length `{}`", len))?;
+                Command::ReadWatch(Some((self.address(address)?, len)))
+            }
+            ("unwatch", [address]) => Command::Unwatch(self.address(address)?),
+            ("expect", [_, ..]) => {
+                let condition = Expr::parse(line[name.len()..].trim_start())
+                    .map_err(|error| format!("invalid condition: {}", error))?;
+                Command::Expect(condition)
+            }
+            ("help" | "h", []) => Command::Help,
+            ("quit" | "q", []) => Command::Quit,
+            (
+                "step" | "s" | "rstep" | "rewind" | "next" | "n" | "finish" | "fin" | "continue"
+                | "c" | "regs" | "r" | "bt" | "mem" | "x" | "break" | "b" | "delete" | "d"
+                | "watch" | "w" | "rwatch" | "unwatch" | "expect" | "help" | "h" | "quit" | "q",
+                _,
+            ) => return Err(format!("invalid arguments for `{}`, see `help`", name)),
+            _ => return Err(format!("unknown command `{}`, see `help`", name)),
+        };
+        return Ok(command);
+    }
+
+    // An address written as a number or as a label of the debug info
+    pub(crate) fn address(&self, word: &str) -> Result<u32, String> {
+        if let Some(address) = parse_number(word) {
+            return Ok(address);
+        }
+        return self
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.symbols().address(word))
+            .ok_or(format!("invalid address `{}`", word));
+    }
+
+    // Execute an instruction, returning whether the program can go on. The
+    // reason it can't is written on `output`.
+    fn step<W: Write>(&mut self, output: &mut W) -> io::Result<bool> {
+        if self.finished {
+            writeln!(output, "the program is not running")?;
+            return Ok(false);
+        }
+        let ip = self.machine.regs()[0];
+        let outcome = match &mut self.program_output {
+            Some(program_output) => self.machine.step_on(program_output),
+            None => self.machine.step_on(output),
+        };
+        match outcome {
+            Ok(StepOutcome::Continue) => return Ok(true),
+            Ok(StepOutcome::Breakpoint) => {
+                writeln!(output, "breakpoint instruction at {}", self.describe(ip))?;
+                self.show_next(output)?;
+                return Ok(false);
+            }
+            Ok(StepOutcome::Watchpoint(write)) => {
+                writeln!(
+                    output,
+                    "watchpoint: {} wrote 0x{:04x}: {} -> {}",
+                    self.describe(write.instruction),
+                    write.address,
+                    hex_bytes(&write.old),
+                    hex_bytes(&write.new)
+                )?;
+                self.show_next(output)?;
+                return Ok(false);
+            }
+            Ok(StepOutcome::ReadWatchpoint(read)) => {
+                writeln!(
+                    output,
+                    "read watchpoint: {} read 0x{:04x}: {}",
+                    self.describe(read.instruction),
+                    read.address,
+                    hex_bytes(&read.bytes)
+                )?;
+                self.show_next(output)?;
+                return Ok(false);
+            }
+            Ok(StepOutcome::Paused) => {
+                writeln!(output, "paused by the hook at {}", self.describe(ip))?;
+                self.show_next(output)?;
+                return Ok(false);
+            }
+            Ok(StepOutcome::OutOfFuel) => {
+                writeln!(output, "out of fuel at {}", self.describe(ip))?;
+                self.show_next(output)?;
+                return Ok(false);
+            }
+            Ok(StepOutcome::Exited) => {
+                let code = self.machine.exit_code().unwrap_or(0);
+                writeln!(output, "the program exited with code {}", code)?;
+            }
+            Err(error) => writeln!(output, "fault at {}: {}", self.describe(ip), error)?,
+        }
+        self.finished = true;
+        return Ok(false);
+    }
+
+    fn show_breakpoint<W: Write>(&self, address: u32, output: &mut W) -> io::Result<()> {
+        write!(output, "breakpoint at {}", self.describe(address))?;
+        if let Some(condition) = self.machine.breakpoint_condition(address) {
+            write!(output, " if {}", condition)?;
+        }
+        return writeln!(output);
+    }
+
+    // Write the next instruction to execute
+    fn show_next<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let ip = self.machine.regs()[0];
+        let memory = self.machine.memory();
+        if ip as usize >= memory.len() {
+            return writeln!(output, "0x{:04x}: outside the memory", ip);
+        }
+        let insn = decode(&memory[ip as usize..], ip as usize);
+        let options = FormatOptions {
+            symbols: self
+                .debug_info
+                .as_ref()
+                .map(|debug_info| debug_info.symbols()),
+            aliases: false,
+        };
+        write!(output, "0x{:04x}: {}", ip, insn.format(&options))?;
+        if let Some(location) = self.debug_info.as_ref().and_then(|d| d.location(ip)) {
+            write!(output, " ; {}", location)?;
+        }
+        return writeln!(output);
+    }
+
+    fn regs<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (i, chunk) in self.machine.regs().chunks(4).enumerate() {
+            let line: Vec<String> = chunk
+                .iter()
+                .enumerate()
+                .map(|(j, value)| format!("{:<3} 0x{:08x}", format!("r{}", i * 4 + j), value))
+                .collect();
+            writeln!(output, "{}", line.join("  "))?;
+        }
+        let flags = self.machine.flags();
+        return writeln!(
+            output,
+            "flags: zero={} negative={} carry={} overflow={}",
+            flags.zero as u8, flags.negative as u8, flags.carry as u8, flags.overflow as u8
+        );
+    }
+
+    fn mem<W: Write>(&self, address: u32, len: usize, output: &mut W) -> io::Result<()> {
+        if address as usize >= self.machine.memory().len() {
+            return writeln!(output, "address 0x{:04x} is outside the memory", address);
+        }
+        let end = address.saturating_add(len.try_into().unwrap_or(u32::MAX));
+        return write!(output, "{}", self.machine.hexdump(address..end));
+    }
+
+    // Run until the current function returns, following the calls and the
+    // returns of the functions it calls on the way, and stopping at the
+    // breakpoints. It returns whether the program can go on, in which case
+    // a breakpoint stopping it is written on `output`.
+    fn run_until_return<W: Write>(&mut self, output: &mut W) -> io::Result<bool> {
+        let mut depth = 1;
+        loop {
+            let mnemonic = self.mnemonic_at(self.machine.regs()[0]);
+            if !self.step(output)? {
+                return Ok(false);
+            }
+            match mnemonic {
+                Some("call") => depth += 1,
+                Some("ret") => depth -= 1,
+                _ => {}
+            }
+            let ip = self.machine.regs()[0];
+            if self.machine.at_breakpoint() {
+                writeln!(output, "breakpoint at {}", self.describe(ip))?;
+                return Ok(true);
+            }
+            if depth == 0 {
+                return Ok(true);
+            }
+        }
+    }
+
+    // The mnemonic of the instruction at `address`, if it is a valid one
+    fn mnemonic_at(&self, address: u32) -> Option<&'static str> {
+        let opcode = self.machine.memory().get(address as usize)?;
+        return instruction_info(*opcode).map(|info| info.mnemonic);
+    }
+
+    // An address for a message, with its label and source line if known
+    fn describe(&self, address: u32) -> String {
+        let Some(debug_info) = &self.debug_info else {
+            return format!("0x{:04x}", address);
+        };
+        let mut text = format!("0x{:04x}", address);
+        if let Some((label, offset)) = debug_info.label_for(address) {
+            if offset == 0 {
+                text += &format!(" <{}>", label);
+            } else {
+                text += &format!(" <{}+{}>", label, offset);
+            }
+        }
+        if let Some(location) = debug_info.location(address) {
+            text += &format!(" ({})", location);
+        }
+        return text;
+    }
+}
+
+// The opcode of the instructions named `name`
+fn opcode(name: &str) -> Result<u8, String> {
+    return instruction_by_mnemonic(name)
+        .map(|info| info.opcode)
+        .ok_or(format!("unknown instruction `{}`", name));
+}
+
+// The name of the instructions whose opcode is `opcode`
+fn mnemonic(opcode: u8) -> &'static str {
+    return instruction_info(opcode).map_or("?", |info| info.mnemonic);
+}
+
+// Bytes in hexadecimal, separated by spaces
+fn hex_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    return bytes.join(" ");
+}
+
+// A number, in decimal or in hexadecimal with the `0x` prefix
+fn parse_number(word: &str) -> Option<u32> {
+    match word.strip_prefix("0x") {
+        Some(digits) => return u32::from_str_radix(digits, 16).ok(),
+        None => return word.parse().ok(),
+    }
+}
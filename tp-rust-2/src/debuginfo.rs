@@ -0,0 +1,214 @@
+use crate::symbols::SymbolTable;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// A line of a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}:{}", self.file, self.line);
+    }
+}
+
+/// The source lines and the labels a program was assembled from, as
+/// produced by [assemble_with_debug_info](crate::assemble_with_debug_info).
+///
+/// It is stored next to the program as a text file made of one record per
+/// line, `;` starting a comment:
+///   - `line <address> <size> <file>:<line>`: the `size` bytes starting at
+///     `address` come from this line of `file`
+///   - `label <address> <name>`: the label `name` stands for `address`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    lines: BTreeMap<u32, (u32, SourceLocation)>, // The size and location of the bytes at every address
+    symbols: SymbolTable,
+}
+
+// An error found while parsing debug info
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugInfoError {
+    pub line: usize,
+    pub kind: DebugInfoErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugInfoErrorKind {
+    UnknownRecord(String),   // Record other than `line` or `label`
+    MissingField,            // Record with too few fields
+    InvalidNumber(String),   // Address or size that can't be parsed
+    InvalidLocation(String), // Location not written as `file:line`
+}
+
+impl fmt::Display for DebugInfoErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DebugInfoErrorKind::UnknownRecord(s) => write!(f, "unknown record `{}`", s),
+            DebugInfoErrorKind::MissingField => write!(f, "missing field"),
+            DebugInfoErrorKind::InvalidNumber(s) => write!(f, "invalid number `{}`", s),
+            DebugInfoErrorKind::InvalidLocation(s) => write!(f, "invalid location `{}`", s),
+        }
+    }
+}
+
+impl fmt::Display for DebugInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.kind);
+    }
+}
+
+impl std::error::Error for DebugInfoError {}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Record that the `size` bytes starting at `address` come from
+    /// `location`.
+    pub fn insert_line(&mut self, address: u32, size: u32, location: SourceLocation) {
+        self.lines.insert(address, (size, location));
+    }
+
+    /// Define the label `name` at `address`.
+    pub fn insert_label(&mut self, name: &str, address: u32) {
+        self.symbols.insert(name, address);
+    }
+
+    /// The labels of the program.
+    pub fn symbols(&self) -> &SymbolTable {
+        return &self.symbols;
+    }
+
+    /// The source line the byte at `address` comes from, if known.
+    pub fn location(&self, address: u32) -> Option<&SourceLocation> {
+        let (start, (size, location)) = self.lines.range(..=address).next_back()?;
+        if address - start >= *size {
+            return None;
+        }
+        return Some(location);
+    }
+
+    /// The first address of the first line of `file` at or after `line`
+    /// that holds bytes of the program, and the number of this line.
+    pub fn address_of_line(&self, file: &str, line: usize) -> Option<(u32, usize)> {
+        return self
+            .lines
+            .iter()
+            .filter(|(_, (_, location))| location.file == file && location.line >= line)
+            .min_by_key(|(address, (_, location))| (location.line, **address))
+            .map(|(address, (_, location))| (*address, location.line));
+    }
+
+    /// The closest label defined at or before `address`, and the offset of
+    /// `address` from it.
+    pub fn label_for(&self, address: u32) -> Option<(&str, u32)> {
+        return self
+            .symbols
+            .iter()
+            .filter(|(_, a)| *a <= address)
+            .max_by_key(|(_, a)| *a)
+            .map(|(name, a)| (name, address - a));
+    }
+
+    /// Describe `address` for a message: its source line such as
+    /// `loop.s:42` if known, the address in hexadecimal otherwise.
+    pub fn describe(&self, address: u32) -> String {
+        match self.location(address) {
+            Some(location) => return location.to_string(),
+            None => return format!("0x{:04x}", address),
+        }
+    }
+
+    /// Parse debug info written in the text format of [DebugInfo].
+    pub fn parse(text: &str) -> Result<Self, DebugInfoError> {
+        let mut info = DebugInfo::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |kind| DebugInfoError {
+                line: index + 1,
+                kind,
+            };
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut rest = line;
+            let record = next_field(&mut rest).unwrap_or("");
+            let address = parse_number(next_field(&mut rest)).map_err(error)?;
+            match record {
+                "line" => {
+                    let size = parse_number(next_field(&mut rest)).map_err(error)?;
+                    // The file name is the rest of the line, which may contain spaces
+                    let text = Some(rest)
+                        .filter(|rest| !rest.is_empty())
+                        .ok_or(error(DebugInfoErrorKind::MissingField))?;
+                    let location = text
+                        .rsplit_once(':')
+                        .and_then(|(file, line)| Some((file, line.parse::<usize>().ok()?)))
+                        .filter(|(file, _)| !file.is_empty())
+                        .map(|(file, line)| SourceLocation {
+                            file: file.to_string(),
+                            line,
+                        })
+                        .ok_or(error(DebugInfoErrorKind::InvalidLocation(text.to_string())))?;
+                    info.insert_line(address, size, location);
+                }
+                "label" => {
+                    let name =
+                        next_field(&mut rest).ok_or(error(DebugInfoErrorKind::MissingField))?;
+                    info.insert_label(name, address);
+                }
+                _ => return Err(error(DebugInfoErrorKind::UnknownRecord(record.to_string()))),
+            }
+        }
+        return Ok(info);
+    }
+
+    /// Read debug info from the file at `path`. Parse errors are returned as
+    /// [io::ErrorKind::InvalidData] errors.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        return DebugInfo::parse(&text)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error));
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    /// Write the debug info in its text format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "; rvm debug info")?;
+        for (address, (size, location)) in &self.lines {
+            writeln!(f, "line 0x{:04x} {} {}", address, size, location)?;
+        }
+        for (name, address) in self.symbols.iter() {
+            writeln!(f, "label 0x{:04x} {}", address, name)?;
+        }
+        return Ok(());
+    }
+}
+
+// Remove the first whitespace-separated field of `text` and return it
+fn next_field<'a>(text: &mut &'a str) -> Option<&'a str> {
+    if text.is_empty() {
+        return None;
+    }
+    let (field, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    *text = rest.trim_start();
+    return Some(field);
+}
+
+// Parse a decimal or hexadecimal (`0x`) number
+fn parse_number(text: Option<&str>) -> Result<u32, DebugInfoErrorKind> {
+    let text = text.ok_or(DebugInfoErrorKind::MissingField)?;
+    let number = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse::<u32>(),
+    };
+    return number.map_err(|_| DebugInfoErrorKind::InvalidNumber(text.to_string()));
+}
@@ -0,0 +1,193 @@
+use crate::isa::{instruction_info, register_alias, InstructionInfo, Operand};
+use crate::symbols::SymbolTable;
+use std::fmt;
+
+/// An instruction decoded from a program image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInsn {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    /// The description of the instruction, or `None` if the opcode is unknown
+    /// or if the instruction is truncated. Its bytes are then emitted as data.
+    pub info: Option<&'static InstructionInfo>,
+    /// The value of every operand, in the order of `info.operands`.
+    pub operands: Vec<u32>,
+}
+
+/// How the disassembler formats instructions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormatOptions<'a> {
+    /// The labels to use for the addresses they define (see
+    /// [DecodedInsn::format_with]).
+    pub symbols: Option<&'a SymbolTable>,
+    /// Whether to name the registers by their alias, such as `sp` for `r15`
+    /// (see [REGISTER_ALIASES](crate::REGISTER_ALIASES)).
+    pub aliases: bool,
+}
+
+impl DecodedInsn {
+    /// The target of the jump, branch or call instruction, if any. It may
+    /// be negative for the relative jump `jrel`.
+    pub fn target(&self) -> Option<i64> {
+        let info = self.info?;
+        let value = *self.operands.last()?;
+        match info.operands.last()? {
+            Operand::Addr16 => return Some(value as i64),
+            Operand::Rel16 => {
+                let next = (self.address + self.bytes.len()) as i64;
+                return Some(next + value as u16 as i16 as i64);
+            }
+            _ => return None,
+        }
+    }
+
+    /// Format the instruction in the syntax of the [assembler](crate::assemble),
+    /// replacing the jump and branch targets found in `symbols` by their
+    /// label, and annotating the immediate values that are label addresses.
+    pub fn format_with(&self, symbols: &SymbolTable) -> String {
+        return self.format(&FormatOptions {
+            symbols: Some(symbols),
+            aliases: false,
+        });
+    }
+
+    /// Format the instruction in the syntax of the [assembler](crate::assemble)
+    /// as specified by `options`.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let symbols = options.symbols;
+        let Some(info) = self.info else {
+            let bytes: Vec<String> = self.bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+            return format!(".byte {}", bytes.join(", "));
+        };
+        let label_at = |address: u32| symbols.and_then(|symbols| symbols.label_at(address));
+        let mut text = info.mnemonic.to_string();
+        let mut references: Vec<&str> = Vec::new();
+        for (i, (kind, value)) in info.operands.iter().zip(&self.operands).enumerate() {
+            text.push_str(if i == 0 { " " } else { ", " });
+            let operand = match kind {
+                Operand::Reg => match register_alias(*value as u8).filter(|_| options.aliases) {
+                    Some(alias) => alias.to_string(),
+                    None => format!("r{}", value),
+                },
+                Operand::FReg => format!("f{}", value),
+                Operand::Addr16 => match label_at(*value) {
+                    Some(label) => label.to_string(),
+                    None => format!("0x{:04x}", value),
+                },
+                Operand::Rel16 => {
+                    let offset = *value as u16 as i16;
+                    let target = (self.address + self.bytes.len()) as i64 + offset as i64;
+                    match label_at(target as u32).filter(|_| target >= 0) {
+                        Some(label) => label.to_string(),
+                        None => offset.to_string(),
+                    }
+                }
+                Operand::Imm8 => value.to_string(),
+                Operand::Imm16 | Operand::Imm32 => {
+                    references.extend(label_at(*value));
+                    value.to_string()
+                }
+            };
+            text.push_str(&operand);
+        }
+        if !references.is_empty() {
+            text.push_str(&format!(" ; {}", references.join(", ")));
+        }
+        return text;
+    }
+}
+
+impl fmt::Display for DecodedInsn {
+    /// Format the instruction in the syntax of the [assembler](crate::assemble).
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.format(&FormatOptions::default()));
+    }
+}
+
+/// Decode the `len` bytes of `memory` starting at `start` into a list of
+/// instructions. Decoding stops at the end of `memory`.
+pub fn disassemble(memory: &[u8], start: usize, len: usize) -> Vec<DecodedInsn> {
+    let end = start.saturating_add(len).min(memory.len());
+    let mut instructions: Vec<DecodedInsn> = Vec::new();
+    let mut address = start;
+    while address < end {
+        let instruction = decode(&memory[address..end], address);
+        address += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+    return instructions;
+}
+
+/// Format `instructions` as a listing with one line per instruction,
+/// holding its address, its raw bytes and its assembler text.
+pub fn format_listing(instructions: &[DecodedInsn]) -> String {
+    return format_listing_as(instructions, &FormatOptions::default());
+}
+
+/// Format `instructions` like [format_listing], using the labels of
+/// `symbols` for the addresses they define and in the instructions
+/// (see [DecodedInsn::format_with]).
+pub fn format_listing_with(instructions: &[DecodedInsn], symbols: &SymbolTable) -> String {
+    let options = FormatOptions {
+        symbols: Some(symbols),
+        aliases: false,
+    };
+    return format_listing_as(instructions, &options);
+}
+
+/// Format `instructions` like [format_listing], formatting every instruction
+/// as specified by `options` (see [DecodedInsn::format]).
+pub fn format_listing_as(instructions: &[DecodedInsn], options: &FormatOptions) -> String {
+    let mut listing = String::new();
+    for instruction in instructions {
+        if let Some(symbols) = options.symbols {
+            for label in symbols.labels_at(instruction.address as u32) {
+                listing.push_str(&format!("{}:\n", label));
+            }
+        }
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        listing.push_str(&format!(
+            "{:04x}  {:<18} {}\n",
+            instruction.address,
+            bytes.join(" "),
+            instruction.format(options)
+        ));
+    }
+    return listing;
+}
+
+// Decode the instruction at the beginning of `code`, which is not empty
+pub(crate) fn decode(code: &[u8], address: usize) -> DecodedInsn {
+    let data = DecodedInsn {
+        address,
+        bytes: code[..1].to_vec(),
+        info: None,
+        operands: Vec::new(),
+    };
+    let Some(info) = instruction_info(code[0]) else {
+        return data;
+    };
+    if code.len() < info.size() {
+        return data;
+    }
+    let mut operands: Vec<u32> = Vec::new();
+    let mut offset: usize = 1;
+    for kind in info.operands {
+        let mut value: u32 = 0;
+        for (i, byte) in code[offset..offset + kind.size()].iter().enumerate() {
+            value |= (*byte as u32) << (8 * i);
+        }
+        operands.push(value);
+        offset += kind.size();
+    }
+    return DecodedInsn {
+        address,
+        bytes: code[..offset].to_vec(),
+        info: Some(info),
+        operands,
+    };
+}
@@ -0,0 +1,321 @@
+use crate::asm::{parse_number, parse_register};
+use crate::machine::{Machine, MachineError};
+use std::fmt;
+
+// There are 16 general-purpose registers
+const NREGS: u8 = 16;
+
+// The binary operators, from the lowest to the highest precedence
+const PRECEDENCE: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+// The symbols of the expressions, the longest ones first
+const SYMBOLS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "[", "]",
+];
+
+/// An expression over the registers and the memory of a machine, such as
+/// `r3 == 10 && mem[0x100] != 0`, used as the condition of a breakpoint
+/// (see [add_conditional_breakpoint](Machine::add_conditional_breakpoint)).
+///
+/// Its values are 32-bit unsigned integers. It is made of:
+///   - numbers, in decimal, hexadecimal (`0x2a`) or binary (`0b101`)
+///   - registers, such as `r3` or `sp`, and `mem[addr]`, the 32-bit word at
+///     address `addr`
+///   - parentheses and the operators, from the highest to the lowest
+///     precedence: unary `!` and `-`, `*` `/` `%`, `+` `-`, `==` `!=` `<`
+///     `<=` `>` `>=`, `&&` and `||`
+///
+/// The arithmetic wraps around and the comparisons are unsigned. The
+/// comparison and logical operators give 1 for true and 0 for false, any
+/// value other than 0 being true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String, // Written back by Display
+    node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(u32),
+    Register(u8),
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Neg(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+// An error found while parsing an expression, at the index of a character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub position: usize,
+    pub kind: ExprErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprErrorKind {
+    UnexpectedEnd,             // The expression stops before it is complete
+    UnexpectedCharacter(char), // Character that can't be found there
+    InvalidNumber(String),     // Number that is no 32-bit unsigned integer
+    UnknownName(String),       // Word that is neither a register nor `mem`
+}
+
+impl fmt::Display for ExprErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprErrorKind::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ExprErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected `{}`", c),
+            ExprErrorKind::InvalidNumber(n) => write!(f, "invalid number `{}`", n),
+            ExprErrorKind::UnknownName(n) => write!(f, "unknown name `{}`", n),
+        }
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "column {}: {}", self.position + 1, self.kind);
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl Expr {
+    /// Parse an expression written in the syntax described by [Expr].
+    pub fn parse(source: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+            end: source.len(),
+        };
+        let node = parser.binary(0)?;
+        if let Some((position, token)) = parser.tokens.get(parser.next) {
+            return Err(parser.unexpected(*position, token));
+        }
+        return Ok(Expr {
+            source: source.trim().to_string(),
+            node,
+        });
+    }
+
+    /// Compute the value of the expression on `machine`. Reading outside of
+    /// the memory gives [MachineError::NonExistingAddress], and dividing by
+    /// zero [MachineError::DivisionByZero].
+    pub fn eval(&self, machine: &Machine) -> Result<u32, MachineError> {
+        return eval(&self.node, machine);
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.source);
+    }
+}
+
+fn eval(node: &Node, machine: &Machine) -> Result<u32, MachineError> {
+    let value = match node {
+        Node::Number(value) => *value,
+        Node::Register(register) => machine.regs()[*register as usize],
+        Node::Memory(address) => {
+            let address = eval(address, machine)? as usize;
+            let bytes = address
+                .checked_add(4)
+                .and_then(|end| machine.memory().get(address..end))
+                .ok_or(MachineError::address(address))?;
+            u32::from_le_bytes(bytes.try_into().unwrap())
+        }
+        Node::Not(operand) => (eval(operand, machine)? == 0) as u32,
+        Node::Neg(operand) => eval(operand, machine)?.wrapping_neg(),
+        // The logical operators don't evaluate their right operand when the
+        // left one is enough
+        Node::Binary(BinaryOp::Or, left, right) => {
+            (eval(left, machine)? != 0 || eval(right, machine)? != 0) as u32
+        }
+        Node::Binary(BinaryOp::And, left, right) => {
+            (eval(left, machine)? != 0 && eval(right, machine)? != 0) as u32
+        }
+        Node::Binary(op, left, right) => {
+            let (a, b) = (eval(left, machine)?, eval(right, machine)?);
+            match op {
+                BinaryOp::Eq => (a == b) as u32,
+                BinaryOp::Ne => (a != b) as u32,
+                BinaryOp::Lt => (a < b) as u32,
+                BinaryOp::Le => (a <= b) as u32,
+                BinaryOp::Gt => (a > b) as u32,
+                BinaryOp::Ge => (a >= b) as u32,
+                BinaryOp::Add => a.wrapping_add(b),
+                BinaryOp::Sub => a.wrapping_sub(b),
+                BinaryOp::Mul => a.wrapping_mul(b),
+                BinaryOp::Div => a
+                    .checked_div(b)
+                    .ok_or_else(MachineError::division_by_zero)?,
+                BinaryOp::Rem => a
+                    .checked_rem(b)
+                    .ok_or_else(MachineError::division_by_zero)?,
+                BinaryOp::Or | BinaryOp::And => unreachable!(),
+            }
+        }
+    };
+    return Ok(value);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String), // A number or a name
+    Symbol(&'static str),
+}
+
+// Split `source` into tokens, with the index of their first character
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens: Vec<(usize, Token)> = Vec::new();
+    let mut position = 0;
+    while let Some(c) = source[position..].chars().next() {
+        if c.is_whitespace() {
+            position += c.len_utf8();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let len = source[position..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(source.len() - position);
+            let word = source[position..position + len].to_string();
+            tokens.push((position, Token::Word(word)));
+            position += len;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| source[position..].starts_with(**s)) {
+            tokens.push((position, Token::Symbol(symbol)));
+            position += symbol.len();
+        } else {
+            return Err(ExprError {
+                position,
+                kind: ExprErrorKind::UnexpectedCharacter(c),
+            });
+        }
+    }
+    return Ok(tokens);
+}
+
+// A recursive descent parser over the tokens of an expression
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize, // The index of the next token
+    end: usize,  // The position of the end of the expression
+}
+
+impl Parser {
+    // Parse the binary operators of the given precedence level and above
+    fn binary(&mut self, level: usize) -> Result<Node, ExprError> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some((_, op)) = operators.iter().find(|(s, _)| self.peek_symbol(s)) {
+            self.next += 1;
+            let right = self.binary(level + 1)?;
+            left = Node::Binary(*op, Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        if self.eat_symbol("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat_symbol("-") {
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat_symbol("(") {
+            let node = self.binary(0)?;
+            self.expect_symbol(")")?;
+            return Ok(node);
+        }
+        let Some((position, token)) = self.tokens.get(self.next).cloned() else {
+            return Err(self.error(self.end, ExprErrorKind::UnexpectedEnd));
+        };
+        let Token::Word(word) = &token else {
+            return Err(self.unexpected(position, &token));
+        };
+        self.next += 1;
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            let value = parse_number(word).ok().and_then(|n| u32::try_from(n).ok());
+            return match value {
+                Some(value) => Ok(Node::Number(value)),
+                None => Err(self.error(position, ExprErrorKind::InvalidNumber(word.clone()))),
+            };
+        }
+        if word == "mem" {
+            self.expect_symbol("[")?;
+            let address = self.binary(0)?;
+            self.expect_symbol("]")?;
+            return Ok(Node::Memory(Box::new(address)));
+        }
+        match parse_register(word, 'r', NREGS) {
+            Ok(register) => return Ok(Node::Register(register)),
+            Err(_) => return Err(self.error(position, ExprErrorKind::UnknownName(word.clone()))),
+        }
+    }
+
+    fn peek_symbol(&self, symbol: &str) -> bool {
+        return matches!(self.tokens.get(self.next), Some((_, Token::Symbol(s))) if *s == symbol);
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if self.peek_symbol(symbol) {
+            self.next += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ExprError> {
+        if self.eat_symbol(symbol) {
+            return Ok(());
+        }
+        match self.tokens.get(self.next) {
+            Some((position, token)) => return Err(self.unexpected(*position, token)),
+            None => return Err(self.error(self.end, ExprErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    // The error of a token found where it can't be
+    fn unexpected(&self, position: usize, token: &Token) -> ExprError {
+        let c = match token {
+            Token::Word(word) => word.chars().next().unwrap(),
+            Token::Symbol(symbol) => symbol.chars().next().unwrap(),
+        };
+        return self.error(position, ExprErrorKind::UnexpectedCharacter(c));
+    }
+
+    fn error(&self, position: usize, kind: ExprErrorKind) -> ExprError {
+        return ExprError { position, kind };
+    }
+}
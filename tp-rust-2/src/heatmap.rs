@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+
+// The number of bytes of memory on every row of the image
+const IMAGE_WIDTH: usize = 64;
+
+/// The number of times every byte of the memory has been read and written
+/// by the instructions, kept by a machine once
+/// [enable_heatmap](crate::Machine::enable_heatmap) has been called.
+///
+/// It can be written as CSV, a line per byte accessed, or as a PPM image
+/// with a pixel per byte and a row per 64 bytes, where the reads are
+/// green and the writes red, brighter as the byte is accessed more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Heatmap {
+    /// The heatmap of a memory of `size` bytes which has not been accessed.
+    pub fn new(size: usize) -> Self {
+        return Heatmap {
+            reads: vec![0; size],
+            writes: vec![0; size],
+        };
+    }
+
+    /// Count a read of the `len` bytes from `address`.
+    pub fn record_read(&mut self, address: u32, len: usize) {
+        count(&mut self.reads, address, len);
+    }
+
+    /// Count a write of the `len` bytes from `address`.
+    pub fn record_write(&mut self, address: u32, len: usize) {
+        count(&mut self.writes, address, len);
+    }
+
+    /// The number of times the byte at `address` has been read.
+    pub fn reads(&self, address: u32) -> u64 {
+        return self.reads.get(address as usize).copied().unwrap_or(0);
+    }
+
+    /// The number of times the byte at `address` has been written.
+    pub fn writes(&self, address: u32) -> u64 {
+        return self.writes.get(address as usize).copied().unwrap_or(0);
+    }
+
+    /// Write a `address,reads,writes` header, then a line for every byte
+    /// read or written with its address in decimal and its counts.
+    pub fn write_csv<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "address,reads,writes")?;
+        for (address, (reads, writes)) in self.reads.iter().zip(&self.writes).enumerate() {
+            if *reads > 0 || *writes > 0 {
+                writeln!(output, "{},{},{}", address, reads, writes)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Write the heatmap as a binary PPM image. The brightness of a color
+    /// is proportional to the count, the most accessed byte being at 255.
+    pub fn write_ppm<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let height = self.reads.len().div_ceil(IMAGE_WIDTH);
+        write!(output, "P6\n{} {}\n255\n", IMAGE_WIDTH, height)?;
+        let max = self.reads.iter().chain(&self.writes).max().copied();
+        let max = max.unwrap_or(0).max(1);
+        let level = |count: u64| (count * 255 / max) as u8;
+        let mut pixels = Vec::with_capacity(IMAGE_WIDTH * height * 3);
+        for address in 0..IMAGE_WIDTH * height {
+            let address = address as u32;
+            pixels.extend_from_slice(&[level(self.writes(address)), level(self.reads(address)), 0]);
+        }
+        return output.write_all(&pixels);
+    }
+}
+
+// Add one to the counts of the `len` bytes from `address`
+fn count(counts: &mut [u64], address: u32, len: usize) {
+    let start = (address as usize).min(counts.len());
+    let end = start.saturating_add(len).min(counts.len());
+    for count in &mut counts[start..end] {
+        *count += 1;
+    }
+}
@@ -0,0 +1,172 @@
+use crate::machine::MEMORY_SIZE;
+use std::fmt;
+
+// An error found while parsing a program written in hexadecimal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexError {
+    pub line: usize,
+    pub kind: HexErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexErrorKind {
+    MissingStartCode,         // Intel HEX record not starting with `:`
+    InvalidHex(String),       // Text that is not an even number of hex digits
+    InvalidLength,            // Record length not matching its byte count
+    InvalidChecksum(u8, u8),  // The expected and the actual checksum
+    UnknownRecordType(u8),    // Record type other than 00 to 05
+    InvalidRecord(u8),        // Record of this type with a wrong data length
+    MissingEndOfFile,         // No end-of-file record
+    DataAfterEndOfFile,       // Record following the end-of-file one
+    AddressOutOfRange(usize), // Data beyond the end of the memory
+}
+
+impl fmt::Display for HexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HexErrorKind::MissingStartCode => write!(f, "record does not start with `:`"),
+            HexErrorKind::InvalidHex(s) => write!(f, "invalid hexadecimal `{}`", s),
+            HexErrorKind::InvalidLength => write!(f, "record length does not match its byte count"),
+            HexErrorKind::InvalidChecksum(e, a) => {
+                write!(f, "invalid checksum 0x{:02x}, expected 0x{:02x}", a, e)
+            }
+            HexErrorKind::UnknownRecordType(t) => write!(f, "unknown record type 0x{:02x}", t),
+            HexErrorKind::InvalidRecord(t) => {
+                write!(f, "invalid data length for record type 0x{:02x}", t)
+            }
+            HexErrorKind::MissingEndOfFile => write!(f, "missing end-of-file record"),
+            HexErrorKind::DataAfterEndOfFile => write!(f, "record after the end-of-file one"),
+            HexErrorKind::AddressOutOfRange(a) => {
+                write!(f, "address 0x{:x} is beyond the memory", a)
+            }
+        }
+    }
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.kind);
+    }
+}
+
+impl std::error::Error for HexError {}
+
+// A memory image read from an Intel HEX file
+pub(crate) struct IntelHex {
+    pub(crate) memory: Vec<u8>,
+    pub(crate) start: Option<u32>, // The start address given by a type 03 or 05 record
+}
+
+// Parse an Intel HEX file made of `:LLAAAATT<data>CC` records
+pub(crate) fn parse_ihex(text: &str) -> Result<IntelHex, HexError> {
+    let mut image = IntelHex {
+        memory: Vec::new(),
+        start: None,
+    };
+    let mut base: usize = 0; // Set by the extended address records
+    let mut end_of_file = false;
+    let mut count = 0;
+    for (index, line) in text.lines().enumerate() {
+        count = index + 1;
+        let error = |kind| HexError {
+            line: index + 1,
+            kind,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if end_of_file {
+            return Err(error(HexErrorKind::DataAfterEndOfFile));
+        }
+        let digits = line
+            .strip_prefix(':')
+            .ok_or(error(HexErrorKind::MissingStartCode))?;
+        let bytes = parse_bytes(digits).map_err(error)?;
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(error(HexErrorKind::InvalidLength));
+        }
+        let (record, checksum) = bytes.split_at(bytes.len() - 1);
+        let expected = record
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            .wrapping_neg();
+        if checksum[0] != expected {
+            return Err(error(HexErrorKind::InvalidChecksum(expected, checksum[0])));
+        }
+
+        let address = u16::from_be_bytes([record[1], record[2]]) as usize;
+        let kind = record[3];
+        let data = &record[4..];
+        let value = |size: usize| {
+            if data.len() != size {
+                return Err(error(HexErrorKind::InvalidRecord(kind)));
+            }
+            return Ok(data.iter().fold(0u32, |value, b| (value << 8) | *b as u32));
+        };
+        match kind {
+            0x00 => {
+                let start = base + address;
+                let end = start + data.len();
+                if end > MEMORY_SIZE {
+                    return Err(error(HexErrorKind::AddressOutOfRange(end - 1)));
+                }
+                if image.memory.len() < end {
+                    image.memory.resize(end, 0);
+                }
+                image.memory[start..end].copy_from_slice(data);
+            }
+            0x01 => {
+                value(0)?;
+                end_of_file = true;
+            }
+            0x02 => base = (value(2)? as usize) << 4,
+            0x03 => {
+                let segment = value(4)?;
+                image.start = Some(((segment >> 16) << 4) + (segment & 0xffff));
+            }
+            0x04 => base = (value(2)? as usize) << 16,
+            0x05 => image.start = Some(value(4)?),
+            _ => return Err(error(HexErrorKind::UnknownRecordType(kind))),
+        }
+    }
+    if !end_of_file {
+        return Err(HexError {
+            line: count.max(1),
+            kind: HexErrorKind::MissingEndOfFile,
+        });
+    }
+    return Ok(image);
+}
+
+// Parse a memory image written as hexadecimal bytes separated by
+// whitespace, `;` starting a comment
+pub(crate) fn parse_hex_text(text: &str) -> Result<Vec<u8>, HexError> {
+    let mut memory: Vec<u8> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |kind| HexError {
+            line: index + 1,
+            kind,
+        };
+        let line = line.split(';').next().unwrap_or("");
+        for word in line.split_whitespace() {
+            memory.extend(parse_bytes(word).map_err(error)?);
+            if memory.len() > MEMORY_SIZE {
+                return Err(error(HexErrorKind::AddressOutOfRange(MEMORY_SIZE)));
+            }
+        }
+    }
+    return Ok(memory);
+}
+
+// Parse pairs of hexadecimal digits
+fn parse_bytes(digits: &str) -> Result<Vec<u8>, HexErrorKind> {
+    let invalid = || HexErrorKind::InvalidHex(digits.to_string());
+    if !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    return (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid()))
+        .collect();
+}
@@ -0,0 +1,157 @@
+use crate::machine::MachineError;
+
+/// The first bytes of a program image.
+pub const IMAGE_MAGIC: &[u8; 4] = b"RVMI";
+
+/// The version of the image format described by [Image].
+pub const IMAGE_VERSION: u8 = 1;
+
+// The size of the header: magic, version, flags, segment count and entry
+const HEADER_SIZE: usize = 12;
+
+// The flag telling that the header is followed by a CRC32
+const FLAG_CRC: u8 = 1;
+
+/// A program image, to be given to [Machine::load_image](crate::Machine::load_image).
+///
+/// It is encoded as a header followed by the segments, all the numbers
+/// being little-endian:
+///   - the magic `RVMI`, the version (1 byte), flags (1 byte), the number
+///     of segments (2 bytes) and the entry point (4 bytes)
+///   - if bit 0 of the flags is set, the CRC32 (4 bytes) of the header and
+///     of the segments
+///   - for every segment, its address (4 bytes), its length (4 bytes)
+///     and its content
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    /// The address of the first instruction to execute.
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    /// Whether the encoded image holds a CRC32 of its content, checked when
+    /// it is decoded.
+    pub crc: bool,
+}
+
+/// A part of an image, loaded at a given address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// An image made of a flat program loaded and started at address 0.
+    pub fn from_program(program: &[u8]) -> Self {
+        return Image {
+            entry: 0,
+            segments: vec![Segment {
+                address: 0,
+                data: program.to_vec(),
+            }],
+            crc: false,
+        };
+    }
+
+    /// Decode an image, returning [MachineError::InvalidImage] if `bytes`
+    /// do not hold a well-formed one, and [MachineError::CorruptImage] if
+    /// its CRC32 doesn't match its content.
+    pub fn parse(bytes: &[u8]) -> Result<Self, MachineError> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != IMAGE_MAGIC {
+            return Err(MachineError::InvalidImage);
+        }
+        let header = reader.take(HEADER_SIZE - 4)?;
+        let (version, flags) = (header[0], header[1]);
+        if version != IMAGE_VERSION || flags & !FLAG_CRC != 0 {
+            return Err(MachineError::InvalidImage);
+        }
+        let crc = flags & FLAG_CRC != 0;
+        if crc {
+            let expected = reader.u32()?;
+            let content = [&bytes[..HEADER_SIZE], reader.bytes].concat();
+            if crc32(&content) != expected {
+                return Err(MachineError::CorruptImage);
+            }
+        }
+        let count = u16::from_le_bytes([header[2], header[3]]);
+        let entry = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let mut segments: Vec<Segment> = Vec::new();
+        for _ in 0..count {
+            let address = reader.u32()?;
+            let length = reader.u32()? as usize;
+            let data = reader.take(length)?.to_vec();
+            segments.push(Segment { address, data });
+        }
+        if !reader.bytes.is_empty() {
+            return Err(MachineError::InvalidImage);
+        }
+        return Ok(Image {
+            entry,
+            segments,
+            crc,
+        });
+    }
+
+    /// Encode the image.
+    ///
+    /// # Panics
+    /// This function panics when there are more than 65535 segments.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count: u16 = self.segments.len().try_into().unwrap();
+        let flags = if self.crc { FLAG_CRC } else { 0 };
+        let mut bytes: Vec<u8> = IMAGE_MAGIC.to_vec();
+        bytes.extend_from_slice(&[IMAGE_VERSION, flags]);
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(&self.entry.to_le_bytes());
+        let mut segments: Vec<u8> = Vec::new();
+        for segment in &self.segments {
+            segments.extend_from_slice(&segment.address.to_le_bytes());
+            segments.extend_from_slice(&(segment.data.len() as u32).to_le_bytes());
+            segments.extend_from_slice(&segment.data);
+        }
+        if self.crc {
+            let crc = crc32(&[&bytes[..], &segments[..]].concat());
+            bytes.extend_from_slice(&crc.to_le_bytes());
+        }
+        bytes.extend_from_slice(&segments);
+        return bytes;
+    }
+
+    /// Whether `bytes` start like an image rather than a flat program.
+    pub fn is_image(bytes: &[u8]) -> bool {
+        return bytes.starts_with(IMAGE_MAGIC);
+    }
+}
+
+// Read the encoded image from its start
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MachineError> {
+        if self.bytes.len() < len {
+            return Err(MachineError::InvalidImage);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        return Ok(taken);
+    }
+
+    fn u32(&mut self) -> Result<u32, MachineError> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+}
+
+// The CRC32 of `bytes`, as computed by zlib (reflected polynomial 0xedb88320)
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = !0;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    return !crc;
+}
@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+
+// The magic number starting an encoded log
+const MAGIC: &[u8; 4] = b"RVML";
+
+/// Bytes read or printed by a program, see [IoLog].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoEvent {
+    Input(Vec<u8>),
+    Output(Vec<u8>),
+}
+
+/// The log of what a program read and printed, in order, to run it again
+/// with the same input and check that it prints the same output.
+///
+/// It is filled by a [RecordingReader] and a [RecordingWriter] given to
+/// [step_with_io](crate::Machine::step_with_io) or
+/// [run_with_io](crate::Machine::run_with_io), and its input is fed back
+/// to the program by [replay_input](IoLog::replay_input). Its encoding is
+/// the magic `RVML` followed by the events, each of them being `I` or `O`,
+/// the number of bytes as a little-endian 32-bit value, and the bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IoLog {
+    events: Vec<IoEvent>,
+}
+
+impl IoLog {
+    pub fn new() -> Self {
+        return IoLog::default();
+    }
+
+    /// What the program read and printed, the consecutive reads and the
+    /// consecutive writes being merged.
+    pub fn events(&self) -> &[IoEvent] {
+        return &self.events;
+    }
+
+    /// All the bytes the program read.
+    pub fn input(&self) -> Vec<u8> {
+        return self
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                IoEvent::Input(bytes) => return Some(&bytes[..]),
+                IoEvent::Output(_) => return None,
+            })
+            .collect::<Vec<_>>()
+            .concat();
+    }
+
+    /// All the bytes the program printed.
+    pub fn output(&self) -> Vec<u8> {
+        return self
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                IoEvent::Input(_) => return None,
+                IoEvent::Output(bytes) => return Some(&bytes[..]),
+            })
+            .collect::<Vec<_>>()
+            .concat();
+    }
+
+    pub fn record_input(&mut self, bytes: &[u8]) {
+        if let Some(IoEvent::Input(last)) = self.events.last_mut() {
+            last.extend_from_slice(bytes);
+        } else if !bytes.is_empty() {
+            self.events.push(IoEvent::Input(bytes.to_vec()));
+        }
+    }
+
+    pub fn record_output(&mut self, bytes: &[u8]) {
+        if let Some(IoEvent::Output(last)) = self.events.last_mut() {
+            last.extend_from_slice(bytes);
+        } else if !bytes.is_empty() {
+            self.events.push(IoEvent::Output(bytes.to_vec()));
+        }
+    }
+
+    /// A reader giving back the input of the program, then the end of
+    /// input.
+    pub fn replay_input(&self) -> impl Read {
+        return io::Cursor::new(self.input());
+    }
+
+    /// Encode the log.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = MAGIC.to_vec();
+        for event in &self.events {
+            let (kind, data) = match event {
+                IoEvent::Input(data) => (b'I', data),
+                IoEvent::Output(data) => (b'O', data),
+            };
+            bytes.push(kind);
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        return bytes;
+    }
+
+    /// Decode a log encoded by [to_bytes](IoLog::to_bytes), returning an
+    /// [io::ErrorKind::InvalidData] error if `bytes` do not hold one.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid I/O log");
+        let mut rest = bytes.strip_prefix(MAGIC).ok_or_else(invalid)?;
+        let mut log = IoLog::new();
+        while let Some((&kind, after_kind)) = rest.split_first() {
+            let (length, after_length) = after_kind.split_first_chunk::<4>().ok_or_else(invalid)?;
+            let length = u32::from_le_bytes(*length) as usize;
+            if after_length.len() < length {
+                return Err(invalid());
+            }
+            let (data, after_data) = after_length.split_at(length);
+            match kind {
+                b'I' => log.events.push(IoEvent::Input(data.to_vec())),
+                b'O' => log.events.push(IoEvent::Output(data.to_vec())),
+                _ => return Err(invalid()),
+            }
+            rest = after_data;
+        }
+        return Ok(log);
+    }
+}
+
+/// A reader recording into a log the bytes read from another one.
+pub struct RecordingReader<'a, R: Read> {
+    inner: R,
+    log: &'a RefCell<IoLog>,
+}
+
+impl<'a, R: Read> RecordingReader<'a, R> {
+    pub fn new(inner: R, log: &'a RefCell<IoLog>) -> Self {
+        return RecordingReader { inner, log };
+    }
+}
+
+impl<R: Read> Read for RecordingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.log.borrow_mut().record_input(&buf[..count]);
+        return Ok(count);
+    }
+}
+
+/// A writer recording into a log the bytes written to another one.
+pub struct RecordingWriter<'a, W: Write> {
+    inner: W,
+    log: &'a RefCell<IoLog>,
+}
+
+impl<'a, W: Write> RecordingWriter<'a, W> {
+    pub fn new(inner: W, log: &'a RefCell<IoLog>) -> Self {
+        return RecordingWriter { inner, log };
+    }
+}
+
+impl<W: Write> Write for RecordingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.log.borrow_mut().record_output(&buf[..count]);
+        return Ok(count);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
@@ -0,0 +1,187 @@
+// The instruction set of the machine, shared by the tools that encode or
+// decode programs.
+
+/// The kind of an instruction operand. Operands are encoded in the order
+/// they appear in [InstructionInfo::operands], right after the opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg,    // General-purpose register number, 1 byte
+    FReg,   // Floating-point register number, 1 byte
+    Imm8,   // 8-bit immediate value, 1 byte
+    Imm16,  // 16-bit immediate value, 2 bytes (low-order byte first)
+    Imm32,  // 32-bit immediate value, 4 bytes (little-endian)
+    Addr16, // 16-bit absolute code address, 2 bytes (low-order byte first)
+    Rel16,  // 16-bit signed offset from the next instruction, 2 bytes
+}
+
+impl Operand {
+    /// The number of bytes used to encode this operand.
+    pub const fn size(self) -> usize {
+        match self {
+            Operand::Reg | Operand::FReg | Operand::Imm8 => return 1,
+            Operand::Imm16 | Operand::Addr16 | Operand::Rel16 => return 2,
+            Operand::Imm32 => return 4,
+        }
+    }
+}
+
+/// The description of an instruction: its opcode, its assembler mnemonic,
+/// and the kind of its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionInfo {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub operands: &'static [Operand],
+}
+
+impl InstructionInfo {
+    /// The number of bytes used to encode this instruction.
+    pub fn size(&self) -> usize {
+        return 1 + self.operands.iter().map(|op| op.size()).sum::<usize>();
+    }
+
+    /// Whether the execution can continue with the instruction following
+    /// this one, which is the case of every instruction but the jumps and
+    /// those ending the program or returning from a function.
+    pub fn falls_through(&self) -> bool {
+        return !matches!(self.mnemonic, "exit" | "halt" | "ret" | "jmp" | "jrel");
+    }
+}
+
+use Operand::*;
+
+const fn info(opcode: u8, mnemonic: &'static str, operands: &'static [Operand]) -> InstructionInfo {
+    return InstructionInfo {
+        opcode,
+        mnemonic,
+        operands,
+    };
+}
+
+/// Every instruction known by the machine, sorted by opcode.
+pub const INSTRUCTIONS: &[InstructionInfo] = &[
+    info(1, "move_if", &[Reg, Reg, Reg]),
+    info(2, "store", &[Reg, Reg]),
+    info(3, "load", &[Reg, Reg]),
+    info(4, "loadimm", &[Reg, Imm16]),
+    info(5, "sub", &[Reg, Reg, Reg]),
+    info(6, "out", &[Reg]),
+    info(7, "exit", &[]),
+    info(8, "out_number", &[Reg]),
+    info(9, "add", &[Reg, Reg, Reg]),
+    info(10, "mul", &[Reg, Reg, Reg]),
+    info(11, "div", &[Reg, Reg, Reg]),
+    info(12, "divu", &[Reg, Reg, Reg]),
+    info(13, "mod", &[Reg, Reg, Reg]),
+    info(14, "modu", &[Reg, Reg, Reg]),
+    info(15, "and", &[Reg, Reg, Reg]),
+    info(16, "or", &[Reg, Reg, Reg]),
+    info(17, "xor", &[Reg, Reg, Reg]),
+    info(18, "not", &[Reg, Reg]),
+    info(19, "shl", &[Reg, Reg, Reg]),
+    info(20, "shr", &[Reg, Reg, Reg]),
+    info(21, "sar", &[Reg, Reg, Reg]),
+    info(22, "rol", &[Reg, Reg, Reg]),
+    info(23, "ror", &[Reg, Reg, Reg]),
+    info(24, "jmp", &[Addr16]),
+    info(25, "jrel", &[Rel16]),
+    info(26, "beq", &[Reg, Reg, Addr16]),
+    info(27, "bne", &[Reg, Reg, Addr16]),
+    info(28, "blt", &[Reg, Reg, Addr16]),
+    info(29, "bge", &[Reg, Reg, Addr16]),
+    info(30, "call", &[Addr16]),
+    info(31, "ret", &[]),
+    info(32, "push", &[Reg]),
+    info(33, "pop", &[Reg]),
+    info(34, "in", &[Reg]),
+    info(35, "in_number", &[Reg]),
+    info(36, "loadimm32", &[Reg, Imm32]),
+    info(37, "loadb", &[Reg, Reg]),
+    info(38, "loadbs", &[Reg, Reg]),
+    info(39, "storeb", &[Reg, Reg]),
+    info(40, "loadh", &[Reg, Reg]),
+    info(41, "loadhs", &[Reg, Reg]),
+    info(42, "storeh", &[Reg, Reg]),
+    info(43, "cmp", &[Reg, Reg]),
+    info(44, "halt", &[Reg]),
+    info(45, "mov", &[Reg, Reg]),
+    info(46, "loadx", &[Reg, Reg, Imm8]),
+    info(47, "storex", &[Reg, Imm8, Reg]),
+    info(48, "syscall", &[Imm8]),
+    info(49, "brk", &[]),
+    info(50, "rand", &[Reg]),
+    info(51, "outs", &[Reg]),
+    info(52, "memcpy", &[Reg, Reg, Reg]),
+    info(53, "memset", &[Reg, Reg, Reg]),
+    info(54, "clz", &[Reg, Reg]),
+    info(55, "popcnt", &[Reg, Reg]),
+    info(56, "adc", &[Reg, Reg, Reg]),
+    info(57, "sbb", &[Reg, Reg, Reg]),
+    info(58, "fadd", &[FReg, FReg, FReg]),
+    info(59, "fsub", &[FReg, FReg, FReg]),
+    info(60, "fmul", &[FReg, FReg, FReg]),
+    info(61, "fdiv", &[FReg, FReg, FReg]),
+    info(62, "fcvt", &[FReg, Reg]),
+    info(63, "fcvti", &[Reg, FReg]),
+    info(64, "fout", &[FReg]),
+    info(65, "bswap", &[Reg]),
+    info(66, "min", &[Reg, Reg, Reg]),
+    info(67, "max", &[Reg, Reg, Reg]),
+    info(68, "abs", &[Reg, Reg]),
+    info(69, "cas", &[Reg, Reg, Reg]),
+    info(70, "rdinsn", &[Reg, Reg]),
+];
+
+/// The description of the instruction encoded by `opcode`, if any.
+pub fn instruction_info(opcode: u8) -> Option<&'static InstructionInfo> {
+    return INSTRUCTIONS.iter().find(|info| info.opcode == opcode);
+}
+
+// The number of bytes of the longest instruction
+pub(crate) const MAX_INSTRUCTION_SIZE: usize = max_instruction_size();
+
+const fn max_instruction_size() -> usize {
+    let mut max = 0;
+    let mut i = 0;
+    while i < INSTRUCTIONS.len() {
+        let operands = INSTRUCTIONS[i].operands;
+        let mut size = 1;
+        let mut j = 0;
+        while j < operands.len() {
+            size += operands[j].size();
+            j += 1;
+        }
+        if size > max {
+            max = size;
+        }
+        i += 1;
+    }
+    return max;
+}
+
+/// The description of the instruction whose mnemonic is `mnemonic`, if any.
+pub fn instruction_by_mnemonic(mnemonic: &str) -> Option<&'static InstructionInfo> {
+    return INSTRUCTIONS.iter().find(|info| info.mnemonic == mnemonic);
+}
+
+/// The conventional names of some general-purpose registers, accepted by the
+/// assembler in place of their number: the instruction pointer, the link
+/// and stack registers, and the registers holding the first four arguments
+/// of a function.
+pub const REGISTER_ALIASES: &[(&str, u8)] = &[
+    ("ip", 0),
+    ("a0", 1),
+    ("a1", 2),
+    ("a2", 3),
+    ("a3", 4),
+    ("lr", 14),
+    ("sp", 15),
+];
+
+/// The conventional name of the general-purpose register `number`, if any.
+pub fn register_alias(number: u8) -> Option<&'static str> {
+    return REGISTER_ALIASES
+        .iter()
+        .find(|(_, n)| *n == number)
+        .map(|(alias, _)| *alias);
+}
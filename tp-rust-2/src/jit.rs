@@ -0,0 +1,452 @@
+// The compilation of the hot basic blocks of the programs to native code
+// with Cranelift, used by the runs of the machines which enabled it. Only
+// the instructions working on the registers which can't fault are compiled,
+// a block ending before any other instruction, which the interpreter
+// executes, or after a jump or a branch. A block jumping back to its start
+// loops in the native code.
+use crate::disasm::{decode, DecodedInsn};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, BlockArg, InstBuilder, MemFlagsData, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+// The registers the compiled code works on, IP included
+type Registers = [u32; 16];
+
+// The native code of a block, executing it on the registers up to the given
+// number of times while it jumps back to its start, setting the IP to the
+// next instruction and returning the number of times it has been executed
+type Code = extern "C" fn(*mut u32, u64) -> u64;
+
+// The times the interpreter reaches an address before the block starting
+// there is compiled
+const HOT_THRESHOLD: u32 = 16;
+
+// The instructions of a block at most
+const MAX_BLOCK_LEN: usize = 64;
+
+// The compilations of a block whose bytes keep changing before it's left to
+// the interpreter, as the native code of the previous ones isn't freed
+const MAX_COMPILATIONS: u32 = 4;
+
+// A basic block compiled to native code
+pub(crate) struct Block {
+    code: Code,
+    bytes: Vec<u8>,               // the bytes compiled
+    memory_size: usize,           // the size of the memory the jumps were checked against
+    instructions: Vec<(u32, u8)>, // the address and the opcode of every instruction
+    compilations: u32,            // the times the block has been compiled
+}
+
+impl Block {
+    // The address and the opcode of every instruction of the block
+    pub(crate) fn instructions(&self) -> &[(u32, u8)] {
+        return &self.instructions;
+    }
+
+    // Execute the block on `regs` up to `times` times, at least once, while
+    // it jumps back to its start, setting the IP to the next instruction
+    // and returning the number of times it has been executed
+    pub(crate) fn run(&self, regs: &mut Registers, times: u64) -> u64 {
+        return (self.code)(regs.as_mut_ptr(), times);
+    }
+
+    // Whether `memory` still holds the bytes the block was compiled from
+    fn is_valid(&self, memory: &[u8], ip: usize) -> bool {
+        return memory.len() == self.memory_size
+            && memory.get(ip..ip + self.bytes.len()) == Some(&self.bytes[..]);
+    }
+}
+
+// What is known of the block starting at an address
+#[derive(Default)]
+enum Entry {
+    #[default]
+    Unknown, // It hasn't been reached yet
+    Cold(u32),            // It has been reached this many times
+    Compiled(Box<Block>), // It has been compiled
+    Interpreted(u8),      // It starts with this instruction, which can't be compiled
+    Abandoned,            // Its bytes kept changing
+}
+
+pub(crate) struct Jit {
+    module: JITModule,
+    context: FunctionBuilderContext,
+    entries: Vec<Entry>, // the blocks starting at every address
+}
+
+impl Jit {
+    // A compiler for the host, or None if Cranelift doesn't support it
+    pub(crate) fn new() -> Option<Jit> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+        let isa = cranelift_native::builder()
+            .ok()?
+            .finish(settings::Flags::new(flags))
+            .ok()?;
+        return Some(Jit {
+            module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+            context: FunctionBuilderContext::new(),
+            entries: Vec::new(),
+        });
+    }
+
+    // The number of blocks compiled
+    pub(crate) fn compiled_blocks(&self) -> usize {
+        return self
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Compiled(_)))
+            .count();
+    }
+
+    // The compiled block starting at `ip` in `memory`, once the interpreter
+    // has reached it often enough, or None if it must be interpreted.
+    // `builtin` tells whether an opcode is executed by its built-in handler.
+    pub(crate) fn block(
+        &mut self,
+        memory: &[u8],
+        ip: u32,
+        builtin: impl Fn(u8) -> bool,
+    ) -> Option<&Block> {
+        let opcode = *memory.get(ip as usize)?;
+        if self.entries.len() != memory.len() {
+            self.entries.clear();
+            self.entries.resize_with(memory.len(), Entry::default);
+        }
+        let entry = &mut self.entries[ip as usize];
+        let compilations = match entry {
+            Entry::Compiled(block) if block.is_valid(memory, ip as usize) => None,
+            Entry::Compiled(block) if block.compilations == MAX_COMPILATIONS => {
+                *entry = Entry::Abandoned;
+                return None;
+            }
+            Entry::Compiled(block) => Some(block.compilations),
+            Entry::Unknown => {
+                *entry = Entry::Cold(1);
+                return None;
+            }
+            Entry::Cold(count) if *count + 1 < HOT_THRESHOLD => {
+                *count += 1;
+                return None;
+            }
+            Entry::Cold(_) => Some(0),
+            Entry::Interpreted(first) if *first == opcode => return None,
+            Entry::Interpreted(_) => Some(0),
+            Entry::Abandoned => return None,
+        };
+        if let Some(compilations) = compilations {
+            let entry = match lower(memory, ip as usize, &builtin) {
+                Some(instructions) => match self.compile(memory, &instructions, compilations + 1) {
+                    Some(block) => Entry::Compiled(Box::new(block)),
+                    None => Entry::Abandoned,
+                },
+                None => Entry::Interpreted(opcode),
+            };
+            self.entries[ip as usize] = entry;
+        }
+        match &self.entries[ip as usize] {
+            Entry::Compiled(block) => return Some(block),
+            _ => return None,
+        }
+    }
+
+    // Compile the block made of `instructions` taken from `memory`
+    fn compile(
+        &mut self,
+        memory: &[u8],
+        instructions: &[DecodedInsn],
+        compilations: u32,
+    ) -> Option<Block> {
+        let first = instructions.first()?;
+        let last = instructions.last()?;
+        let (start, end) = (first.address, last.address + last.bytes.len());
+        let mut context = self.module.make_context();
+        let pointer = self.module.target_config().pointer_type();
+        let signature = &mut context.func.signature;
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(types::I64));
+        signature.returns.push(AbiParam::new(types::I64));
+
+        // entry(regs, times): jump body(0)
+        // body(count): execute the instructions, storing the registers
+        //   written, then jump back to body(count + 1) if the next
+        //   instruction is the first one and count + 1 < times, or to
+        //   exit(next, count + 1) otherwise
+        // exit(next, count): set the IP to next and return count
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut self.context);
+        let (entry, body, exit) = (
+            builder.create_block(),
+            builder.create_block(),
+            builder.create_block(),
+        );
+        builder.append_block_params_for_function_params(entry);
+        builder.append_block_param(body, types::I64);
+        builder.append_block_param(exit, types::I32);
+        builder.append_block_param(exit, types::I64);
+
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let (base, times) = (
+            builder.block_params(entry)[0],
+            builder.block_params(entry)[1],
+        );
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.ins().jump(body, &[BlockArg::Value(zero)]);
+
+        builder.switch_to_block(body);
+        let count = builder.block_params(body)[0];
+        let mut lowering = Lowering {
+            builder,
+            base,
+            next: start,
+            values: [None; 16],
+            written: [false; 16],
+        };
+        let mut next = None;
+        for insn in instructions {
+            next = lowering.instruction(insn);
+        }
+        let next = match next {
+            Some(next) => next,
+            None => lowering.constant(end),
+        };
+        let mut builder = lowering.store();
+        let count = builder.ins().iadd_imm_u(count, 1);
+        let exit_args = [BlockArg::Value(next), BlockArg::Value(count)];
+        if instructions.iter().any(|insn| jumps_to(insn, start)) {
+            let back = builder.ins().icmp_imm_u(IntCC::Equal, next, start as i64);
+            let more = builder.ins().icmp(IntCC::UnsignedLessThan, count, times);
+            let again = builder.ins().band(back, more);
+            builder
+                .ins()
+                .brif(again, body, &[BlockArg::Value(count)], exit, &exit_args);
+        } else {
+            builder.ins().jump(exit, &exit_args);
+        }
+        builder.seal_block(body);
+
+        builder.switch_to_block(exit);
+        builder.seal_block(exit);
+        let (next, count) = (builder.block_params(exit)[0], builder.block_params(exit)[1]);
+        builder.ins().store(MemFlagsData::trusted(), next, base, 0);
+        builder.ins().return_(&[count]);
+        builder.finalize(self.module.target_config());
+
+        let id = self
+            .module
+            .declare_anonymous_function(&context.func.signature)
+            .ok()?;
+        self.module.define_function(id, &mut context).ok()?;
+        self.module.clear_context(&mut context);
+        self.module.finalize_definitions().ok()?;
+        let address = self.module.get_finalized_function(id);
+        // The function has the signature declared above, and only accesses
+        // the 16 registers it's given
+        let code = unsafe { std::mem::transmute::<*const u8, Code>(address) };
+        return Some(Block {
+            code,
+            bytes: memory[start..end].to_vec(),
+            memory_size: memory.len(),
+            instructions: instructions
+                .iter()
+                .map(|insn| (insn.address as u32, insn.bytes[0]))
+                .collect(),
+            compilations,
+        });
+    }
+}
+
+// The instructions of the block starting at `ip` which can be compiled, or
+// None if the first one can't be
+fn lower(memory: &[u8], ip: usize, builtin: &impl Fn(u8) -> bool) -> Option<Vec<DecodedInsn>> {
+    let mut instructions = Vec::new();
+    let mut address = ip;
+    while address < memory.len() && instructions.len() < MAX_BLOCK_LEN {
+        let insn = decode(&memory[address..], address);
+        if !builtin(insn.bytes[0]) || !is_compilable(&insn, memory.len()) {
+            break;
+        }
+        address += insn.bytes.len();
+        let jump = is_jump(insn.bytes[0]);
+        instructions.push(insn);
+        if jump {
+            break;
+        }
+    }
+    if instructions.is_empty() {
+        return None;
+    }
+    return Some(instructions);
+}
+
+// Whether `insn` is a jump or a branch whose target is `address`
+fn jumps_to(insn: &DecodedInsn, address: usize) -> bool {
+    let next = insn.address + insn.bytes.len();
+    match insn.bytes[0] {
+        24 => return insn.operands[0] as usize == address,
+        25 => return next as i64 + insn.operands[0] as u16 as i16 as i64 == address as i64,
+        26..=29 => return insn.operands[2] as usize == address,
+        _ => return false,
+    }
+}
+
+// Whether `opcode` is a jump or a branch, which ends a block
+fn is_jump(opcode: u8) -> bool {
+    return matches!(opcode, 24..=29);
+}
+
+// Whether `insn` is one of the instructions compiled and can't fault in a
+// memory of `size` bytes: its registers exist, it doesn't write the IP
+// but to jump, and its jump target is in the memory
+fn is_compilable(insn: &DecodedInsn, size: usize) -> bool {
+    let Some(info) = insn.info else {
+        return false;
+    };
+    let ops = &insn.operands;
+    let registers = |count: usize| ops[..count].iter().all(|&reg| reg < 16);
+    let next = insn.address + insn.bytes.len();
+    match info.opcode {
+        1 | 5 | 9 | 10 | 15..=17 | 19..=23 | 66 | 67 => return registers(3) && ops[0] != 0,
+        18 | 45 => return registers(2) && ops[0] != 0,
+        4 | 36 => return registers(1) && ops[0] != 0,
+        24 => return (ops[0] as usize) < size,
+        25 => return (0..size as i64).contains(&(next as i64 + ops[0] as u16 as i16 as i64)),
+        26..=29 => return registers(2) && (ops[2] as usize) < size,
+        _ => return false,
+    }
+}
+
+// The state of the compilation of a block: the values of the registers
+// read or written so far
+struct Lowering<'a> {
+    builder: FunctionBuilder<'a>,
+    base: Value,                 // the address of the registers
+    next: usize,                 // the address of the instruction after the one compiled
+    values: [Option<Value>; 16], // the values of the registers once read or written
+    written: [bool; 16],         // the registers written
+}
+
+impl<'a> Lowering<'a> {
+    // The value of register `reg`, loaded the first time it's read. The IP
+    // read by an instruction is the address of the next one.
+    fn get(&mut self, reg: u32) -> Value {
+        let reg = reg as usize;
+        if reg == 0 {
+            return self.constant(self.next);
+        }
+        if let Some(value) = self.values[reg] {
+            return value;
+        }
+        let value = self.builder.ins().load(
+            types::I32,
+            MemFlagsData::trusted(),
+            self.base,
+            (reg * 4) as i32,
+        );
+        self.values[reg] = Some(value);
+        return value;
+    }
+
+    // Set register `reg` to `value`, stored once the block is executed
+    fn set(&mut self, reg: u32, value: Value) {
+        self.values[reg as usize] = Some(value);
+        self.written[reg as usize] = true;
+    }
+
+    fn constant(&mut self, value: usize) -> Value {
+        return self
+            .builder
+            .ins()
+            .iconst(types::I32, value as u32 as i32 as i64);
+    }
+
+    // Compile `insn`, returning the address of the next instruction if
+    // it's a jump or a branch
+    fn instruction(&mut self, insn: &DecodedInsn) -> Option<Value> {
+        let ops = &insn.operands;
+        let next = insn.address + insn.bytes.len();
+        let opcode = insn.bytes[0];
+        self.next = next;
+        match opcode {
+            1 => {
+                let (old, value, condition) =
+                    (self.get(ops[0]), self.get(ops[1]), self.get(ops[2]));
+                let value = self.builder.ins().select(condition, value, old);
+                self.set(ops[0], value);
+            }
+            5 | 9 | 10 | 15..=17 | 19..=23 | 66 | 67 => {
+                let (b, c) = (self.get(ops[1]), self.get(ops[2]));
+                let ins = self.builder.ins();
+                let value = match opcode {
+                    5 => ins.isub(b, c),
+                    9 => ins.iadd(b, c),
+                    10 => ins.imul(b, c),
+                    15 => ins.band(b, c),
+                    16 => ins.bor(b, c),
+                    17 => ins.bxor(b, c),
+                    19 => ins.ishl(b, c),
+                    20 => ins.ushr(b, c),
+                    21 => ins.sshr(b, c),
+                    22 => ins.rotl(b, c),
+                    23 => ins.rotr(b, c),
+                    66 => ins.smin(b, c),
+                    _ => ins.smax(b, c),
+                };
+                self.set(ops[0], value);
+            }
+            18 => {
+                let b = self.get(ops[1]);
+                let value = self.builder.ins().bnot(b);
+                self.set(ops[0], value);
+            }
+            45 => {
+                let b = self.get(ops[1]);
+                self.set(ops[0], b);
+            }
+            4 => {
+                let value = self.constant(ops[1] as u16 as i16 as i32 as u32 as usize);
+                self.set(ops[0], value);
+            }
+            36 => {
+                let value = self.constant(ops[1] as usize);
+                self.set(ops[0], value);
+            }
+            24 => return Some(self.constant(ops[0] as usize)),
+            25 => {
+                let target = next as i64 + ops[0] as u16 as i16 as i64;
+                return Some(self.constant(target as usize));
+            }
+            _ => {
+                let (a, b) = (self.get(ops[0]), self.get(ops[1]));
+                let condition = match opcode {
+                    26 => IntCC::Equal,
+                    27 => IntCC::NotEqual,
+                    28 => IntCC::SignedLessThan,
+                    _ => IntCC::SignedGreaterThanOrEqual,
+                };
+                let taken = self.builder.ins().icmp(condition, a, b);
+                let (target, next) = (self.constant(ops[2] as usize), self.constant(next));
+                return Some(self.builder.ins().select(taken, target, next));
+            }
+        }
+        return None;
+    }
+
+    // Store the registers written, giving the builder back
+    fn store(mut self) -> FunctionBuilder<'a> {
+        for reg in 0..16 {
+            if let (true, Some(value)) = (self.written[reg], self.values[reg]) {
+                self.builder.ins().store(
+                    MemFlagsData::trusted(),
+                    value,
+                    self.base,
+                    (reg * 4) as i32,
+                );
+            }
+        }
+        return self.builder;
+    }
+}
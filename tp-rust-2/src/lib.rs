@@ -1,3 +1,55 @@
+mod asm;
+#[cfg(feature = "rayon")]
+mod batch;
+mod builder;
+mod coverage;
+mod debugger;
+mod debuginfo;
+mod disasm;
+mod expr;
+mod heatmap;
+mod hex;
+mod image;
+mod iolog;
+mod isa;
+#[cfg(feature = "jit")]
+mod jit;
 mod machine;
+mod object;
+mod optimizer;
+mod profiler;
+mod program;
+mod stats;
+mod symbols;
+#[cfg(feature = "tracing")]
+mod telemetry;
+mod tracer;
+mod transpiler;
+mod tui;
+mod verifier;
 
+pub use asm::*;
+#[cfg(feature = "rayon")]
+pub use batch::*;
+pub use builder::*;
+pub use coverage::*;
+pub use debugger::*;
+pub use debuginfo::*;
+pub use disasm::*;
+pub use expr::*;
+pub use heatmap::*;
+pub use hex::*;
+pub use image::*;
+pub use iolog::*;
+pub use isa::*;
 pub use machine::*;
+pub use object::*;
+pub use optimizer::*;
+pub use profiler::*;
+pub use program::*;
+pub use stats::*;
+pub use symbols::*;
+pub use tracer::*;
+pub use transpiler::*;
+pub use tui::*;
+pub use verifier::*;
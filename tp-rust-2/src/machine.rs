@@ -1,7 +1,32 @@
-use std::io::{self, Write};
+use crate::coverage::Coverage;
+use crate::disasm::{decode, DecodedInsn};
+use crate::expr::Expr;
+use crate::heatmap::Heatmap;
+use crate::hex::{parse_hex_text, parse_ihex, HexError};
+use crate::image::Image;
+use crate::isa::{instruction_info, Operand, MAX_INSTRUCTION_SIZE};
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::profiler::Profiler;
+use crate::stats::{BlockStats, ExecStats, FusionStats};
+use crate::tracer::{TraceEntry, Tracer};
+use crate::verifier::{verify, VerifyError};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-// The memory contains 4096 bytes
-const MEMORY_SIZE: usize = 4096;
+// The memory contains 4096 bytes, unless the machine is created with another
+// size
+pub(crate) const MEMORY_SIZE: usize = 4096;
 
 // There are 16 32-bit registers
 const NREGS: usize = 16;
@@ -9,298 +34,4169 @@ const NREGS: usize = 16;
 // Register 0 is the instruction pointer (IP)
 const IP: usize = 0;
 
+// There are 8 32-bit floating-point registers
+#[cfg(feature = "fp")]
+const NFREGS: usize = 8;
+
+// The outs instruction prints at most 1024 bytes
+const MAX_STRING_LENGTH: usize = 1024;
+
+// Register 14 is the link register (LR) holding the return address of a call
+const LR: usize = 14;
+
+// Register 15 is the stack pointer (SP), the stack grows downwards from
+// the end of the memory
+const SP: usize = 15;
+
+// The number of bytes per line of a hexdump
+const HEXDUMP_LINE_SIZE: usize = 16;
+
 // The memory contains both the program and the data
 pub struct Machine {
-    memory: [u8; MEMORY_SIZE], // it's addressed from address 0 to address 4095
-    regs: [u32; NREGS],        // it's numbered from 0 to 15
+    memory: Vec<u8>, // it's addressed from address 0 to its size minus 1, 4095 by default
+    regs: [u32; NREGS], // it's numbered from 0 to 15
+    flags: Flags,    // it's updated by comparisons
+    exit_code: Option<u32>, // it's set once the program terminates
+    syscall_handler: Option<BoxedSyscallHandler>, // it serves the syscall instruction
+    hook: Option<BoxedStepHook>, // it's called around every step
+    memory_observer: Option<BoxedMemoryObserver>, // it's told about the memory accesses
+    tracer: Option<Tracer>, // it records the executed instructions
+    coverage: Option<Coverage>, // it counts the executions of every address
+    stats: ExecStats, // it counts the executions by opcode and by address
+    profiler: Option<Profiler>, // it samples the call stacks
+    heatmap: Option<Heatmap>, // it counts the accesses to every byte
+    rng_state: u64,  // it feeds the rand instruction
+    instructions_retired: u64, // it counts the successfully executed instructions
+    breakpoints: BTreeMap<u32, Option<Expr>>, // the addresses where running stops, if the condition holds
+    watchpoints: Vec<Range<u32>>,             // the memory written by an instruction stops running
+    watch_hit: Option<MemoryWrite>,           // it's set by the instruction writing a watchpoint
+    read_watchpoints: Vec<Range<u32>>,        // the memory read by an instruction stops running
+    read_hit: Option<MemoryRead>,             // it's set by the instruction reading a watchpoint
+    opcode_breakpoints: BTreeSet<u8>,         // the instructions running stops before
+    journal: VecDeque<Undo>, // the undo information of the last steps, the latest at the back
+    pending: Option<Undo>,   // the undo information of the step being executed
+    journal_capacity: usize, // the maximum number of steps in the journal
+    calls: Vec<Frame>,       // the calls not returned yet, the latest at the end
+    checkpoints: VecDeque<Snapshot>, // the states taken along the way, the latest at the back
+    checkpoint_interval: u64, // the instructions between two checkpoints
+    checkpoint_capacity: usize, // the maximum number of checkpoints
+    fuel: Option<u64>,       // the fuel left for the instructions, unlimited if None
+    fuel_costs: Vec<u64>,    // the fuel consumed by every opcode
+    initial: Option<Snapshot>, // the state once loaded, which reset goes back to
+    decoded: Vec<Option<Decoded>>, // the instructions decoded at every address, until written
+    handlers: Box<[Handler; 256]>, // the functions executing every opcode
+    replaced_opcodes: BTreeSet<u8>, // the opcodes whose built-in handler has been replaced
+    fusion: bool,            // whether the pairs of FUSED are executed by one handler
+    fused: Vec<Option<Option<usize>>>, // the pair of FUSED at every address, if any, once looked for
+    fusion_stats: FusionStats,         // the number of times every fused pair has been executed
+    block_cache: bool,                 // whether the basic blocks are executed at once
+    blocks: BTreeMap<usize, Arc<Block>>, // the basic blocks by start, until written
+    block_stats: BlockStats,           // the lookups and invalidations of the blocks
+    output_buffer: Vec<u8>,            // the bytes printed by the program, not written yet
+    output_capacity: usize,            // the size the buffer is written at, 0 if unbuffered
+    #[cfg(feature = "jit")]
+    jit: Option<Box<Jit>>, // it compiles the hot basic blocks, if enabled
+    #[cfg(feature = "fp")]
+    fregs: [f32; NFREGS], // it's numbered from 0 to 7
+}
+
+/// The whole state of a machine, taken by [snapshot](Machine::snapshot) to
+/// go back to it with [restore](Machine::restore): its memory, its
+/// registers and flags, its exit code, the state of its random number
+/// generator, the instructions retired and the calls not returned yet. The
+/// handlers, the breakpoints and the tools attached aren't part of it.
+///
+/// With the `serde` feature, snapshots can be serialized, and so can
+/// machines, as their snapshot.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    memory: Vec<u8>,
+    regs: [u32; NREGS],
+    flags: Flags,
+    exit_code: Option<u32>,
+    rng_state: u64,
+    instructions_retired: u64,
+    calls: Vec<Frame>,
+    #[cfg(feature = "fp")]
+    fregs: [f32; NFREGS],
+}
+
+// A clone is in the same state, with the same breakpoints, journal,
+// checkpoints and tools, but without the syscall handler, the step hook and
+// the memory observer, which can't be cloned, nor the compiled blocks
+impl Clone for Machine {
+    fn clone(&self) -> Self {
+        return Machine {
+            memory: self.memory.clone(),
+            regs: self.regs,
+            flags: self.flags,
+            exit_code: self.exit_code,
+            syscall_handler: None,
+            hook: None,
+            memory_observer: None,
+            tracer: self.tracer.clone(),
+            coverage: self.coverage.clone(),
+            stats: self.stats.clone(),
+            profiler: self.profiler.clone(),
+            heatmap: self.heatmap.clone(),
+            rng_state: self.rng_state,
+            instructions_retired: self.instructions_retired,
+            breakpoints: self.breakpoints.clone(),
+            watchpoints: self.watchpoints.clone(),
+            watch_hit: self.watch_hit.clone(),
+            read_watchpoints: self.read_watchpoints.clone(),
+            read_hit: self.read_hit.clone(),
+            opcode_breakpoints: self.opcode_breakpoints.clone(),
+            journal: self.journal.clone(),
+            pending: self.pending.clone(),
+            journal_capacity: self.journal_capacity,
+            calls: self.calls.clone(),
+            checkpoints: self.checkpoints.clone(),
+            checkpoint_interval: self.checkpoint_interval,
+            checkpoint_capacity: self.checkpoint_capacity,
+            fuel: self.fuel,
+            fuel_costs: self.fuel_costs.clone(),
+            initial: self.initial.clone(),
+            decoded: self.decoded.clone(),
+            handlers: self.handlers.clone(),
+            replaced_opcodes: self.replaced_opcodes.clone(),
+            fusion: self.fusion,
+            fused: self.fused.clone(),
+            fusion_stats: self.fusion_stats.clone(),
+            block_cache: self.block_cache,
+            blocks: self.blocks.clone(),
+            block_stats: self.block_stats,
+            output_buffer: self.output_buffer.clone(),
+            output_capacity: self.output_capacity,
+            #[cfg(feature = "jit")]
+            jit: None,
+            #[cfg(feature = "fp")]
+            fregs: self.fregs,
+        };
+    }
+}
+
+// The memory is summarized by its size and the number of its bytes which
+// aren't 0
+impl fmt::Debug for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let used = self.memory.iter().filter(|byte| **byte != 0).count();
+        let mut machine = f.debug_struct("Machine");
+        machine
+            .field("regs", &self.regs)
+            .field("flags", &self.flags)
+            .field("exit_code", &self.exit_code)
+            .field("instructions_retired", &self.instructions_retired)
+            .field(
+                "memory",
+                &format_args!("{} bytes, {} not zero", self.memory.len(), used),
+            );
+        #[cfg(feature = "fp")]
+        machine.field("fregs", &self.fregs);
+        return machine.finish_non_exhaustive();
+    }
+}
+
+// Two machines are equal when they are in the same state, the one of their
+// snapshot, whatever is attached to them
+impl PartialEq for Machine {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "fp")]
+        if self.fregs != other.fregs {
+            return false;
+        }
+        return self.memory == other.memory
+            && self.regs == other.regs
+            && self.flags == other.flags
+            && self.exit_code == other.exit_code
+            && self.rng_state == other.rng_state
+            && self.instructions_retired == other.instructions_retired
+            && self.calls == other.calls;
+    }
+}
+
+// A machine is serialized as its snapshot, the handlers and the tools
+// attached being left out
+#[cfg(feature = "serde")]
+impl serde::Serialize for Machine {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return self.snapshot().serialize(serializer);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Machine {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = Snapshot::deserialize(deserializer)?;
+        let mut machine = Machine::with_memory_size(snapshot.memory.len(), &[]).unwrap();
+        machine.restore(&snapshot);
+        machine.keep_initial_state();
+        return Ok(machine);
+    }
+}
+
+impl Snapshot {
+    /// The content of the memory.
+    pub fn memory(&self) -> &[u8] {
+        return &self.memory;
+    }
+
+    /// The general-purpose registers.
+    pub fn regs(&self) -> &[u32] {
+        return &self.regs;
+    }
+
+    /// The exit code, once the program has terminated.
+    pub fn exit_code(&self) -> Option<u32> {
+        return self.exit_code;
+    }
+
+    /// The number of instructions retired.
+    pub fn instructions_retired(&self) -> u64 {
+        return self.instructions_retired;
+    }
+}
+
+// The state of the machine before a step, to undo it
+#[derive(Clone)]
+struct Undo {
+    regs: [u32; NREGS],
+    flags: Flags,
+    exit_code: Option<u32>,
+    rng_state: u64,
+    instructions_retired: u64,
+    memory: Vec<(usize, Vec<u8>)>, // The previous content of the memory written by the step
+    calls: Option<Vec<Frame>>,     // The previous calls, if the step changed them
+    #[cfg(feature = "fp")]
+    fregs: [f32; NFREGS],
+}
+
+/// A service provided by the host to the programs through the `syscall`
+/// instruction. Any `FnMut(&mut Machine, u8) -> Result<(), MachineError>`
+/// closure can be used as a handler.
+pub trait SyscallHandler {
+    /// Serve the syscall `number`. The handler has full access to the machine,
+    /// whose IP already points to the instruction following the `syscall`.
+    fn syscall(&mut self, machine: &mut Machine, number: u8) -> Result<(), MachineError>;
+}
+
+impl<F: FnMut(&mut Machine, u8) -> Result<(), MachineError>> SyscallHandler for F {
+    fn syscall(&mut self, machine: &mut Machine, number: u8) -> Result<(), MachineError> {
+        return self(machine, number);
+    }
+}
+
+/// The bound of the handlers a machine holds: `Send` with the `rayon`
+/// feature, as [run_all](crate::run_all) sends the machines to other
+/// threads, and implemented by every type otherwise.
+#[cfg(feature = "rayon")]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> MaybeSend for T {}
+
+/// The bound of the handlers a machine holds: `Send` with the `rayon`
+/// feature, as `run_all` sends the machines to other threads, and
+/// implemented by every type otherwise.
+#[cfg(not(feature = "rayon"))]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "rayon"))]
+impl<T> MaybeSend for T {}
+
+// The handlers held by a machine, see MaybeSend
+#[cfg(feature = "rayon")]
+type BoxedSyscallHandler = Box<dyn SyscallHandler + Send>;
+#[cfg(feature = "rayon")]
+type BoxedStepHook = Box<dyn StepHook + Send>;
+#[cfg(feature = "rayon")]
+type BoxedMemoryObserver = Box<dyn MemoryObserver + Send>;
+#[cfg(not(feature = "rayon"))]
+type BoxedSyscallHandler = Box<dyn SyscallHandler>;
+#[cfg(not(feature = "rayon"))]
+type BoxedStepHook = Box<dyn StepHook>;
+#[cfg(not(feature = "rayon"))]
+type BoxedMemoryObserver = Box<dyn MemoryObserver>;
+
+/// The function executing an instruction, given the machine with its IP at
+/// the instruction, the program input and its output, see
+/// [set_instruction_handler](Machine::set_instruction_handler).
+pub type InstructionHandler =
+    fn(&mut Machine, &mut dyn Read, &mut dyn Write) -> Result<StepOutcome, MachineError>;
+
+/// What the execution does once a [StepHook] has been called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookAction {
+    /// Go on with the execution.
+    Continue,
+    /// Stop stepping, [StepOutcome::Paused] being returned.
+    Pause,
+    /// Stop stepping with [MachineError::Aborted].
+    Abort,
+}
+
+/// Code of the host called around every step of the machine, to trace or
+/// profile the programs or to enforce some policy. Any
+/// `FnMut(&Machine, &DecodedInsn) -> HookAction` closure can be used as a
+/// hook, called before every step.
+pub trait StepHook {
+    /// Called before executing `insn`, the instruction at IP. When the hook
+    /// pauses or aborts, the instruction isn't executed.
+    fn before_step(&mut self, machine: &Machine, insn: &DecodedInsn) -> HookAction;
+
+    /// Called once `insn` has been executed without a fault. When the hook
+    /// pauses, an instruction which would have let the execution continue
+    /// returns [StepOutcome::Paused] instead.
+    fn after_step(&mut self, _machine: &Machine, _insn: &DecodedInsn) -> HookAction {
+        return HookAction::Continue;
+    }
+}
+
+impl<F: FnMut(&Machine, &DecodedInsn) -> HookAction> StepHook for F {
+    fn before_step(&mut self, machine: &Machine, insn: &DecodedInsn) -> HookAction {
+        return self(machine, insn);
+    }
+}
+
+/// Code of the host told about every access of the instructions to the
+/// memory, to build heatmaps, taint trackers or cache simulators. The
+/// accesses of the host itself, such as [load_at](Machine::load_at), are
+/// not reported, and a faulting instruction may report only part of its
+/// accesses.
+pub trait MemoryObserver {
+    /// Called when an instruction reads `len` bytes from `address`.
+    fn on_read(&mut self, _address: u32, _len: usize) {}
+
+    /// Called when an instruction writes `bytes`, `len` bytes long, at
+    /// `address`, before they are written.
+    fn on_write(&mut self, _address: u32, _len: usize, _bytes: &[u8]) {}
+}
+
+/// A general-purpose register, to be read and written with
+/// [reg](Machine::reg) and [set](Machine::set) without the errors of
+/// the register numbers which don't exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    /// The instruction pointer, r0.
+    pub const IP: Register = Register::R0;
+    /// The link register holding the return address of a call, r14.
+    pub const LR: Register = Register::R14;
+    /// The stack pointer, r15.
+    pub const SP: Register = Register::R15;
+
+    /// The registers, from r0 to r15.
+    pub const ALL: [Register; NREGS] = [
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::R3,
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::R7,
+        Register::R8,
+        Register::R9,
+        Register::R10,
+        Register::R11,
+        Register::R12,
+        Register::R13,
+        Register::R14,
+        Register::R15,
+    ];
+
+    /// The register numbered `index`, if it exists.
+    pub fn from_index(index: usize) -> Option<Register> {
+        return Register::ALL.get(index).copied();
+    }
+
+    /// The number of the register.
+    pub fn index(self) -> usize {
+        return self as usize;
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "r{}", self.index());
+    }
+}
+
+// The condition flags describe the result of the last comparison
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Flags {
+    pub zero: bool,     // Both operands were equal
+    pub negative: bool, // The difference was negative when read as a signed value
+    pub carry: bool,    // The subtraction needed a borrow (unsigned lower than)
+    pub overflow: bool, // The signed subtraction overflowed
+}
+
+/// An error stopping the machine. The errors of an instruction tell the
+/// address `ip` of the instruction and its `opcode`, which are 0 when the
+/// error doesn't come from an instruction, such as the errors of
+/// [set_reg](Machine::set_reg) called by the host: no instruction fails
+/// with opcode 0 but [NonExistingInstruction](MachineError::NonExistingInstruction).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    /// The opcode isn't the one of an instruction.
+    NonExistingInstruction { ip: u32, opcode: u8 },
+    /// An operand is a register that doesn't exist.
+    NonExistingRegister { ip: u32, opcode: u8, register: u8 },
+    /// The instruction accesses or jumps to `address`, outside the memory.
+    /// An instruction whose operands run past the end of the memory
+    /// accesses the address following it.
+    NonExistingAddress { ip: u32, opcode: u8, address: u32 },
+    /// The input or the output of the program failed with this `kind` of
+    /// error.
+    IoError {
+        ip: u32,
+        opcode: u8,
+        kind: io::ErrorKind,
+    },
+    /// The value to print is not the one of a character.
+    InvalidCharacter { ip: u32, opcode: u8, value: u32 },
+    /// Division or modulo by zero.
+    DivisionByZero { ip: u32, opcode: u8 },
+    /// Stack overflow or underflow, `sp` being the stack pointer.
+    StackFault { ip: u32, opcode: u8, sp: u32 },
+    /// The input is not a valid decimal number.
+    InvalidNumber { ip: u32, opcode: u8 },
+    /// No handler serves the syscall `number`.
+    UnhandledSyscall { ip: u32, opcode: u8, number: u8 },
+    /// The string at `address` is longer than the allowed maximum.
+    StringTooLong { ip: u32, opcode: u8, address: u32 },
+    /// Malformed program image.
+    InvalidImage,
+    /// Program image not matching its checksum.
+    CorruptImage,
+    /// A step hook aborted the execution around the instruction.
+    Aborted { ip: u32, opcode: u8 },
+}
+
+// The errors raised by the instructions, their IP and opcode being set once
+// the step returns them (see MachineError::at)
+impl MachineError {
+    pub(crate) fn register(register: usize) -> Self {
+        let register = u8::try_from(register).unwrap_or(u8::MAX);
+        return MachineError::NonExistingRegister {
+            ip: 0,
+            opcode: 0,
+            register,
+        };
+    }
+
+    pub(crate) fn address(address: usize) -> Self {
+        let address = u32::try_from(address).unwrap_or(u32::MAX);
+        return MachineError::NonExistingAddress {
+            ip: 0,
+            opcode: 0,
+            address,
+        };
+    }
+
+    // The register is found by decoding the instruction, see Machine::locate
+    fn operand_register() -> Self {
+        return MachineError::register(u8::MAX as usize);
+    }
+
+    fn io(error: io::Error) -> Self {
+        return MachineError::IoError {
+            ip: 0,
+            opcode: 0,
+            kind: error.kind(),
+        };
+    }
+
+    fn invalid_character(value: u32) -> Self {
+        return MachineError::InvalidCharacter {
+            ip: 0,
+            opcode: 0,
+            value,
+        };
+    }
+
+    pub(crate) fn division_by_zero() -> Self {
+        return MachineError::DivisionByZero { ip: 0, opcode: 0 };
+    }
+
+    fn stack_fault(sp: usize) -> Self {
+        let sp = u32::try_from(sp).unwrap_or(u32::MAX);
+        return MachineError::StackFault {
+            ip: 0,
+            opcode: 0,
+            sp,
+        };
+    }
+
+    // Tell that the error comes from the instruction `opcode` at `ip`
+    fn at(self, ip: u32, opcode: u8) -> Self {
+        match self {
+            MachineError::NonExistingInstruction { .. } => {
+                return MachineError::NonExistingInstruction { ip, opcode }
+            }
+            MachineError::NonExistingRegister { register, .. } => {
+                return MachineError::NonExistingRegister {
+                    ip,
+                    opcode,
+                    register,
+                }
+            }
+            MachineError::NonExistingAddress { address, .. } => {
+                return MachineError::NonExistingAddress {
+                    ip,
+                    opcode,
+                    address,
+                }
+            }
+            MachineError::IoError { kind, .. } => {
+                return MachineError::IoError { ip, opcode, kind }
+            }
+            MachineError::InvalidCharacter { value, .. } => {
+                return MachineError::InvalidCharacter { ip, opcode, value }
+            }
+            MachineError::DivisionByZero { .. } => {
+                return MachineError::DivisionByZero { ip, opcode }
+            }
+            MachineError::StackFault { sp, .. } => {
+                return MachineError::StackFault { ip, opcode, sp }
+            }
+            MachineError::InvalidNumber { .. } => {
+                return MachineError::InvalidNumber { ip, opcode }
+            }
+            MachineError::UnhandledSyscall { number, .. } => {
+                return MachineError::UnhandledSyscall { ip, opcode, number }
+            }
+            MachineError::StringTooLong { address, .. } => {
+                return MachineError::StringTooLong {
+                    ip,
+                    opcode,
+                    address,
+                }
+            }
+            MachineError::InvalidImage | MachineError::CorruptImage => return self,
+            MachineError::Aborted { .. } => return MachineError::Aborted { ip, opcode },
+        }
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The instruction, opcode 0 telling that there is none
+        let at = |ip: u32, opcode: u8| match instruction_info(opcode) {
+            _ if opcode == 0 => return String::new(),
+            Some(info) => return format!(" in `{}` at 0x{:04x}", info.mnemonic, ip),
+            None => return format!(" at 0x{:04x}", ip),
+        };
+        match self {
+            MachineError::NonExistingInstruction { ip, opcode } => {
+                write!(f, "invalid opcode {} at 0x{:04x}", opcode, ip)
+            }
+            MachineError::NonExistingRegister {
+                ip,
+                opcode,
+                register,
+            } => write!(f, "non-existing register r{}{}", register, at(*ip, *opcode)),
+            MachineError::NonExistingAddress {
+                ip,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "address 0x{:04x} outside the memory{}",
+                address,
+                at(*ip, *opcode)
+            ),
+            MachineError::IoError { ip, opcode, kind } => {
+                write!(f, "input or output error: {}{}", kind, at(*ip, *opcode))
+            }
+            MachineError::InvalidCharacter { ip, opcode, value } => write!(
+                f,
+                "0x{:x} is not the code of a character{}",
+                value,
+                at(*ip, *opcode)
+            ),
+            MachineError::DivisionByZero { ip, opcode } => {
+                write!(f, "division by zero{}", at(*ip, *opcode))
+            }
+            MachineError::StackFault { ip, opcode, sp } => write!(
+                f,
+                "stack overflow or underflow with sp = 0x{:04x}{}",
+                sp,
+                at(*ip, *opcode)
+            ),
+            MachineError::InvalidNumber { ip, opcode } => {
+                write!(f, "the input is not a decimal number{}", at(*ip, *opcode))
+            }
+            MachineError::UnhandledSyscall { ip, opcode, number } => {
+                write!(f, "unhandled syscall {}{}", number, at(*ip, *opcode))
+            }
+            MachineError::StringTooLong {
+                ip,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "the string at 0x{:04x} is longer than {} bytes{}",
+                address,
+                MAX_STRING_LENGTH,
+                at(*ip, *opcode)
+            ),
+            MachineError::InvalidImage => write!(f, "malformed program image"),
+            MachineError::CorruptImage => write!(f, "program image not matching its checksum"),
+            MachineError::Aborted { ip, opcode } => {
+                write!(f, "aborted by the step hook{}", at(*ip, *opcode))
+            }
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+// An error found while loading a program from a reader
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error), // The program could not be read
+    TooLarge,      // Program larger than the memory
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "cannot read the program: {}", error),
+            LoadError::TooLarge => write!(f, "the program is larger than the memory"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(error) => return Some(error),
+            LoadError::TooLarge => return None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        return LoadError::Io(error);
+    }
+}
+
+// The state of the machine after an instruction has been executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,                   // The execution must continue
+    Exited,                     // The program is terminated
+    Breakpoint,                 // The program stopped on a breakpoint instruction
+    Watchpoint(MemoryWrite),    // The instruction wrote memory within a watchpoint
+    ReadWatchpoint(MemoryRead), // The instruction read memory within a read watchpoint
+    Paused,                     // The step hook paused the execution
+    OutOfFuel, // The fuel left doesn't cover the instruction, which is not executed
+}
+
+/// An instruction executed by the iterator of [steps_on](Machine::steps_on).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    /// The address of the instruction.
+    pub address: u32,
+    /// The opcode of the instruction.
+    pub opcode: u8,
+    /// What the step returned.
+    pub outcome: StepOutcome,
+}
+
+/// A write of an instruction into memory within a watchpoint, see
+/// [add_watchpoint](Machine::add_watchpoint).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    /// The address of the instruction.
+    pub instruction: u32,
+    /// The address of the first byte written.
+    pub address: u32,
+    /// The content of the memory before and after the write. The whole
+    /// write is reported, even the bytes outside of the watchpoint.
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// A read of an instruction from memory within a read watchpoint, see
+/// [add_read_watchpoint](Machine::add_read_watchpoint).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRead {
+    /// The address of the instruction.
+    pub instruction: u32,
+    /// The address of the first byte read.
+    pub address: u32,
+    /// The bytes read, even the ones outside of the watchpoint.
+    pub bytes: Vec<u8>,
+}
+
+/// A call not returned yet, see [backtrace](Machine::backtrace).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frame {
+    /// The address of the called function.
+    pub function: u32,
+    /// The address of the instruction following the `call`.
+    pub return_address: u32,
+}
+
+/// The reason why [run_with_io](Machine::run_with_io) returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    Exited,                     // The program is terminated
+    BreakInstruction,           // The program stopped on a breakpoint instruction
+    Breakpoint(u32), // The IP reached this breakpoint, whose instruction is not executed yet
+    Watchpoint(MemoryWrite), // An instruction wrote memory within a watchpoint
+    ReadWatchpoint(MemoryRead), // An instruction read memory within a read watchpoint
+    Paused,          // The step hook paused the execution
+    StepLimitReached, // The steps allowed by run_for_with_io have been executed
+    TimedOut,        // The deadline given to run_until_with_io has passed
+    OutOfFuel,       // The fuel left doesn't cover the next instruction
+}
+
+// The bounds of a run, which stops at the first one reached
+#[derive(Default)]
+struct RunLimit {
+    steps: Option<u64>,        // the steps executed at most
+    deadline: Option<Instant>, // the time after which no step is executed
+}
+
+// The steps executed between two readings of the clock when running with a
+// deadline
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+// The number of instructions of the longest basic block
+const MAX_BLOCK_LENGTH: usize = 64;
+
+// The instructions which end a basic block, as they may not continue with
+// the next one: the jumps, the calls and returns, and the ones stopping
+const BLOCK_ENDS: [u8; 12] = [7, 24, 25, 26, 27, 28, 29, 30, 31, 44, 48, 49];
+
+// The instructions from an address up to the first one which may not
+// continue with the next one, where a basic block ends
+struct Block {
+    instructions: Vec<(usize, u8)>, // their address and opcode
+    end: usize,                     // the address after the last one
 }
 
-#[derive(Debug)]
-pub enum MachineError {
-    NonExistingInstruction, // Non-existing instruction
-    NonExistingRegister,    // Non-existing register
-    NonExistingAddress,     // Non-existing address
-    NonExistingFormat,      // Invalid format
-}
+// The operands of an instruction in encoding order, as given to its
+// built-in handler
+type Operands = [u32; 3];
+
+// An instruction decoded once until its bytes are written: as shown to the
+// tools, and its operands as executed
+#[derive(Clone)]
+struct Decoded {
+    insn: Arc<DecodedInsn>,
+    operands: Operands,
+}
+
+// The function executing a built-in instruction, given the machine with its
+// IP at the instruction and the decoded operands
+type BuiltinHandler =
+    fn(&mut Machine, Operands, &mut dyn Read, &mut dyn Write) -> Result<StepOutcome, MachineError>;
+
+// The function executing an opcode, the extensions decoding their operands
+#[derive(Clone, Copy)]
+enum Handler {
+    Builtin(BuiltinHandler),
+    Extension(InstructionHandler),
+}
+
+impl Machine {
+    /// Create a new machine in its reset state. The `memory` parameter will
+    /// be copied at the beginning of the machine memory.
+    ///
+    /// This is only meant for programs known to fit in the memory, such as
+    /// the ones built in the tests: [try_new](Machine::try_new) should be
+    /// used for the programs coming from elsewhere.
+    ///
+    /// # Panics
+    /// This function panics when `memory` is larger than the machine memory.
+    #[deprecated(note = "use Machine::try_new")]
+    pub fn new(memory: &[u8]) -> Self {
+        return Self::try_new(memory).expect("the program is larger than the memory");
+    }
+
+    /// Create a new machine like [try_new](Machine::try_new), returning
+    /// [LoadError::TooLarge] instead of panicking if `memory` is larger than
+    /// the machine memory.
+    pub fn try_new(memory: &[u8]) -> Result<Self, LoadError> {
+        return Self::with_memory_size(MEMORY_SIZE, memory);
+    }
+
+    /// Create a new machine like [try_new](Machine::try_new), with a memory
+    /// of `size` bytes instead of 4096. The addresses past the end of the
+    /// memory are still outside it, the ones beyond 0xFFFF_FFFF being out
+    /// of reach.
+    pub fn with_memory_size(size: usize, memory: &[u8]) -> Result<Self, LoadError> {
+        if memory.len() > size {
+            return Err(LoadError::TooLarge);
+        }
+        let mut machine = Self {
+            memory: vec![0; size],
+            regs: [0; NREGS],
+            flags: Flags::default(),
+            exit_code: None,
+            syscall_handler: None,
+            hook: None,
+            memory_observer: None,
+            tracer: None,
+            coverage: None,
+            stats: ExecStats::new(size),
+            profiler: None,
+            heatmap: None,
+            rng_state: 0,
+            instructions_retired: 0,
+            breakpoints: BTreeMap::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            read_watchpoints: Vec::new(),
+            read_hit: None,
+            opcode_breakpoints: BTreeSet::new(),
+            journal: VecDeque::new(),
+            pending: None,
+            journal_capacity: 0,
+            calls: Vec::new(),
+            checkpoints: VecDeque::new(),
+            checkpoint_interval: 0,
+            checkpoint_capacity: 0,
+            fuel: None,
+            fuel_costs: vec![1; 256],
+            initial: None,
+            decoded: Vec::new(),
+            handlers: builtin_handlers(),
+            replaced_opcodes: BTreeSet::new(),
+            fusion: true,
+            fused: Vec::new(),
+            fusion_stats: FusionStats::default(),
+            block_cache: true,
+            blocks: BTreeMap::new(),
+            block_stats: BlockStats::default(),
+            output_buffer: Vec::new(),
+            output_capacity: 0,
+            #[cfg(feature = "jit")]
+            jit: None,
+            #[cfg(feature = "fp")]
+            fregs: [0.0; NFREGS],
+        };
+        machine.memory[..memory.len()].copy_from_slice(memory); // 'source slice length (840) does not match destination slice length (4096)'
+        machine.keep_initial_state();
+        return Ok(machine);
+    }
+
+    /// Create a new machine like [try_new](Machine::try_new), with the program read
+    /// from `reader` until its end directly into the memory. Instead of
+    /// panicking, [LoadError::TooLarge] is returned if the program is larger
+    /// than the memory. Interrupted reads are retried.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, LoadError> {
+        let mut machine = Self::try_new(&[]).unwrap();
+        let mut len: usize = 0;
+        let mut extra = [0u8; 1];
+        loop {
+            // Once the memory is full, the end of the program must follow
+            let buffer = if len < machine.memory.len() {
+                &mut machine.memory[len..]
+            } else {
+                &mut extra[..]
+            };
+            match reader.read(buffer) {
+                Ok(0) => {
+                    machine.keep_initial_state();
+                    return Ok(machine);
+                }
+                Ok(_) if len == machine.memory.len() => return Err(LoadError::TooLarge),
+                Ok(count) => len += count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(LoadError::Io(error)),
+            }
+        }
+    }
+
+    /// Create a new machine like [try_new](Machine::try_new), once [verify] has
+    /// checked the program held by `memory`. Otherwise, return the errors
+    /// found by the verifier.
+    ///
+    /// # Panics
+    /// This function panics when `memory` is larger than the machine memory.
+    pub fn new_verified(memory: &[u8]) -> Result<Self, Vec<VerifyError>> {
+        verify(memory)?;
+        return Ok(Self::try_new(memory).expect("the program is larger than the memory"));
+    }
+
+    /// Create a new machine in its reset state, with the segments of the
+    /// program `image` (see [Image]) placed at their address and the IP set
+    /// to its entry point. [MachineError::InvalidImage] is returned if the
+    /// image is malformed, [MachineError::CorruptImage] if it doesn't match
+    /// its CRC32, and [MachineError::NonExistingAddress] if a segment
+    /// doesn't fit in the memory.
+    pub fn load_image(image: &[u8]) -> Result<Self, MachineError> {
+        let image = Image::parse(image)?;
+        let mut machine = Self::try_new(&[]).unwrap();
+        for segment in &image.segments {
+            machine.load_at(segment.address as usize, &segment.data)?;
+        }
+        machine.regs[IP] = image.entry;
+        machine.keep_initial_state();
+        return Ok(machine);
+    }
+
+    /// Create a new machine like [try_new](Machine::try_new), with the memory content
+    /// described by `text` in the Intel HEX format. The unspecified bytes are
+    /// zero, and the IP is set to the start address record, if any.
+    pub fn from_ihex(text: &str) -> Result<Self, HexError> {
+        let image = parse_ihex(text)?;
+        let mut machine =
+            Self::try_new(&image.memory).expect("the program is larger than the memory");
+        if let Some(start) = image.start {
+            machine.regs[IP] = start;
+        }
+        machine.keep_initial_state();
+        return Ok(machine);
+    }
+
+    /// Create a new machine like [try_new](Machine::try_new), with the memory content
+    /// written in `text` as hexadecimal bytes, such as `04 01 2a 00 07`.
+    /// Bytes may also be written together, such as `04012a00`, and `;`
+    /// starts a comment.
+    pub fn from_hex_text(text: &str) -> Result<Self, HexError> {
+        return Ok(
+            Self::try_new(&parse_hex_text(text)?).expect("the program is larger than the memory")
+        );
+    }
+
+    /// Run until the program terminates, reaches a breakpoint instruction
+    /// or a breakpoint added with [add_breakpoint](Machine::add_breakpoint),
+    /// writes a watchpoint (see [add_watchpoint](Machine::add_watchpoint)),
+    /// or until an error happens. Input instructions read from `input`
+    /// and output instructions print on `output`.
+    ///
+    /// The first instruction is executed even if a breakpoint is set at
+    /// its address, so that running again resumes from the breakpoint.
+    pub fn run_with_io<R: Read, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<RunOutcome, MachineError> {
+        return self.run_limited(RunLimit::default(), input, output);
+    }
+
+    /// Run like [run_with_io](Machine::run_with_io), stopping with
+    /// [RunOutcome::StepLimitReached] once `steps` steps have been executed
+    /// if the run didn't stop before. Running again resumes the program.
+    pub fn run_for_with_io<R: Read, W: Write>(
+        &mut self,
+        steps: u64,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<RunOutcome, MachineError> {
+        let limit = RunLimit {
+            steps: Some(steps),
+            ..RunLimit::default()
+        };
+        return self.run_limited(limit, input, output);
+    }
+
+    /// Similar to [run_for_with_io](Machine::run_for_with_io).
+    /// If output instructions are run, they print on `fd`. Input
+    /// instructions always see the end of input.
+    pub fn run_for_on<T: Write>(
+        &mut self,
+        steps: u64,
+        fd: &mut T,
+    ) -> Result<RunOutcome, MachineError> {
+        return self.run_for_with_io(steps, &mut io::empty(), fd);
+    }
+
+    /// Run like [run_with_io](Machine::run_with_io), stopping with
+    /// [RunOutcome::TimedOut] once `deadline` has passed if the run didn't
+    /// stop before, so that a program looping forever doesn't block the
+    /// host. The clock is only read every 1024 steps, and before the first
+    /// one, so the run may go on for a few more instructions; the machine is
+    /// left between two instructions, and running again resumes the program.
+    pub fn run_until_with_io<R: Read, W: Write>(
+        &mut self,
+        deadline: Instant,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<RunOutcome, MachineError> {
+        let limit = RunLimit {
+            deadline: Some(deadline),
+            ..RunLimit::default()
+        };
+        return self.run_limited(limit, input, output);
+    }
+
+    /// Similar to [run_until_with_io](Machine::run_until_with_io), stopping
+    /// once `timeout` has elapsed. If output instructions are run, they
+    /// print on `fd`. Input instructions always see the end of input.
+    pub fn run_with_timeout_on<T: Write>(
+        &mut self,
+        timeout: Duration,
+        fd: &mut T,
+    ) -> Result<RunOutcome, MachineError> {
+        let deadline = Instant::now() + timeout;
+        return self.run_until_with_io(deadline, &mut io::empty(), fd);
+    }
+
+    /// Similar to [run_until_with_io](Machine::run_until_with_io), stopping
+    /// once `timeout` has elapsed. Input instructions read from standard
+    /// input and output instructions print on standard output.
+    pub fn run_with_timeout(&mut self, timeout: Duration) -> Result<RunOutcome, MachineError> {
+        let deadline = Instant::now() + timeout;
+        return self.run_until_with_io(deadline, &mut io::stdin().lock(), &mut io::stdout().lock());
+    }
+
+    /// Run like [run_with_io](Machine::run_with_io) in a future, which
+    /// yields to the executor every `interval` steps so as not to block
+    /// the thread polling it. Dropping the future cancels the run, leaving
+    /// the machine between two instructions: running again resumes the
+    /// program. The input and the output are still read and written
+    /// synchronously.
+    #[cfg(feature = "async")]
+    pub async fn run_async<R: Read, W: Write>(
+        &mut self,
+        interval: u64,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<RunOutcome, MachineError> {
+        loop {
+            match self.run_for_with_io(interval.max(1), input, output)? {
+                RunOutcome::StepLimitReached => YieldNow(false).await,
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+
+    // Run within `limit`, see run_with_io
+    fn run_limited<R: Read, W: Write>(
+        &mut self,
+        limit: RunLimit,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<RunOutcome, MachineError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("run", ip = self.regs[IP]).entered();
+        #[cfg(feature = "tracing")]
+        tracing::info!("run started");
+        let mut result = self.run_steps(limit, input, output);
+        // What the program printed is written once the run stops
+        if let (Err(error), Ok(_)) = (self.flush_buffered(output), &result) {
+            result = Err(self.output_error(error));
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(ip = self.regs[IP], ?result, "run stopped");
+        return result;
+    }
+
+    // Execute the compiled blocks from IP, if the JIT is enabled and
+    // nothing needs to see the instructions one by one, until reaching an
+    // instruction to interpret or executing about the instructions between
+    // two readings of the clock, at most `budget` ones. It returns the
+    // number of instructions executed, if any.
+    #[cfg(feature = "jit")]
+    fn run_compiled(&mut self, budget: Option<u64>) -> Option<u64> {
+        if self.observed() {
+            return None;
+        }
+        // The compiled blocks don't emit the events of the instructions
+        #[cfg(feature = "tracing")]
+        if tracing::enabled!(tracing::Level::TRACE) {
+            return None;
+        }
+        let mut jit = self.jit.take()?;
+        let budget = budget.unwrap_or(u64::MAX).min(DEADLINE_CHECK_INTERVAL);
+        let mut executed: u64 = 0;
+        while executed < budget {
+            let replaced = &self.replaced_opcodes;
+            let builtin = |opcode| return !replaced.contains(&opcode);
+            let Some(block) = jit.block(&self.memory, self.regs[IP], builtin) else {
+                break;
+            };
+            let length = block.instructions().len() as u64;
+            let times = (budget - executed) / length;
+            if times == 0 {
+                break;
+            }
+            let times = block.run(&mut self.regs, times);
+            for &(address, opcode) in block.instructions() {
+                self.stats.record_times(address, opcode, times);
+            }
+            executed += times * length;
+        }
+        self.jit = Some(jit);
+        self.instructions_retired += executed;
+        return (executed > 0).then_some(executed);
+    }
+
+    // Whether something needs to see the instructions executed one by one
+    fn observed(&self) -> bool {
+        return self.hook.is_some()
+            || self.tracer.is_some()
+            || self.coverage.is_some()
+            || self.profiler.is_some()
+            || self.journal_capacity > 0
+            || self.checkpoint_capacity > 0
+            || self.fuel.is_some()
+            || !self.breakpoints.is_empty()
+            || !self.opcode_breakpoints.is_empty();
+    }
+
+    // Execute the pair of fused instructions at IP, if the fusion is
+    // enabled, nothing needs to see the instructions one by one and
+    // `budget` allows two more. It returns the 2 instructions executed, if
+    // they were.
+    fn run_fused(&mut self, budget: Option<u64>) -> Option<u64> {
+        let ip = self.regs[IP] as usize;
+        if !self.fusion
+            || budget.is_some_and(|budget| budget < 2)
+            || ip >= self.memory.len()
+            || self.observed()
+        {
+            return None;
+        }
+        let (first, second, handler) = FUSED[self.fusion_at(ip)?];
+        let next = ip + self.decoded(ip).bytes.len();
+        let operands = (self.decoded_at(ip).operands, self.decoded_at(next).operands);
+        #[cfg(feature = "tracing")]
+        for address in [ip, next] {
+            tracing::trace!(address, instruction = %self.decoded(address));
+        }
+        // The pairs are fused only if none of their instructions can fault
+        let result = handler(self, operands.0, operands.1);
+        debug_assert!(result.is_ok());
+        self.stats.record(ip as u32, first);
+        self.stats.record(next as u32, second);
+        self.fusion_stats.record(first, second);
+        self.instructions_retired += 2;
+        return Some(2);
+    }
+
+    // Execute the instructions of the basic block at IP one after the
+    // other, fusing their pairs, if the block cache is enabled, nothing
+    // needs to see the instructions one by one and `budget` allows one more.
+    // It stops early if the IP leaves the block or its memory is written,
+    // and returns the number of instructions executed with the outcome of
+    // the last one, if there's a block at IP.
+    fn run_block<R: Read, W: Write>(
+        &mut self,
+        budget: Option<u64>,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Option<(u64, StepOutcome)>, MachineError> {
+        let ip = self.regs[IP] as usize;
+        if !self.block_cache || budget == Some(0) || ip >= self.memory.len() || self.observed() {
+            return Ok(None);
+        }
+        let Some(block) = self.block_at(ip) else {
+            return Ok(None);
+        };
+        #[cfg(feature = "tracing")]
+        let (input, output) = (
+            &mut crate::telemetry::TracedReader(input),
+            &mut crate::telemetry::TracedWriter(output),
+        );
+        let budget = budget.unwrap_or(u64::MAX);
+        let invalidations = self.block_stats.invalidations;
+        let mut executed: u64 = 0;
+        let mut index = 0;
+        while index < block.instructions.len()
+            && executed < budget
+            && self.block_stats.invalidations == invalidations
+        {
+            let (address, opcode) = block.instructions[index];
+            if self.regs[IP] as usize != address {
+                break;
+            }
+            if let Some(count) = self.run_fused(Some(budget - executed)) {
+                executed += count;
+                index += count as usize;
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(address, instruction = %self.decoded(address));
+            let result = self.execute_step(input, output);
+            let result = self.flush_stopped(result, address, opcode, output);
+            #[cfg(feature = "tracing")]
+            if let Err(error) = &result {
+                tracing::warn!(address, %error, "fault");
+            }
+            let outcome = result?;
+            self.stats.record(address as u32, opcode);
+            executed += 1;
+            index += 1;
+            if outcome != StepOutcome::Continue {
+                return Ok(Some((executed, outcome)));
+            }
+        }
+        return Ok(Some((executed, StepOutcome::Continue)));
+    }
+
+    // The basic block starting at `ip`, which must be in the memory, built
+    // once until the memory it's made of is written, or `None` if there's
+    // no instruction at `ip`
+    fn block_at(&mut self, ip: usize) -> Option<Arc<Block>> {
+        if let Some(block) = self.blocks.get(&ip) {
+            self.block_stats.hits += 1;
+            return Some(block.clone());
+        }
+        self.block_stats.misses += 1;
+        let mut instructions = Vec::new();
+        let mut address = ip;
+        while address < self.memory.len() && instructions.len() < MAX_BLOCK_LENGTH {
+            let insn = self.decoded(address).clone();
+            let Some(info) = insn.info else {
+                break;
+            };
+            instructions.push((address, info.opcode));
+            address += insn.bytes.len();
+            // As the extensions may do anything, so may their replacements
+            let writes_ip = info.operands.first() == Some(&Operand::Reg) && insn.operands[0] == 0;
+            if BLOCK_ENDS.contains(&info.opcode)
+                || writes_ip
+                || self.replaced_opcodes.contains(&info.opcode)
+            {
+                break;
+            }
+        }
+        if instructions.is_empty() {
+            return None;
+        }
+        let block = Arc::new(Block {
+            instructions,
+            end: address,
+        });
+        self.blocks.insert(ip, block.clone());
+        return Some(block);
+    }
+
+    // Step until the run stops, see run_limited
+    fn run_steps<R: Read, W: Write>(
+        &mut self,
+        limit: RunLimit,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<RunOutcome, MachineError> {
+        let mut steps: u64 = 0;
+        let mut deadline_check: u64 = 0;
+        loop {
+            if limit.steps.is_some_and(|limit| steps == limit) {
+                return Ok(RunOutcome::StepLimitReached);
+            }
+            if steps >= deadline_check {
+                if limit
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    return Ok(RunOutcome::TimedOut);
+                }
+                deadline_check = steps + DEADLINE_CHECK_INTERVAL;
+            }
+            #[cfg(feature = "jit")]
+            if let Some(count) = self.run_compiled(limit.steps.map(|limit| limit - steps)) {
+                steps += count;
+                continue;
+            }
+            let budget = limit.steps.map(|limit| limit - steps);
+            let outcome = match self.run_block(budget, input, output)? {
+                Some((count, outcome)) => {
+                    steps += count;
+                    outcome
+                }
+                None => match self.run_fused(budget) {
+                    Some(count) => {
+                        steps += count;
+                        continue;
+                    }
+                    None => {
+                        steps += 1;
+                        self.step_with_io(input, output)?
+                    }
+                },
+            };
+            match outcome {
+                StepOutcome::Continue => {}
+                StepOutcome::Exited => return Ok(RunOutcome::Exited),
+                StepOutcome::Breakpoint => return Ok(RunOutcome::BreakInstruction),
+                StepOutcome::Watchpoint(write) => return Ok(RunOutcome::Watchpoint(write)),
+                StepOutcome::ReadWatchpoint(read) => return Ok(RunOutcome::ReadWatchpoint(read)),
+                StepOutcome::Paused => return Ok(RunOutcome::Paused),
+                StepOutcome::OutOfFuel => return Ok(RunOutcome::OutOfFuel),
+            }
+            if self.at_breakpoint() {
+                return Ok(RunOutcome::Breakpoint(self.regs[IP]));
+            }
+        }
+    }
+
+    /// Similar to [run_with_io](Machine::run_with_io).
+    /// If output instructions are run, they print on `fd`. Input
+    /// instructions always see the end of input.
+    pub fn run_on<T: Write>(&mut self, fd: &mut T) -> Result<RunOutcome, MachineError> {
+        return self.run_with_io(&mut io::empty(), fd);
+    }
+
+    /// Similar to [run_on](Machine::run_on), returning what the output
+    /// instructions printed along with the outcome of the run.
+    pub fn run_capture(&mut self) -> Result<(RunOutcome, Vec<u8>), MachineError> {
+        let mut output = Vec::new();
+        let outcome = self.run_on(&mut output)?;
+        return Ok((outcome, output));
+    }
+
+    /// An iterator stepping the machine (see [step_on](Machine::step_on))
+    /// every time it is advanced, printing on `fd`. It yields the
+    /// instructions executed, and ends after the program terminates or
+    /// once a step has returned an error. The other outcomes, such as
+    /// breakpoints, don't end it, so that it can be bounded with the
+    /// iterator adapters instead.
+    pub fn steps_on<'a, T: Write>(
+        &'a mut self,
+        fd: &'a mut T,
+    ) -> impl Iterator<Item = Result<StepInfo, MachineError>> + 'a {
+        let mut ended = false;
+        return std::iter::from_fn(move || {
+            if ended {
+                return None;
+            }
+            let address = self.regs[IP];
+            let opcode = self.memory.get(address as usize).copied().unwrap_or(0);
+            let result = self.step_on(fd);
+            ended = matches!(result, Ok(StepOutcome::Exited) | Err(_));
+            return Some(result.map(|outcome| StepInfo {
+                address,
+                opcode,
+                outcome,
+            }));
+        });
+    }
+
+    /// Similar to [run_with_io](Machine::run_with_io).
+    /// Input instructions read from standard input and output
+    /// instructions print on standard output.
+    pub fn run(&mut self) -> Result<RunOutcome, MachineError> {
+        return self.run_with_io(&mut io::stdin().lock(), &mut io::stdout().lock());
+    }
+
+    /// Stop running when the IP reaches `address`, replacing the condition
+    /// of the breakpoint there if any. It returns `false` if there already
+    /// was a breakpoint there.
+    pub fn add_breakpoint(&mut self, address: u32) -> bool {
+        return self.breakpoints.insert(address, None).is_none();
+    }
+
+    /// Stop running when the IP reaches `address` and `condition` holds,
+    /// that is when its value isn't 0. It also stops if the condition can't
+    /// be evaluated, such as when it reads outside of the memory. It returns
+    /// `false` if there already was a breakpoint there, which is replaced.
+    pub fn add_conditional_breakpoint(&mut self, address: u32, condition: Expr) -> bool {
+        return self.breakpoints.insert(address, Some(condition)).is_none();
+    }
+
+    /// Remove the breakpoint at `address`. It returns `false` if there was
+    /// none.
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        return self.breakpoints.remove(&address).is_some();
+    }
+
+    /// Whether there is a breakpoint at `address`, whatever its condition.
+    pub fn has_breakpoint(&self, address: u32) -> bool {
+        return self.breakpoints.contains_key(&address);
+    }
+
+    /// The condition of the breakpoint at `address`, if it has one.
+    pub fn breakpoint_condition(&self, address: u32) -> Option<&Expr> {
+        return self.breakpoints.get(&address)?.as_ref();
+    }
+
+    /// The addresses of the breakpoints, sorted.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        return self.breakpoints.keys().copied();
+    }
+
+    /// Stop running before every instruction whose opcode is `opcode`,
+    /// wherever it is. It returns `false` if it already did.
+    pub fn add_opcode_breakpoint(&mut self, opcode: u8) -> bool {
+        return self.opcode_breakpoints.insert(opcode);
+    }
+
+    /// Remove the breakpoint on the instructions whose opcode is `opcode`.
+    /// It returns `false` if there was none.
+    pub fn remove_opcode_breakpoint(&mut self, opcode: u8) -> bool {
+        return self.opcode_breakpoints.remove(&opcode);
+    }
+
+    /// The opcodes of the instructions running stops before, in increasing
+    /// order.
+    pub fn opcode_breakpoints(&self) -> impl Iterator<Item = u8> + '_ {
+        return self.opcode_breakpoints.iter().copied();
+    }
+
+    /// Whether running stops before the instruction at IP: there is a
+    /// breakpoint at its address, whose condition holds if it has one, or
+    /// on its opcode.
+    pub fn at_breakpoint(&self) -> bool {
+        let opcode = self.memory.get(self.regs[IP] as usize);
+        if opcode.is_some_and(|opcode| self.opcode_breakpoints.contains(opcode)) {
+            return true;
+        }
+        match self.breakpoints.get(&self.regs[IP]) {
+            None => return false,
+            Some(None) => return true,
+            Some(Some(condition)) => match condition.eval(self) {
+                Ok(value) => return value != 0,
+                Err(_) => return true,
+            },
+        }
+    }
+
+    /// Record what the next steps change in a journal holding the last
+    /// `capacity` ones, so that [step_back](Machine::step_back) can undo
+    /// them. A capacity of 0, the default, stops recording and clears the
+    /// journal. A smaller capacity drops the oldest steps.
+    pub fn set_journal_capacity(&mut self, capacity: usize) {
+        self.journal_capacity = capacity;
+        while self.journal.len() > capacity {
+            self.journal.pop_front();
+        }
+    }
+
+    /// The calls executed and not returned from yet, the latest first. A
+    /// `ret` returns from the latest call whose return address it jumps to,
+    /// and from the ones made after it.
+    pub fn backtrace(&self) -> Vec<Frame> {
+        return self.calls.iter().rev().copied().collect();
+    }
+
+    /// The number of steps [step_back](Machine::step_back) can undo.
+    pub fn journal_len(&self) -> usize {
+        return self.journal.len();
+    }
+
+    /// Undo the last step recorded in the journal (see
+    /// [set_journal_capacity](Machine::set_journal_capacity)), restoring the
+    /// registers, the flags and the memory written by the instruction. What
+    /// the instruction read or printed isn't undone. The steps which faulted,
+    /// having already been undone, are not recorded. It returns `false` if
+    /// there is no step to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.journal.pop_back() else {
+            return false;
+        };
+        self.apply_undo(undo);
+        return true;
+    }
+
+    // Put the machine back in the state `undo` was taken in
+    fn apply_undo(&mut self, undo: Undo) {
+        for (address, bytes) in undo.memory.iter().rev() {
+            self.invalidate_decoded(*address..*address + bytes.len());
+            self.memory[*address..*address + bytes.len()].copy_from_slice(bytes);
+        }
+        self.regs = undo.regs;
+        self.flags = undo.flags;
+        self.exit_code = undo.exit_code;
+        self.rng_state = undo.rng_state;
+        self.instructions_retired = undo.instructions_retired;
+        if let Some(calls) = undo.calls {
+            self.calls = calls;
+        }
+        #[cfg(feature = "fp")]
+        {
+            self.fregs = undo.fregs;
+        }
+    }
+
+    /// Take a checkpoint of the whole state of the machine every `interval`
+    /// instructions retired, keeping the last `capacity` ones, so that
+    /// [rewind](Machine::rewind) can go back far without recording every
+    /// step. An interval or a capacity of 0, the default, stops taking
+    /// checkpoints and drops them.
+    pub fn set_checkpoints(&mut self, interval: u64, capacity: usize) {
+        self.checkpoint_interval = interval;
+        self.checkpoint_capacity = if interval == 0 { 0 } else { capacity };
+        while self.checkpoints.len() > self.checkpoint_capacity {
+            self.checkpoints.pop_front();
+        }
+    }
+
+    /// Go back to the state of the machine `instructions` instructions
+    /// retired ago, by restoring the latest checkpoint before it (see
+    /// [set_checkpoints](Machine::set_checkpoints)) and replaying the
+    /// instructions from there. The replayed instructions read the end of
+    /// input and their output is dropped. The journal is cleared, then
+    /// records the replayed steps. The hook, the memory observer, the
+    /// tracer, the statistics, the coverage, the profiler and the heatmap,
+    /// which saw them the first time, leave them out.
+    ///
+    /// It returns `false`, leaving the machine untouched, if there is no
+    /// checkpoint that old, and also when the replay did not reach the same
+    /// state, leaving the machine where it stopped: the program read some
+    /// input or a syscall handler behaved differently.
+    pub fn rewind(&mut self, instructions: u64) -> bool {
+        let Some(target) = self.instructions_retired.checked_sub(instructions) else {
+            return false;
+        };
+        // The checkpoints are sorted by the instructions retired
+        let after = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.instructions_retired <= target);
+        if after == 0 {
+            return false;
+        }
+        self.checkpoints.truncate(after);
+        let checkpoint = self.checkpoints.pop_back().unwrap();
+        self.load_snapshot(&checkpoint);
+        self.checkpoints.push_back(checkpoint);
+
+        // The replay is not shown to what saw the steps the first time
+        let heatmap = self.heatmap.take();
+        let hook = self.hook.take();
+        let observer = self.memory_observer.take();
+        let mut replayed = true;
+        while self.instructions_retired < target {
+            let outcome = self.execute_step(&mut io::empty(), &mut io::sink());
+            let stopped = outcome.is_err() || self.exit_code.is_some();
+            if stopped && self.instructions_retired < target {
+                replayed = false;
+                break;
+            }
+        }
+        self.heatmap = heatmap;
+        self.hook = hook;
+        self.memory_observer = observer;
+        return replayed;
+    }
+
+    /// The number of checkpoints [rewind](Machine::rewind) can go back to.
+    pub fn checkpoints_len(&self) -> usize {
+        return self.checkpoints.len();
+    }
+
+    // Take a checkpoint if one is due before the next step
+    fn take_checkpoint(&mut self) {
+        let due = self.checkpoint_capacity > 0
+            && self
+                .instructions_retired
+                .is_multiple_of(self.checkpoint_interval)
+            && self.checkpoints.back().is_none_or(|checkpoint| {
+                return checkpoint.instructions_retired < self.instructions_retired;
+            });
+        if !due {
+            return;
+        }
+        if self.checkpoints.len() == self.checkpoint_capacity {
+            self.checkpoints.pop_front();
+        }
+        let checkpoint = self.snapshot();
+        self.checkpoints.push_back(checkpoint);
+    }
+
+    /// Take a snapshot of the whole state of the machine.
+    pub fn snapshot(&self) -> Snapshot {
+        return Snapshot {
+            memory: self.memory.clone(),
+            regs: self.regs,
+            flags: self.flags,
+            exit_code: self.exit_code,
+            rng_state: self.rng_state,
+            instructions_retired: self.instructions_retired,
+            calls: self.calls.clone(),
+            #[cfg(feature = "fp")]
+            fregs: self.fregs,
+        };
+    }
+
+    /// Put the machine back in the state of `snapshot`, which may have been
+    /// taken from another machine. The journal and the checkpoints, which
+    /// may not lead to this state, are dropped.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.load_snapshot(snapshot);
+        self.checkpoints.clear();
+    }
+
+    /// Put the machine back in the state it was once loaded, as after
+    /// [restore](Machine::restore): the memory holds the program image
+    /// again, the registers have their initial value, 0 but for the entry
+    /// point of the images and the values given to [MachineBuilder], and so
+    /// for the flags, the exit code, the random number generator and the
+    /// instructions retired. The handlers, the breakpoints, the fuel and the
+    /// tools attached are kept, so that the same program can be run again.
+    ///
+    /// [MachineBuilder]: crate::MachineBuilder
+    pub fn reset(&mut self) {
+        if let Some(initial) = self.initial.take() {
+            self.restore(&initial);
+            self.initial = Some(initial);
+        }
+    }
+
+    // Remember the current state as the one reset goes back to
+    pub(crate) fn keep_initial_state(&mut self) {
+        self.initial = Some(self.snapshot());
+    }
+
+    // Put the machine in the state of `snapshot`, clearing the journal
+    fn load_snapshot(&mut self, snapshot: &Snapshot) {
+        self.memory.clone_from(&snapshot.memory);
+        self.decoded.clear();
+        self.fused.clear();
+        self.blocks.clear();
+        self.regs = snapshot.regs;
+        self.flags = snapshot.flags;
+        self.exit_code = snapshot.exit_code;
+        self.rng_state = snapshot.rng_state;
+        self.instructions_retired = snapshot.instructions_retired;
+        self.calls.clone_from(&snapshot.calls);
+        #[cfg(feature = "fp")]
+        {
+            self.fregs = snapshot.fregs;
+        }
+        self.journal.clear();
+        self.watch_hit = None;
+        self.read_hit = None;
+    }
+
+    /// Watch the memory at the addresses of `range`: once an instruction
+    /// writes any of them, stepping returns [StepOutcome::Watchpoint] and
+    /// running stops with [RunOutcome::Watchpoint], telling the content of
+    /// the memory before and after the write. The write happens anyway.
+    pub fn add_watchpoint(&mut self, range: Range<u32>) {
+        self.watchpoints.push(range);
+    }
+
+    /// Remove the watchpoint of `range`. It returns `false` if there was
+    /// none.
+    pub fn remove_watchpoint(&mut self, range: Range<u32>) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| *watchpoint != range);
+        return self.watchpoints.len() != count;
+    }
+
+    /// The address ranges of the watchpoints, in the order they were added.
+    pub fn watchpoints(&self) -> impl Iterator<Item = Range<u32>> + '_ {
+        return self.watchpoints.iter().cloned();
+    }
+
+    /// Watch the reads of the memory at the addresses of `range`: once an
+    /// instruction reads any of them, stepping returns
+    /// [StepOutcome::ReadWatchpoint] and running stops with
+    /// [RunOutcome::ReadWatchpoint], telling the bytes read. An instruction
+    /// hitting both a watchpoint and a read watchpoint reports the write.
+    pub fn add_read_watchpoint(&mut self, range: Range<u32>) {
+        self.read_watchpoints.push(range);
+    }
+
+    /// Remove the read watchpoint of `range`. It returns `false` if there
+    /// was none.
+    pub fn remove_read_watchpoint(&mut self, range: Range<u32>) -> bool {
+        let count = self.read_watchpoints.len();
+        self.read_watchpoints
+            .retain(|watchpoint| *watchpoint != range);
+        return self.read_watchpoints.len() != count;
+    }
+
+    /// The address ranges of the read watchpoints, in the order they were
+    /// added.
+    pub fn read_watchpoints(&self) -> impl Iterator<Item = Range<u32>> + '_ {
+        return self.read_watchpoints.iter().cloned();
+    }
+
+    /// Execute the next instruction by doing the following steps:
+    ///   - decode the instruction located at IP (register 0)
+    ///   - increment the IP by the size of the instruction
+    ///   - execute the decoded instruction
+    ///
+    /// Input instructions read from `input` and output instructions
+    /// print on `output`. If an error happens at either of those steps,
+    /// an error is returned and the instruction is undone: the registers,
+    /// the flags and the memory are left as they were before it, only what
+    /// it read or printed being lost.
+    ///
+    /// In case of success, [StepOutcome::Exited] is returned if the program
+    /// is terminated (upon encountering an exit instruction),
+    /// [StepOutcome::Breakpoint] if a breakpoint instruction has been
+    /// reached, or [StepOutcome::Continue] if the execution must continue.
+    /// The hook set with [set_hook](Machine::set_hook) is called around the
+    /// step, and the instruction is recorded by the attached tracer if any
+    /// and counted in the statistics, in the coverage if it is enabled and
+    /// in the samples of the attached profiler if any.
+    pub fn step_with_io<R: Read, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        let ip = self.regs[IP] as usize;
+        let opcode = self.memory.get(ip).copied();
+        let cost = opcode.map_or(0, |opcode| self.fuel_costs[opcode as usize]);
+        if self.fuel.is_some_and(|fuel| fuel < cost) {
+            return Ok(StepOutcome::OutOfFuel);
+        }
+        let traced = match &self.tracer {
+            Some(_) if ip < self.memory.len() => Some((self.decoded(ip).clone(), self.regs)),
+            _ => None,
+        };
+        let retired = self.instructions_retired;
+        #[cfg(feature = "tracing")]
+        if ip < self.memory.len() {
+            tracing::trace!(address = ip, instruction = %self.decoded(ip));
+        }
+        #[cfg(feature = "tracing")]
+        let (input, output) = (
+            &mut crate::telemetry::TracedReader(input),
+            &mut crate::telemetry::TracedWriter(output),
+        );
+        let calls = match &self.profiler {
+            Some(profiler) if profiler.is_due() => Some(self.calls.clone()),
+            _ => None,
+        };
+
+        let result = match self.hook.take() {
+            Some(mut hook) => {
+                let result = self.hooked_step(&mut *hook, input, output);
+                self.hook = Some(hook);
+                result
+            }
+            None => self.execute_step(input, output),
+        };
+
+        let result = self.flush_stopped(result, ip, opcode.unwrap_or(0), output);
+
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            tracing::warn!(address = ip, %error, "fault");
+        }
+
+        // The instructions paused before being executed are not recorded
+        if let (Some(opcode), true) = (opcode, self.instructions_retired != retired) {
+            if let Some(fuel) = &mut self.fuel {
+                *fuel = fuel.saturating_sub(cost);
+            }
+            self.stats.record(ip as u32, opcode);
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(ip as u32);
+            }
+            if let Some(profiler) = &mut self.profiler {
+                let mut calls = calls.unwrap_or_default();
+                calls.reverse();
+                profiler.record(&calls, ip as u32);
+            }
+        }
+        if let (Some(tracer), Some((insn, regs))) = (&mut self.tracer, traced) {
+            if self.instructions_retired != retired {
+                let registers = (1..NREGS)
+                    .filter(|&reg| regs[reg] != self.regs[reg])
+                    .map(|reg| (reg as u8, self.regs[reg]))
+                    .collect();
+                tracer.record(TraceEntry {
+                    address: ip as u32,
+                    opcode: insn.bytes[0],
+                    operands: insn.operands.clone(),
+                    registers,
+                });
+            }
+        }
+        return result;
+    }
+
+    // Write the output buffer on `output` if the step of the instruction
+    // `opcode` at `ip` doesn't continue, so that what the program printed
+    // is seen once it has exited or paused. Failing to write it is a fault
+    // of the instruction.
+    fn flush_stopped<W: Write + ?Sized>(
+        &mut self,
+        result: Result<StepOutcome, MachineError>,
+        ip: usize,
+        opcode: u8,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        match result {
+            Ok(outcome) if outcome != StepOutcome::Continue => match self.flush_buffered(output) {
+                Ok(()) => return Ok(outcome),
+                Err(error) => return Err(MachineError::io(error).at(ip as u32, opcode)),
+            },
+            result => return result,
+        }
+    }
+
+    // Step, calling `hook` around the instruction if there is one at IP
+    fn hooked_step<R: Read, W: Write>(
+        &mut self,
+        hook: &mut dyn StepHook,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        let ip = self.regs[IP] as usize;
+        if ip >= self.memory.len() {
+            return self.execute_step(input, output);
+        }
+        let insn = self.decoded(ip).clone();
+        let (ip, opcode) = (ip as u32, insn.bytes[0]);
+        match hook.before_step(self, &insn) {
+            HookAction::Continue => {}
+            HookAction::Pause => return Ok(StepOutcome::Paused),
+            HookAction::Abort => return Err(MachineError::Aborted { ip, opcode }),
+        }
+        let outcome = self.execute_step(input, output)?;
+        match hook.after_step(self, &insn) {
+            HookAction::Continue => return Ok(outcome),
+            HookAction::Pause if outcome == StepOutcome::Continue => {
+                return Ok(StepOutcome::Paused)
+            }
+            HookAction::Pause => return Ok(outcome),
+            HookAction::Abort => return Err(MachineError::Aborted { ip, opcode }),
+        }
+    }
+
+    // Tell that `error` comes from the instruction `opcode` at `ip`, finding
+    // the register which doesn't exist among its operands
+    fn locate(&self, error: MachineError, ip: usize, opcode: u8) -> MachineError {
+        let error = error.at(ip as u32, opcode);
+        let MachineError::NonExistingRegister { register, .. } = error else {
+            return error;
+        };
+        let operands = instruction_info(opcode).map_or(&[][..], |info| info.operands);
+        let values = decode(&self.memory[ip..], ip).operands;
+        let invalid = operands
+            .iter()
+            .zip(values)
+            .find(|(operand, value)| match operand {
+                Operand::Reg => return *value as usize >= NREGS,
+                #[cfg(feature = "fp")]
+                Operand::FReg => return *value as usize >= NFREGS,
+                _ => return false,
+            });
+        let register = invalid.map_or(register, |(_, value)| value as u8);
+        return MachineError::NonExistingRegister {
+            ip: ip as u32,
+            opcode,
+            register,
+        };
+    }
+
+    // Execute the instruction at IP, see step_with_io. A faulting
+    // instruction is undone, so that the machine is left as it was before.
+    fn execute_step<R: Read, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        self.take_checkpoint();
+        self.pending = Some(Undo {
+            regs: self.regs,
+            flags: self.flags,
+            exit_code: self.exit_code,
+            rng_state: self.rng_state,
+            instructions_retired: self.instructions_retired,
+            memory: Vec::new(),
+            calls: None,
+            #[cfg(feature = "fp")]
+            fregs: self.fregs,
+        });
+        let result = self.execute_instruction(input, output);
+        let undo = self.pending.take().unwrap();
+        if result.is_err() {
+            self.apply_undo(undo);
+        } else if self.journal_capacity > 0 {
+            if self.journal.len() == self.journal_capacity {
+                self.journal.pop_front();
+            }
+            self.journal.push_back(undo);
+        }
+        return result;
+    }
+
+    // Execute the instruction at IP, see execute_step
+    fn execute_instruction<R: Read, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        // It contains the address of the next instruction to be executed
+        let ip_aux: usize = self.regs[IP].try_into().unwrap();
+
+        if ip_aux < self.memory.len() {
+            let instruction: u8 = self.memory[ip_aux];
+
+            // The operands are decoded with the instruction: a known
+            // instruction which can't be decoded runs past its end
+            let decoded = self.decoded_at(ip_aux);
+            if decoded.insn.info.is_none() && instruction_info(instruction).is_some() {
+                let error = MachineError::address(self.memory.len());
+                return Err(error.at(ip_aux as u32, instruction));
+            }
+            let operands = decoded.operands;
+
+            let result = match self.handlers[instruction as usize] {
+                Handler::Builtin(handler) => handler(self, operands, input, output),
+                Handler::Extension(handler) => handler(self, input, output),
+            };
+            let result = result.map_err(|error| self.locate(error, ip_aux, instruction));
+            if result.is_ok() {
+                self.instructions_retired += 1;
+            }
+            let read = self.read_hit.take();
+            if let Some(mut write) = self.watch_hit.take() {
+                if matches!(result, Ok(StepOutcome::Continue)) {
+                    write.instruction = ip_aux as u32;
+                    return Ok(StepOutcome::Watchpoint(write));
+                }
+            }
+            if let Some(mut read) = read {
+                if matches!(result, Ok(StepOutcome::Continue)) {
+                    read.instruction = ip_aux as u32;
+                    return Ok(StepOutcome::ReadWatchpoint(read));
+                }
+            }
+            return result;
+        }
+        return Err(MachineError::address(ip_aux).at(ip_aux as u32, 0));
+    }
+
+    /// Similar to [step_with_io](Machine::step_with_io).
+    /// If output instructions are run, they print on `fd`. Input
+    /// instructions always see the end of input.
+    pub fn step_on<T: Write>(&mut self, fd: &mut T) -> Result<StepOutcome, MachineError> {
+        return self.step_with_io(&mut io::empty(), fd);
+    }
+
+    /// Similar to [step_with_io](Machine::step_with_io).
+    /// Input instructions read from standard input and output
+    /// instructions print on standard output.
+    pub fn step(&mut self) -> Result<StepOutcome, MachineError> {
+        return self.step_with_io(&mut io::stdin().lock(), &mut io::stdout().lock());
+    }
+
+    /// Reference onto the machine current set of regs.
+    pub fn regs(&self) -> &[u32] {
+        return &self.regs;
+    }
+
+    /// The value of `register`.
+    pub fn reg(&self, register: Register) -> u32 {
+        return self.regs[register.index()];
+    }
+
+    /// Set `register` to `value`.
+    pub fn set(&mut self, register: Register, value: u32) {
+        self.regs[register.index()] = value;
+    }
+
+    /// Sets a register to the given value.
+    pub fn set_reg(&mut self, reg: usize, value: u32) -> Result<(), MachineError> {
+        if reg < NREGS {
+            self.regs[reg] = value;
+            return Ok(());
+        }
+        return Err(MachineError::register(reg));
+    }
+
+    /// Reference onto the machine current set of floating-point regs.
+    #[cfg(feature = "fp")]
+    pub fn fregs(&self) -> &[f32] {
+        return &self.fregs;
+    }
+
+    /// Sets a floating-point register to the given value.
+    #[cfg(feature = "fp")]
+    pub fn set_freg(&mut self, reg: usize, value: f32) -> Result<(), MachineError> {
+        if reg < NFREGS {
+            self.fregs[reg] = value;
+            return Ok(());
+        }
+        return Err(MachineError::register(reg));
+    }
+
+    /// The number of instructions successfully executed since the machine
+    /// has been created.
+    pub fn instructions_retired(&self) -> u64 {
+        return self.instructions_retired;
+    }
+
+    /// The exit code of the program, or `None` if it has not terminated yet.
+    pub fn exit_code(&self) -> Option<u32> {
+        return self.exit_code;
+    }
+
+    /// The machine current condition flags.
+    pub fn flags(&self) -> Flags {
+        return self.flags;
+    }
+
+    /// Register the handler serving the `syscall` instruction, replacing the
+    /// previous one if any.
+    pub fn set_syscall_handler<H: SyscallHandler + MaybeSend + 'static>(&mut self, handler: H) {
+        self.syscall_handler = Some(Box::new(handler));
+    }
+
+    /// Execute the instruction `opcode` with `handler`, replacing the
+    /// built-in instruction if any, so as to extend the instruction set.
+    /// The handler must advance the IP past the instruction, such as with
+    /// [ip_inc](Machine::ip_inc). As the instruction set doesn't describe
+    /// the extensions, it must also check that their operands are in the
+    /// memory, and the disassembler, the verifier and the tools decoding
+    /// instructions see them as data. The errors it returns are located at
+    /// the instruction, like the ones of the built-in instructions.
+    pub fn set_instruction_handler(&mut self, opcode: u8, handler: InstructionHandler) {
+        self.handlers[opcode as usize] = Handler::Extension(handler);
+        self.replaced_opcodes.insert(opcode);
+        self.fused.clear();
+        self.blocks.clear();
+    }
+
+    /// Register the hook called around every step, replacing the previous
+    /// one if any.
+    pub fn set_hook<H: StepHook + MaybeSend + 'static>(&mut self, hook: H) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Remove the hook called around every step, if any.
+    pub fn remove_hook(&mut self) {
+        self.hook = None;
+    }
+
+    /// Register the observer of the memory accesses, replacing the previous
+    /// one if any.
+    pub fn set_memory_observer<O: MemoryObserver + MaybeSend + 'static>(&mut self, observer: O) {
+        self.memory_observer = Some(Box::new(observer));
+    }
+
+    /// Remove the observer of the memory accesses, if any.
+    pub fn remove_memory_observer(&mut self) {
+        self.memory_observer = None;
+    }
+
+    /// Record the instructions executed from now on into `tracer`,
+    /// replacing the tracer attached before if any.
+    pub fn attach_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// The tracer recording the instructions, if any.
+    pub fn tracer(&self) -> Option<&Tracer> {
+        return self.tracer.as_ref();
+    }
+
+    /// Stop recording the instructions, giving back the tracer.
+    pub fn detach_tracer(&mut self) -> Option<Tracer> {
+        return self.tracer.take();
+    }
+
+    /// Sample the call stacks from now on into `profiler`, replacing the
+    /// profiler attached before if any.
+    pub fn attach_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// The profiler sampling the call stacks, if any.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        return self.profiler.as_ref();
+    }
+
+    /// Stop sampling the call stacks, giving back the profiler.
+    pub fn detach_profiler(&mut self) -> Option<Profiler> {
+        return self.profiler.take();
+    }
+
+    /// Count from now on the executions of the instruction at every
+    /// address, keeping the counts if they were already kept.
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Coverage::new(self.memory.len()));
+        }
+    }
+
+    /// The executions counted since
+    /// [enable_coverage](Machine::enable_coverage) was called, if it was.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        return self.coverage.as_ref();
+    }
+
+    /// Stop counting the executions, giving back the counts.
+    pub fn disable_coverage(&mut self) -> Option<Coverage> {
+        return self.coverage.take();
+    }
+
+    /// Count from now on the reads and the writes of every byte of the
+    /// memory by the instructions, keeping the counts if they were
+    /// already kept.
+    pub fn enable_heatmap(&mut self) {
+        if self.heatmap.is_none() {
+            self.heatmap = Some(Heatmap::new(self.memory.len()));
+        }
+    }
+
+    /// The accesses counted since [enable_heatmap](Machine::enable_heatmap)
+    /// was called, if it was.
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        return self.heatmap.as_ref();
+    }
+
+    /// Stop counting the accesses, giving back the counts.
+    pub fn disable_heatmap(&mut self) -> Option<Heatmap> {
+        return self.heatmap.take();
+    }
+
+    /// Compile the basic blocks the runs go through often to native code,
+    /// so that the long-running programs run faster. It returns `false` if
+    /// the host isn't supported by the compiler.
+    ///
+    /// Only the sequences of instructions working on the registers which
+    /// can't fault are compiled, up to a jump or a branch: the other
+    /// instructions are still interpreted. The compiled blocks are checked
+    /// against the memory before being executed, and compiled again if
+    /// they have been modified. They're only used by the runs, such as
+    /// [run_with_io](Machine::run_with_io), while nothing needs to see the
+    /// instructions one by one: no step hook, tracer, coverage or
+    /// profiler, no journal, checkpoints or fuel limit, and no breakpoints.
+    /// The instructions executed are still counted in the statistics, and
+    /// the blocks are interpreted while a subscriber of the `tracing` feature
+    /// wants the events of the instructions.
+    #[cfg(feature = "jit")]
+    pub fn enable_jit(&mut self) -> bool {
+        if self.jit.is_none() {
+            self.jit = Jit::new().map(Box::new);
+        }
+        return self.jit.is_some();
+    }
+
+    /// Stop compiling the basic blocks, freeing the ones compiled.
+    #[cfg(feature = "jit")]
+    pub fn disable_jit(&mut self) {
+        self.jit = None;
+    }
+
+    /// The number of basic blocks compiled since
+    /// [enable_jit](Machine::enable_jit) has been called.
+    #[cfg(feature = "jit")]
+    pub fn compiled_blocks(&self) -> usize {
+        return self.jit.as_ref().map_or(0, |jit| jit.compiled_blocks());
+    }
+
+    /// The number of instructions executed by opcode and by address, since
+    /// the machine was created or [reset_stats](Machine::reset_stats) was
+    /// called.
+    pub fn stats(&self) -> &ExecStats {
+        return &self.stats;
+    }
+
+    /// Start counting the executions from zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = ExecStats::new(self.memory.len());
+        self.fusion_stats = FusionStats::default();
+        self.block_stats = BlockStats::default();
+    }
+
+    /// Execute the frequent pairs of instructions, such as a `loadimm` or a
+    /// `sub` followed by a `sub` or a `move_if`, with one handler, in the
+    /// runs which nothing observes. It's enabled when the machine is
+    /// created, and doesn't change what the program does.
+    pub fn enable_fusion(&mut self) {
+        self.fusion = true;
+    }
+
+    /// Execute every instruction with its own handler.
+    pub fn disable_fusion(&mut self) {
+        self.fusion = false;
+    }
+
+    /// Execute the basic blocks of the program, the instructions up to a
+    /// jump, a call, a return or an instruction stopping the program, one
+    /// instruction after the other without looking for breakpoints or
+    /// limits between them, in the runs which nothing observes. The blocks
+    /// are kept until their memory is written. It's enabled when the
+    /// machine is created, and doesn't change what the program does.
+    pub fn enable_block_cache(&mut self) {
+        self.block_cache = true;
+    }
+
+    /// Step every instruction on its own.
+    pub fn disable_block_cache(&mut self) {
+        self.block_cache = false;
+    }
+
+    /// The lookups of the basic blocks and their invalidations, since the
+    /// machine was created or [reset_stats](Machine::reset_stats) was
+    /// called.
+    pub fn block_stats(&self) -> BlockStats {
+        return self.block_stats;
+    }
+
+    /// The number of times every pair of instructions has been executed
+    /// fused, since the machine was created or
+    /// [reset_stats](Machine::reset_stats) was called.
+    pub fn fusion_stats(&self) -> &FusionStats {
+        return &self.fusion_stats;
+    }
+
+    /// Keep the bytes printed by the output instructions in a buffer of
+    /// `capacity` bytes, written when it's full, when an input instruction
+    /// is executed, when a step doesn't continue, the program having
+    /// exited or paused, and when a run stops, or with
+    /// [flush_output](Machine::flush_output). With 0, the default, they're
+    /// written by the instruction printing them.
+    pub fn set_output_buffer(&mut self, capacity: usize) {
+        self.output_capacity = capacity;
+    }
+
+    /// Write the bytes kept in the output buffer on `fd`, and flush it. The
+    /// bytes are lost if it fails.
+    pub fn flush_output<T: Write + ?Sized>(&mut self, fd: &mut T) -> io::Result<()> {
+        let bytes = std::mem::take(&mut self.output_buffer);
+        fd.write_all(&bytes)?;
+        return fd.flush();
+    }
+
+    // Write the output buffer on `fd` if it holds bytes, see flush_output
+    fn flush_buffered<T: Write + ?Sized>(&mut self, fd: &mut T) -> io::Result<()> {
+        if self.output_buffer.is_empty() {
+            return Ok(());
+        }
+        return self.flush_output(fd);
+    }
+
+    // The error of writing the output buffer once a run has stopped,
+    // located at IP
+    fn output_error(&self, error: io::Error) -> MachineError {
+        let ip = self.regs[IP];
+        let opcode = self.memory.get(ip as usize).copied().unwrap_or(0);
+        return MachineError::io(error).at(ip, opcode);
+    }
+
+    // Print `bytes` on `fd`, or add them to the output buffer if there's
+    // one, writing it once full
+    fn print<T: Write + ?Sized>(&mut self, fd: &mut T, bytes: &[u8]) -> io::Result<()> {
+        if self.output_capacity == 0 {
+            return fd.write_all(bytes);
+        }
+        self.output_buffer.extend_from_slice(bytes);
+        if self.output_buffer.len() >= self.output_capacity {
+            return self.flush_buffered(fd);
+        }
+        return Ok(());
+    }
+
+    /// Limit the execution to `fuel` units of fuel, or lift the limit with
+    /// `None`. Every instruction executed consumes its cost, 1 unless set by
+    /// [set_fuel_cost](Machine::set_fuel_cost), and a step whose instruction
+    /// costs more than the fuel left returns [StepOutcome::OutOfFuel]
+    /// without executing it, so that the execution can be resumed with
+    /// [add_fuel](Machine::add_fuel). The faulting instructions and the ones
+    /// paused by the hook before being executed consume nothing.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Add `fuel` units to the fuel left, if the fuel is limited.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(left) = &mut self.fuel {
+            *left = left.saturating_add(fuel);
+        }
+    }
+
+    /// The fuel left, or `None` if it's unlimited.
+    pub fn fuel(&self) -> Option<u64> {
+        return self.fuel;
+    }
+
+    /// Make the instruction `opcode` consume `cost` units of fuel.
+    pub fn set_fuel_cost(&mut self, opcode: u8, cost: u64) {
+        self.fuel_costs[opcode as usize] = cost;
+    }
+
+    /// The fuel consumed by the instruction `opcode`.
+    pub fn fuel_cost(&self, opcode: u8) -> u64 {
+        return self.fuel_costs[opcode as usize];
+    }
+
+    /// Seed the pseudo-random number generator used by the `rand`
+    /// instruction. Two machines seeded identically produce the same
+    /// sequence of numbers; a new machine behaves as if seeded with 0.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng_state = seed;
+    }
+
+    /// Reference onto the machine current memory.
+    pub fn memory(&self) -> &[u8] {
+        return &self.memory;
+    }
+
+    /// Mutable reference onto the machine current memory, for the host to
+    /// patch the program or exchange data with it. The writes through it
+    /// are seen neither by the watchpoints nor by the memory observer, and
+    /// [step_back](Machine::step_back) doesn't undo them.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.decoded.clear();
+        self.fused.clear();
+        self.blocks.clear();
+        return &mut self.memory;
+    }
+
+    /// Copy `bytes` into the memory starting at `address`, like
+    /// [load_at](Machine::load_at): nothing is copied and
+    /// [MachineError::NonExistingAddress] is returned if the bytes don't fit
+    /// in the memory.
+    pub fn write_memory(&mut self, address: u32, bytes: &[u8]) -> Result<(), MachineError> {
+        return self.load_at(address as usize, bytes);
+    }
+
+    /// Copy `bytes` into the memory starting at address `offset`, leaving
+    /// the rest of the memory and the registers untouched. Nothing is copied
+    /// if the bytes don't fit in the memory, and
+    /// [MachineError::NonExistingAddress] is returned.
+    pub fn load_at(&mut self, offset: usize, bytes: &[u8]) -> Result<(), MachineError> {
+        let size = self.memory.len();
+        if offset > size || bytes.len() > size - offset {
+            return Err(MachineError::address(offset.max(size)));
+        }
+        // A syscall handler may load bytes during the step
+        self.save_memory(offset..offset + bytes.len());
+        self.invalidate_decoded(offset..offset + bytes.len());
+        self.memory[offset..offset + bytes.len()].copy_from_slice(bytes);
+        return Ok(());
+    }
+
+    /// The memory in `range` as text, one line of 16 bytes ending with a
+    /// newline per line, such as
+    /// `0x0000: 48 65 6c 6c 6f 00 ...  Hello.`: the address of the first
+    /// byte, the bytes in hexadecimal and as ASCII characters, a `.`
+    /// standing for the bytes not printable. The part of the range outside
+    /// the memory is left out.
+    pub fn hexdump(&self, range: Range<u32>) -> String {
+        let end = (range.end as usize).min(self.memory.len());
+        let mut text = String::new();
+        for start in (range.start as usize..end).step_by(HEXDUMP_LINE_SIZE) {
+            let bytes = &self.memory[start..(start + HEXDUMP_LINE_SIZE).min(end)];
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            text += &format!(
+                "0x{:04x}: {:<width$}  {}\n",
+                start,
+                hex.join(" "),
+                ascii,
+                width = HEXDUMP_LINE_SIZE * 3 - 1
+            );
+        }
+        return text;
+    }
+
+    /**
+     * Instruction Set
+     */
+
+    // --------auxiliary functions--------
+    pub fn ip_sum(&self, offset: usize) -> usize {
+        return (self.regs[IP] as usize) + offset;
+    }
+
+    /// Advance the IP by `offset`, stopping at the end of the address
+    /// space, where fetching the next instruction fails.
+    pub fn ip_inc(&mut self, offset: u32) {
+        self.regs[IP] = self.regs[IP].saturating_add(offset);
+    }
+
+    // Read a single byte from `input`, or `None` at the end of the input.
+    fn read_byte<R: Read + ?Sized>(input: &mut R) -> Result<Option<u8>, MachineError> {
+        let mut byte: [u8; 1] = [0];
+        match input.read_exact(&mut byte) {
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(MachineError::io(error)),
+        }
+    }
+
+    // Given the operands `reg_a reg_b`, load the `len` bytes pointed by
+    // register reg_b into register reg_a, extending the sign of the value if
+    // `signed` is set.
+    fn load_narrow(
+        &mut self,
+        operands: Operands,
+        len: usize,
+        signed: bool,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let address = self.memory_range(self.regs[reg_b], len as u32)?.start;
+            let mut value: u32 = 0;
+            for i in 0..len {
+                value |= (self.memory[address + i] as u32) << (i * 8);
+            }
+            self.read_memory(address..address + len);
+            if signed {
+                let unused_bits = 32 - 8 * len as u32;
+                value = (((value << unused_bits) as i32) >> unused_bits) as u32;
+            }
+            self.set_reg(reg_a, value)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    // Given the operands `reg_a reg_b`, store the `len` low-order bytes of
+    // register reg_b into the memory pointed by register reg_a.
+    fn store_narrow(
+        &mut self,
+        operands: Operands,
+        len: usize,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let address = self.memory_range(self.regs[reg_a], len as u32)?.start;
+            let bytes: [u8; 4] = self.regs[reg_b].to_le_bytes();
+            self.write_bytes(address, &bytes[..len]);
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    // Check that the `len` bytes starting at `address` are all in memory and
+    // return the corresponding range. The addresses don't wrap around: bytes
+    // past 0xFFFF_FFFF are outside the memory too, and the error tells the
+    // starting address.
+    fn memory_range(&self, address: u32, len: u32) -> Result<Range<usize>, MachineError> {
+        let (start, len, size) = (address as usize, len as usize, self.memory.len());
+        if start <= size && len <= size - start {
+            return Ok(start..start + len);
+        }
+        return Err(MachineError::address(start));
+    }
+
+    // Write `bytes` into the memory at `address`, which must hold them,
+    // keeping the previous content if they touch a watchpoint
+    fn write_bytes(&mut self, address: usize, bytes: &[u8]) {
+        if let Some(observer) = &mut self.memory_observer {
+            observer.on_write(address as u32, bytes.len(), bytes);
+        }
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.record_write(address as u32, bytes.len());
+        }
+        let range = address..address + bytes.len();
+        let watched = self.watchpoints.iter().any(|watchpoint| {
+            return (watchpoint.start as usize) < range.end
+                && range.start < watchpoint.end as usize;
+        });
+        if watched && !bytes.is_empty() && self.watch_hit.is_none() {
+            self.watch_hit = Some(MemoryWrite {
+                instruction: 0, // Set once the instruction is executed
+                address: address as u32,
+                old: self.memory[range.clone()].to_vec(),
+                new: bytes.to_vec(),
+            });
+        }
+        self.save_memory(range.clone());
+        self.invalidate_decoded(range.clone());
+        self.memory[range].copy_from_slice(bytes);
+    }
+
+    // The instruction at `ip`, which must be in the memory, decoded once
+    // until the memory it's made of is written
+    fn decoded(&mut self, ip: usize) -> &Arc<DecodedInsn> {
+        return &self.decoded_at(ip).insn;
+    }
+
+    // The instruction at `ip` with its operands, see decoded
+    fn decoded_at(&mut self, ip: usize) -> &Decoded {
+        if self.decoded.len() != self.memory.len() {
+            self.decoded = vec![None; self.memory.len()];
+        }
+        let memory = &self.memory;
+        return self.decoded[ip].get_or_insert_with(|| {
+            let insn = decode(&memory[ip..], ip);
+            let mut operands: Operands = [0; 3];
+            operands[..insn.operands.len()].copy_from_slice(&insn.operands);
+            return Decoded {
+                insn: Arc::new(insn),
+                operands,
+            };
+        });
+    }
+
+    // Forget the instructions decoded from the bytes in `range`, which
+    // include the ones starting a few bytes before it, the pairs fused and
+    // the blocks made of them
+    fn invalidate_decoded(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.decoded.len());
+        let start = range
+            .start
+            .saturating_sub(MAX_INSTRUCTION_SIZE - 1)
+            .min(end);
+        self.decoded[start..end].fill(None);
+        let end = range.end.min(self.fused.len());
+        let start = range
+            .start
+            .saturating_sub(2 * MAX_INSTRUCTION_SIZE - 1)
+            .min(end);
+        self.fused[start..end].fill(None);
+        let first = range
+            .start
+            .saturating_sub(MAX_BLOCK_LENGTH * MAX_INSTRUCTION_SIZE);
+        let written: Vec<usize> = self
+            .blocks
+            .range(first..range.end)
+            .filter(|(_, block)| return block.end > range.start)
+            .map(|(&start, _)| return start)
+            .collect();
+        for start in written {
+            self.blocks.remove(&start);
+            self.block_stats.invalidations += 1;
+        }
+    }
+
+    // The index in FUSED of the pair of instructions at `ip`, which must be
+    // in the memory, looked for once until the memory they're made of is
+    // written
+    fn fusion_at(&mut self, ip: usize) -> Option<usize> {
+        if self.fused.len() != self.memory.len() {
+            self.fused = vec![None; self.memory.len()];
+        }
+        if let Some(fusion) = self.fused[ip] {
+            return fusion;
+        }
+        let fusion = self.find_fusion(ip);
+        self.fused[ip] = Some(fusion);
+        return fusion;
+    }
+
+    // Look for a pair of FUSED at `ip`. Both instructions must be built in
+    // and unable to fault: their registers exist and the target of the
+    // branch is in the memory. The first one mustn't write the IP.
+    fn find_fusion(&mut self, ip: usize) -> Option<usize> {
+        let first = self.decoded(ip).clone();
+        let next = ip + first.bytes.len();
+        if first.info.is_none() || next >= self.memory.len() {
+            return None;
+        }
+        let second = self.decoded(next).clone();
+        let (opcode_a, opcode_b) = (first.bytes[0], second.bytes[0]);
+        let index = FUSED
+            .iter()
+            .position(|&(a, b, _)| return a == opcode_a && b == opcode_b)?;
+        if self.replaced_opcodes.contains(&opcode_a)
+            || self.replaced_opcodes.contains(&opcode_b)
+            || first.operands[0] as usize == IP
+        {
+            return None;
+        }
+        for insn in [&first, &second] {
+            let operands = insn.info?.operands.iter().zip(&insn.operands);
+            for (operand, &value) in operands {
+                match operand {
+                    Operand::Reg if value as usize >= NREGS => return None,
+                    Operand::Addr16 if value as usize >= self.memory.len() => return None,
+                    _ => {}
+                }
+            }
+        }
+        return Some(index);
+    }
+
+    // Keep the memory in `range` before the step being executed writes it
+    fn save_memory(&mut self, range: Range<usize>) {
+        if let Some(undo) = &mut self.pending {
+            undo.memory.push((range.start, self.memory[range].to_vec()));
+        }
+    }
+
+    // Note that an instruction read the memory in `range`, for the memory
+    // observer and the read watchpoints
+    fn read_memory(&mut self, range: Range<usize>) {
+        if let Some(observer) = &mut self.memory_observer {
+            observer.on_read(range.start as u32, range.len());
+        }
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.record_read(range.start as u32, range.len());
+        }
+        let watched = self.read_watchpoints.iter().any(|watchpoint| {
+            return (watchpoint.start as usize) < range.end
+                && range.start < watchpoint.end as usize;
+        });
+        if watched && !range.is_empty() && self.read_hit.is_none() {
+            self.read_hit = Some(MemoryRead {
+                instruction: 0, // Set once the instruction is executed
+                address: range.start as u32,
+                bytes: self.memory[range].to_vec(),
+            });
+        }
+    }
+
+    // Keep the calls in the journal before a step changes them. The
+    // instructions change them last, once they can't fault.
+    fn save_calls(&mut self) {
+        if self.journal_capacity > 0 {
+            if let Some(undo) = &mut self.pending {
+                undo.calls.get_or_insert_with(|| self.calls.clone());
+            }
+        }
+    }
+
+    // Compute the address of a 32-bit word located `offset` bytes after the address
+    // contained in register `reg`, checking that the whole word is in memory.
+    fn indexed_address(&self, reg: usize, offset: u8) -> Result<usize, MachineError> {
+        let address: i64 = self.regs[reg] as i64 + (offset as i8) as i64;
+        if 0 <= address && address + 4 <= self.memory.len() as i64 {
+            return Ok(address as usize);
+        }
+        return Err(MachineError::address(address as u32 as usize));
+    }
+
+    // Advance the pseudo-random number generator (SplitMix64) and return
+    // its next 32-bit output.
+    fn next_random(&mut self) -> u32 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return ((z ^ (z >> 31)) >> 32) as u32;
+    }
+
+    // Given the operands `freg_a freg_b freg_c`, store `op(freg_b, freg_c)`
+    // into freg_a.
+    #[cfg(feature = "fp")]
+    fn float_op(
+        &mut self,
+        operands: Operands,
+        op: fn(f32, f32) -> f32,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NFREGS && reg_b < NFREGS && reg_c < NFREGS {
+            self.fregs[reg_a] = op(self.fregs[reg_b], self.fregs[reg_c]);
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    // Given the operands `reg_a reg_b L H`, jump to the 16-bit address if
+    // `condition` holds for the contents of reg_a and reg_b.
+    fn branch_if(
+        &mut self,
+        operands: Operands,
+        condition: fn(u32, u32) -> bool,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let target: usize = operands[2] as usize;
+
+        self.ip_inc(5);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            if !condition(self.regs[reg_a], self.regs[reg_b]) {
+                return Ok(StepOutcome::Continue);
+            }
+            if target < self.memory.len() {
+                self.set_reg(IP, target as u32)?;
+                return Ok(StepOutcome::Continue);
+            }
+            return Err(MachineError::address(target));
+        }
+        return Err(MachineError::operand_register());
+    }
+    // -----------------------------------
+
+    /**
+     * 1 reg_a reg_b reg_c: if register reg_c contains a non-zero value,
+     * copy the content of register reg_b into register reg_a; otherwise do nothing.
+     */
+    fn move_if(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_c < NREGS && reg_b < NREGS {
+            if self.regs[reg_c] == 0 {
+                return Ok(StepOutcome::Continue);
+            }
+            /*
+             The ? at the end of the call to self.set_reg, which returns an Ok(()) if we got success.
+             The function always returns Ok(true) if everything is ok.
+            */
+            self.set_reg(reg_a, self.regs[reg_b])?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 2 reg_a reg_b: store the content of register reg_b into the memory starting
+     * at address pointed by register reg_a using little-endian representation.
+     */
+    fn store(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.set_reg(IP, self.ip_sum(3) as u32)?;
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let bytes: [u8; 4] = self.regs[reg_b].to_le_bytes();
+            let range = self.memory_range(self.regs[reg_a], 4)?;
+            self.write_bytes(range.start, &bytes);
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 3 reg_a reg_b: load the 32-bit content from memory at address pointed by
+     * register reg_b into register reg_a using little-endian representation.
+     */
+    fn load(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let range = self.memory_range(self.regs[reg_b], 4)?;
+            let value = u32::from_le_bytes(self.memory[range.clone()].try_into().unwrap());
+            self.read_memory(range);
+            self.regs[reg_a] = value;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 4 reg_a L H: interpret H and L respectively as the high-order and the low-order bytes
+     * of a 16-bit signed value, sign-extend it to 32 bits, and store it into register reg_a.
+     */
+    fn loadimm(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let value: u32 = operands[1] as u16 as i16 as u32;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS {
+            self.set_reg(reg_a, value)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 5 reg_a reg_b reg_c: store the content of register reg_b minus the
+     * content of register reg_c into register reg_a.
+     */
+    fn sub(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, u32::wrapping_sub(self.regs[reg_b], self.regs[reg_c]))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 6 reg_a: output the character whose unicode value is stored in
+     * the 8 low bits of register reg_a.
+     */
+    fn out<T: Write + ?Sized>(
+        &mut self,
+        operands: Operands,
+        fd: &mut T,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            let character_v = 0x000000FF & self.regs[reg_a];
+            let character = char::from_u32(character_v)
+                .ok_or_else(|| MachineError::invalid_character(character_v))?;
+            let result = self.print(fd, character.encode_utf8(&mut [0; 4]).as_bytes());
+
+            match result {
+                Ok(_) => return Ok(StepOutcome::Continue),
+                Err(error) => return Err(MachineError::io(error)),
+            }
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 7: exit the current program with exit code 0.
+     */
+    fn exit(&mut self) -> Result<StepOutcome, MachineError> {
+        self.ip_inc(1);
+        self.exit_code = Some(0);
+        return Ok(StepOutcome::Exited);
+    }
+
+    /**
+     * 8 reg_a: output the signed number stored in register reg_a in decimal.
+     */
+    fn out_number<T: Write + ?Sized>(
+        &mut self,
+        operands: Operands,
+        fd: &mut T,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            let decimal = self.regs[reg_a] as i32;
+            let result = self.print(fd, decimal.to_string().as_bytes());
+
+            match result {
+                Ok(_) => return Ok(StepOutcome::Continue),
+                Err(error) => return Err(MachineError::io(error)),
+            }
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 9 reg_a reg_b reg_c: store the content of register reg_b plus the
+     * content of register reg_c into register reg_a.
+     */
+    fn add(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, u32::wrapping_add(self.regs[reg_b], self.regs[reg_c]))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 10 reg_a reg_b reg_c: store the content of register reg_b times the
+     * content of register reg_c into register reg_a. Only the low-order 32 bits
+     * of the product are kept, which gives the same result for signed and
+     * unsigned operands.
+     */
+    fn mul(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, u32::wrapping_mul(self.regs[reg_b], self.regs[reg_c]))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 11 reg_a reg_b reg_c: store the signed quotient of register reg_b divided by
+     * register reg_c into register reg_a, rounding towards zero. Dividing i32::MIN
+     * by -1 wraps around to i32::MIN.
+     */
+    fn div(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            let divisor = self.regs[reg_c] as i32;
+            if divisor == 0 {
+                return Err(MachineError::division_by_zero());
+            }
+            let quotient = i32::wrapping_div(self.regs[reg_b] as i32, divisor);
+            self.set_reg(reg_a, quotient as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 12 reg_a reg_b reg_c: store the unsigned quotient of register reg_b divided by
+     * register reg_c into register reg_a.
+     */
+    fn divu(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            match u32::checked_div(self.regs[reg_b], self.regs[reg_c]) {
+                Some(quotient) => self.set_reg(reg_a, quotient)?,
+                None => return Err(MachineError::division_by_zero()),
+            }
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 13 reg_a reg_b reg_c: store the signed remainder of register reg_b divided by
+     * register reg_c into register reg_a. The remainder has the sign of the dividend,
+     * and i32::MIN modulo -1 gives 0.
+     */
+    fn modulo(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            let divisor = self.regs[reg_c] as i32;
+            if divisor == 0 {
+                return Err(MachineError::division_by_zero());
+            }
+            let remainder = i32::wrapping_rem(self.regs[reg_b] as i32, divisor);
+            self.set_reg(reg_a, remainder as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 14 reg_a reg_b reg_c: store the unsigned remainder of register reg_b divided by
+     * register reg_c into register reg_a.
+     */
+    fn modu(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            match u32::checked_rem(self.regs[reg_b], self.regs[reg_c]) {
+                Some(remainder) => self.set_reg(reg_a, remainder)?,
+                None => return Err(MachineError::division_by_zero()),
+            }
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 15 reg_a reg_b reg_c: store the bitwise and of registers reg_b and reg_c
+     * into register reg_a.
+     */
+    fn and(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b] & self.regs[reg_c])?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 16 reg_a reg_b reg_c: store the bitwise or of registers reg_b and reg_c
+     * into register reg_a.
+     */
+    fn or(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b] | self.regs[reg_c])?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 17 reg_a reg_b reg_c: store the bitwise exclusive or of registers reg_b and
+     * reg_c into register reg_a.
+     */
+    fn xor(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b] ^ self.regs[reg_c])?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 18 reg_a reg_b: store the bitwise complement of register reg_b into
+     * register reg_a.
+     */
+    fn not(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            self.set_reg(reg_a, !self.regs[reg_b])?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 19 reg_a reg_b reg_c: shift register reg_b left by the content of register
+     * reg_c modulo 32 and store the result into register reg_a.
+     */
+    fn shl(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b] << (self.regs[reg_c] % 32))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 20 reg_a reg_b reg_c: logically shift register reg_b right by the content of
+     * register reg_c modulo 32, filling with zeros, and store the result into
+     * register reg_a.
+     */
+    fn shr(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b] >> (self.regs[reg_c] % 32))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 21 reg_a reg_b reg_c: arithmetically shift register reg_b right by the content
+     * of register reg_c modulo 32, replicating the sign bit, and store the result
+     * into register reg_a.
+     */
+    fn sar(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(
+                reg_a,
+                ((self.regs[reg_b] as i32) >> (self.regs[reg_c] % 32)) as u32,
+            )?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 22 reg_a reg_b reg_c: rotate register reg_b left by the content of register
+     * reg_c modulo 32 and store the result into register reg_a.
+     */
+    fn rol(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b].rotate_left(self.regs[reg_c] % 32))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 23 reg_a reg_b reg_c: rotate register reg_b right by the content of register
+     * reg_c modulo 32 and store the result into register reg_a.
+     */
+    fn ror(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b].rotate_right(self.regs[reg_c] % 32))?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 24 L H: interpret H and L respectively as the high-order and the low-order bytes
+     * of a 16-bit unsigned address and continue the execution at this address.
+     */
+    fn jmp(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let target: usize = operands[0] as usize;
+
+        self.ip_inc(3);
+
+        if target < self.memory.len() {
+            self.set_reg(IP, target as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::address(target));
+    }
+
+    /**
+     * 25 L H: interpret H and L respectively as the high-order and the low-order bytes
+     * of a 16-bit signed offset and add it to the address of the next instruction.
+     */
+    fn jrel(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let offset: i64 = operands[0] as u16 as i16 as i64;
+
+        self.ip_inc(3);
+        let target: i64 = self.regs[IP] as i64 + offset;
+
+        if 0 <= target && target < self.memory.len() as i64 {
+            self.set_reg(IP, target as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::address(target as u32 as usize));
+    }
+
+    /**
+     * 26 reg_a reg_b L H: if registers reg_a and reg_b are equal, continue the
+     * execution at the 16-bit address whose low-order and high-order bytes are
+     * L and H; otherwise do nothing.
+     */
+    fn beq(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.branch_if(operands, |a, b| a == b);
+    }
+
+    /**
+     * 27 reg_a reg_b L H: if registers reg_a and reg_b differ, continue the
+     * execution at the 16-bit address L H; otherwise do nothing.
+     */
+    fn bne(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.branch_if(operands, |a, b| a != b);
+    }
+
+    /**
+     * 28 reg_a reg_b L H: if register reg_a is lower than register reg_b when both
+     * are interpreted as signed values, continue the execution at the 16-bit
+     * address L H; otherwise do nothing.
+     */
+    fn blt(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.branch_if(operands, |a, b| (a as i32) < (b as i32));
+    }
+
+    /**
+     * 29 reg_a reg_b L H: if register reg_a is greater than or equal to register
+     * reg_b when both are interpreted as signed values, continue the execution at
+     * the 16-bit address L H; otherwise do nothing.
+     */
+    fn bge(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.branch_if(operands, |a, b| (a as i32) >= (b as i32));
+    }
+
+    /**
+     * 30 L H: save the address of the next instruction into the link register (r14)
+     * and continue the execution at the 16-bit address whose low-order and
+     * high-order bytes are L and H. A function calling another one must save r14
+     * beforehand.
+     */
+    fn call(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let target: usize = operands[0] as usize;
+
+        self.ip_inc(3);
+
+        if target < self.memory.len() {
+            self.save_calls();
+            self.calls.push(Frame {
+                function: target as u32,
+                return_address: self.regs[IP],
+            });
+            self.set_reg(LR, self.regs[IP])?;
+            self.set_reg(IP, target as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::address(target));
+    }
+
+    /**
+     * 31: continue the execution at the address stored in the link register (r14).
+     */
+    fn ret(&mut self) -> Result<StepOutcome, MachineError> {
+        self.ip_inc(1);
+
+        let target: usize = self.regs[LR] as usize;
+
+        if target < self.memory.len() {
+            let returned = |frame: &Frame| frame.return_address == target as u32;
+            if let Some(depth) = self.calls.iter().rposition(returned) {
+                self.save_calls();
+                self.calls.truncate(depth);
+            }
+            self.set_reg(IP, target as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::address(target));
+    }
+
+    /**
+     * 32 reg_a: decrement the stack pointer (r15) by 4 and store the content of
+     * register reg_a at the address it now points to using little-endian
+     * representation.
+     */
+    fn push(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            let sp: usize = self.regs[SP] as usize;
+            if !(4..=self.memory.len()).contains(&sp) {
+                return Err(MachineError::stack_fault(sp));
+            }
+            let bytes: [u8; 4] = self.regs[reg_a].to_le_bytes();
+            self.write_bytes(sp - 4, &bytes);
+            self.set_reg(SP, (sp - 4) as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 33 reg_a: load the 32-bit content pointed by the stack pointer (r15) into
+     * register reg_a using little-endian representation, then increment the
+     * stack pointer by 4.
+     */
+    fn pop(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            let sp: usize = self.regs[SP] as usize;
+            if sp.saturating_add(4) > self.memory.len() {
+                return Err(MachineError::stack_fault(sp));
+            }
+            let mut bytes: [u8; 4] = [0; 4];
+            bytes.copy_from_slice(&self.memory[sp..sp + 4]);
+            self.read_memory(sp..sp + 4);
+            self.set_reg(SP, (sp + 4) as u32)?;
+            self.set_reg(reg_a, u32::from_le_bytes(bytes))?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 34 reg_a: read one byte from the input and store it into register reg_a.
+     * At the end of the input, 0xFFFFFFFF (-1) is stored instead.
+     */
+    fn input<R: Read + ?Sized, W: Write + ?Sized>(
+        &mut self,
+        operands: Operands,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+        // What the program printed, such as a prompt, shows before it waits
+        self.flush_buffered(output).map_err(MachineError::io)?;
+
+        if reg_a < NREGS {
+            let value: u32 = match Self::read_byte(input)? {
+                Some(byte) => byte as u32,
+                None => 0xFFFF_FFFF,
+            };
+            self.set_reg(reg_a, value)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 35 reg_a: read a signed decimal number from the input and store it into
+     * register reg_a. Leading whitespace is skipped, and the byte following the
+     * last digit is consumed.
+     */
+    fn in_number<R: Read + ?Sized, W: Write + ?Sized>(
+        &mut self,
+        operands: Operands,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+        self.flush_buffered(output).map_err(MachineError::io)?;
+
+        if reg_a < NREGS {
+            let mut text = String::new();
+            while let Some(byte) = Self::read_byte(input)? {
+                let c = byte as char;
+                if c.is_ascii_digit() || (text.is_empty() && (c == '-' || c == '+')) {
+                    text.push(c);
+                } else if !(text.is_empty() && c.is_ascii_whitespace()) {
+                    break;
+                }
+            }
+            let value: i32 = text
+                .parse()
+                .map_err(|_| MachineError::InvalidNumber { ip: 0, opcode: 0 })?;
+            self.set_reg(reg_a, value as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 36 reg_a b0 b1 b2 b3: interpret b0 to b3 as the bytes of a 32-bit value in
+     * little-endian representation and store it into register reg_a.
+     */
+    fn loadimm32(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let value: u32 = operands[1];
+
+        self.ip_inc(6);
+
+        if reg_a < NREGS {
+            self.set_reg(reg_a, value)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 37 reg_a reg_b: load the byte at address pointed by register reg_b into
+     * register reg_a, extending it with zeros.
+     */
+    fn loadb(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.load_narrow(operands, 1, false);
+    }
+
+    /**
+     * 38 reg_a reg_b: load the byte at address pointed by register reg_b into
+     * register reg_a, extending its sign.
+     */
+    fn loadbs(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.load_narrow(operands, 1, true);
+    }
+
+    /**
+     * 39 reg_a reg_b: store the low-order byte of register reg_b into the memory
+     * at address pointed by register reg_a.
+     */
+    fn storeb(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.store_narrow(operands, 1);
+    }
+
+    /**
+     * 40 reg_a reg_b: load the 16-bit content from memory at address pointed by
+     * register reg_b into register reg_a using little-endian representation,
+     * extending it with zeros.
+     */
+    fn loadh(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.load_narrow(operands, 2, false);
+    }
+
+    /**
+     * 41 reg_a reg_b: load the 16-bit content from memory at address pointed by
+     * register reg_b into register reg_a using little-endian representation,
+     * extending its sign.
+     */
+    fn loadhs(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.load_narrow(operands, 2, true);
+    }
 
-impl Machine {
-    /// Create a new machine in its reset state. The `memory` parameter will
-    /// be copied at the beginning of the machine memory.
-    ///
-    /// # Panics
-    /// This function panics when `memory` is larger than the machine memory.
-    pub fn new(memory: &[u8]) -> Self {
-        if memory.len() > MEMORY_SIZE {
-            panic!();
-        }
-        let mut machine = Self {
-            memory: [0; MEMORY_SIZE],
-            regs: [0; NREGS],
-        };
-        machine.memory[..memory.len()].copy_from_slice(memory); // 'source slice length (840) does not match destination slice length (4096)'
-        return machine;
+    /**
+     * 42 reg_a reg_b: store the 16 low-order bits of register reg_b into the memory
+     * starting at address pointed by register reg_a using little-endian
+     * representation.
+     */
+    fn storeh(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.store_narrow(operands, 2);
     }
 
-    /// Run until the program terminates or until an error happens.
-    /// If output instructions are run, they print on `fd`.
-    pub fn run_on<T: Write>(&mut self, fd: &mut T) -> Result<(), MachineError> {
-        loop {
-            if self.step_on(fd)? {
-                break;
-            }
+    /**
+     * 43 reg_a reg_b: compute the content of register reg_a minus the content of
+     * register reg_b and update the flags accordingly, without storing the result.
+     */
+    fn cmp(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let (a, b) = (self.regs[reg_a], self.regs[reg_b]);
+            let (difference, borrow) = a.overflowing_sub(b);
+            self.flags = Flags {
+                zero: difference == 0,
+                negative: (difference as i32) < 0,
+                carry: borrow,
+                overflow: (a as i32).overflowing_sub(b as i32).1,
+            };
+            return Ok(StepOutcome::Continue);
         }
-        return Ok(());
+        return Err(MachineError::operand_register());
     }
 
-    /// Run until the program terminates or until an error happens.
-    /// If output instructions are run, they print on standard output.
-    pub fn run(&mut self) -> Result<(), MachineError> {
-        return self.run_on(&mut io::stdout().lock());
+    /**
+     * 44 reg_a: exit the current program using the content of register reg_a
+     * as exit code.
+     */
+    fn halt(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            self.exit_code = Some(self.regs[reg_a]);
+            return Ok(StepOutcome::Exited);
+        }
+        return Err(MachineError::operand_register());
     }
 
-    /// Execute the next instruction by doing the following steps:
-    ///   - decode the instruction located at IP (register 0)
-    ///   - increment the IP by the size of the instruction
-    ///   - execute the decoded instruction
-    ///
-    /// If output instructions are run, they print on `fd`.
-    /// If an error happens at either of those steps, an error is
-    /// returned.
-    ///
-    /// In case of success, `true` is returned if the program is
-    /// terminated (upon encountering an exit instruction), or
-    /// `false` if the execution must continue.
-    pub fn step_on<T: Write>(&mut self, fd: &mut T) -> Result<bool, MachineError> {
-        // It contains the address of the next instruction to be executed
-        let ip_aux: usize = self.regs[IP].try_into().unwrap();
+    /**
+     * 45 reg_a reg_b: copy the content of register reg_b into register reg_a.
+     */
+    fn mov(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
 
-        if ip_aux < MEMORY_SIZE {
-            let instruction: u8 = self.memory[ip_aux];
+        self.ip_inc(3);
 
-            let result = match instruction {
-                1 => self.move_if(),
-                2 => self.store(),
-                3 => self.load(),
-                4 => self.loadimm(),
-                5 => self.sub(),
-                6 => self.out(fd),
-                7 => self.exit(),
-                8 => self.out_number(fd),
-                _ => Err(MachineError::NonExistingInstruction),
-            };
-            if instruction == 7 {
-                return result.map(|_| true); // map transforms the result of the match into a Result<bool, MachineError>
-            }
-            return result.map(|_| false); // map transforms the result of the match into a Result<bool, MachineError>
+        if reg_a < NREGS && reg_b < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b])?;
+            return Ok(StepOutcome::Continue);
         }
-        return Err(MachineError::NonExistingAddress);
+
+        return Err(MachineError::operand_register());
     }
 
-    /// Similar to [step_on](Machine::step_on).
-    /// If output instructions are run, they print on standard output.
-    pub fn step(&mut self) -> Result<bool, MachineError> {
-        return self.step_on(&mut io::stdout().lock());
+    /**
+     * 46 reg_a reg_b imm8: load the 32-bit content from memory at address pointed by
+     * register reg_b plus the sign-extended offset imm8 into register reg_a using
+     * little-endian representation.
+     */
+    fn loadx(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let offset: u8 = operands[2] as u8;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let address = self.indexed_address(reg_b, offset)?;
+            let mut bytes: [u8; 4] = [0; 4];
+            bytes.copy_from_slice(&self.memory[address..address + 4]);
+            self.read_memory(address..address + 4);
+            self.set_reg(reg_a, u32::from_le_bytes(bytes))?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
     }
 
-    /// Reference onto the machine current set of regs.
-    pub fn regs(&self) -> &[u32] {
-        return &self.regs;
+    /**
+     * 47 reg_a imm8 reg_b: store the content of register reg_b into the memory
+     * starting at address pointed by register reg_a plus the sign-extended offset
+     * imm8 using little-endian representation.
+     */
+    fn storex(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let offset: u8 = operands[1] as u8;
+        let reg_b: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            let address = self.indexed_address(reg_a, offset)?;
+            let bytes: [u8; 4] = self.regs[reg_b].to_le_bytes();
+            self.write_bytes(address, &bytes);
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
     }
 
-    /// Sets a register to the given value.
-    pub fn set_reg(&mut self, reg: usize, value: u32) -> Result<(), MachineError> {
-        if reg < NREGS {
-            self.regs[reg] = value;
-            return Ok(());
+    /**
+     * 48 imm8: call the host syscall handler with the number imm8.
+     */
+    fn syscall(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let number: u8 = operands[0] as u8;
+
+        self.ip_inc(2);
+
+        // The handler is moved out of the machine so that it can borrow it mutably
+        match self.syscall_handler.take() {
+            Some(mut handler) => {
+                let result = handler.syscall(self, number);
+                if self.syscall_handler.is_none() {
+                    self.syscall_handler = Some(handler);
+                }
+                result?;
+                return Ok(StepOutcome::Continue);
+            }
+            None => {
+                return Err(MachineError::UnhandledSyscall {
+                    ip: 0,
+                    opcode: 0,
+                    number,
+                })
+            }
         }
-        return Err(MachineError::NonExistingRegister);
     }
 
-    /// Reference onto the machine current memory.
-    pub fn memory(&self) -> &[u8] {
-        return &self.memory;
+    /**
+     * 49: pause the execution. Running the machine again resumes the program
+     * after this instruction.
+     */
+    fn brk(&mut self) -> Result<StepOutcome, MachineError> {
+        self.ip_inc(1);
+        return Ok(StepOutcome::Breakpoint);
     }
 
     /**
-     * Instruction Set
+     * 50 reg_a: store a pseudo-random 32-bit value into register reg_a.
      */
+    fn rand(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
 
-    // --------auxiliary functions--------
-    pub fn ip_sum(&self, offset: usize) -> usize {
-        return (self.regs[IP] as usize) + offset;
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            let value = self.next_random();
+            self.set_reg(reg_a, value)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
     }
 
-    pub fn ip_inc(&mut self, offset: u32) -> () {
-        self.regs[IP] += offset;
+    /**
+     * 51 reg_a: output the NUL-terminated byte string starting at address pointed
+     * by register reg_a. The bytes are written as is, and strings longer than 1024
+     * bytes are rejected.
+     */
+    fn outs<T: Write + ?Sized>(
+        &mut self,
+        operands: Operands,
+        fd: &mut T,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            let start: usize = self.regs[reg_a] as usize;
+            let size = self.memory.len();
+            if start >= size {
+                return Err(MachineError::address(start));
+            }
+            let limit: usize = usize::min(start + MAX_STRING_LENGTH + 1, size);
+            let length = match self.memory[start..limit].iter().position(|&b| b == 0) {
+                Some(length) => length,
+                None if limit == size && limit - start <= MAX_STRING_LENGTH => {
+                    return Err(MachineError::address(start))
+                }
+                None => {
+                    return Err(MachineError::StringTooLong {
+                        ip: 0,
+                        opcode: 0,
+                        address: start as u32,
+                    })
+                }
+            };
+            self.read_memory(start..start + length + 1);
+            let bytes = self.memory[start..start + length].to_vec();
+            match self.print(fd, &bytes) {
+                Ok(_) => return Ok(StepOutcome::Continue),
+                Err(error) => return Err(MachineError::io(error)),
+            }
+        }
+        return Err(MachineError::operand_register());
     }
-    // -----------------------------------
 
     /**
-     * 1 reg_a reg_b reg_c: if register reg_c contains a non-zero value,
-     * copy the content of register reg_b into register reg_a; otherwise do nothing.
+     * 52 reg_a reg_b reg_c: copy the number of bytes given by register reg_c from
+     * the address pointed by register reg_b to the address pointed by register
+     * reg_a. Overlapping areas are copied as if through a temporary buffer.
      */
-    fn move_if(&mut self) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
-        let reg_b: usize = self.memory[self.ip_sum(2)] as usize;
-        let reg_c: usize = self.memory[self.ip_sum(3)] as usize;
+    fn memcpy(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
 
         self.ip_inc(4);
 
-        if reg_c < NREGS && reg_b < NREGS {
-            if self.regs[reg_c] == 0 {
-                return Ok(false);
-            }
-            /*
-             The ? at the end of the call to self.set_reg, which returns an Ok(()) if we got success.
-             The function always returns Ok(true) if everything is ok.
-            */
-            self.set_reg(reg_a.into(), self.regs[reg_b as usize])?;
-            return Ok(false);
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            let destination = self.memory_range(self.regs[reg_a], self.regs[reg_c])?;
+            let source = self.memory_range(self.regs[reg_b], self.regs[reg_c])?;
+            let bytes = self.memory[source.clone()].to_vec();
+            self.read_memory(source);
+            self.write_bytes(destination.start, &bytes);
+            return Ok(StepOutcome::Continue);
         }
-        return Err(MachineError::NonExistingRegister);
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 2 reg_a reg_b: store the content of register reg_b into the memory starting
-     * at address pointed by register reg_a using little-endian representation.
+     * 53 reg_a reg_b reg_c: fill the number of bytes given by register reg_c,
+     * starting at the address pointed by register reg_a, with the low-order byte
+     * of register reg_b.
      */
-    fn store(&mut self) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
-        let reg_b: usize = self.memory[self.ip_sum(2)] as usize;
+    fn memset(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
 
-        self.set_reg(IP, self.ip_sum(3) as u32)?;
+        self.ip_inc(4);
 
-        if reg_a < NREGS && reg_b < NREGS {
-            let bytes: [u8; 4] = self.regs[reg_b].to_le_bytes();
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            let destination = self.memory_range(self.regs[reg_a], self.regs[reg_c])?;
+            let bytes = vec![self.regs[reg_b] as u8; destination.len()];
+            self.write_bytes(destination.start, &bytes);
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
 
-            for i in 0..=3 {
-                let index = (self.regs[reg_a] + i) as usize;
-                if index < MEMORY_SIZE {
-                    self.memory[index] = bytes[i as usize];
-                } else {
-                    return Err(MachineError::NonExistingAddress);
-                }
-            }
-            return Ok(false);
+    /**
+     * 54 reg_a reg_b: store the number of leading zero bits of register reg_b
+     * into register reg_a (32 if reg_b is zero).
+     */
+    fn clz(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            self.set_reg(reg_a, self.regs[reg_b].leading_zeros())?;
+            return Ok(StepOutcome::Continue);
         }
 
-        return Err(MachineError::NonExistingRegister);
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 3 reg_a reg_b: load the 32-bit content from memory at address pointed by
-     * register reg_b into register reg_a using little-endian representation.
+     * 55 reg_a reg_b: store the number of bits set in register reg_b into
+     * register reg_a.
      */
-    fn load(&mut self) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
-        let reg_b: usize = self.memory[self.ip_sum(2)] as usize;
+    fn popcnt(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
 
         self.ip_inc(3);
 
         if reg_a < NREGS && reg_b < NREGS {
-            let mut value: u32;
-            value = 0;
-            for i in 0..=3 {
-                let index = (self.regs[reg_b] + i) as usize;
-                if index < MEMORY_SIZE {
-                    value += (self.memory[index] as u32) << i * 8;
-                } else {
-                    return Err(MachineError::NonExistingAddress);
-                }
-            }
-            self.regs[reg_a] = value;
-            return Ok(false);
+            self.set_reg(reg_a, self.regs[reg_b].count_ones())?;
+            return Ok(StepOutcome::Continue);
         }
 
-        return Err(MachineError::NonExistingRegister);
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 4 reg_a L H: interpret H and L respectively as the high-order and the low-order bytes
-     * of a 16-bit signed value, sign-extend it to 32 bits, and store it into register reg_a.
+     * 56 reg_a reg_b reg_c: store the content of register reg_b plus the content of
+     * register reg_c plus the carry flag into register reg_a. The carry flag is set
+     * if the unsigned addition overflowed and cleared otherwise.
      */
-    fn loadimm(&mut self) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
-        let l: u16 = self.memory[self.ip_sum(2)] as u16;
-        let h: u16 = self.memory[self.ip_sum(3)] as u16;
+    fn adc(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
 
         self.ip_inc(4);
 
-        let value: u32 = (((h as i16) << 8) + (l as i16)) as u32;
-
-        if reg_a < NREGS {
-            self.set_reg(reg_a, value)?;
-            return Ok(false);
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            let (partial, carry_1) = self.regs[reg_b].overflowing_add(self.regs[reg_c]);
+            let (sum, carry_2) = partial.overflowing_add(self.flags.carry as u32);
+            self.flags.carry = carry_1 || carry_2;
+            self.set_reg(reg_a, sum)?;
+            return Ok(StepOutcome::Continue);
         }
-        return Err(MachineError::NonExistingRegister);
+
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 5 reg_a reg_b reg_c: store the content of register reg_b minus the
-     * content of register reg_c into register reg_a.
+     * 57 reg_a reg_b reg_c: store the content of register reg_b minus the content of
+     * register reg_c minus the carry flag into register reg_a. The carry flag is set
+     * if the unsigned subtraction needed a borrow and cleared otherwise.
      */
-    fn sub(&mut self) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
-        let reg_b: usize = self.memory[self.ip_sum(2)] as usize;
-        let reg_c: usize = self.memory[self.ip_sum(3)] as usize;
+    fn sbb(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
 
         self.ip_inc(4);
 
         if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
-            self.set_reg(reg_a, u32::wrapping_sub(self.regs[reg_b], self.regs[reg_c]))?;
-            return Ok(false);
+            let (partial, borrow_1) = self.regs[reg_b].overflowing_sub(self.regs[reg_c]);
+            let (difference, borrow_2) = partial.overflowing_sub(self.flags.carry as u32);
+            self.flags.carry = borrow_1 || borrow_2;
+            self.set_reg(reg_a, difference)?;
+            return Ok(StepOutcome::Continue);
         }
 
-        return Err(MachineError::NonExistingRegister);
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 6 reg_a: output the character whose unicode value is stored in
-     * the 8 low bits of register reg_a.
+     * 58 freg_a freg_b freg_c: store the floating-point sum of registers freg_b and
+     * freg_c into register freg_a.
+     */
+    #[cfg(feature = "fp")]
+    fn fadd(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.float_op(operands, |b, c| b + c);
+    }
+
+    /**
+     * 59 freg_a freg_b freg_c: store the floating-point difference of registers
+     * freg_b and freg_c into register freg_a.
+     */
+    #[cfg(feature = "fp")]
+    fn fsub(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.float_op(operands, |b, c| b - c);
+    }
+
+    /**
+     * 60 freg_a freg_b freg_c: store the floating-point product of registers freg_b
+     * and freg_c into register freg_a.
+     */
+    #[cfg(feature = "fp")]
+    fn fmul(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.float_op(operands, |b, c| b * c);
+    }
+
+    /**
+     * 61 freg_a freg_b freg_c: store the floating-point quotient of registers freg_b
+     * and freg_c into register freg_a. Dividing by zero gives an infinity or NaN
+     * as mandated by IEEE 754.
+     */
+    #[cfg(feature = "fp")]
+    fn fdiv(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        return self.float_op(operands, |b, c| b / c);
+    }
+
+    /**
+     * 62 freg_a reg_b: convert the signed integer stored in register reg_b to the
+     * nearest floating-point value and store it into register freg_a.
+     */
+    #[cfg(feature = "fp")]
+    fn fcvt(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NFREGS && reg_b < NREGS {
+            self.fregs[reg_a] = self.regs[reg_b] as i32 as f32;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 63 reg_a freg_b: convert the floating-point value stored in register freg_b to
+     * a signed integer, rounding towards zero and saturating, and store it into
+     * register reg_a. NaN is converted to 0.
      */
-    fn out<T: Write>(&mut self, fd: &mut T) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
+    #[cfg(feature = "fp")]
+    fn fcvti(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
 
+        if reg_a < NREGS && reg_b < NFREGS {
+            self.set_reg(reg_a, self.fregs[reg_b] as i32 as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 64 freg_a: output the floating-point value stored in register freg_a in decimal.
+     */
+    #[cfg(feature = "fp")]
+    fn fout<T: Write + ?Sized>(
+        &mut self,
+        operands: Operands,
+        fd: &mut T,
+    ) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
         self.ip_inc(2);
 
-        if reg_a < NREGS {
-            let character_v = 0x000000FF & self.regs[reg_a];
-            let character = char::from_u32(character_v as u32).unwrap();
-            let result = write!(fd, "{}", character);
+        if reg_a < NFREGS {
+            let result = self.print(fd, self.fregs[reg_a].to_string().as_bytes());
 
             match result {
-                Ok(_) => return Ok(false),
-                Err(_) => return Err(MachineError::NonExistingFormat),
+                Ok(_) => return Ok(StepOutcome::Continue),
+                Err(error) => return Err(MachineError::io(error)),
             }
         }
-        return Err(MachineError::NonExistingRegister);
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 65 reg_a: reverse the order of the bytes of register reg_a.
+     */
+    fn bswap(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+
+        self.ip_inc(2);
+
+        if reg_a < NREGS {
+            self.set_reg(reg_a, self.regs[reg_a].swap_bytes())?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 7: exit the current program.
+     * 66 reg_a reg_b reg_c: store the lowest of registers reg_b and reg_c, interpreted
+     * as signed values, into register reg_a.
      */
-    fn exit(&mut self) -> Result<bool, MachineError> {
-        self.ip_inc(1);
-        return Ok(true);
+    fn min(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(
+                reg_a,
+                i32::min(self.regs[reg_b] as i32, self.regs[reg_c] as i32) as u32,
+            )?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
     }
 
     /**
-     * 8 reg_a: output the signed number stored in register reg_a in decimal.
+     * 67 reg_a reg_b reg_c: store the greatest of registers reg_b and reg_c,
+     * interpreted as signed values, into register reg_a.
      */
-    fn out_number<T: Write>(&mut self, fd: &mut T) -> Result<bool, MachineError> {
-        let reg_a: usize = self.memory[self.ip_sum(1)] as usize;
-        self.ip_inc(2);
+    fn max(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
 
-        if reg_a < NREGS {
-            let decimal = self.regs[reg_a] as i32;
-            let result = write!(fd, "{}", decimal);
+        self.ip_inc(4);
 
-            match result {
-                Ok(_) => return Ok(false),
-                Err(_) => return Err(MachineError::NonExistingFormat),
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            self.set_reg(
+                reg_a,
+                i32::max(self.regs[reg_b] as i32, self.regs[reg_c] as i32) as u32,
+            )?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 68 reg_a reg_b: store the absolute value of the signed register reg_b into
+     * register reg_a. The absolute value of i32::MIN wraps around to i32::MIN.
+     */
+    fn abs(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            self.set_reg(reg_a, (self.regs[reg_b] as i32).wrapping_abs() as u32)?;
+            return Ok(StepOutcome::Continue);
+        }
+
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 69 reg_a reg_b reg_c: compare the 32-bit content of the memory at address
+     * pointed by register reg_a with register reg_b. If they are equal, store
+     * register reg_c at this address. In every case, the previous content of the
+     * memory is loaded into register reg_b, and the zero flag tells whether the
+     * store happened. The whole instruction is executed as a single atomic step.
+     */
+    fn cas(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+        let reg_c: usize = operands[2] as usize;
+
+        self.ip_inc(4);
+
+        if reg_a < NREGS && reg_b < NREGS && reg_c < NREGS {
+            let range = self.memory_range(self.regs[reg_a], 4)?;
+            let mut bytes: [u8; 4] = [0; 4];
+            bytes.copy_from_slice(&self.memory[range.clone()]);
+            self.read_memory(range.clone());
+            let previous = u32::from_le_bytes(bytes);
+            let swapped = previous == self.regs[reg_b];
+            if swapped {
+                self.write_bytes(range.start, &self.regs[reg_c].to_le_bytes());
             }
+            self.flags.zero = swapped;
+            self.set_reg(reg_b, previous)?;
+            return Ok(StepOutcome::Continue);
+        }
+        return Err(MachineError::operand_register());
+    }
+
+    /**
+     * 70 reg_a reg_b: store the number of instructions executed before this one
+     * into registers reg_a (32 low-order bits) and reg_b (32 high-order bits).
+     */
+    fn rdinsn(&mut self, operands: Operands) -> Result<StepOutcome, MachineError> {
+        let reg_a: usize = operands[0] as usize;
+        let reg_b: usize = operands[1] as usize;
+
+        self.ip_inc(3);
+
+        if reg_a < NREGS && reg_b < NREGS {
+            self.set_reg(reg_a, self.instructions_retired as u32)?;
+            self.set_reg(reg_b, (self.instructions_retired >> 32) as u32)?;
+            return Ok(StepOutcome::Continue);
         }
+        return Err(MachineError::operand_register());
+    }
+}
+
+// The handlers of the built-in instructions, by opcode, the other opcodes
+// being invalid
+fn builtin_handlers() -> Box<[Handler; 256]> {
+    let mut handlers: Box<[BuiltinHandler; 256]> = Box::new([invalid_instruction; 256]);
+    handlers[1] = |machine, operands, _, _| machine.move_if(operands);
+    handlers[2] = |machine, operands, _, _| machine.store(operands);
+    handlers[3] = |machine, operands, _, _| machine.load(operands);
+    handlers[4] = |machine, operands, _, _| machine.loadimm(operands);
+    handlers[5] = |machine, operands, _, _| machine.sub(operands);
+    handlers[6] = |machine, operands, _, output| machine.out(operands, output);
+    handlers[7] = |machine, _, _, _| machine.exit();
+    handlers[8] = |machine, operands, _, output| machine.out_number(operands, output);
+    handlers[9] = |machine, operands, _, _| machine.add(operands);
+    handlers[10] = |machine, operands, _, _| machine.mul(operands);
+    handlers[11] = |machine, operands, _, _| machine.div(operands);
+    handlers[12] = |machine, operands, _, _| machine.divu(operands);
+    handlers[13] = |machine, operands, _, _| machine.modulo(operands);
+    handlers[14] = |machine, operands, _, _| machine.modu(operands);
+    handlers[15] = |machine, operands, _, _| machine.and(operands);
+    handlers[16] = |machine, operands, _, _| machine.or(operands);
+    handlers[17] = |machine, operands, _, _| machine.xor(operands);
+    handlers[18] = |machine, operands, _, _| machine.not(operands);
+    handlers[19] = |machine, operands, _, _| machine.shl(operands);
+    handlers[20] = |machine, operands, _, _| machine.shr(operands);
+    handlers[21] = |machine, operands, _, _| machine.sar(operands);
+    handlers[22] = |machine, operands, _, _| machine.rol(operands);
+    handlers[23] = |machine, operands, _, _| machine.ror(operands);
+    handlers[24] = |machine, operands, _, _| machine.jmp(operands);
+    handlers[25] = |machine, operands, _, _| machine.jrel(operands);
+    handlers[26] = |machine, operands, _, _| machine.beq(operands);
+    handlers[27] = |machine, operands, _, _| machine.bne(operands);
+    handlers[28] = |machine, operands, _, _| machine.blt(operands);
+    handlers[29] = |machine, operands, _, _| machine.bge(operands);
+    handlers[30] = |machine, operands, _, _| machine.call(operands);
+    handlers[31] = |machine, _, _, _| machine.ret();
+    handlers[32] = |machine, operands, _, _| machine.push(operands);
+    handlers[33] = |machine, operands, _, _| machine.pop(operands);
+    handlers[34] = |machine, operands, input, output| machine.input(operands, input, output);
+    handlers[35] = |machine, operands, input, output| machine.in_number(operands, input, output);
+    handlers[36] = |machine, operands, _, _| machine.loadimm32(operands);
+    handlers[37] = |machine, operands, _, _| machine.loadb(operands);
+    handlers[38] = |machine, operands, _, _| machine.loadbs(operands);
+    handlers[39] = |machine, operands, _, _| machine.storeb(operands);
+    handlers[40] = |machine, operands, _, _| machine.loadh(operands);
+    handlers[41] = |machine, operands, _, _| machine.loadhs(operands);
+    handlers[42] = |machine, operands, _, _| machine.storeh(operands);
+    handlers[43] = |machine, operands, _, _| machine.cmp(operands);
+    handlers[44] = |machine, operands, _, _| machine.halt(operands);
+    handlers[45] = |machine, operands, _, _| machine.mov(operands);
+    handlers[46] = |machine, operands, _, _| machine.loadx(operands);
+    handlers[47] = |machine, operands, _, _| machine.storex(operands);
+    handlers[48] = |machine, operands, _, _| machine.syscall(operands);
+    handlers[49] = |machine, _, _, _| machine.brk();
+    handlers[50] = |machine, operands, _, _| machine.rand(operands);
+    handlers[51] = |machine, operands, _, output| machine.outs(operands, output);
+    handlers[52] = |machine, operands, _, _| machine.memcpy(operands);
+    handlers[53] = |machine, operands, _, _| machine.memset(operands);
+    handlers[54] = |machine, operands, _, _| machine.clz(operands);
+    handlers[55] = |machine, operands, _, _| machine.popcnt(operands);
+    handlers[56] = |machine, operands, _, _| machine.adc(operands);
+    handlers[57] = |machine, operands, _, _| machine.sbb(operands);
+    #[cfg(feature = "fp")]
+    {
+        handlers[58] = |machine, operands, _, _| machine.fadd(operands);
+        handlers[59] = |machine, operands, _, _| machine.fsub(operands);
+        handlers[60] = |machine, operands, _, _| machine.fmul(operands);
+        handlers[61] = |machine, operands, _, _| machine.fdiv(operands);
+        handlers[62] = |machine, operands, _, _| machine.fcvt(operands);
+        handlers[63] = |machine, operands, _, _| machine.fcvti(operands);
+        handlers[64] = |machine, operands, _, output| machine.fout(operands, output);
+    }
+    handlers[65] = |machine, operands, _, _| machine.bswap(operands);
+    handlers[66] = |machine, operands, _, _| machine.min(operands);
+    handlers[67] = |machine, operands, _, _| machine.max(operands);
+    handlers[68] = |machine, operands, _, _| machine.abs(operands);
+    handlers[69] = |machine, operands, _, _| machine.cas(operands);
+    handlers[70] = |machine, operands, _, _| machine.rdinsn(operands);
+    return Box::new(handlers.map(Handler::Builtin));
+}
+
+// A future pending the first time it's polled, waking its task at once so
+// that the executor runs the other tasks before polling it again
+#[cfg(feature = "async")]
+struct YieldNow(bool);
 
-        return Err(MachineError::NonExistingRegister);
+#[cfg(feature = "async")]
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        context.waker().wake_by_ref();
+        return Poll::Pending;
     }
 }
+
+// The function executing a pair of fused instructions, given the machine
+// with its IP at the first one and the operands of both
+type FusedHandler = fn(&mut Machine, Operands, Operands) -> Result<StepOutcome, MachineError>;
+
+// The pairs of opcodes executed by one handler: the loads of an immediate
+// value or the arithmetic instructions followed by the ones using them
+const FUSED: [(u8, u8, FusedHandler); 10] = [
+    (4, 5, |machine, first, second| {
+        machine.loadimm(first)?;
+        return machine.sub(second);
+    }),
+    (4, 9, |machine, first, second| {
+        machine.loadimm(first)?;
+        return machine.add(second);
+    }),
+    (5, 1, |machine, first, second| {
+        machine.sub(first)?;
+        return machine.move_if(second);
+    }),
+    (9, 1, |machine, first, second| {
+        machine.add(first)?;
+        return machine.move_if(second);
+    }),
+    (5, 26, |machine, first, second| {
+        machine.sub(first)?;
+        return machine.beq(second);
+    }),
+    (5, 27, |machine, first, second| {
+        machine.sub(first)?;
+        return machine.bne(second);
+    }),
+    (5, 28, |machine, first, second| {
+        machine.sub(first)?;
+        return machine.blt(second);
+    }),
+    (5, 29, |machine, first, second| {
+        machine.sub(first)?;
+        return machine.bge(second);
+    }),
+    (9, 27, |machine, first, second| {
+        machine.add(first)?;
+        return machine.bne(second);
+    }),
+    (9, 28, |machine, first, second| {
+        machine.add(first)?;
+        return machine.blt(second);
+    }),
+];
+
+// The handler of the opcodes which aren't instructions
+fn invalid_instruction(
+    _: &mut Machine,
+    _: Operands,
+    _: &mut dyn Read,
+    _: &mut dyn Write,
+) -> Result<StepOutcome, MachineError> {
+    return Err(MachineError::NonExistingInstruction { ip: 0, opcode: 0 });
+}
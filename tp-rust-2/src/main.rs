@@ -1,19 +1,290 @@
-use interpreter::{Machine, MachineError};
+use interpreter::{
+    disassemble, verify, DebugInfo, Debugger, FormatOptions, Image, IoLog, Machine, MachineError,
+    Profiler, RecordingReader, RecordingWriter, Tracer, Tui,
+};
+use std::cell::RefCell;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// The number of bytes printed by the program written at once
+const OUTPUT_BUFFER_SIZE: usize = 8192;
 
 fn main() -> Result<(), MachineError> {
-    // Take a filename as argument on the command line
-    let filename = std::env::args().nth(1).unwrap();
+    // Take a filename as argument on the command line, after `dbg` to run
+    // it in the debugger, or after `tui` to run it in its terminal interface
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mode = match args.first().map(|arg| arg.as_str()) {
+        Some(mode @ ("dbg" | "tui")) => Some(mode.to_string()),
+        _ => None,
+    };
+    if mode.is_some() {
+        args.remove(0);
+    }
+
+    // `dbg --script cmds.txt` runs the debugger commands of the file
+    // instead of reading them on the standard input
+    let script = if mode.as_deref() == Some("dbg") && args.first().is_some_and(|a| a == "--script")
+    {
+        args.remove(0);
+        Some(std::fs::read_to_string(args.remove(0)).unwrap())
+    } else {
+        None
+    };
+
+    // `--trace-file trace.jsonl` records the executed instructions as JSON
+    // Lines, or in binary when the file name ends with `.bin`
+    let trace_file = take_option(&mut args, "--trace-file");
+
+    // `--record io.log` saves what the program reads and prints, and
+    // `--replay io.log` runs it again on the recorded input, failing if it
+    // doesn't print the recorded output
+    let record_file = take_option(&mut args, "--record");
+    let replayed = take_option(&mut args, "--replay").map(|file| {
+        let log = IoLog::parse(&std::fs::read(&file).unwrap());
+        log.unwrap_or_else(|error| {
+            eprintln!("cannot replay {}: {}", file, error);
+            std::process::exit(1);
+        })
+    });
+
+    // `--coverage report.txt` writes the disassembly of the program with
+    // the number of times every instruction has been executed
+    let coverage_file = take_option(&mut args, "--coverage");
+
+    // `--profile stacks.folded` samples the call stacks of the program, in
+    // the folded format of the flamegraph tools
+    let profile_file = take_option(&mut args, "--profile");
+
+    // `--heatmap heatmap.csv` writes the number of reads and writes of
+    // every byte of the memory as CSV, or as a PPM image when the file name
+    // ends with `.ppm`
+    let heatmap_file = take_option(&mut args, "--heatmap");
+
+    // `--stats` prints how many times every instruction has been executed
+    // on the standard error once the program stops, with the pairs fused
+    // and the lookups of the basic blocks
+    let print_stats = take_flag(&mut args, "--stats");
+
+    // `--jit` compiles the hot basic blocks of the program to native code,
+    // with the `jit` feature, unless a tool needs to see every instruction
+    let jit = take_flag(&mut args, "--jit");
+    let filename = args.into_iter().next().unwrap();
 
     // Read content to buffer
     let mut fs = File::open(&filename).unwrap();
     let mut buffer = Vec::new();
     fs.read_to_end(&mut buffer).unwrap();
 
-    // Create a machine with this memory content
-    let mut machine = Machine::new(&buffer);
+    // The debug info written by `rvm-asm -g`, if it is next to the program
+    let debug_info = DebugInfo::load(Path::new(&filename).with_extension("dbg")).ok();
+
+    // The debugger also takes the programs the verifier would refuse, to
+    // look at where they fault
+    if let Some(mode) = mode {
+        let machine = if Image::is_image(&buffer) {
+            Machine::load_image(&buffer)?
+        } else {
+            load_flat(&buffer, &filename)
+        };
+        let mut debugger = Debugger::new(machine);
+        if let Some(debug_info) = debug_info {
+            debugger.set_debug_info(debug_info);
+        }
+        let input = std::io::stdin().lock();
+        let mut output = std::io::stdout().lock();
+        if let Some(script) = script {
+            debugger.run_script(&script, &mut output).unwrap();
+            if debugger.failures() > 0 {
+                eprintln!("{} of the script commands failed", debugger.failures());
+                std::process::exit(1);
+            }
+        } else if mode == "tui" {
+            Tui::new(debugger).run(input, &mut output).unwrap();
+        } else {
+            debugger.run(input, &mut output).unwrap();
+        }
+        return Ok(());
+    }
+
+    // Create a machine with this memory content. A flat program is verified
+    // first, the verifier expecting it to be loaded and started at 0.
+    let verified = if Image::is_image(&buffer) {
+        Ok(Machine::load_image(&buffer)?)
+    } else {
+        verify(&buffer).map(|()| load_flat(&buffer, &filename))
+    };
+    let mut machine = match verified {
+        Ok(machine) => machine,
+        Err(errors) => {
+            for error in errors {
+                match &debug_info {
+                    Some(debug_info) => {
+                        let location = debug_info.describe(error.address() as u32);
+                        eprintln!("invalid program at {}: {}", location, error)
+                    }
+                    None => eprintln!("invalid program: {}", error),
+                }
+            }
+            std::process::exit(1);
+        }
+    };
+
+    // Run the machine until the end, telling where it faulted
+    if trace_file.is_some() {
+        machine.attach_tracer(Tracer::new());
+    }
+    if coverage_file.is_some() {
+        machine.enable_coverage();
+    }
+    if profile_file.is_some() {
+        machine.attach_profiler(Profiler::new());
+    }
+    if heatmap_file.is_some() {
+        machine.enable_heatmap();
+    }
+    if jit {
+        enable_jit(&mut machine);
+    }
+    let log = RefCell::new(IoLog::new());
+    let mut input: Box<dyn Read> = match &replayed {
+        Some(replayed) => Box::new(replayed.replay_input()),
+        None => Box::new(RecordingReader::new(std::io::stdin().lock(), &log)),
+    };
+    let mut output = RecordingWriter::new(std::io::stdout().lock(), &log);
+    // The output is written by blocks, and before reading the input
+    machine.set_output_buffer(OUTPUT_BUFFER_SIZE);
+    let result = machine.run_with_io(&mut input, &mut output);
+    // The faulting instruction isn't executed, leaving the IP at it
+    if let (Err(_), Some(debug_info)) = (&result, &debug_info) {
+        eprintln!("fault at {}", debug_info.describe(machine.regs()[0]));
+    }
+    // A fault leaves what the program printed before it in the buffer
+    if let Err(error) = machine.flush_output(&mut output) {
+        eprintln!("error: cannot write the output: {}", error);
+        std::process::exit(1);
+    }
+    if let (Some(trace_file), Some(tracer)) = (trace_file, machine.tracer()) {
+        let mut file = std::io::BufWriter::new(File::create(&trace_file).unwrap());
+        if trace_file.ends_with(".bin") {
+            tracer.write_binary(&mut file).unwrap();
+        } else {
+            tracer.write_json_lines(&mut file).unwrap();
+        }
+        file.flush().unwrap();
+    }
+    if let (Some(profile_file), Some(profiler)) = (profile_file, machine.profiler()) {
+        let mut file = std::io::BufWriter::new(File::create(&profile_file).unwrap());
+        profiler
+            .write_folded(debug_info.as_ref(), &mut file)
+            .unwrap();
+        file.flush().unwrap();
+    }
+    if let (Some(heatmap_file), Some(heatmap)) = (heatmap_file, machine.heatmap()) {
+        let mut file = std::io::BufWriter::new(File::create(&heatmap_file).unwrap());
+        if heatmap_file.ends_with(".ppm") {
+            heatmap.write_ppm(&mut file).unwrap();
+        } else {
+            heatmap.write_csv(&mut file).unwrap();
+        }
+        file.flush().unwrap();
+    }
+    if print_stats {
+        eprint!("{}", machine.stats().summary());
+        if machine.fusion_stats().hits() > 0 {
+            eprint!("{}", machine.fusion_stats().summary(machine.stats()));
+        }
+        let blocks = machine.block_stats();
+        if blocks.hits + blocks.misses > 0 {
+            eprint!("{}", blocks.summary());
+        }
+        #[cfg(feature = "jit")]
+        if machine.compiled_blocks() > 0 {
+            eprintln!(
+                "{:<20} {:>10}",
+                "compiled blocks",
+                machine.compiled_blocks()
+            );
+        }
+    }
+    if let (Some(coverage_file), Some(coverage)) = (coverage_file, machine.coverage()) {
+        let image = match Image::is_image(&buffer) {
+            true => Image::parse(&buffer)?,
+            false => Image::from_program(&buffer),
+        };
+        let options = FormatOptions {
+            symbols: debug_info.as_ref().map(|d| d.symbols()),
+            aliases: false,
+        };
+        let instructions: Vec<_> = image
+            .segments
+            .iter()
+            .flat_map(|segment| {
+                disassemble(
+                    machine.memory(),
+                    segment.address as usize,
+                    segment.data.len(),
+                )
+            })
+            .collect();
+        let report = coverage.report(&instructions, &options);
+        std::fs::write(coverage_file, report).unwrap();
+    }
+    if let Some(record_file) = record_file {
+        std::fs::write(record_file, log.borrow().to_bytes()).unwrap();
+    }
+    if replayed.is_some_and(|replayed| replayed.output() != log.borrow().output()) {
+        eprintln!("the output differs from the recorded one");
+        std::process::exit(1);
+    }
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+
+    // Forward the program exit code to the shell
+    match machine.exit_code() {
+        Some(0) | None => Ok(()),
+        Some(code) => std::process::exit(code as i32),
+    }
+}
+
+// Remove the `name value` option from the arguments, returning its value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == name)?;
+    args.remove(i);
+    Some(args.remove(i))
+}
+
+// Remove the `name` flag from the arguments, returning whether it was there
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let Some(i) = args.iter().position(|arg| arg == name) else {
+        return false;
+    };
+    args.remove(i);
+    true
+}
+
+// Compile the hot basic blocks of the program run by `machine`, which is
+// interpreted if the JIT doesn't support the host
+#[cfg(feature = "jit")]
+fn enable_jit(machine: &mut Machine) {
+    if !machine.enable_jit() {
+        eprintln!("warning: the JIT doesn't support this host");
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn enable_jit(_: &mut Machine) {
+    eprintln!("error: --jit needs the jit feature");
+    std::process::exit(1);
+}
 
-    // Run the machine until the end
-    machine.run()
+// Create a machine with the flat program `buffer` read from `filename`,
+// exiting if it doesn't fit in the memory
+fn load_flat(buffer: &[u8], filename: &str) -> Machine {
+    Machine::try_new(buffer).unwrap_or_else(|error| {
+        eprintln!("{}: {}", filename, error);
+        std::process::exit(1);
+    })
 }
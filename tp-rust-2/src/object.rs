@@ -0,0 +1,156 @@
+use crate::asm::encode_immediate;
+use crate::isa::Operand;
+use crate::symbols::SymbolTable;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+/// A relocatable program produced by [assemble_object](crate::assemble_object),
+/// to be combined with other ones by [link].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Object {
+    pub sections: Vec<Section>,
+    /// The labels visible to the other objects.
+    pub globals: BTreeSet<String>,
+}
+
+/// A named part of an object, which is placed at its final address by
+/// the linker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// The labels defined in the section, with their offset from its start.
+    pub symbols: BTreeMap<String, u32>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// A value of a section to be filled by the linker, once the address of
+/// its target is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// The offset of the value from the start of the section.
+    pub offset: u32,
+    /// How the value is encoded. For [Operand::Rel16], the value is relative
+    /// to its own address.
+    pub kind: Operand,
+    pub target: RelocationTarget,
+    /// The number added to the target address.
+    pub addend: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelocationTarget {
+    Section(usize), // The start of a section of the same object, by index
+    Symbol(String), // A global label of another object
+}
+
+// An error found while linking objects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    UndefinedSymbol(String),           // No object defines this global label
+    DuplicateSymbol(String),           // Global label defined by several objects
+    RelocationOutOfRange(String, i64), // The target and value that do not fit
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkError::UndefinedSymbol(s) => write!(f, "symbol `{}` is not defined", s),
+            LinkError::DuplicateSymbol(s) => write!(f, "symbol `{}` is defined twice", s),
+            LinkError::RelocationOutOfRange(s, v) => {
+                write!(f, "value {} of `{}` does not fit in the operand", v, s)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Combine `objects` into a program image ready to be given to
+/// [Machine::try_new](crate::Machine::try_new).
+///
+/// The sections with the same name are placed together, in the order of
+/// `objects`, and the groups of sections follow each other in the order
+/// their name first appears. Then the relocations are resolved.
+pub fn link(objects: &[Object]) -> Result<Vec<u8>, LinkError> {
+    return Ok(link_with_symbols(objects)?.0);
+}
+
+/// Link `objects` like [link], and also return the final address of their
+/// global labels.
+pub fn link_with_symbols(objects: &[Object]) -> Result<(Vec<u8>, SymbolTable), LinkError> {
+    // Lay out the sections
+    let mut names: Vec<&str> = Vec::new();
+    for section in objects.iter().flat_map(|object| &object.sections) {
+        if !names.contains(&section.name.as_str()) {
+            names.push(&section.name);
+        }
+    }
+    let mut bases: Vec<Vec<usize>> = objects
+        .iter()
+        .map(|object| vec![0; object.sections.len()])
+        .collect();
+    let mut address: usize = 0;
+    for name in &names {
+        for (object, bases) in objects.iter().zip(&mut bases) {
+            for (index, section) in object.sections.iter().enumerate() {
+                if section.name == *name {
+                    bases[index] = address;
+                    address += section.data.len();
+                }
+            }
+        }
+    }
+
+    // Find the address of the global labels
+    let mut globals: HashMap<&str, usize> = HashMap::new();
+    for (object, bases) in objects.iter().zip(&bases) {
+        for name in &object.globals {
+            let address = object
+                .sections
+                .iter()
+                .zip(bases)
+                .find_map(|(section, base)| section.symbols.get(name).map(|o| base + *o as usize))
+                .ok_or_else(|| LinkError::UndefinedSymbol(name.clone()))?;
+            if globals.insert(name, address).is_some() {
+                return Err(LinkError::DuplicateSymbol(name.clone()));
+            }
+        }
+    }
+
+    // Copy the sections and resolve their relocations
+    let mut program: Vec<u8> = vec![0; address];
+    for (object, bases) in objects.iter().zip(&bases) {
+        for (section, base) in object.sections.iter().zip(bases) {
+            program[*base..*base + section.data.len()].copy_from_slice(&section.data);
+            for relocation in &section.relocations {
+                let (name, target) = match &relocation.target {
+                    RelocationTarget::Section(index) => {
+                        (&object.sections[*index].name, bases[*index])
+                    }
+                    RelocationTarget::Symbol(name) => match globals.get(name.as_str()) {
+                        Some(address) => (name, *address),
+                        None => return Err(LinkError::UndefinedSymbol(name.clone())),
+                    },
+                };
+                let place = base + relocation.offset as usize;
+                let mut value = target as i64 + relocation.addend;
+                if relocation.kind == Operand::Rel16 {
+                    value -= place as i64;
+                }
+                let mut bytes: Vec<u8> = Vec::new();
+                encode_immediate(relocation.kind, value, &mut bytes)
+                    .map_err(|_| LinkError::RelocationOutOfRange(name.clone(), value))?;
+                program[place..place + bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+    }
+
+    let mut symbols = SymbolTable::new();
+    let mut names: Vec<(&str, usize)> = globals.into_iter().collect();
+    names.sort();
+    for (name, address) in names {
+        symbols.insert(name, address as u32);
+    }
+    return Ok((program, symbols));
+}
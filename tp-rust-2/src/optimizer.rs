@@ -0,0 +1,275 @@
+use crate::disasm::{decode, DecodedInsn};
+use crate::isa::{instruction_info, Operand};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// The opcodes the optimizer handles specifically
+const LOADIMM: u8 = 4;
+const SUB: u8 = 5;
+const CALL: u8 = 30;
+const PUSH: u8 = 32;
+const POP: u8 = 33;
+const MOV: u8 = 45;
+
+// The instructions accessing the memory through an address held in a
+// register, which may point into the program
+const MEMORY_ACCESSES: &[u8] = &[2, 3, 37, 38, 39, 40, 41, 42, 46, 47, 48, 51, 52, 53, 69];
+
+// The registers whose content is a code address: the instruction pointer
+// and the link register
+const IP: u32 = 0;
+const LR: u32 = 14;
+
+/// What [optimize_with_stats] did to a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptimizeStats {
+    pub size_before: usize,
+    pub size_after: usize,
+    /// The number of reachable instructions.
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+    pub moves_removed: usize,
+    pub constants_folded: usize,
+    pub unreachable_bytes_removed: usize,
+    /// Whether the program was returned unchanged, as it can't be optimized
+    /// safely. The other counts are then zero, besides the sizes.
+    pub skipped: bool,
+}
+
+impl fmt::Display for OptimizeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.skipped {
+            return write!(f, "{} bytes, not optimized safely", self.size_before);
+        }
+        return write!(
+            f,
+            "{} -> {} bytes, {} -> {} instructions ({} moves removed, {} constants folded, {} unreachable bytes removed)",
+            self.size_before,
+            self.size_after,
+            self.instructions_before,
+            self.instructions_after,
+            self.moves_removed,
+            self.constants_folded,
+            self.unreachable_bytes_removed
+        );
+    }
+}
+
+/// Optimize a program image, without changing what it does besides the
+/// number of instructions it executes:
+///   - the moves of a register to itself, or back to the register it was
+///     just copied from, are removed
+///   - a `sub` of two registers just loaded with `loadimm` is replaced by
+///     the loading of the result, when it fits in a `loadimm`
+///   - the code that can't be reached, such as the code following an
+///     `exit`, is removed
+///
+/// The jump, branch and call targets are updated. The program is returned
+/// unchanged when it can't be optimized safely: when a reachable
+/// instruction is invalid or accesses the memory through an address held in a
+/// register (which may point to data of the program), when it uses the
+/// instruction pointer, or when it uses the link register other than by
+/// saving and restoring it.
+pub fn optimize(program: Vec<u8>) -> Vec<u8> {
+    return optimize_with_stats(program).0;
+}
+
+/// Optimize `program` like [optimize], and also tell how it was changed.
+pub fn optimize_with_stats(program: Vec<u8>) -> (Vec<u8>, OptimizeStats) {
+    let mut stats = OptimizeStats {
+        size_before: program.len(),
+        size_after: program.len(),
+        ..Default::default()
+    };
+    let Some((instructions, targets)) = reachable(&program) else {
+        stats.skipped = true;
+        return (program, stats);
+    };
+    stats.instructions_before = instructions.len();
+
+    // Rewrite the instructions, keeping the address they come from
+    let mut output: Vec<(usize, DecodedInsn)> = Vec::new();
+    let mut index = 0;
+    while index < instructions.len() {
+        let window = following(&instructions[index..], &targets);
+        let insn = &instructions[index];
+        let opcode = insn.bytes[0];
+        let ops = &insn.operands;
+        if opcode == MOV && ops[0] == ops[1] {
+            stats.moves_removed += 1;
+            index += 1;
+            continue;
+        }
+        if let [_, second, ..] = window {
+            let moved_back = || second.operands[..] == [ops[1], ops[0]];
+            if opcode == MOV && second.bytes[0] == MOV && moved_back() {
+                output.push((insn.address, insn.clone()));
+                stats.moves_removed += 1;
+                index += 2;
+                continue;
+            }
+        }
+        if let Some(folded) = fold_constant(window) {
+            output.extend(folded.into_iter().map(|i| (insn.address, i)));
+            stats.constants_folded += 1;
+            index += 3;
+            continue;
+        }
+        output.push((insn.address, insn.clone()));
+        index += 1;
+    }
+    stats.instructions_after = output.len();
+    let reachable_size: usize = instructions.iter().map(|i| i.bytes.len()).sum();
+    stats.unreachable_bytes_removed = program.len() - reachable_size;
+
+    // Lay out the instructions, and map every address they come from to
+    // its new address
+    let mut addresses: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut address = 0;
+    for (origin, insn) in &output {
+        addresses.entry(*origin).or_insert(address);
+        address += insn.bytes.len();
+    }
+    let end = address;
+    let relocate = |target: usize| match addresses.range(target..).next() {
+        Some((_, address)) => *address,
+        None => end,
+    };
+
+    let mut optimized: Vec<u8> = Vec::new();
+    for (_, insn) in &output {
+        let info = insn.info.unwrap();
+        let next = optimized.len() + insn.bytes.len();
+        optimized.push(info.opcode);
+        for (kind, value) in info.operands.iter().zip(&insn.operands) {
+            let value = match kind {
+                Operand::Addr16 => relocate(*value as usize) as u32,
+                Operand::Rel16 => {
+                    let target = insn.target().unwrap() as usize;
+                    (relocate(target) as i64 - next as i64) as u32
+                }
+                _ => *value,
+            };
+            optimized.extend_from_slice(&value.to_le_bytes()[..kind.size()]);
+        }
+    }
+    stats.size_after = optimized.len();
+    return (optimized, stats);
+}
+
+// Find the instructions that can be reached from address 0, sorted by
+// address, and the addresses the execution can jump to. Return `None`
+// if they can't be optimized safely.
+fn reachable(program: &[u8]) -> Option<(Vec<DecodedInsn>, BTreeSet<usize>)> {
+    let mut instructions: BTreeMap<usize, DecodedInsn> = BTreeMap::new();
+    let mut targets: BTreeSet<usize> = BTreeSet::from([0]);
+    let mut pending: Vec<usize> = vec![0];
+    while let Some(address) = pending.pop() {
+        if instructions.contains_key(&address) || address == program.len() {
+            continue;
+        }
+        if address > program.len() {
+            return None;
+        }
+        let insn = decode(&program[address..], address);
+        let info = insn.info?;
+        let end = address + insn.bytes.len();
+
+        // Instructions must not overlap
+        if let Some((start, previous)) = instructions.range(..address).next_back() {
+            if start + previous.bytes.len() > address {
+                return None;
+            }
+        }
+        if instructions.range(address..end).next().is_some() {
+            return None;
+        }
+
+        let opcode = info.opcode;
+        if MEMORY_ACCESSES.contains(&opcode) {
+            return None;
+        }
+        for (kind, value) in info.operands.iter().zip(&insn.operands) {
+            let invalid = match kind {
+                Operand::Reg => {
+                    *value >= 16
+                        || *value == IP
+                        || (*value == LR && opcode != PUSH && opcode != POP)
+                }
+                Operand::FReg => *value >= 8,
+                _ => false,
+            };
+            if invalid {
+                return None;
+            }
+        }
+
+        if let Some(target) = insn.target() {
+            let target = usize::try_from(target).ok()?;
+            targets.insert(target);
+            pending.push(target);
+        }
+        if opcode == CALL {
+            // The return address
+            targets.insert(end);
+        }
+        if info.falls_through() {
+            pending.push(end);
+        }
+        instructions.insert(address, insn);
+    }
+    return Some((instructions.into_values().collect(), targets));
+}
+
+// The instructions starting `instructions` which are executed one after
+// the other, the execution not being able to jump to any but the first
+fn following<'a>(instructions: &'a [DecodedInsn], targets: &BTreeSet<usize>) -> &'a [DecodedInsn] {
+    let mut count = 1;
+    while count < instructions.len() {
+        let previous = &instructions[count - 1];
+        let insn = &instructions[count];
+        if previous.address + previous.bytes.len() != insn.address
+            || targets.contains(&insn.address)
+            || !previous.info.unwrap().falls_through()
+        {
+            break;
+        }
+        count += 1;
+    }
+    return &instructions[..count];
+}
+
+// Fold `loadimm ra, x ; loadimm rb, y ; sub rd, ra, rb` at the beginning of
+// `window` into loads of constants
+fn fold_constant(window: &[DecodedInsn]) -> Option<Vec<DecodedInsn>> {
+    let [first, second, third, ..] = window else {
+        return None;
+    };
+    if first.bytes[0] != LOADIMM || second.bytes[0] != LOADIMM || third.bytes[0] != SUB {
+        return None;
+    }
+    let (a, x) = (first.operands[0], first.operands[1] as u16 as i16 as i32);
+    let (b, y) = (second.operands[0], second.operands[1] as u16 as i16 as i32);
+    let d = third.operands[0];
+    if a == b || third.operands[1] != a || third.operands[2] != b {
+        return None;
+    }
+    let value = i16::try_from(x.wrapping_sub(y)).ok()?;
+    let load = |register: u32, value: i16| {
+        let mut bytes = vec![LOADIMM, register as u8];
+        bytes.extend_from_slice(&value.to_le_bytes());
+        return DecodedInsn {
+            address: first.address,
+            bytes,
+            info: instruction_info(LOADIMM),
+            operands: vec![register, value as u16 as u32],
+        };
+    };
+    if d == a {
+        return Some(vec![second.clone(), load(a, value)]);
+    }
+    if d == b {
+        return Some(vec![first.clone(), load(b, value)]);
+    }
+    return Some(vec![first.clone(), second.clone(), load(d, value)]);
+}
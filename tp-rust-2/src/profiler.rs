@@ -0,0 +1,100 @@
+use crate::debuginfo::DebugInfo;
+use crate::machine::Frame;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A sampler of the call stacks of the machine it is attached to (see
+/// [attach_profiler](crate::Machine::attach_profiler)), recording before
+/// every sampled instruction the return addresses of the calls not
+/// returned yet and the IP. Only the instructions executed without a fault
+/// are sampled.
+///
+/// The samples are written in the folded stacks format read by flamegraph
+/// tools such as `inferno-flamegraph`, a line per stack such as
+/// `main;double;inc 12`, by [write_folded](Profiler::write_folded).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profiler {
+    period: u64,                      // One instruction out of `period` is sampled
+    executed: u64,                    // The instructions executed since the profiler was created
+    samples: BTreeMap<Vec<u32>, u64>, // The number of samples of every stack
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        return Profiler::with_period(1);
+    }
+}
+
+impl Profiler {
+    /// A profiler sampling every instruction.
+    pub fn new() -> Self {
+        return Profiler::default();
+    }
+
+    /// A profiler sampling one instruction out of `period`, which must
+    /// not be 0.
+    pub fn with_period(period: u64) -> Self {
+        assert!(period > 0, "the sampling period must not be 0");
+        return Profiler {
+            period,
+            executed: 0,
+            samples: BTreeMap::new(),
+        };
+    }
+
+    /// Whether the next instruction executed will be sampled.
+    pub fn is_due(&self) -> bool {
+        return self.executed.is_multiple_of(self.period);
+    }
+
+    /// Count an executed instruction at `ip`, sampling it with the calls
+    /// `frames` (the latest first, see
+    /// [backtrace](crate::Machine::backtrace)) if it is due.
+    pub fn record(&mut self, frames: &[Frame], ip: u32) {
+        if self.is_due() {
+            let mut stack: Vec<u32> = frames.iter().rev().map(|f| f.return_address).collect();
+            stack.push(ip);
+            *self.samples.entry(stack).or_insert(0) += 1;
+        }
+        self.executed += 1;
+    }
+
+    /// The stacks sampled, as the return addresses of the calls from the
+    /// oldest one followed by the IP, with their number of samples.
+    pub fn samples(&self) -> impl Iterator<Item = (&[u32], u64)> {
+        return self
+            .samples
+            .iter()
+            .map(|(stack, count)| (&stack[..], *count));
+    }
+
+    /// Write the samples in the folded stacks format. The frames are the
+    /// closest labels of `debug_info` at or before the calls and the IP,
+    /// the addresses in hexadecimal without it. The stacks with the same
+    /// frames are merged.
+    pub fn write_folded<W: Write>(
+        &self,
+        debug_info: Option<&DebugInfo>,
+        output: &mut W,
+    ) -> io::Result<()> {
+        let mut folded: BTreeMap<String, u64> = BTreeMap::new();
+        for (stack, count) in self.samples() {
+            let (ip, calls) = stack.split_last().unwrap();
+            // A call is in the function before its return address
+            let addresses = calls.iter().map(|a| a.saturating_sub(1)).chain([*ip]);
+            let frames: Vec<String> = addresses
+                .map(
+                    |address| match debug_info.and_then(|d| d.label_for(address)) {
+                        Some((label, _)) => return label.to_string(),
+                        None => return format!("0x{:04x}", address),
+                    },
+                )
+                .collect();
+            *folded.entry(frames.join(";")).or_insert(0) += count;
+        }
+        for (frames, count) in folded {
+            writeln!(output, "{} {}", frames, count)?;
+        }
+        return Ok(());
+    }
+}
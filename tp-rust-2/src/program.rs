@@ -0,0 +1,334 @@
+use crate::asm::{encode_immediate, AsmErrorKind};
+use crate::isa::{instruction_by_mnemonic, InstructionInfo, Operand};
+use std::collections::HashMap;
+
+/// The names of the registers, to write the operands of a [Program] like
+/// in the assembler syntax with `use interpreter::registers::*`.
+#[allow(non_upper_case_globals)]
+pub mod registers {
+    pub const r0: u8 = 0;
+    pub const r1: u8 = 1;
+    pub const r2: u8 = 2;
+    pub const r3: u8 = 3;
+    pub const r4: u8 = 4;
+    pub const r5: u8 = 5;
+    pub const r6: u8 = 6;
+    pub const r7: u8 = 7;
+    pub const r8: u8 = 8;
+    pub const r9: u8 = 9;
+    pub const r10: u8 = 10;
+    pub const r11: u8 = 11;
+    pub const r12: u8 = 12;
+    pub const r13: u8 = 13;
+    pub const r14: u8 = 14;
+    pub const r15: u8 = 15;
+    pub const ip: u8 = 0;
+    pub const a0: u8 = 1;
+    pub const a1: u8 = 2;
+    pub const a2: u8 = 3;
+    pub const a3: u8 = 4;
+    pub const lr: u8 = 14;
+    pub const sp: u8 = 15;
+    pub const f0: u8 = 0;
+    pub const f1: u8 = 1;
+    pub const f2: u8 = 2;
+    pub const f3: u8 = 3;
+    pub const f4: u8 = 4;
+    pub const f5: u8 = 5;
+    pub const f6: u8 = 6;
+    pub const f7: u8 = 7;
+}
+
+/// An immediate operand of a [Program] instruction: a number, or the
+/// address of a label of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Immediate {
+    Number(i64),
+    Label(String),
+}
+
+macro_rules! immediate_from_number {
+    ($($type:ty),*) => {
+        $(impl From<$type> for Immediate {
+            fn from(number: $type) -> Self {
+                return Immediate::Number(number as i64);
+            }
+        })*
+    };
+}
+
+immediate_from_number!(i8, u8, i16, u16, i32, u32, i64);
+
+impl From<&str> for Immediate {
+    fn from(label: &str) -> Self {
+        return Immediate::Label(label.to_string());
+    }
+}
+
+/// A program built in Rust, one instruction per method call:
+///
+/// ```
+/// use interpreter::registers::*;
+/// use interpreter::Program;
+///
+/// let program = Program::new()
+///     .loadimm(r1, 10)
+///     .label("loop")
+///     .out_number(r1)
+///     .sub(r1, r1, r2)
+///     .bne(r1, r3, "loop")
+///     .exit()
+///     .build()
+///     .unwrap();
+/// assert_eq!(&[4, 1, 10, 0], &program[..4]);
+/// ```
+///
+/// The immediate operands are numbers or labels. For the relative jump
+/// `jrel`, the offset to a label is computed. [build](Program::build)
+/// checks the operands like the [assembler](crate::assemble).
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Instruction(&'static InstructionInfo, Vec<Argument>),
+    Label(String),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+enum Argument {
+    Register(u8),
+    Immediate(Immediate),
+}
+
+// Define a method for every instruction, taking its operands in encoding
+// order: `reg` and `freg` for register numbers, `imm` for immediates
+macro_rules! instructions {
+    ($($mnemonic:ident($($kind:ident $operand:ident),*);)*) => {
+        impl Program {
+            $(
+                #[doc = concat!("Append a `", stringify!($mnemonic), "` instruction.")]
+                pub fn $mnemonic(self, $($operand: instructions!(@type $kind)),*) -> Self {
+                    let mnemonic = stringify!($mnemonic).trim_start_matches("r#");
+                    let arguments = vec![$(instructions!(@argument $kind $operand)),*];
+                    return self.instruction(mnemonic, arguments);
+                }
+            )*
+        }
+    };
+    (@type reg) => { u8 };
+    (@type freg) => { u8 };
+    (@type imm) => { impl Into<Immediate> };
+    (@argument imm $operand:ident) => { Argument::Immediate($operand.into()) };
+    (@argument $kind:ident $operand:ident) => { Argument::Register($operand) };
+}
+
+instructions! {
+    move_if(reg a, reg b, reg c);
+    store(reg a, reg b);
+    load(reg a, reg b);
+    loadimm(reg a, imm value);
+    sub(reg a, reg b, reg c);
+    out(reg a);
+    exit();
+    out_number(reg a);
+    add(reg a, reg b, reg c);
+    mul(reg a, reg b, reg c);
+    div(reg a, reg b, reg c);
+    divu(reg a, reg b, reg c);
+    r#mod(reg a, reg b, reg c);
+    modu(reg a, reg b, reg c);
+    and(reg a, reg b, reg c);
+    or(reg a, reg b, reg c);
+    xor(reg a, reg b, reg c);
+    not(reg a, reg b);
+    shl(reg a, reg b, reg c);
+    shr(reg a, reg b, reg c);
+    sar(reg a, reg b, reg c);
+    rol(reg a, reg b, reg c);
+    ror(reg a, reg b, reg c);
+    jmp(imm target);
+    jrel(imm target);
+    beq(reg a, reg b, imm target);
+    bne(reg a, reg b, imm target);
+    blt(reg a, reg b, imm target);
+    bge(reg a, reg b, imm target);
+    call(imm target);
+    ret();
+    push(reg a);
+    pop(reg a);
+    r#in(reg a);
+    in_number(reg a);
+    loadimm32(reg a, imm value);
+    loadb(reg a, reg b);
+    loadbs(reg a, reg b);
+    storeb(reg a, reg b);
+    loadh(reg a, reg b);
+    loadhs(reg a, reg b);
+    storeh(reg a, reg b);
+    cmp(reg a, reg b);
+    halt(reg a);
+    mov(reg a, reg b);
+    loadx(reg a, reg b, imm value);
+    storex(reg a, imm value, reg b);
+    syscall(imm value);
+    brk();
+    rand(reg a);
+    outs(reg a);
+    memcpy(reg a, reg b, reg c);
+    memset(reg a, reg b, reg c);
+    clz(reg a, reg b);
+    popcnt(reg a, reg b);
+    adc(reg a, reg b, reg c);
+    sbb(reg a, reg b, reg c);
+    fadd(freg a, freg b, freg c);
+    fsub(freg a, freg b, freg c);
+    fmul(freg a, freg b, freg c);
+    fdiv(freg a, freg b, freg c);
+    fcvt(freg a, reg b);
+    fcvti(reg a, freg b);
+    fout(freg a);
+    bswap(reg a);
+    min(reg a, reg b, reg c);
+    max(reg a, reg b, reg c);
+    abs(reg a, reg b);
+    cas(reg a, reg b, reg c);
+    rdinsn(reg a, reg b);
+}
+
+impl Program {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Define the label `name` at the address of the next instruction.
+    pub fn label(mut self, name: &str) -> Self {
+        self.items.push(Item::Label(name.to_string()));
+        return self;
+    }
+
+    /// Append raw bytes, such as data.
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.items.push(Item::Bytes(bytes.to_vec()));
+        return self;
+    }
+
+    /// Append the bytes of `text` followed by a NUL byte, like `.asciiz`.
+    pub fn asciiz(self, text: &str) -> Self {
+        let bytes = [text.as_bytes(), &[0]].concat();
+        return self.bytes(&bytes);
+    }
+
+    /// Encode the program into an image ready to be given to
+    /// [Machine::try_new](crate::Machine::try_new), or return the first invalid
+    /// operand or label.
+    pub fn build(&self) -> Result<Vec<u8>, AsmErrorKind> {
+        // Find the address of the labels
+        let mut labels: HashMap<&str, i64> = HashMap::new();
+        let mut address: usize = 0;
+        for item in &self.items {
+            match item {
+                Item::Instruction(info, _) => address += info.size(),
+                Item::Bytes(bytes) => address += bytes.len(),
+                Item::Label(name) => {
+                    if labels.insert(name, address as i64).is_some() {
+                        return Err(AsmErrorKind::DuplicateLabel(name.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut program: Vec<u8> = Vec::new();
+        for item in &self.items {
+            let (info, arguments) = match item {
+                Item::Instruction(info, arguments) => (info, arguments),
+                Item::Bytes(bytes) => {
+                    program.extend_from_slice(bytes);
+                    continue;
+                }
+                Item::Label(_) => continue,
+            };
+            let next = (program.len() + info.size()) as i64;
+            program.push(info.opcode);
+            for (kind, argument) in info.operands.iter().zip(arguments) {
+                match argument {
+                    Argument::Register(number) => {
+                        let (prefix, count) = match kind {
+                            Operand::FReg => ('f', 8),
+                            _ => ('r', 16),
+                        };
+                        if *number >= count {
+                            let name = format!("{}{}", prefix, number);
+                            return Err(AsmErrorKind::InvalidRegister(name));
+                        }
+                        program.push(*number);
+                    }
+                    Argument::Immediate(Immediate::Number(value)) => {
+                        encode_immediate(*kind, *value, &mut program)?;
+                    }
+                    Argument::Immediate(Immediate::Label(name)) => {
+                        let Some(address) = labels.get(name.as_str()) else {
+                            return Err(AsmErrorKind::UndefinedLabel(name.clone()));
+                        };
+                        let value = match kind {
+                            Operand::Rel16 => address - next,
+                            _ => *address,
+                        };
+                        encode_immediate(*kind, value, &mut program)?;
+                    }
+                }
+            }
+        }
+        return Ok(program);
+    }
+
+    fn instruction(mut self, mnemonic: &str, arguments: Vec<Argument>) -> Self {
+        let info = instruction_by_mnemonic(mnemonic).unwrap();
+        self.items.push(Item::Instruction(info, arguments));
+        return self;
+    }
+}
+
+/// Write a [Program] in the assembler syntax, one instruction per `;`:
+///
+/// ```
+/// use interpreter::program;
+///
+/// let program = program! {
+///     loadimm r1, 10;
+///     again:
+///     out_number r1;
+///     sub r1, r1, r2;
+///     bne r1, r3, "again";
+///     exit
+/// }
+/// .build()
+/// .unwrap();
+/// assert_eq!(&[4, 1, 10, 0], &program[..4]);
+/// ```
+///
+/// Every statement is a call to the [Program] method of the same name, so
+/// `label`, `bytes` and `asciiz` can be used too. A label is defined with
+/// `name:` and referenced like a string. The register names of
+/// [registers](crate::registers) are in scope.
+#[macro_export]
+macro_rules! program {
+    (@statements $program:expr;) => { $program };
+    (@statements $program:expr; $label:ident : $($rest:tt)*) => {
+        $crate::program!(@statements $program.label(stringify!($label)); $($rest)*)
+    };
+    (@statements $program:expr; $method:ident $($argument:expr),* ; $($rest:tt)*) => {
+        $crate::program!(@statements $program.$method($($argument),*); $($rest)*)
+    };
+    (@statements $program:expr; $method:ident $($argument:expr),*) => {
+        $program.$method($($argument),*)
+    };
+    ($($body:tt)*) => {{
+        #[allow(unused_imports)]
+        use $crate::registers::*;
+        $crate::program!(@statements $crate::Program::new(); $($body)*)
+    }};
+}
@@ -0,0 +1,205 @@
+use crate::isa::instruction_info;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// The number of addresses listed by the summary
+const SUMMARY_ADDRESSES: usize = 10;
+
+/// The number of instructions a machine executed, by opcode and by address
+/// (see [stats](crate::Machine::stats)). Only the instructions executed
+/// without a fault are counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecStats {
+    by_opcode: Vec<u64>,
+    by_address: Vec<u64>,
+}
+
+impl ExecStats {
+    /// The statistics of a memory of `size` bytes where nothing has been
+    /// executed.
+    pub fn new(size: usize) -> Self {
+        return ExecStats {
+            by_opcode: vec![0; 256],
+            by_address: vec![0; size],
+        };
+    }
+
+    /// Count an execution of the instruction `opcode` at `address`.
+    pub fn record(&mut self, address: u32, opcode: u8) {
+        self.record_times(address, opcode, 1);
+    }
+
+    // Count `times` executions of the instruction `opcode` at `address`
+    pub(crate) fn record_times(&mut self, address: u32, opcode: u8, times: u64) {
+        self.by_opcode[opcode as usize] += times;
+        if let Some(count) = self.by_address.get_mut(address as usize) {
+            *count += times;
+        }
+    }
+
+    /// The number of instructions executed.
+    pub fn total(&self) -> u64 {
+        return self.by_opcode.iter().sum();
+    }
+
+    pub fn opcode_count(&self, opcode: u8) -> u64 {
+        return self.by_opcode[opcode as usize];
+    }
+
+    pub fn address_count(&self, address: u32) -> u64 {
+        return self.by_address.get(address as usize).copied().unwrap_or(0);
+    }
+
+    /// The opcodes executed with their count, the most executed first and
+    /// by increasing opcode for the same count.
+    pub fn opcodes(&self) -> Vec<(u8, u64)> {
+        return sorted_counts(&self.by_opcode)
+            .into_iter()
+            .map(|(opcode, count)| (opcode as u8, count))
+            .collect();
+    }
+
+    /// The addresses executed with their count, the most executed first and
+    /// by increasing address for the same count.
+    pub fn addresses(&self) -> Vec<(u32, u64)> {
+        return sorted_counts(&self.by_address);
+    }
+
+    /// A table of the executions of every opcode, followed by the most
+    /// executed addresses.
+    pub fn summary(&self) -> String {
+        let total = self.total();
+        let share = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+        let mut summary = String::new();
+        writeln!(summary, "{:<12} {:>10} {:>7}", "opcode", "count", "share").unwrap();
+        for (opcode, count) in self.opcodes() {
+            let mnemonic = instruction_info(opcode).map_or("?", |info| info.mnemonic);
+            writeln!(
+                summary,
+                "{:<12} {:>10} {:>6.1}%",
+                mnemonic,
+                count,
+                share(count)
+            )
+            .unwrap();
+        }
+        writeln!(summary, "{:<12} {:>10}", "total", total).unwrap();
+        writeln!(summary, "{:<12} {:>10} {:>7}", "address", "count", "share").unwrap();
+        for (address, count) in self.addresses().into_iter().take(SUMMARY_ADDRESSES) {
+            let address = format!("0x{:04x}", address);
+            writeln!(
+                summary,
+                "{:<12} {:>10} {:>6.1}%",
+                address,
+                count,
+                share(count)
+            )
+            .unwrap();
+        }
+        return summary;
+    }
+}
+
+/// The lookups of the basic blocks a machine executes at once and their
+/// invalidations (see [block_stats](crate::Machine::block_stats)).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockStats {
+    /// The number of blocks found in the cache
+    pub hits: u64,
+    /// The number of blocks built as they weren't in the cache
+    pub misses: u64,
+    /// The number of blocks removed from the cache as their memory was
+    /// written
+    pub invalidations: u64,
+}
+
+impl BlockStats {
+    /// A table of the lookups of the blocks and their invalidations.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        writeln!(summary, "{:<20} {:>10}", "blocks", "count").unwrap();
+        for (name, count) in [
+            ("hits", self.hits),
+            ("misses", self.misses),
+            ("invalidations", self.invalidations),
+        ] {
+            writeln!(summary, "{:<20} {:>10}", name, count).unwrap();
+        }
+        return summary;
+    }
+}
+
+/// The number of times every pair of instructions fused by a machine has
+/// been executed by one handler (see
+/// [fusion_stats](crate::Machine::fusion_stats)).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FusionStats {
+    by_pair: BTreeMap<(u8, u8), u64>,
+}
+
+impl FusionStats {
+    // Count an execution of the instruction `first` fused with `second`
+    pub(crate) fn record(&mut self, first: u8, second: u8) {
+        *self.by_pair.entry((first, second)).or_default() += 1;
+    }
+
+    /// The number of pairs executed fused.
+    pub fn hits(&self) -> u64 {
+        return self.by_pair.values().sum();
+    }
+
+    pub fn pair_count(&self, first: u8, second: u8) -> u64 {
+        return self.by_pair.get(&(first, second)).copied().unwrap_or(0);
+    }
+
+    /// The opcodes of the pairs executed fused with their count, the most
+    /// executed first and by increasing opcodes for the same count.
+    pub fn pairs(&self) -> Vec<((u8, u8), u64)> {
+        let mut pairs: Vec<((u8, u8), u64)> = self
+            .by_pair
+            .iter()
+            .map(|(&pair, &count)| (pair, count))
+            .collect();
+        pairs.sort_by_key(|&(pair, count)| (std::cmp::Reverse(count), pair));
+        return pairs;
+    }
+
+    /// A table of the executions of every fused pair, with the share of
+    /// the instructions counted by `stats` they make.
+    pub fn summary(&self, stats: &ExecStats) -> String {
+        let total = stats.total();
+        let share = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+        let mnemonic = |opcode| instruction_info(opcode).map_or("?", |info| info.mnemonic);
+        let mut summary = String::new();
+        writeln!(summary, "{:<20} {:>10} {:>7}", "fused", "count", "share").unwrap();
+        for ((first, second), count) in self.pairs() {
+            writeln!(
+                summary,
+                "{:<20} {:>10} {:>6.1}%",
+                format!("{}+{}", mnemonic(first), mnemonic(second)),
+                count,
+                share(2 * count)
+            )
+            .unwrap();
+        }
+        writeln!(
+            summary,
+            "{:<20} {:>10} {:>6.1}%",
+            "total",
+            self.hits(),
+            share(2 * self.hits())
+        )
+        .unwrap();
+        return summary;
+    }
+}
+
+// The indexes of the non-zero counts, the largest count first
+fn sorted_counts(counts: &[u64]) -> Vec<(u32, u64)> {
+    let mut sorted: Vec<(u32, u64)> = (0..counts.len() as u32)
+        .map(|index| (index, counts[index as usize]))
+        .filter(|&(_, count)| count > 0)
+        .collect();
+    sorted.sort_by_key(|&(index, count)| (std::cmp::Reverse(count), index));
+    return sorted;
+}
@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+
+/// The labels of a program and the address they stand for, as produced by
+/// [assemble_with_symbols](crate::assemble_with_symbols).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    addresses: BTreeMap<String, u32>,
+    labels: BTreeMap<u32, Vec<String>>, // The labels at every address, in definition order
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Define the label `name` at `address`, replacing any previous
+    /// definition of `name`.
+    pub fn insert(&mut self, name: &str, address: u32) {
+        self.remove(name);
+        self.addresses.insert(name.to_string(), address);
+        self.labels
+            .entry(address)
+            .or_default()
+            .push(name.to_string());
+    }
+
+    /// Remove the label `name` and return its address, if it was defined.
+    pub fn remove(&mut self, name: &str) -> Option<u32> {
+        let address = self.addresses.remove(name)?;
+        if let Some(labels) = self.labels.get_mut(&address) {
+            labels.retain(|label| label != name);
+            if labels.is_empty() {
+                self.labels.remove(&address);
+            }
+        }
+        return Some(address);
+    }
+
+    /// The address of the label `name`, if it is defined.
+    pub fn address(&self, name: &str) -> Option<u32> {
+        return self.addresses.get(name).copied();
+    }
+
+    /// The first label defined at `address`, if any.
+    pub fn label_at(&self, address: u32) -> Option<&str> {
+        return self
+            .labels
+            .get(&address)
+            .and_then(|labels| labels.first())
+            .map(|label| label.as_str());
+    }
+
+    /// Every label defined at `address`.
+    pub fn labels_at(&self, address: u32) -> &[String] {
+        return self.labels.get(&address).map_or(&[], |labels| &labels[..]);
+    }
+
+    /// Every label and its address, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        return self
+            .addresses
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address));
+    }
+
+    pub fn len(&self) -> usize {
+        return self.addresses.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.addresses.is_empty();
+    }
+}
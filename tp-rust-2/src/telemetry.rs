@@ -0,0 +1,27 @@
+// The `tracing` events of the input and the output of the programs, emitted
+// by wrapping the reader and the writer given to the machine
+use std::io::{self, Read, Write};
+
+pub(crate) struct TracedReader<R: Read>(pub(crate) R);
+
+impl<R: Read> Read for TracedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.0.read(buf)?;
+        tracing::debug!(bytes = ?&buf[..count], "input");
+        return Ok(count);
+    }
+}
+
+pub(crate) struct TracedWriter<W: Write>(pub(crate) W);
+
+impl<W: Write> Write for TracedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.0.write(buf)?;
+        tracing::debug!(bytes = ?&buf[..count], "output");
+        return Ok(count);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.0.flush();
+    }
+}
@@ -0,0 +1,141 @@
+use crate::isa::instruction_info;
+use std::io::{self, Read, Write};
+
+// The magic number starting a binary trace
+const MAGIC: &[u8; 4] = b"RVMT";
+
+/// An instruction executed by a machine, as recorded by a [Tracer].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The address of the instruction.
+    pub address: u32,
+    pub opcode: u8,
+    /// The value of every operand, in the order of the instruction
+    /// description (see [INSTRUCTIONS](crate::INSTRUCTIONS)).
+    pub operands: Vec<u32>,
+    /// The registers the instruction changed with their new value, by
+    /// increasing number. The IP is left out, the next entry telling where
+    /// the execution went.
+    pub registers: Vec<(u8, u32)>,
+}
+
+/// A recorder of the instructions executed by a machine it is attached to
+/// (see [attach_tracer](crate::Machine::attach_tracer)). Only the
+/// instructions executed without a fault are recorded.
+///
+/// The trace can be written as JSON Lines, one object per instruction such
+/// as `{"address":8,"opcode":5,"mnemonic":"sub","operands":[1,1,2],"registers":{"r1":2}}`,
+/// or in a compact binary format read back by
+/// [read_binary](Tracer::read_binary).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tracer {
+    entries: Vec<TraceEntry>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        return Tracer::default();
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        self.entries.push(entry);
+    }
+
+    /// The instructions recorded, the oldest first.
+    pub fn entries(&self) -> &[TraceEntry] {
+        return &self.entries;
+    }
+
+    /// Write the trace as JSON Lines, an object per instruction.
+    pub fn write_json_lines<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for entry in &self.entries {
+            let mnemonic = instruction_info(entry.opcode).map_or("?", |info| info.mnemonic);
+            let operands: Vec<String> = entry.operands.iter().map(|o| o.to_string()).collect();
+            let registers: Vec<String> = entry
+                .registers
+                .iter()
+                .map(|(reg, value)| format!("\"r{}\":{}", reg, value))
+                .collect();
+            writeln!(
+                output,
+                "{{\"address\":{},\"opcode\":{},\"mnemonic\":\"{}\",\"operands\":[{}],\"registers\":{{{}}}}}",
+                entry.address,
+                entry.opcode,
+                mnemonic,
+                operands.join(","),
+                registers.join(",")
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// Write the trace in binary: the `RVMT` magic number, then for every
+    /// instruction its address as a little-endian 32-bit value, its opcode,
+    /// the number of its operands and their values, and the number of the
+    /// registers it changed followed by the number and the new value of
+    /// each of them.
+    pub fn write_binary<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        output.write_all(MAGIC)?;
+        for entry in &self.entries {
+            output.write_all(&entry.address.to_le_bytes())?;
+            output.write_all(&[entry.opcode, entry.operands.len() as u8])?;
+            for operand in &entry.operands {
+                output.write_all(&operand.to_le_bytes())?;
+            }
+            output.write_all(&[entry.registers.len() as u8])?;
+            for (reg, value) in &entry.registers {
+                output.write_all(&[*reg])?;
+                output.write_all(&value.to_le_bytes())?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Read a trace written by [write_binary](Tracer::write_binary). An
+    /// [io::ErrorKind::InvalidData] error is returned if it doesn't start
+    /// with the magic number or is truncated.
+    pub fn read_binary<R: Read>(input: &mut R) -> io::Result<Tracer> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid trace");
+        let Some(mut rest) = bytes.strip_prefix(MAGIC) else {
+            return Err(invalid());
+        };
+        let mut tracer = Tracer::new();
+        while !rest.is_empty() {
+            let address = take_u32(&mut rest).ok_or_else(invalid)?;
+            let opcode = take_u8(&mut rest).ok_or_else(invalid)?;
+            let count = take_u8(&mut rest).ok_or_else(invalid)?;
+            let operands = (0..count)
+                .map(|_| take_u32(&mut rest))
+                .collect::<Option<Vec<u32>>>()
+                .ok_or_else(invalid)?;
+            let count = take_u8(&mut rest).ok_or_else(invalid)?;
+            let registers = (0..count)
+                .map(|_| Some((take_u8(&mut rest)?, take_u32(&mut rest)?)))
+                .collect::<Option<Vec<(u8, u32)>>>()
+                .ok_or_else(invalid)?;
+            tracer.record(TraceEntry {
+                address,
+                opcode,
+                operands,
+                registers,
+            });
+        }
+        return Ok(tracer);
+    }
+}
+
+// Take a byte from the beginning of `bytes`
+fn take_u8(bytes: &mut &[u8]) -> Option<u8> {
+    let (first, rest) = bytes.split_first()?;
+    *bytes = rest;
+    return Some(*first);
+}
+
+// Take a little-endian 32-bit value from the beginning of `bytes`
+fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let (value, rest) = bytes.split_first_chunk::<4>()?;
+    *bytes = rest;
+    return Some(u32::from_le_bytes(*value));
+}
@@ -0,0 +1,687 @@
+// The translation of the programs to Rust source, to compile a program known
+// ahead of time into a host binary instead of interpreting it. The generated
+// module doesn't depend on this crate: it has its own state of the machine,
+// and a `run` function executing the instructions of the program, every one
+// of them being an arm of a `match` on the IP.
+use crate::disasm::{decode, DecodedInsn};
+use crate::image::Image;
+use crate::isa::{instruction_info, Operand, INSTRUCTIONS};
+use crate::machine::{Machine, MachineError};
+use std::collections::BTreeMap;
+
+// The opcodes of the floating-point instructions, which aren't transpiled
+const FLOATING_POINT: [u8; 7] = [58, 59, 60, 61, 62, 63, 64];
+
+// The registers with a specific role
+const IP: u32 = 0;
+const LR: u32 = 14;
+
+/// Transpile the program of `image` to the source of a standalone Rust
+/// module, loading it in memory like [Machine::load_image]. The module
+/// defines the `State` of the machine, created by `State::new` with the
+/// program loaded, and `run(&mut state, &mut input, &mut output)` which
+/// executes it until it exits, executes `brk` or `syscall`, which the host
+/// serves before running it again, or faults like the machine would.
+///
+/// The instructions transpiled are those found by decoding the segments
+/// from their start, and by following the jumps, branches and calls from
+/// the entry. The program can't modify them: reaching an instruction which
+/// wasn't transpiled, such as one written by the program, is a fault. The
+/// floating-point instructions are invalid, as on a machine without the
+/// `fp` feature.
+///
+/// Returns [MachineError::NonExistingAddress] if a segment doesn't fit in
+/// the memory.
+pub fn transpile(image: &Image) -> Result<String, MachineError> {
+    let mut machine = Machine::try_new(&[]).unwrap();
+    for segment in &image.segments {
+        machine.load_at(segment.address as usize, &segment.data)?;
+    }
+    let memory = machine.memory();
+
+    let mut source = String::new();
+    source.push_str(HEADER);
+    source.push_str(&format!(
+        "/// The size of the memory, in bytes.\npub const MEMORY_SIZE: usize = {};\n\n",
+        memory.len()
+    ));
+    source.push_str(&format!(
+        "/// The address of the first instruction.\npub const ENTRY: u32 = 0x{:04x};\n\n",
+        image.entry
+    ));
+    source.push_str("// The address and the content of every segment of the program\n");
+    source.push_str("const SEGMENTS: &[(usize, &[u8])] = &[\n");
+    for segment in &image.segments {
+        source.push_str(&format!(
+            "    (\n        0x{:04x},\n        &[\n",
+            segment.address
+        ));
+        for line in segment.data.chunks(12) {
+            let bytes: Vec<String> = line.iter().map(|byte| format!("0x{:02x},", byte)).collect();
+            source.push_str(&format!("            {}\n", bytes.join(" ")));
+        }
+        source.push_str("        ],\n    ),\n");
+    }
+    source.push_str("];\n");
+    source.push_str(RUNTIME);
+
+    source.push_str("\n// Whether `opcode` is the one of an instruction transpiled\n");
+    source.push_str("fn is_instruction(opcode: u8) -> bool {\n");
+    let opcodes: Vec<String> = INSTRUCTIONS
+        .iter()
+        .filter(|info| !FLOATING_POINT.contains(&info.opcode))
+        .map(|info| info.opcode.to_string())
+        .collect();
+    source.push_str(&format!(
+        "    matches!(opcode, {})\n}}\n",
+        opcodes.join(" | ")
+    ));
+
+    source.push_str(RUN);
+    for (address, insn) in instructions(image, memory) {
+        source.push_str(&format!("            0x{:04x} => {{\n", address));
+        if insn.info.is_some() {
+            source.push_str(&format!(
+                "                // {}\n",
+                insn.format(&Default::default())
+            ));
+        }
+        for line in statements(&insn, memory.len()) {
+            source.push_str(&format!("                {}\n", line));
+        }
+        source.push_str("            }\n");
+    }
+    source.push_str("            _ => return Err(s.untranspiled()),\n");
+    source.push_str("        }\n        s.instructions_retired += 1;\n    }\n}\n");
+    return Ok(source);
+}
+
+// The instructions to transpile, by address: those decoded from the start
+// of every segment, and those reached from the entry by falling through,
+// jumping, branching or calling. An instruction whose operands run past the
+// end of the memory is kept, to fault like the machine.
+fn instructions(image: &Image, memory: &[u8]) -> BTreeMap<usize, DecodedInsn> {
+    let transpiled = |insn: &DecodedInsn| {
+        let opcode = insn.bytes[0];
+        return !FLOATING_POINT.contains(&opcode) && instruction_info(opcode).is_some();
+    };
+    let mut instructions = BTreeMap::new();
+    for segment in &image.segments {
+        let mut address = segment.address as usize;
+        while address < segment.address as usize + segment.data.len() {
+            let insn = decode(&memory[address..], address);
+            if !transpiled(&insn) {
+                address += 1;
+                continue;
+            }
+            address += insn.bytes.len();
+            instructions.insert(insn.address, insn);
+        }
+    }
+
+    let mut pending = vec![image.entry as usize];
+    while let Some(address) = pending.pop() {
+        if address >= memory.len() || instructions.contains_key(&address) {
+            continue;
+        }
+        let insn = decode(&memory[address..], address);
+        if !transpiled(&insn) {
+            continue;
+        }
+        if let Some(info) = insn.info {
+            if info.falls_through() {
+                pending.push(address + insn.bytes.len());
+            }
+            if let Some(target) = insn.target().filter(|&target| target >= 0) {
+                pending.push(target as usize);
+            }
+        }
+        instructions.insert(address, insn);
+    }
+    return instructions;
+}
+
+// The statements of the arm of `insn`, in a memory of `size` bytes
+fn statements(insn: &DecodedInsn, size: usize) -> Vec<String> {
+    let opcode = insn.bytes[0];
+    let next = insn.address + insn.bytes.len();
+    let mut arm = Arm {
+        ip: insn.address,
+        opcode,
+        next,
+        lines: Vec::new(),
+        ip_written: false,
+    };
+    let Some(info) = insn.info else {
+        // The operands are fetched past the end of the memory
+        arm.fault("NonExistingAddress", &format!("address: 0x{:04x}", size));
+        return arm.lines;
+    };
+    let invalid = info
+        .operands
+        .iter()
+        .zip(&insn.operands)
+        .find(|&(&operand, &value)| operand == Operand::Reg && value >= 16);
+    if let Some((_, register)) = invalid {
+        arm.fault("NonExistingRegister", &format!("register: {}", register));
+        return arm.lines;
+    }
+
+    let ops = &insn.operands;
+    let operand = |i: usize| return ops.get(i).copied().unwrap_or(0);
+    let (a, b, c) = (operand(0), operand(1), operand(2));
+    let (ra, rb, rc) = (arm.read(a), arm.read(b), arm.read(c));
+    let at = arm.at();
+    match opcode {
+        1 => arm.write(a, &format!("if {} != 0 {{ {} }} else {{ {} }}", rc, rb, ra)),
+        2 => {
+            arm.line(format!("let address = s.range({}, 4, {})?;", ra, at));
+            arm.line(format!("s.store(address, &{}.to_le_bytes());", rb));
+        }
+        3 => {
+            arm.line(format!("let address = s.range({}, 4, {})?;", rb, at));
+            arm.write(a, "s.word(address)");
+        }
+        4 => arm.write(a, &format!("0x{:x}", b as u16 as i16 as i32 as u32)),
+        5 => arm.write(a, &format!("{}.wrapping_sub({})", rb, rc)),
+        6 => arm.line(format!(
+            "write!(output, \"{{}}\", char::from({} as u8)).map_err(|error| io_error(error, {}))?;",
+            ra, at
+        )),
+        7 => arm.stop("Stop::Exited(0)"),
+        8 => arm.line(format!(
+            "write!(output, \"{{}}\", {} as i32).map_err(|error| io_error(error, {}))?;",
+            ra, at
+        )),
+        9 => arm.write(a, &format!("{}.wrapping_add({})", rb, rc)),
+        10 => arm.write(a, &format!("{}.wrapping_mul({})", rb, rc)),
+        11 | 13 => {
+            let operation = if opcode == 11 { "div" } else { "rem" };
+            arm.line(format!("let divisor = {} as i32;", rc));
+            arm.line(format!(
+                "if divisor == 0 {{ return Err({}); }}",
+                arm.error("DivisionByZero", "")
+            ));
+            arm.write(
+                a,
+                &format!("({} as i32).wrapping_{}(divisor) as u32", rb, operation),
+            );
+        }
+        12 | 14 => {
+            let operation = if opcode == 12 { "div" } else { "rem" };
+            let error = arm.error("DivisionByZero", "");
+            arm.write(
+                a,
+                &format!("{}.checked_{}({}).ok_or({})?", rb, operation, rc, error),
+            );
+        }
+        15 => arm.write(a, &format!("{} & {}", rb, rc)),
+        16 => arm.write(a, &format!("{} | {}", rb, rc)),
+        17 => arm.write(a, &format!("{} ^ {}", rb, rc)),
+        18 => arm.write(a, &format!("!{}", rb)),
+        19 => arm.write(a, &format!("{} << ({} % 32)", rb, rc)),
+        20 => arm.write(a, &format!("{} >> ({} % 32)", rb, rc)),
+        21 => arm.write(a, &format!("(({} as i32) >> ({} % 32)) as u32", rb, rc)),
+        22 => arm.write(a, &format!("{}.rotate_left({} % 32)", rb, rc)),
+        23 => arm.write(a, &format!("{}.rotate_right({} % 32)", rb, rc)),
+        24 | 25 | 30 => {
+            let target = insn.target().unwrap_or(-1);
+            if !(0..size as i64).contains(&target) {
+                let address = format!("address: 0x{:04x}", target as u32);
+                arm.fault("NonExistingAddress", &address);
+                return arm.lines;
+            }
+            if opcode == 30 {
+                arm.write(LR, &arm.read(IP));
+            }
+            arm.write(IP, &format!("0x{:04x}", target));
+        }
+        26..=29 => {
+            let condition = match opcode {
+                26 => format!("{} == {}", ra, rb),
+                27 => format!("{} != {}", ra, rb),
+                28 => format!("({} as i32) < ({} as i32)", ra, rb),
+                _ => format!("({} as i32) >= ({} as i32)", ra, rb),
+            };
+            if (c as usize) < size {
+                let target = format!("0x{:04x}", c);
+                let value = format!(
+                    "if {} {{ {} }} else {{ {} }}",
+                    condition,
+                    target,
+                    arm.read(IP)
+                );
+                arm.write(IP, &value);
+            } else {
+                let error = arm.error("NonExistingAddress", &format!("address: 0x{:04x}", c));
+                arm.line(format!("if {} {{ return Err({}); }}", condition, error));
+            }
+        }
+        31 => {
+            arm.line(format!("let target = {};", arm.read(LR)));
+            let error = arm.error("NonExistingAddress", "address: target");
+            arm.line(format!(
+                "if target as usize >= MEMORY_SIZE {{ return Err({}); }}",
+                error
+            ));
+            arm.write(IP, "target");
+        }
+        32 => arm.line(format!("s.push({}, {})?;", ra, at)),
+        33 => arm.write(a, &format!("s.pop({})?", at)),
+        34 => arm.write(
+            a,
+            &format!("read_byte(input, {})?.map_or(u32::MAX, u32::from)", at),
+        ),
+        35 => arm.write(a, &format!("read_number(input, {})? as u32", at)),
+        36 => arm.write(a, &format!("0x{:x}", b)),
+        37 | 38 | 40 | 41 => {
+            let (len, signed) = match opcode {
+                37 => (1, false),
+                38 => (1, true),
+                40 => (2, false),
+                _ => (2, true),
+            };
+            arm.line(format!("let address = s.range({}, {}, {})?;", rb, len, at));
+            arm.write(a, &format!("s.load(address, {}, {})", len, signed));
+        }
+        39 | 42 => {
+            let len = if opcode == 39 { 1 } else { 2 };
+            arm.line(format!("let address = s.range({}, {}, {})?;", ra, len, at));
+            arm.line(format!(
+                "s.store(address, &{}.to_le_bytes()[..{}]);",
+                rb, len
+            ));
+        }
+        43 => arm.line(format!("s.cmp({}, {});", ra, rb)),
+        44 => arm.stop(&format!("Stop::Exited({})", ra)),
+        45 => arm.write(a, &rb),
+        46 => {
+            let offset = c as u8 as i8;
+            arm.line(format!(
+                "let address = s.indexed({}, {}, {})?;",
+                rb, offset, at
+            ));
+            arm.write(a, "s.word(address)");
+        }
+        47 => {
+            let offset = b as u8 as i8;
+            arm.line(format!(
+                "let address = s.indexed({}, {}, {})?;",
+                ra, offset, at
+            ));
+            arm.line(format!("s.store(address, &{}.to_le_bytes());", rc));
+        }
+        48 => arm.stop(&format!("Stop::Syscall({})", a)),
+        49 => arm.stop("Stop::Break"),
+        50 => arm.write(a, "s.next_random()"),
+        51 => arm.line(format!("s.outs({}, output, {})?;", ra, at)),
+        52 => arm.line(format!("s.copy({}, {}, {}, {})?;", ra, rb, rc, at)),
+        53 => arm.line(format!("s.fill({}, {} as u8, {}, {})?;", ra, rb, rc, at)),
+        54 => arm.write(a, &format!("{}.leading_zeros()", rb)),
+        55 => arm.write(a, &format!("{}.count_ones()", rb)),
+        56 => arm.write(a, &format!("s.adc({}, {})", rb, rc)),
+        57 => arm.write(a, &format!("s.sbb({}, {})", rb, rc)),
+        65 => arm.write(a, &format!("{}.swap_bytes()", ra)),
+        66 => arm.write(a, &format!("({} as i32).min({} as i32) as u32", rb, rc)),
+        67 => arm.write(a, &format!("({} as i32).max({} as i32) as u32", rb, rc)),
+        68 => arm.write(a, &format!("({} as i32).wrapping_abs() as u32", rb)),
+        69 => {
+            arm.line(format!("let address = s.range({}, 4, {})?;", ra, at));
+            arm.line("let previous = s.word(address);".to_string());
+            arm.line(format!("let swapped = previous == {};", rb));
+            arm.line(format!(
+                "if swapped {{ s.store(address, &{}.to_le_bytes()); }}",
+                rc
+            ));
+            arm.line("s.flags.zero = swapped;".to_string());
+            arm.write(b, "previous");
+        }
+        70 => {
+            arm.write(a, "s.instructions_retired as u32");
+            arm.write(b, "(s.instructions_retired >> 32) as u32");
+        }
+        _ => unreachable!("{} isn't transpiled", info.mnemonic),
+    }
+    if !arm.ip_written {
+        arm.line(format!("s.regs[0] = 0x{:04x};", next));
+    }
+    return arm.lines;
+}
+
+// The arm of an instruction being generated
+struct Arm {
+    ip: usize,
+    opcode: u8,
+    next: usize,
+    lines: Vec<String>,
+    ip_written: bool, // whether a statement sets the IP
+}
+
+impl Arm {
+    // The value of register `reg`, the IP being the address of the next
+    // instruction as it's incremented before the instruction is executed
+    fn read(&self, reg: u32) -> String {
+        if reg == IP {
+            return format!("0x{:04x}u32", self.next);
+        }
+        return format!("s.regs[{}]", reg);
+    }
+
+    fn write(&mut self, reg: u32, value: &str) {
+        self.ip_written |= reg == IP;
+        self.lines.push(format!("s.regs[{}] = {};", reg, value));
+    }
+
+    fn line(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    // The IP and the opcode of the instruction, given to the helpers
+    fn at(&self) -> String {
+        return format!("(0x{:04x}, {})", self.ip, self.opcode);
+    }
+
+    // The fault `variant` of the instruction, with the other `fields`
+    fn error(&self, variant: &str, fields: &str) -> String {
+        let fields = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(", {}", fields)
+        };
+        return format!(
+            "Fault::{} {{ ip: 0x{:04x}, opcode: {}{} }}",
+            variant, self.ip, self.opcode, fields
+        );
+    }
+
+    // Fault whenever the instruction is executed
+    fn fault(&mut self, variant: &str, fields: &str) {
+        let error = self.error(variant, fields);
+        self.lines.push(format!("return Err({});", error));
+    }
+
+    // Stop the run once the instruction is executed
+    fn stop(&mut self, stop: &str) {
+        self.lines.push(format!("s.regs[0] = 0x{:04x};", self.next));
+        self.lines.push("s.instructions_retired += 1;".to_string());
+        self.lines.push(format!("return Ok({});", stop));
+        self.ip_written = true;
+    }
+}
+
+// The start of the module, up to the constants describing the program
+const HEADER: &str = "\
+// Generated by rvm-aot: a program of the virtual machine transpiled to Rust.
+// `run` executes the instructions of the program as they were loaded, and
+// faults on reaching any other one. The program may not use every helper,
+// nor every kind of stop and fault.
+#![allow(dead_code)]
+
+use std::io::{self, Read, Write};
+
+";
+
+// The types and the helpers of the module, which don't depend on the
+// program
+const RUNTIME: &str = "
+// The longest string printed by outs
+const MAX_STRING_LENGTH: usize = 1024;
+
+/// The flags of the machine, set by cmp, adc, sbb and cas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub zero: bool,
+    pub negative: bool,
+    pub carry: bool,
+    pub overflow: bool,
+}
+
+/// The state of the machine running the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub regs: [u32; 16],
+    pub flags: Flags,
+    pub memory: Box<[u8; MEMORY_SIZE]>,
+    /// The number of instructions executed so far.
+    pub instructions_retired: u64,
+    /// The state of the generator of the rand instruction.
+    pub rng_state: u64,
+}
+
+/// Why [run] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The program exited with this code.
+    Exited(u32),
+    /// The program executed brk. Running it again resumes it.
+    Break,
+    /// The program executed syscall with this number, which the host serves
+    /// before running it again.
+    Syscall(u8),
+}
+
+/// The faults of the program, as reported by the virtual machine. The state
+/// is left as it was before the faulting instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    NonExistingInstruction { ip: u32, opcode: u8 },
+    NonExistingRegister { ip: u32, opcode: u8, register: u8 },
+    NonExistingAddress { ip: u32, opcode: u8, address: u32 },
+    IoError { ip: u32, opcode: u8, kind: io::ErrorKind },
+    DivisionByZero { ip: u32, opcode: u8 },
+    StackFault { ip: u32, opcode: u8, sp: u32 },
+    InvalidNumber { ip: u32, opcode: u8 },
+    StringTooLong { ip: u32, opcode: u8, address: u32 },
+    /// The instruction at ip wasn't transpiled, being written by the program
+    /// or starting in the middle of another one.
+    NotTranspiled { ip: u32, opcode: u8 },
+}
+
+impl State {
+    /// The state of the machine with the program loaded.
+    pub fn new() -> State {
+        let mut memory = Box::new([0; MEMORY_SIZE]);
+        for (address, bytes) in SEGMENTS {
+            memory[*address..*address + bytes.len()].copy_from_slice(bytes);
+        }
+        let mut regs = [0; 16];
+        regs[0] = ENTRY;
+        State {
+            regs,
+            flags: Flags::default(),
+            memory,
+            instructions_retired: 0,
+            rng_state: 0,
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
+// The helpers of the instructions, given the IP and the opcode of the
+// instruction for the faults
+impl State {
+    // The start of the `len` bytes at `address`, which must be in memory
+    fn range(&self, address: u32, len: u32, (ip, opcode): (u32, u8)) -> Result<usize, Fault> {
+        let (start, len) = (address as usize, len as usize);
+        if start <= MEMORY_SIZE && len <= MEMORY_SIZE - start {
+            return Ok(start);
+        }
+        Err(Fault::NonExistingAddress { ip, opcode, address })
+    }
+
+    // The address of the word `offset` bytes after `base`, which must be
+    // in memory
+    fn indexed(&self, base: u32, offset: i8, (ip, opcode): (u32, u8)) -> Result<usize, Fault> {
+        let address = base as i64 + offset as i64;
+        if 0 <= address && address + 4 <= MEMORY_SIZE as i64 {
+            return Ok(address as usize);
+        }
+        Err(Fault::NonExistingAddress { ip, opcode, address: address as u32 })
+    }
+
+    fn word(&self, address: usize) -> u32 {
+        u32::from_le_bytes(self.memory[address..address + 4].try_into().unwrap())
+    }
+
+    // The `len` bytes at `address`, extending their sign if `signed` is set
+    fn load(&self, address: usize, len: usize, signed: bool) -> u32 {
+        let mut value = 0;
+        for i in 0..len {
+            value |= (self.memory[address + i] as u32) << (i * 8);
+        }
+        if signed {
+            let unused_bits = 32 - 8 * len as u32;
+            value = (((value << unused_bits) as i32) >> unused_bits) as u32;
+        }
+        value
+    }
+
+    fn store(&mut self, address: usize, bytes: &[u8]) {
+        self.memory[address..address + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn push(&mut self, value: u32, (ip, opcode): (u32, u8)) -> Result<(), Fault> {
+        let sp = self.regs[15] as usize;
+        if !(4..=MEMORY_SIZE).contains(&sp) {
+            return Err(Fault::StackFault { ip, opcode, sp: sp as u32 });
+        }
+        self.store(sp - 4, &value.to_le_bytes());
+        self.regs[15] = (sp - 4) as u32;
+        Ok(())
+    }
+
+    fn pop(&mut self, (ip, opcode): (u32, u8)) -> Result<u32, Fault> {
+        let sp = self.regs[15] as usize;
+        if sp.saturating_add(4) > MEMORY_SIZE {
+            return Err(Fault::StackFault { ip, opcode, sp: sp as u32 });
+        }
+        self.regs[15] = (sp + 4) as u32;
+        Ok(self.word(sp))
+    }
+
+    fn cmp(&mut self, a: u32, b: u32) {
+        let (difference, borrow) = a.overflowing_sub(b);
+        self.flags = Flags {
+            zero: difference == 0,
+            negative: (difference as i32) < 0,
+            carry: borrow,
+            overflow: (a as i32).overflowing_sub(b as i32).1,
+        };
+    }
+
+    fn adc(&mut self, b: u32, c: u32) -> u32 {
+        let (partial, carry_1) = b.overflowing_add(c);
+        let (sum, carry_2) = partial.overflowing_add(self.flags.carry as u32);
+        self.flags.carry = carry_1 || carry_2;
+        sum
+    }
+
+    fn sbb(&mut self, b: u32, c: u32) -> u32 {
+        let (partial, borrow_1) = b.overflowing_sub(c);
+        let (difference, borrow_2) = partial.overflowing_sub(self.flags.carry as u32);
+        self.flags.carry = borrow_1 || borrow_2;
+        difference
+    }
+
+    // Copy `len` bytes from `source` to `destination`
+    fn copy(&mut self, destination: u32, source: u32, len: u32, at: (u32, u8)) -> Result<(), Fault> {
+        let destination = self.range(destination, len, at)?;
+        let source = self.range(source, len, at)?;
+        self.memory.copy_within(source..source + len as usize, destination);
+        Ok(())
+    }
+
+    fn fill(&mut self, destination: u32, byte: u8, len: u32, at: (u32, u8)) -> Result<(), Fault> {
+        let destination = self.range(destination, len, at)?;
+        self.memory[destination..destination + len as usize].fill(byte);
+        Ok(())
+    }
+
+    // Print the NUL-terminated string at `address`
+    fn outs<W: Write>(&self, address: u32, output: &mut W, (ip, opcode): (u32, u8)) -> Result<(), Fault> {
+        let start = address as usize;
+        if start >= MEMORY_SIZE {
+            return Err(Fault::NonExistingAddress { ip, opcode, address });
+        }
+        let limit = usize::min(start + MAX_STRING_LENGTH + 1, MEMORY_SIZE);
+        let length = match self.memory[start..limit].iter().position(|&b| b == 0) {
+            Some(length) => length,
+            None if limit == MEMORY_SIZE && limit - start <= MAX_STRING_LENGTH => {
+                return Err(Fault::NonExistingAddress { ip, opcode, address })
+            }
+            None => return Err(Fault::StringTooLong { ip, opcode, address }),
+        };
+        output
+            .write_all(&self.memory[start..start + length])
+            .map_err(|error| io_error(error, (ip, opcode)))
+    }
+
+    // The next output of the generator of the rand instruction (SplitMix64)
+    fn next_random(&mut self) -> u32 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 32) as u32
+    }
+
+    // The fault of reaching an instruction which wasn't transpiled
+    fn untranspiled(&self) -> Fault {
+        let ip = self.regs[0];
+        match self.memory.get(ip as usize) {
+            None => Fault::NonExistingAddress { ip, opcode: 0, address: ip },
+            Some(&opcode) if is_instruction(opcode) => Fault::NotTranspiled { ip, opcode },
+            Some(&opcode) => Fault::NonExistingInstruction { ip, opcode },
+        }
+    }
+}
+
+fn io_error(error: io::Error, (ip, opcode): (u32, u8)) -> Fault {
+    Fault::IoError { ip, opcode, kind: error.kind() }
+}
+
+// A single byte of `input`, or None at its end
+fn read_byte<R: Read>(input: &mut R, at: (u32, u8)) -> Result<Option<u8>, Fault> {
+    let mut byte = [0];
+    match input.read_exact(&mut byte) {
+        Ok(()) => Ok(Some(byte[0])),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(error) => Err(io_error(error, at)),
+    }
+}
+
+// A signed decimal number of `input`, after the leading whitespace, the
+// byte following its last digit being consumed
+fn read_number<R: Read>(input: &mut R, (ip, opcode): (u32, u8)) -> Result<i32, Fault> {
+    let mut text = String::new();
+    while let Some(byte) = read_byte(input, (ip, opcode))? {
+        let c = byte as char;
+        if c.is_ascii_digit() || (text.is_empty() && (c == '-' || c == '+')) {
+            text.push(c);
+        } else if !(text.is_empty() && c.is_ascii_whitespace()) {
+            break;
+        }
+    }
+    text.parse().map_err(|_| Fault::InvalidNumber { ip, opcode })
+}
+";
+
+// The start of the run function, up to the arms of the instructions
+const RUN: &str = "
+/// Run the program on `state` until it stops, the input instructions
+/// reading `input` and the output ones printing on `output`.
+#[allow(unused_variables)]
+pub fn run<R: Read, W: Write>(
+    state: &mut State,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Stop, Fault> {
+    let s = state;
+    loop {
+        match s.regs[0] {
+";
@@ -0,0 +1,203 @@
+use crate::debugger::Debugger;
+use crate::disasm::{decode, DecodedInsn, FormatOptions};
+use std::io::{self, BufRead, Write};
+
+// The ANSI escape sequences drawing the screen
+const CLEAR: &str = "\x1b[H\x1b[2J";
+const BOLD: &str = "\x1b[1m";
+const REVERSE: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+// The size of the panes: the disassembly and the registers are side by side
+const CODE_WIDTH: usize = 44;
+const CODE_LINES: usize = 9;
+const CODE_CONTEXT: usize = 3; // The instructions shown before the IP
+const MEMORY_PANE_SIZE: u32 = 64; // The bytes of the memory pane
+const OUTPUT_LINES: usize = 4;
+const MESSAGE_LINES: usize = 4;
+
+/// A terminal user interface over a [Debugger]. After every command, the
+/// screen is drawn again with panes showing the disassembly around the IP,
+/// the registers, the memory, the output of the program and the result of
+/// the command.
+///
+/// It takes the commands of the debugger, and `view <addr>` which scrolls
+/// the memory pane to `addr`.
+pub struct Tui {
+    debugger: Debugger,
+    memory_view: u32,  // The first address of the memory pane
+    messages: Vec<u8>, // What the last command wrote
+}
+
+impl Tui {
+    pub fn new(mut debugger: Debugger) -> Self {
+        debugger.capture_program_output();
+        return Tui {
+            debugger,
+            memory_view: 0,
+            messages: Vec::new(),
+        };
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        return &self.debugger;
+    }
+
+    /// Read commands from `input` until `quit` or the end of input, drawing
+    /// the screen on `output` before every one of them.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "{}(rvm) ", self.render())?;
+            output.flush()?;
+            let Some(line) = lines.next() else {
+                writeln!(output)?;
+                return Ok(());
+            };
+            if !self.execute(&line?)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute a command line, keeping what it writes for the screen. It
+    /// returns `false` if the command is `quit`.
+    pub fn execute(&mut self, line: &str) -> io::Result<bool> {
+        self.messages.clear();
+        if let ["view", address] = line.split_whitespace().collect::<Vec<_>>()[..] {
+            match self.debugger.address(address) {
+                Ok(address) => self.memory_view = address,
+                Err(message) => writeln!(self.messages, "{}", message)?,
+            }
+            return Ok(true);
+        }
+        return self.debugger.execute(line, &mut self.messages);
+    }
+
+    /// The screen, made of the panes and of ANSI escape sequences.
+    pub fn render(&self) -> String {
+        let mut screen = String::from(CLEAR);
+        let code = self.code_pane();
+        let registers = self.registers_pane();
+        screen += &format!(
+            "{}{:<width$}{} {}Registers{}\n",
+            BOLD,
+            "Disassembly",
+            RESET,
+            BOLD,
+            RESET,
+            width = CODE_WIDTH
+        );
+        // The code column is padded below the last instruction, so that the
+        // registers are all shown
+        for line in 0..code.len().max(registers.len()) {
+            let (text, current) = code.get(line).map_or(("", false), |(text, current)| {
+                return (text.as_str(), *current);
+            });
+            let text: String = format!("{:<width$}", text, width = CODE_WIDTH)
+                .chars()
+                .take(CODE_WIDTH)
+                .collect();
+            if current {
+                screen += &format!("{}{}{}", REVERSE, text, RESET);
+            } else {
+                screen += &text;
+            }
+            screen += &format!(" {}\n", registers.get(line).map_or("", |r| r.as_str()));
+        }
+
+        screen += &format!("{}Memory{}\n", BOLD, RESET);
+        let end = self.memory_view.saturating_add(MEMORY_PANE_SIZE);
+        screen += &self.debugger.machine().hexdump(self.memory_view..end);
+
+        let output = String::from_utf8_lossy(self.debugger.program_output()).to_string();
+        let messages = String::from_utf8_lossy(&self.messages).to_string();
+        for (title, text, count) in [
+            ("Output", output, OUTPUT_LINES),
+            ("Debugger", messages, MESSAGE_LINES),
+        ] {
+            screen += &format!("{}{}{}\n", BOLD, title, RESET);
+            let lines: Vec<&str> = text.lines().collect();
+            for line in &lines[lines.len().saturating_sub(count)..] {
+                screen += &format!("{}\n", line);
+            }
+        }
+        return screen;
+    }
+
+    // The lines of the disassembly pane, telling which one is at the IP
+    fn code_pane(&self) -> Vec<(String, bool)> {
+        let machine = self.debugger.machine();
+        let memory = machine.memory();
+        let ip = machine.regs()[0] as usize;
+        if ip >= memory.len() {
+            return vec![(format!("  0x{:04x}: outside the memory", ip), true)];
+        }
+
+        // The instructions before the IP are found by decoding from a little
+        // before it, until an instruction starts at the IP
+        let mut instructions: Vec<DecodedInsn> = Vec::new();
+        for start in ip.saturating_sub(8 * CODE_CONTEXT)..ip {
+            let mut address = start;
+            let mut before: Vec<DecodedInsn> = Vec::new();
+            while address < ip {
+                let insn = decode(&memory[address..], address);
+                address += insn.bytes.len();
+                before.push(insn);
+            }
+            if address == ip {
+                let skipped = before.len().saturating_sub(CODE_CONTEXT);
+                instructions.extend(before.into_iter().skip(skipped));
+                break;
+            }
+        }
+        let mut address = ip;
+        while instructions.len() < CODE_LINES && address < memory.len() {
+            let insn = decode(&memory[address..], address);
+            address += insn.bytes.len();
+            instructions.push(insn);
+        }
+
+        let options = FormatOptions {
+            symbols: self.debugger.debug_info().map(|d| d.symbols()),
+            aliases: false,
+        };
+        return instructions
+            .iter()
+            .map(|insn| {
+                let address = insn.address as u32;
+                let marker = match (insn.address == ip, machine.has_breakpoint(address)) {
+                    (true, _) => '>',
+                    (false, true) => '*',
+                    (false, false) => ' ',
+                };
+                let text = format!("{} 0x{:04x}: {}", marker, address, insn.format(&options));
+                return (text, insn.address == ip);
+            })
+            .collect();
+    }
+
+    // The lines of the registers pane, with the flags at the end
+    fn registers_pane(&self) -> Vec<String> {
+        let machine = self.debugger.machine();
+        let regs = machine.regs();
+        let mut lines: Vec<String> = (0..regs.len() / 2)
+            .map(|i| {
+                let j = i + regs.len() / 2;
+                return format!(
+                    "{:<3} 0x{:08x}  {:<3} 0x{:08x}",
+                    format!("r{}", i),
+                    regs[i],
+                    format!("r{}", j),
+                    regs[j]
+                );
+            })
+            .collect();
+        let flags = machine.flags();
+        lines.push(format!(
+            "zero={} negative={} carry={} overflow={}",
+            flags.zero as u8, flags.negative as u8, flags.carry as u8, flags.overflow as u8
+        ));
+        return lines;
+    }
+}
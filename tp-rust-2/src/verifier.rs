@@ -0,0 +1,108 @@
+use crate::disasm::decode;
+use crate::isa::{instruction_info, Operand};
+use std::collections::BTreeSet;
+use std::fmt;
+
+// There are 16 general-purpose and 8 floating-point registers
+const NREGS: u32 = 16;
+const NFREGS: u32 = 8;
+
+// An error found by the verifier, with the address of the instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    InvalidOpcode(usize, u8),    // Opcode of no instruction
+    InvalidRegister(usize, u32), // Register that doesn't exist
+    Truncated(usize),            // Operands running off the end of the program
+    OutsideProgram(usize, i64),  // Execution continuing outside the program
+}
+
+impl VerifyError {
+    /// The address of the instruction in error.
+    pub fn address(&self) -> usize {
+        match self {
+            VerifyError::InvalidOpcode(address, _)
+            | VerifyError::InvalidRegister(address, _)
+            | VerifyError::Truncated(address)
+            | VerifyError::OutsideProgram(address, _) => return *address,
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::InvalidOpcode(a, o) => write!(f, "0x{:04x}: invalid opcode {}", a, o),
+            VerifyError::InvalidRegister(a, r) => {
+                write!(f, "0x{:04x}: invalid register {}", a, r)
+            }
+            VerifyError::Truncated(a) => write!(f, "0x{:04x}: truncated instruction", a),
+            VerifyError::OutsideProgram(a, t) => {
+                write!(f, "0x{:04x}: continues outside the program at {}", a, t)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Check, before running it, that every instruction of `program` the
+/// execution can reach from address 0 is valid: its opcode is known, its
+/// registers exist, its operands do not run off the end of the program,
+/// and the instructions it continues with are within the program.
+///
+/// The instructions are followed through the jumps, branches and calls.
+/// The addresses computed at run time, such as the return address of `ret`,
+/// are not followed. The errors are sorted by address.
+pub fn verify(program: &[u8]) -> Result<(), Vec<VerifyError>> {
+    let mut errors: Vec<VerifyError> = Vec::new();
+    let mut visited: BTreeSet<usize> = BTreeSet::new();
+    let mut pending: Vec<usize> = if program.is_empty() { vec![] } else { vec![0] };
+    while let Some(address) = pending.pop() {
+        if !visited.insert(address) {
+            continue;
+        }
+        let opcode = program[address];
+        let Some(info) = instruction_info(opcode).filter(|info| is_supported(info.operands)) else {
+            errors.push(VerifyError::InvalidOpcode(address, opcode));
+            continue;
+        };
+        let insn = decode(&program[address..], address);
+        if insn.info.is_none() {
+            errors.push(VerifyError::Truncated(address));
+            continue;
+        }
+        for (kind, register) in info.operands.iter().zip(&insn.operands) {
+            let count = match kind {
+                Operand::Reg => NREGS,
+                Operand::FReg => NFREGS,
+                _ => continue,
+            };
+            if *register >= count {
+                errors.push(VerifyError::InvalidRegister(address, *register));
+            }
+        }
+
+        let next = (address + insn.bytes.len()) as i64;
+        let successors = insn
+            .target()
+            .into_iter()
+            .chain(Some(next).filter(|_| info.falls_through()));
+        for successor in successors {
+            match usize::try_from(successor) {
+                Ok(successor) if successor < program.len() => pending.push(successor),
+                _ => errors.push(VerifyError::OutsideProgram(address, successor)),
+            }
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.sort_by_key(|error| error.address());
+    return Err(errors);
+}
+
+// Whether the machine supports the instructions with these operands: the
+// floating-point ones require the `fp` feature
+fn is_supported(operands: &[Operand]) -> bool {
+    return cfg!(feature = "fp") || !operands.contains(&Operand::FReg);
+}
@@ -0,0 +1,612 @@
+use interpreter::{
+    assemble, assemble_with_listing, assemble_with_symbols, AsmError, AsmErrorKind, Assembler,
+    Machine, INSTRUCTIONS,
+};
+use std::collections::HashMap;
+
+#[test]
+fn assemble_gcd() {
+    let source = "
+        ; greatest common divisor of r10 and r11 into r11
+        beq r10, r11, 24     ; loop
+        bge r10, r11, 17
+        sub r11, r11, r10
+        jmp 0
+        sub r10, r10, r11    ; greater
+        jmp 0
+        exit                 ; done
+    ";
+    assert_eq!(
+        &include_bytes!("gcd.bin")[..],
+        &assemble(source).unwrap()[..]
+    );
+}
+
+#[test]
+fn assemble_function() {
+    let source = "
+        loadimm r2, 4096
+        loadimm r3, 4
+        sub r2, r2, r3
+        loadimm r3, 23
+        store r2, r3
+        loadimm r0, 24
+        exit
+        loadimm r10, 42
+        loadimm r3, -4
+        sub r2, r2, r3
+        loadimm r3, 4
+        sub r3, r2, r3
+        load r0, r3
+    ";
+    let program = assemble(source).unwrap();
+    assert_eq!(&include_bytes!("function.bin")[..], &program[..]);
+
+    let mut machine = Machine::try_new(&program).unwrap();
+    machine.run().unwrap();
+    assert_eq!(42, machine.regs()[10]);
+}
+
+#[test]
+fn assemble_operands() {
+    assert_eq!(vec![4, 1, 0xfe, 0xff], assemble("loadimm r1, -2").unwrap());
+    assert_eq!(
+        vec![4, 1, 0xff, 0xff],
+        assemble("LOADIMM R1, 0xffff").unwrap()
+    );
+    assert_eq!(vec![4, 1, 65, 0], assemble("loadimm r1, 'A'").unwrap());
+    assert_eq!(vec![4, 1, 5, 0], assemble("loadimm r1,0b101").unwrap());
+    assert_eq!(
+        vec![36, 15, 0x78, 0x56, 0x34, 0x12],
+        assemble("loadimm32 r15, 0x12345678").unwrap()
+    );
+    assert_eq!(
+        vec![46, 1, 2, 0x80],
+        assemble("loadx r1, r2, -128").unwrap()
+    );
+    assert_eq!(
+        vec![47, 1, 0x7f, 2],
+        assemble("storex r1, 127, r2").unwrap()
+    );
+    assert_eq!(vec![25, 0xfd, 0xff], assemble("jrel -3").unwrap());
+    assert_eq!(vec![58, 7, 0, 1], assemble("fadd f7, f0, f1").unwrap());
+    assert_eq!(vec![6, 1, 7], assemble("out r1 ; 'x'\n\n   exit").unwrap());
+}
+
+#[test]
+fn assemble_register_aliases() {
+    assert_eq!(
+        assemble("push lr\nmov r1, r15\nadd r4, r3, r2\nout r1\nmov r0, r14").unwrap(),
+        assemble("push r14\nmov a0, sp\nadd a3, a2, a1\nout A0\nmov ip, LR").unwrap()
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidRegister("a4".to_string()),
+        error("out a4").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidRegister("sp".to_string()),
+        error("fout sp").kind
+    );
+}
+
+#[test]
+fn assemble_every_mnemonic() {
+    for info in INSTRUCTIONS {
+        let operands: Vec<&str> = info
+            .operands
+            .iter()
+            .map(|op| match op {
+                interpreter::Operand::Reg => "r1",
+                interpreter::Operand::FReg => "f1",
+                _ => "1",
+            })
+            .collect();
+        let source = format!("{} {}", info.mnemonic, operands.join(", "));
+        let program = assemble(&source).unwrap();
+        assert_eq!(info.opcode, program[0]);
+        assert_eq!(info.size(), program.len());
+    }
+}
+
+fn error(source: &str) -> AsmError {
+    assemble(source).unwrap_err()
+}
+
+#[test]
+fn assembler_errors() {
+    assert_eq!(
+        AsmError {
+            line: 2,
+            kind: AsmErrorKind::UnknownMnemonic("foo".to_string())
+        },
+        error("exit\nfoo r1")
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidRegister("r16".to_string()),
+        error("out r16").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidRegister("f8".to_string()),
+        error("fout f8").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidRegister("42".to_string()),
+        error("out 42").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidImmediate("12z".to_string()),
+        error("loadimm r1, 12z").kind
+    );
+    assert_eq!(
+        AsmErrorKind::ImmediateOutOfRange(65536),
+        error("loadimm r1, 65536").kind
+    );
+    assert_eq!(AsmErrorKind::ImmediateOutOfRange(-1), error("jmp -1").kind);
+    assert_eq!(AsmErrorKind::WrongOperandCount(3), error("sub r1, r2").kind);
+    assert_eq!(AsmErrorKind::WrongOperandCount(0), error("exit r1").kind);
+    assert_eq!(
+        "line 1: expected 0 operand(s)",
+        error("exit r1").to_string()
+    );
+}
+
+#[test]
+fn assemble_labels() {
+    let source = "
+        ; greatest common divisor of r10 and r11 into r11
+        loop:
+            beq r10, r11, done
+            bge r10, r11, greater
+            sub r11, r11, r10
+            jmp loop
+        greater: sub r10, r10, r11
+            jmp loop
+        done:
+            exit
+    ";
+    assert_eq!(
+        &include_bytes!("gcd.bin")[..],
+        &assemble(source).unwrap()[..]
+    );
+}
+
+#[test]
+fn assemble_labels_in_immediates() {
+    let source = "
+            loadimm r1, data
+            jrel skip
+        back:
+            exit
+        skip:
+            jrel back
+            loadimm32 r2, back
+        data:
+    ";
+    assert_eq!(
+        vec![4, 1, 17, 0, 25, 1, 0, 7, 25, 0xfc, 0xff, 36, 2, 7, 0, 0, 0],
+        assemble(source).unwrap()
+    );
+}
+
+#[test]
+fn assemble_nested_calls() {
+    let source = "
+            loadimm r10, 1
+            call outer
+            exit
+        outer:
+            loadimm r3, 256
+            store r3, r14
+            add r10, r10, r10
+            call inner
+            loadimm r3, 256
+            load r14, r3
+            add r10, r10, r10
+            ret
+        inner:
+            loadimm r4, 5
+            add r10, r10, r4
+            ret
+    ";
+    assert_eq!(
+        &include_bytes!("nested_calls.bin")[..],
+        &assemble(source).unwrap()[..]
+    );
+}
+
+#[test]
+fn label_errors() {
+    assert_eq!(
+        AsmError {
+            line: 3,
+            kind: AsmErrorKind::DuplicateLabel("here".to_string())
+        },
+        error("here:\n  exit\nhere: exit")
+    );
+    assert_eq!(
+        AsmError {
+            line: 2,
+            kind: AsmErrorKind::UndefinedLabel("nowhere".to_string())
+        },
+        error("exit\njmp nowhere")
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidLabel("1abc".to_string()),
+        error("1abc: exit").kind
+    );
+}
+
+#[test]
+fn assemble_data_directives() {
+    let source = r#"
+            .byte 1, -1, 'A', '\n', end
+            .word 0x12345678, -2, end
+            .ascii "a, b; \"c\""
+            .asciiz "\x41\0é"
+            .space 3
+            .space 2, 0xaa
+        end:
+    "#;
+    let mut expected = vec![1, 0xff, b'A', b'\n', 36];
+    expected.extend([0x78, 0x56, 0x34, 0x12, 0xfe, 0xff, 0xff, 0xff, 36, 0, 0, 0]);
+    expected.extend(b"a, b; \"c\"");
+    expected.extend([b'A', 0, 0xc3, 0xa9, 0]);
+    expected.extend([0, 0, 0, 0xaa, 0xaa]);
+    assert_eq!(expected, assemble(source).unwrap());
+}
+
+#[test]
+fn print_embedded_string() {
+    let source = r#"
+            loadimm r1, message
+            outs r1
+            exit
+        message:
+            .asciiz "Hello, World!\n"
+    "#;
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!("Hello, World!\n".as_bytes(), &out[..]);
+}
+
+#[test]
+fn directive_errors() {
+    assert_eq!(
+        AsmErrorKind::UnknownDirective(".foo".to_string()),
+        error(".foo 1").kind
+    );
+    assert_eq!(
+        AsmErrorKind::ImmediateOutOfRange(256),
+        error(".byte 256").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidString("\"abc".to_string()),
+        error(".ascii \"abc").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidString("\"\\q\"".to_string()),
+        error(".asciiz \"\\q\"").kind
+    );
+    assert_eq!(AsmErrorKind::WrongOperandCount(1), error(".space").kind);
+    assert_eq!(
+        AsmErrorKind::ImmediateOutOfRange(-1),
+        error(".space -1").kind
+    );
+    assert_eq!(
+        AsmErrorKind::WrongOperandCount(1),
+        error(".ascii \"a\", \"b\"").kind
+    );
+}
+
+#[test]
+fn assemble_macros() {
+    let source = r#"
+        .macro load32 reg, value
+            loadimm32 \reg, \value
+        .endm
+        .macro print reg, text
+            jmp skip\@
+        text\@: .asciiz \text
+        skip\@:
+            load32 \reg, text\@
+            outs \reg
+        .endm
+        start:  print r1, "a, b"
+                print r2, "c"
+                exit
+    "#;
+    let program = assemble(source).unwrap();
+    assert_eq!(
+        vec![24, 8, 0, b'a', b',', b' ', b'b', 0, 36, 1, 3, 0, 0, 0, 51, 1],
+        program[..16]
+    );
+    let mut machine = Machine::try_new(&program).unwrap();
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!(b"a, bc", &out[..]);
+}
+
+#[test]
+fn macro_errors() {
+    let invoke = ".macro inc reg\n add \\reg, \\reg, r9\n.endm\n\ninc r1, r2";
+    assert_eq!(
+        AsmError {
+            line: 5,
+            kind: AsmErrorKind::WrongOperandCount(1)
+        },
+        error(invoke)
+    );
+    let expand = ".macro inc reg\n add \\reg, \\reg, r9\n.endm\n\ninc r16";
+    assert_eq!(
+        AsmError {
+            line: 5,
+            kind: AsmErrorKind::InvalidRegister("r16".to_string())
+        },
+        error(expand)
+    );
+    assert_eq!(
+        AsmError {
+            line: 2,
+            kind: AsmErrorKind::UnterminatedMacro("m".to_string())
+        },
+        error("exit\n.macro m\nexit")
+    );
+    assert_eq!(
+        AsmErrorKind::DuplicateMacro("m".to_string()),
+        error(".macro m\n.endm\n.macro m\n.endm").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidMacroName("add".to_string()),
+        error(".macro add\n.endm").kind
+    );
+    assert_eq!(
+        AsmErrorKind::UnexpectedDirective(".endm".to_string()),
+        error(".endm").kind
+    );
+    assert_eq!(
+        AsmErrorKind::RecursiveMacro("m".to_string()),
+        error(".macro m\nm\n.endm\nm").kind
+    );
+}
+
+#[test]
+fn assemble_expressions() {
+    let source = r#"
+            jmp start
+        data:
+            .byte 'a', '\x41', 1 << 7
+            .space 2
+        end:
+            .equ BUFFER, data + 2
+            .equ INDEX, 3
+            .equ SIZE, end - data
+        start:
+            loadimm r1, (BUFFER + 4*INDEX)
+            loadimm r2, -(1 << 4) / 2 + '\''
+            loadimm r3, SIZE * 2 >> 1
+            jrel end - 2
+            .word INDEX - 4
+            .space SIZE - 3
+    "#;
+    assert_eq!(
+        vec![
+            24, 8, 0, 97, 65, 128, 0, 0, 4, 1, 17, 0, 4, 2, 31, 0, 4, 3, 5, 0, 25, 0xef, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0, 0
+        ],
+        assemble(source).unwrap()
+    );
+}
+
+#[test]
+fn expression_errors() {
+    assert_eq!(
+        AsmErrorKind::DivisionByZero,
+        error("loadimm r1, 1 / (2 - 2)").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidExpression("(1 + 2".to_string()),
+        error("loadimm r1, (1 + 2").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidExpression("1 +".to_string()),
+        error("loadimm r1, 1 +").kind
+    );
+    assert_eq!(
+        AsmErrorKind::InvalidExpression("1 2".to_string()),
+        error("loadimm r1, 1 2").kind
+    );
+    assert_eq!(
+        AsmErrorKind::UndefinedLabel("later".to_string()),
+        error(".equ A, later\nlater: exit").kind
+    );
+    assert_eq!(
+        AsmErrorKind::DuplicateLabel("A".to_string()),
+        error(".equ A, 1\nA: exit").kind
+    );
+    assert_eq!(AsmErrorKind::WrongOperandCount(2), error(".equ A").kind);
+}
+
+#[test]
+fn assemble_symbol_table() {
+    let source = "
+        start: loadimm r1, 1
+        here:
+        again: jmp start
+        end:
+        .equ SIZE, 4
+        .equ AFTER, end + SIZE
+    ";
+    let (_, symbols) = assemble_with_symbols(source).unwrap();
+    assert_eq!(
+        vec![
+            ("AFTER", 11),
+            ("again", 4),
+            ("end", 7),
+            ("here", 4),
+            ("start", 0)
+        ],
+        symbols.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(None, symbols.address("SIZE"));
+    assert_eq!(Some("again"), symbols.label_at(4));
+    assert_eq!(&["again", "here"], symbols.labels_at(4));
+    assert_eq!(None, symbols.label_at(1));
+}
+
+#[test]
+fn assemble_listing() {
+    let source = "\
+.macro twice reg
+    add \\reg, \\reg, \\reg
+    add \\reg, \\reg, \\reg
+.endm
+start:  loadimm r1, 1   ; one
+        twice r1
+
+        .section .data
+msg:    .asciiz \"Hello, World!\"
+        .section .text
+        exit";
+    let (program, listing) = assemble_with_listing(source).unwrap();
+    assert_eq!(assemble(source).unwrap(), program);
+    // The first line of the listing starts with spaces
+    let expected = "
+                                 1  .macro twice reg
+                                 2      add \\reg, \\reg, \\reg
+                                 3      add \\reg, \\reg, \\reg
+                                 4  .endm
+0000  04 01 01 00                5  start:  loadimm r1, 1   ; one
+0004  09 01 01 01                6          twice r1
+0008  09 01 01 01
+                                 7
+                                 8          .section .data
+000d  48 65 6c 6c 6f 2c 20 57    9  msg:    .asciiz \"Hello, World!\"
+0015  6f 72 6c 64 21 00
+                                10          .section .text
+000c  07                        11          exit
+";
+    assert_eq!(&expected[1..], listing);
+}
+
+#[test]
+fn error_columns() {
+    let column = |source: &str| assemble(source).unwrap_err().column(source);
+    assert_eq!(5, column("    foo r1"));
+    assert_eq!(13, column("loadimm r1, 12z ; r1"));
+    assert_eq!(9, column("exit\nadd r1, r16, r2"));
+    assert_eq!(6, column("call ex ; ex"));
+    assert_eq!(19, column("next: .word next, ex"));
+    // The text of the error is not in the line
+    assert_eq!(3, column("  loadimm r1, 0x10000"));
+    assert_eq!(1, column(".macro m\nadd r1, r1, r99\n.endm\nm"));
+}
+
+// An assembler including the files of `files`, given by name and content
+fn assembler(files: &[(&'static str, &'static str)]) -> Assembler {
+    let files: HashMap<&str, &str> = files.iter().copied().collect();
+    let mut assembler = Assembler::new();
+    assembler.set_include_loader(move |name: &str| match files.get(name) {
+        Some(content) => Ok(content.to_string()),
+        None => Err(std::io::Error::from(std::io::ErrorKind::NotFound)),
+    });
+    assembler
+}
+
+#[test]
+fn assemble_includes() {
+    let mut assembler = assembler(&[
+        (
+            "print.s",
+            ".macro print reg\n outs \\reg\n.endm\n.include \"exit.s\"",
+        ),
+        ("exit.s", "done: exit"),
+    ]);
+    let source = "
+            .include \"print.s\"
+            loadimm r1, message
+            print r1
+            jmp done
+        message: .asciiz \"hi\"
+    ";
+    let program = assembler.assemble(source).unwrap();
+    assert_eq!(
+        vec![7, 4, 1, 10, 0, 51, 1, 24, 0, 0, b'h', b'i', 0],
+        program
+    );
+
+    // The same file can be included several times
+    let again = ".include \"exit.s\"\n.include \"exit.s\"";
+    assert_eq!(
+        AsmErrorKind::Included(
+            "exit.s".to_string(),
+            Box::new(AsmError {
+                line: 1,
+                kind: AsmErrorKind::DuplicateLabel("done".to_string())
+            })
+        ),
+        assembler.assemble(again).unwrap_err().kind
+    );
+}
+
+#[test]
+fn include_errors() {
+    let mut assembler = assembler(&[
+        ("a.s", "exit\n.include \"b.s\""),
+        ("b.s", ".include \"a.s\""),
+        ("bad.s", "\n\nfoo"),
+        ("nested.s", "exit\n.include \"bad.s\""),
+    ]);
+    assert_eq!(
+        AsmError {
+            line: 2,
+            kind: AsmErrorKind::Included(
+                "nested.s".to_string(),
+                Box::new(AsmError {
+                    line: 2,
+                    kind: AsmErrorKind::Included(
+                        "bad.s".to_string(),
+                        Box::new(AsmError {
+                            line: 3,
+                            kind: AsmErrorKind::UnknownMnemonic("foo".to_string())
+                        })
+                    )
+                })
+            )
+        },
+        assembler
+            .assemble("exit\n.include \"nested.s\"")
+            .unwrap_err()
+    );
+    assert_eq!(
+        "line 2: in `nested.s`, line 2: in `bad.s`, line 3: unknown mnemonic `foo`",
+        assembler
+            .assemble("exit\n.include \"nested.s\"")
+            .unwrap_err()
+            .to_string()
+    );
+
+    // a.s includes b.s which includes a.s again
+    let cycle = assembler.assemble(".include \"a.s\"").unwrap_err();
+    let AsmErrorKind::Included(_, cycle) = cycle.kind else {
+        panic!("{:?}", cycle)
+    };
+    let AsmErrorKind::Included(_, cycle) = cycle.kind else {
+        panic!("{:?}", cycle)
+    };
+    assert_eq!(AsmErrorKind::IncludeCycle("a.s".to_string()), cycle.kind);
+
+    assert!(matches!(
+        assembler.assemble(".include \"missing.s\"").unwrap_err().kind,
+        AsmErrorKind::IncludeFailed(name, _) if name == "missing.s"
+    ));
+    assert_eq!(
+        AsmErrorKind::InvalidString("missing.s".to_string()),
+        assembler.assemble(".include missing.s").unwrap_err().kind
+    );
+    // Without a loader, nothing can be included
+    assert_eq!(
+        AsmErrorKind::IncludeFailed("a.s".to_string(), "no include loader".to_string()),
+        error(".include \"a.s\"").kind
+    );
+}
@@ -1,3 +1,6 @@
+// The tests build their programs, known to fit, with Machine::new
+#![allow(deprecated)]
+
 use interpreter::Machine;
 
 fn create_machine(code: &[u8]) -> (Machine, Vec<u8>) {
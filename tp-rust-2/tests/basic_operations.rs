@@ -1,5 +1,13 @@
-use interpreter::Machine;
+// The tests build their programs, known to fit, with Machine::new
+#![allow(deprecated)]
+
+use interpreter::{
+    DecodedInsn, Flags, Frame, HookAction, LoadError, Machine, MachineError, MemoryObserver,
+    MemoryRead, MemoryWrite, Register, RunOutcome, StepHook, StepInfo, StepOutcome, SyscallHandler,
+};
 use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[test]
 fn create_with_memory() {
@@ -11,11 +19,92 @@ fn create_with_memory() {
 }
 
 #[test]
-#[should_panic]
+#[should_panic(expected = "the program is larger than the memory")]
 fn create_with_too_large_a_memory() {
     Machine::new(&[0; 4097]);
 }
 
+#[test]
+fn try_create_with_memory() {
+    let machine = Machine::try_new(&[1; 4096]).unwrap();
+    assert!(machine.memory().iter().all(|b| *b == 1));
+    assert!(matches!(
+        Machine::try_new(&[0; 4097]),
+        Err(LoadError::TooLarge)
+    ));
+}
+
+#[test]
+fn write_memory_from_host() {
+    // 0: load r1, r2
+    let mut machine = Machine::new(&[3, 1, 2]);
+    machine.set_reg(2, 0x100).unwrap();
+    machine.write_memory(0x100, &[42, 0, 0, 0]).unwrap();
+    assert_eq!(
+        Err(MachineError::NonExistingAddress {
+            ip: 0,
+            opcode: 0,
+            address: 4096
+        }),
+        machine.write_memory(0xffe, &[1, 2, 3])
+    );
+    assert_eq!(&[0, 0], &machine.memory()[0xffe..]);
+    machine.step_on(&mut io::sink()).unwrap();
+    assert_eq!(42, machine.regs()[1]);
+
+    // The program can be patched in place, here into `load r3, r2`
+    machine.memory_mut()[1] = 3;
+    machine.set_reg(0, 0).unwrap();
+    machine.step_on(&mut io::sink()).unwrap();
+    assert_eq!(42, machine.regs()[3]);
+}
+
+#[test]
+fn access_registers_by_name() {
+    let mut machine = Machine::new(&[]);
+    machine.set(Register::R3, 42);
+    machine.set(Register::SP, 0x1000);
+    assert_eq!(42, machine.reg(Register::R3));
+    assert_eq!(42, machine.regs()[3]);
+    assert_eq!(0x1000, machine.regs()[15]);
+    assert_eq!(0, machine.reg(Register::IP));
+    assert_eq!(Register::LR, Register::R14);
+
+    assert_eq!(Some(Register::R15), Register::from_index(15));
+    assert_eq!(None, Register::from_index(16));
+    assert_eq!(7, Register::R7.index());
+    assert_eq!("r7", Register::R7.to_string());
+}
+
+#[test]
+fn create_with_memory_size() {
+    // 0: store r1, r2
+    // 3: load r3, r1
+    let mut machine = Machine::with_memory_size(0x2004, &[2, 1, 2, 3, 3, 1]).unwrap();
+    assert_eq!(0x2004, machine.memory().len());
+    machine.set_reg(1, 0x2000).unwrap();
+    machine.set_reg(2, 42).unwrap();
+    machine.step_on(&mut io::sink()).unwrap();
+    machine.step_on(&mut io::sink()).unwrap();
+    assert_eq!(42, machine.regs()[3]);
+
+    // The addresses past the end are still outside the memory
+    machine.set_reg(0, 0).unwrap();
+    machine.set_reg(1, 0x2001).unwrap();
+    assert_eq!(
+        Err(MachineError::NonExistingAddress {
+            ip: 0,
+            opcode: 2,
+            address: 0x2001
+        }),
+        machine.step_on(&mut io::sink())
+    );
+    assert!(matches!(
+        Machine::with_memory_size(2, &[1, 2, 3]),
+        Err(LoadError::TooLarge)
+    ));
+}
+
 #[test]
 fn refuse_illegal_instruction() {
     let mut machine = Machine::new(&[]);
@@ -23,8 +112,13 @@ fn refuse_illegal_instruction() {
 }
 
 fn expect_on<T: Write>(machine: &mut Machine, fd: &mut T, end: bool, new_ip: usize) {
+    let outcome = if end {
+        StepOutcome::Exited
+    } else {
+        StepOutcome::Continue
+    };
     match machine.step_on(fd) {
-        Ok(r) if r == end => (),
+        Ok(r) if r == outcome => (),
         _ => panic!(),
     }
     assert_eq!(new_ip, machine.regs()[0] as usize,);
@@ -42,6 +136,32 @@ fn test_exit() {
     expect(&mut machine, true, 1);
 }
 
+#[test]
+fn test_exit_code() {
+    // 0: exit
+    // 1:
+    let mut machine = Machine::new(&[7]);
+    assert_eq!(None, machine.exit_code());
+    expect(&mut machine, true, 1);
+    assert_eq!(Some(0), machine.exit_code());
+}
+
+#[test]
+fn test_halt() {
+    // 0: halt r1
+    // 2:
+    let mut machine = Machine::new(&[44, 1]);
+    machine.set_reg(1, 42).unwrap();
+    expect(&mut machine, true, 2);
+    assert_eq!(Some(42), machine.exit_code());
+
+    // 0: halt r100
+    // 2:
+    let mut machine = Machine::new(&[44, 100]);
+    assert!(machine.step().is_err());
+    assert_eq!(None, machine.exit_code());
+}
+
 #[test]
 fn ip_reg() {
     // 0: invalid
@@ -230,6 +350,117 @@ fn test_out() {
     assert_eq!("A".as_bytes(), &out[..]);
 }
 
+// A writer whose writes all fail
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn out_to_failing_output() {
+    // 0: out r1
+    let mut machine = Machine::new(&[6, 1]);
+    let error = machine.step_on(&mut BrokenPipe).unwrap_err();
+    assert_eq!(
+        MachineError::IoError {
+            ip: 0,
+            opcode: 6,
+            kind: io::ErrorKind::BrokenPipe
+        },
+        error
+    );
+    assert_eq!(
+        "input or output error: broken pipe in `out` at 0x0000",
+        error.to_string()
+    );
+}
+
+#[test]
+fn buffer_the_output() {
+    // 0: out r1
+    // 2: out_number r2
+    // 4: exit
+    let program = [6, 1, 8, 2, 7];
+    let buffered = || {
+        let mut machine = Machine::new(&program);
+        machine.set_output_buffer(16);
+        machine.set_reg(1, b'A' as u32).unwrap();
+        machine.set_reg(2, 42).unwrap();
+        machine
+    };
+    let mut machine = buffered();
+    let mut out = Vec::new();
+    assert_eq!(StepOutcome::Continue, machine.step_on(&mut out).unwrap());
+    assert_eq!(StepOutcome::Continue, machine.step_on(&mut out).unwrap());
+    assert!(out.is_empty());
+    machine.flush_output(&mut out).unwrap();
+    assert_eq!(b"A42", &out[..]);
+
+    // The exit writes the buffer, so does the end of a run
+    let mut machine = buffered();
+    let mut out = Vec::new();
+    machine.step_on(&mut out).unwrap();
+    machine.step_on(&mut out).unwrap();
+    assert_eq!(StepOutcome::Exited, machine.step_on(&mut out).unwrap());
+    assert_eq!(b"A42", &out[..]);
+    let mut machine = buffered();
+    let mut out = Vec::new();
+    let outcome = machine.run_for_on(2, &mut out).unwrap();
+    assert_eq!(RunOutcome::StepLimitReached, outcome);
+    assert_eq!(b"A42", &out[..]);
+
+    // A full buffer is written by the instruction filling it
+    let mut machine = buffered();
+    machine.set_output_buffer(2);
+    let mut out = Vec::new();
+    machine.step_on(&mut out).unwrap();
+    assert!(out.is_empty());
+    machine.step_on(&mut out).unwrap();
+    assert_eq!(b"A42", &out[..]);
+}
+
+#[test]
+fn write_the_output_before_reading() {
+    // 0: out r1
+    // 2: in r2
+    let mut machine = Machine::new(&[6, 1, 34, 2]);
+    machine.set_output_buffer(16);
+    machine.set_reg(1, b'?' as u32).unwrap();
+    let mut out = Vec::new();
+    machine.step_with_io(&mut &b"x"[..], &mut out).unwrap();
+    assert!(out.is_empty());
+    machine.step_with_io(&mut &b"x"[..], &mut out).unwrap();
+    assert_eq!(b"?", &out[..]);
+    assert_eq!(b'x' as u32, machine.regs()[2]);
+}
+
+#[test]
+fn write_the_buffer_to_failing_output() {
+    // 0: out r1
+    // 2: exit
+    let mut machine = Machine::new(&[6, 1, 7]);
+    machine.set_output_buffer(16);
+    assert_eq!(
+        StepOutcome::Continue,
+        machine.step_on(&mut BrokenPipe).unwrap()
+    );
+    assert_eq!(
+        MachineError::IoError {
+            ip: 2,
+            opcode: 7,
+            kind: io::ErrorKind::BrokenPipe
+        },
+        machine.step_on(&mut BrokenPipe).unwrap_err()
+    );
+}
+
 #[test]
 fn test_out_number() {
     // 0: out_number r0
@@ -319,6 +550,31 @@ fn exec_near_end_of_memory() {
     assert!(machine.step().is_err());
 }
 
+#[test]
+fn operands_past_end_of_memory() {
+    // memory_size-2: add r1, r1, ...
+    let mut memory = Machine::new(&[]).memory().to_vec();
+    let memory_size = memory.len();
+    memory[memory_size - 2] = 9;
+    memory[memory_size - 1] = 1;
+    let mut machine = Machine::new(&memory);
+    machine.set_reg(0, (memory_size - 2) as u32).unwrap();
+    assert_eq!(
+        Err(MachineError::NonExistingAddress {
+            ip: memory_size as u32 - 2,
+            opcode: 9,
+            address: memory_size as u32
+        }),
+        machine.step_on(&mut io::sink())
+    );
+    assert_eq!(memory_size as u32 - 2, machine.regs()[0]);
+
+    // The IP doesn't wrap around the address space
+    machine.set_reg(0, 0xFFFF_FFFF).unwrap();
+    machine.ip_inc(4);
+    assert_eq!(0xFFFF_FFFF, machine.regs()[0]);
+}
+
 #[test]
 fn load_near_end_of_memory() {
     // 0: load r1 <- [r1]
@@ -328,7 +584,7 @@ fn load_near_end_of_memory() {
         .set_reg(1, (machine.memory().len() - 2) as u32)
         .unwrap();
     assert!(machine.step().is_err());
-    assert_eq!(machine.regs()[0], 3);
+    assert_eq!(machine.regs()[0], 0);
 }
 
 #[test]
@@ -349,7 +605,7 @@ fn store_near_end_of_memory() {
         .set_reg(1, (machine.memory().len() - 2) as u32)
         .unwrap();
     assert!(machine.step().is_err());
-    assert_eq!(machine.regs()[0], 3);
+    assert_eq!(machine.regs()[0], 0);
 }
 
 #[test]
@@ -361,6 +617,31 @@ fn store_near_end_of_address_space() {
     assert!(machine.step().is_err());
 }
 
+#[test]
+fn access_at_end_of_address_space() {
+    // The addresses don't wrap around, and the error tells the start of
+    // the access
+    // 0: load r2, r1
+    // 3: store r1, r2
+    // 6: loadh r2, r1
+    // 9: storeb r1, r2
+    for (ip, opcode) in [(0, 3), (3, 2), (6, 40), (9, 39)] {
+        for address in [0xFFFF_FFFF, 0xFFFF_FFFE, 0xFFFF_FFFC] {
+            let mut machine = Machine::new(&[3, 2, 1, 2, 1, 2, 40, 2, 1, 39, 1, 2]);
+            machine.set_reg(0, ip).unwrap();
+            machine.set_reg(1, address).unwrap();
+            assert_eq!(
+                Err(MachineError::NonExistingAddress {
+                    ip,
+                    opcode,
+                    address
+                }),
+                machine.step_on(&mut io::sink())
+            );
+        }
+    }
+}
+
 #[test]
 fn sub_with_wraparound() {
     // 0: sub r1 <- r2 - r1
@@ -381,3 +662,2288 @@ fn sub_with_wraparound_neg() {
     expect(&mut machine, false, 4);
     assert_eq!(machine.regs()[1], 2113797824);
 }
+
+#[test]
+fn test_add() {
+    // 0: add r2 <- r1 + r0
+    // 4:
+    let mut machine = Machine::new(&[9, 2, 1, 0]);
+    machine.set_reg(1, 38).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(42, machine.regs()[2]);
+}
+
+#[test]
+fn test_add_out_of_bounds() {
+    // 0: add r100 <- r0 + r0
+    // 4:
+    let mut machine = Machine::new(&[9, 100, 0, 0]);
+    assert!(machine.step().is_err());
+
+    // 0: add r0 <- r100 + r0
+    // 4:
+    let mut machine = Machine::new(&[9, 0, 100, 0]);
+    assert!(machine.step().is_err());
+
+    // 0: add r0 <- r0 + r100
+    // 4:
+    let mut machine = Machine::new(&[9, 0, 0, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn add_with_wraparound() {
+    // 0: add r1 <- r1 + r2
+    // 4:
+    let mut machine = Machine::new(&[9, 1, 1, 2]);
+    machine.set_reg(1, 0xFFFF_FFFF).unwrap();
+    machine.set_reg(2, 3).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(machine.regs()[1], 2);
+}
+
+#[test]
+fn add_with_wraparound_neg() {
+    // 0: add r1 <- r1 + r2
+    // 4:
+    let mut machine = Machine::new(&[9, 1, 1, 2]);
+    machine.set_reg(1, i32::MAX as u32).unwrap();
+    machine.set_reg(2, 1).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(machine.regs()[1] as i32, i32::MIN);
+}
+
+#[test]
+fn test_mul() {
+    // 0: mul r3 <- r1 * r2
+    // 4:
+    let mut machine = Machine::new(&[10, 3, 1, 2]);
+    machine.set_reg(1, 6).unwrap();
+    machine.set_reg(2, -7i32 as u32).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(-42, machine.regs()[3] as i32);
+}
+
+#[test]
+fn mul_with_wraparound() {
+    // 0: mul r3 <- r1 * r2
+    // 4:
+    let mut machine = Machine::new(&[10, 3, 1, 2]);
+    machine.set_reg(1, 0x1234_5678).unwrap();
+    machine.set_reg(2, 0x100).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(0x3456_7800, machine.regs()[3]);
+}
+
+#[test]
+fn test_div() {
+    // 0: div r3 <- r1 / r2
+    // 4: divu r4 <- r1 / r2
+    // 8:
+    let mut machine = Machine::new(&[11, 3, 1, 2, 12, 4, 1, 2]);
+    machine.set_reg(1, -7i32 as u32).unwrap();
+    machine.set_reg(2, 2).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(-3, machine.regs()[3] as i32);
+    expect(&mut machine, false, 8);
+    assert_eq!(0x7FFF_FFFC, machine.regs()[4]);
+}
+
+#[test]
+fn div_with_wraparound() {
+    // 0: div r3 <- r1 / r2
+    // 4:
+    let mut machine = Machine::new(&[11, 3, 1, 2]);
+    machine.set_reg(1, i32::MIN as u32).unwrap();
+    machine.set_reg(2, -1i32 as u32).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(i32::MIN, machine.regs()[3] as i32);
+}
+
+#[test]
+fn test_mod() {
+    // 0: mod r3 <- r1 % r2
+    // 4: modu r4 <- r1 % r2
+    // 8:
+    let mut machine = Machine::new(&[13, 3, 1, 2, 14, 4, 1, 2]);
+    machine.set_reg(1, -7i32 as u32).unwrap();
+    machine.set_reg(2, 3).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(-1, machine.regs()[3] as i32);
+    expect(&mut machine, false, 8);
+    assert_eq!(0xFFFF_FFF9 % 3, machine.regs()[4]);
+
+    // 0: mod r3 <- r1 % r2
+    // 4:
+    let mut machine = Machine::new(&[13, 3, 1, 2]);
+    machine.set_reg(1, i32::MIN as u32).unwrap();
+    machine.set_reg(2, -1i32 as u32).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(0, machine.regs()[3]);
+}
+
+#[test]
+fn division_by_zero() {
+    // 0: div/divu/mod/modu r3 <- r1 op r2 with r2 == 0
+    // 4:
+    for opcode in 11..=14 {
+        let mut machine = Machine::new(&[opcode, 3, 1, 2]);
+        machine.set_reg(1, 42).unwrap();
+        assert_eq!(
+            Err(MachineError::DivisionByZero { ip: 0, opcode }),
+            machine.step()
+        );
+    }
+}
+
+#[test]
+fn test_mul_div_out_of_bounds() {
+    // 0: mul/div/divu/mod/modu r100 <- r0 op r0
+    // 4:
+    for opcode in 10..=14 {
+        let mut machine = Machine::new(&[opcode, 100, 0, 0]);
+        assert_eq!(
+            Err(MachineError::NonExistingRegister {
+                ip: 0,
+                opcode,
+                register: 100
+            }),
+            machine.step()
+        );
+    }
+}
+
+#[test]
+fn test_and_or_xor() {
+    // 0: and r3 <- r1 & r2
+    // 4: or r4 <- r1 | r2
+    // 8: xor r5 <- r1 ^ r2
+    // 12:
+    let mut machine = Machine::new(&[15, 3, 1, 2, 16, 4, 1, 2, 17, 5, 1, 2]);
+    machine.set_reg(1, 0x1234_ABCD).unwrap();
+    machine.set_reg(2, 0x0000_FFFF).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(0x0000_ABCD, machine.regs()[3]);
+    expect(&mut machine, false, 8);
+    assert_eq!(0x1234_FFFF, machine.regs()[4]);
+    expect(&mut machine, false, 12);
+    assert_eq!(0x1234_5432, machine.regs()[5]);
+}
+
+#[test]
+fn test_not() {
+    // 0: not r2 <- !r1
+    // 3: not r1 <- !r1
+    // 6:
+    let mut machine = Machine::new(&[18, 2, 1, 18, 1, 1]);
+    machine.set_reg(1, 0xFF00_F0F0).unwrap();
+    expect(&mut machine, false, 3);
+    assert_eq!(0x00FF_0F0F, machine.regs()[2]);
+    expect(&mut machine, false, 6);
+    assert_eq!(0x00FF_0F0F, machine.regs()[1]);
+}
+
+#[test]
+fn extract_low_byte_with_mask() {
+    // 0: and r1 <- r1 & r2 with r2 == 0xff
+    // 4: xor r3 <- r1 ^ r1
+    // 8:
+    let mut machine = Machine::new(&[15, 1, 1, 2, 17, 3, 1, 1]);
+    machine.set_reg(1, 0xDEAD_BEEF).unwrap();
+    machine.set_reg(2, 0xFF).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(0xEF, machine.regs()[1]);
+    machine.set_reg(3, 12).unwrap();
+    expect(&mut machine, false, 8);
+    assert_eq!(0, machine.regs()[3]);
+}
+
+#[test]
+fn test_bitwise_out_of_bounds() {
+    // 0: and/or/xor r100 <- r0 op r0
+    // 4:
+    for opcode in 15..=17 {
+        let mut machine = Machine::new(&[opcode, 100, 0, 0]);
+        assert!(machine.step().is_err());
+        let mut machine = Machine::new(&[opcode, 0, 0, 100]);
+        assert!(machine.step().is_err());
+    }
+
+    // 0: not r1 <- !r100
+    // 3:
+    let mut machine = Machine::new(&[18, 1, 100]);
+    assert!(machine.step().is_err());
+}
+
+// Run a single shift or rotate instruction `r3 <- r1 op r2` and return r3
+fn shift(opcode: u8, value: u32, amount: u32) -> u32 {
+    let mut machine = Machine::new(&[opcode, 3, 1, 2]);
+    machine.set_reg(1, value).unwrap();
+    machine.set_reg(2, amount).unwrap();
+    expect(&mut machine, false, 4);
+    machine.regs()[3]
+}
+
+#[test]
+fn test_shl() {
+    assert_eq!(0x8000_0001, shift(19, 0x8000_0001, 0));
+    assert_eq!(0x0000_0010, shift(19, 0x8000_0001, 4));
+    assert_eq!(0x8000_0000, shift(19, 0x8000_0001, 31));
+    assert_eq!(0x8000_0001, shift(19, 0x8000_0001, 32));
+    assert_eq!(0x0000_0002, shift(19, 0x8000_0001, 33));
+}
+
+#[test]
+fn test_shr() {
+    assert_eq!(0x8000_0010, shift(20, 0x8000_0010, 0));
+    assert_eq!(0x0800_0001, shift(20, 0x8000_0010, 4));
+    assert_eq!(0x0000_0001, shift(20, 0x8000_0010, 31));
+    assert_eq!(0x8000_0010, shift(20, 0x8000_0010, 32));
+    assert_eq!(0x4000_0008, shift(20, 0x8000_0010, 0xFFFF_FFE1));
+}
+
+#[test]
+fn test_sar() {
+    assert_eq!(0x8000_0010, shift(21, 0x8000_0010, 0));
+    assert_eq!(0xF800_0001, shift(21, 0x8000_0010, 4));
+    assert_eq!(0xFFFF_FFFF, shift(21, 0x8000_0010, 31));
+    assert_eq!(0x0000_0000, shift(21, 0x4000_0010, 31));
+    assert_eq!(0x8000_0010, shift(21, 0x8000_0010, 32));
+    assert_eq!(0xC000_0008, shift(21, 0x8000_0010, 33));
+}
+
+#[test]
+fn test_rotate() {
+    // rol
+    assert_eq!(0x8000_0001, shift(22, 0x8000_0001, 0));
+    assert_eq!(0x0000_0018, shift(22, 0x8000_0001, 4));
+    assert_eq!(0xC000_0000, shift(22, 0x8000_0001, 31));
+    assert_eq!(0x8000_0001, shift(22, 0x8000_0001, 32));
+    assert_eq!(0x0000_0003, shift(22, 0x8000_0001, 33));
+
+    // ror
+    assert_eq!(0x8000_0001, shift(23, 0x8000_0001, 0));
+    assert_eq!(0x1800_0000, shift(23, 0x8000_0001, 4));
+    assert_eq!(0x0000_0003, shift(23, 0x8000_0001, 31));
+    assert_eq!(0x8000_0001, shift(23, 0x8000_0001, 32));
+    assert_eq!(0xC000_0000, shift(23, 0x8000_0001, 33));
+}
+
+#[test]
+fn test_shift_out_of_bounds() {
+    // 0: shl/shr/sar/rol/ror r1 <- r1 op r100
+    // 4:
+    for opcode in 19..=23 {
+        let mut machine = Machine::new(&[opcode, 1, 1, 100]);
+        assert!(machine.step().is_err());
+    }
+}
+
+#[test]
+fn test_jmp() {
+    // 0: jmp 0x0102
+    // 3:
+    let mut machine = Machine::new(&[24, 0x02, 0x01]);
+    expect(&mut machine, false, 0x0102);
+
+    // 0: jmp 0
+    // 3:
+    let mut machine = Machine::new(&[24, 0, 0]);
+    expect(&mut machine, false, 0);
+}
+
+#[test]
+fn test_jmp_out_of_bounds() {
+    // 0: jmp 0xffff
+    // 3:
+    let mut machine = Machine::new(&[24, 0xff, 0xff]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: jmp memory_size
+    // 3:
+    let memory_size = machine.memory().len();
+    let mut machine = Machine::new(&[24, memory_size as u8, (memory_size >> 8) as u8]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_jrel() {
+    // 0: jrel +7
+    // 3:
+    let mut machine = Machine::new(&[25, 7, 0]);
+    expect(&mut machine, false, 10);
+
+    // 0: loadimm r1, 0
+    // 4: jrel -3
+    // 7:
+    let mut machine = Machine::new(&[4, 1, 0, 0, 25, 0xfd, 0xff]);
+    expect(&mut machine, false, 4);
+    expect(&mut machine, false, 4);
+    expect(&mut machine, false, 4);
+
+    // 0: loadimm r1, 0
+    // 4: jrel -7
+    // 7:
+    let mut machine = Machine::new(&[4, 1, 0, 0, 25, 0xf9, 0xff]);
+    expect(&mut machine, false, 4);
+    expect(&mut machine, false, 0);
+}
+
+#[test]
+fn test_jrel_out_of_bounds() {
+    // 0: jrel -4
+    // 3:
+    let mut machine = Machine::new(&[25, 0xfc, 0xff]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: jrel +0x7fff
+    // 3:
+    let mut machine = Machine::new(&[25, 0xff, 0x7f]);
+    assert!(machine.step().is_err());
+}
+
+// Run a single branch instruction `jump #0x0100 if r1 op r2` and return the new IP
+fn branch(opcode: u8, left: i32, right: i32) -> u32 {
+    let mut machine = Machine::new(&[opcode, 1, 2, 0x00, 0x01]);
+    machine.set_reg(1, left as u32).unwrap();
+    machine.set_reg(2, right as u32).unwrap();
+    machine.step().unwrap();
+    machine.regs()[0]
+}
+
+#[test]
+fn test_beq_bne() {
+    assert_eq!(0x100, branch(26, 42, 42));
+    assert_eq!(5, branch(26, 42, 43));
+    assert_eq!(5, branch(27, 42, 42));
+    assert_eq!(0x100, branch(27, 42, 43));
+}
+
+#[test]
+fn test_blt_bge() {
+    assert_eq!(0x100, branch(28, -1, 0));
+    assert_eq!(5, branch(28, 0, 0));
+    assert_eq!(5, branch(28, 1, -1));
+    assert_eq!(5, branch(29, -1, 0));
+    assert_eq!(0x100, branch(29, 0, 0));
+    assert_eq!(0x100, branch(29, 1, -1));
+    assert_eq!(0x100, branch(28, i32::MIN, i32::MAX));
+}
+
+#[test]
+fn test_branch_out_of_bounds() {
+    // 0: jump #0 if r1 == r100
+    // 5:
+    let mut machine = Machine::new(&[26, 1, 100, 0, 0]);
+    assert!(machine.step().is_err());
+
+    // 0: jump #0xffff if r1 == r1
+    // 5:
+    let mut machine = Machine::new(&[26, 1, 1, 0xff, 0xff]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: jump #0xffff if r1 != r1
+    // 5:
+    let mut machine = Machine::new(&[27, 1, 1, 0xff, 0xff]);
+    expect(&mut machine, false, 5);
+}
+
+#[test]
+fn test_call_ret() {
+    // 0: call #8
+    // 3: exit
+    // 4:
+    // 8: ret
+    let mut machine = Machine::new(&[30, 8, 0, 7, 0, 0, 0, 0, 31]);
+    expect(&mut machine, false, 8);
+    assert_eq!(3, machine.regs()[14]);
+    expect(&mut machine, false, 3);
+    expect(&mut machine, true, 4);
+}
+
+#[test]
+fn test_call_ret_out_of_bounds() {
+    // 0: call #0xffff
+    // 3:
+    let mut machine = Machine::new(&[30, 0xff, 0xff]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+    assert_eq!(0, machine.regs()[14]);
+
+    // 0: ret with r14 == 30000
+    // 1:
+    let mut machine = Machine::new(&[31]);
+    machine.set_reg(14, 30000).unwrap();
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_push_pop() {
+    // 0: push r1
+    // 2: push r2
+    // 4: pop r3
+    // 6: pop r4
+    // 8:
+    let mut machine = Machine::new(&[32, 1, 32, 2, 33, 3, 33, 4]);
+    let memory_size = machine.memory().len();
+    machine.set_reg(15, memory_size as u32).unwrap();
+    machine.set_reg(1, 0x01020304).unwrap();
+    machine.set_reg(2, 42).unwrap();
+    expect(&mut machine, false, 2);
+    assert_eq!(memory_size - 4, machine.regs()[15] as usize);
+    assert_eq!(&[4, 3, 2, 1], &machine.memory()[memory_size - 4..]);
+    expect(&mut machine, false, 4);
+    expect(&mut machine, false, 6);
+    assert_eq!(42, machine.regs()[3]);
+    expect(&mut machine, false, 8);
+    assert_eq!(0x01020304, machine.regs()[4]);
+    assert_eq!(memory_size, machine.regs()[15] as usize);
+}
+
+#[test]
+fn test_push_pop_sp() {
+    // 0: push r15
+    // 2: pop r15
+    // 4:
+    let mut machine = Machine::new(&[32, 15, 33, 15]);
+    machine.set_reg(15, 100).unwrap();
+    expect(&mut machine, false, 2);
+    assert_eq!(96, machine.regs()[15]);
+    expect(&mut machine, false, 4);
+    assert_eq!(100, machine.regs()[15]);
+}
+
+#[test]
+fn stack_overflow() {
+    // 0: push r1 with r15 == 2
+    // 2:
+    let mut machine = Machine::new(&[32, 1]);
+    machine.set_reg(15, 2).unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::StackFault { .. })
+    ));
+
+    // 0: push r1 with r15 == 0xffffffff
+    // 2:
+    let mut machine = Machine::new(&[32, 1]);
+    machine.set_reg(15, 0xFFFF_FFFF).unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::StackFault { .. })
+    ));
+}
+
+#[test]
+fn stack_underflow() {
+    // 0: pop r1 with r15 == memory_size
+    // 2:
+    let mut machine = Machine::new(&[33, 1]);
+    let memory_size = machine.memory().len();
+    machine.set_reg(15, memory_size as u32).unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::StackFault { .. })
+    ));
+
+    // 0: pop r1 with r15 == 0xffffffff
+    // 2:
+    let mut machine = Machine::new(&[33, 1]);
+    machine.set_reg(15, 0xFFFF_FFFF).unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::StackFault { .. })
+    ));
+}
+
+#[test]
+fn test_push_pop_out_of_bounds() {
+    // 0: push r100
+    // 2:
+    let mut machine = Machine::new(&[32, 100]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingRegister { .. })
+    ));
+
+    // 0: pop r100
+    // 2:
+    let mut machine = Machine::new(&[33, 100]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingRegister { .. })
+    ));
+}
+
+#[test]
+fn test_in() {
+    // 0: in r1
+    // 2: in r2
+    // 4: in r3
+    // 6:
+    let mut machine = Machine::new(&[34, 1, 34, 2, 34, 3]);
+    let mut input = &b"A\xff"[..];
+    let mut out = Vec::new();
+    machine.step_with_io(&mut input, &mut out).unwrap();
+    assert_eq!(b'A' as u32, machine.regs()[1]);
+    machine.step_with_io(&mut input, &mut out).unwrap();
+    assert_eq!(0xFF, machine.regs()[2]);
+    machine.step_with_io(&mut input, &mut out).unwrap();
+    assert_eq!(-1, machine.regs()[3] as i32);
+    assert_eq!(6, machine.regs()[0]);
+}
+
+#[test]
+fn test_in_without_input() {
+    // 0: in r1
+    // 2:
+    let mut machine = Machine::new(&[34, 1]);
+    let mut out = Vec::new();
+    expect_on(&mut machine, &mut out, false, 2);
+    assert_eq!(0xFFFF_FFFF, machine.regs()[1]);
+}
+
+#[test]
+fn test_in_out_of_bounds() {
+    // 0: in r100
+    // 2:
+    let mut machine = Machine::new(&[34, 100]);
+    let mut out = Vec::new();
+    assert!(machine.step_with_io(&mut &b"A"[..], &mut out).is_err());
+}
+
+#[test]
+fn test_run_with_io() {
+    // 0: in r1
+    // 2: out r1
+    // 4: in r1
+    // 6: out r1
+    // 8: exit
+    // 9:
+    let mut machine = Machine::new(&[34, 1, 6, 1, 34, 1, 6, 1, 7]);
+    let mut out = Vec::new();
+    machine.run_with_io(&mut &b"Hi"[..], &mut out).unwrap();
+    assert_eq!("Hi".as_bytes(), &out[..]);
+}
+
+#[test]
+fn test_in_number() {
+    // 0: in_number r1
+    // 2: in_number r2
+    // 4: in_number r3
+    // 6: in r4
+    // 8: exit
+    // 9:
+    let mut machine = Machine::new(&[35, 1, 35, 2, 35, 3, 34, 4, 7]);
+    let mut input = &b"42 -1234\n  +7;x"[..];
+    let mut out = Vec::new();
+    machine.run_with_io(&mut input, &mut out).unwrap();
+    assert_eq!(42, machine.regs()[1]);
+    assert_eq!(-1234, machine.regs()[2] as i32);
+    assert_eq!(7, machine.regs()[3]);
+    assert_eq!(b'x' as u32, machine.regs()[4]);
+}
+
+#[test]
+fn test_in_number_at_end_of_input() {
+    // 0: in_number r1
+    // 2:
+    let mut machine = Machine::new(&[35, 1]);
+    let mut out = Vec::new();
+    machine
+        .step_with_io(&mut &b"-2147483648"[..], &mut out)
+        .unwrap();
+    assert_eq!(i32::MIN, machine.regs()[1] as i32);
+}
+
+#[test]
+fn refuse_invalid_number() {
+    for input in [&b""[..], b"  ", b"abc", b"-", b"+-3", b"2147483648"] {
+        // 0: in_number r1
+        // 2:
+        let mut machine = Machine::new(&[35, 1]);
+        let mut out = Vec::new();
+        assert!(matches!(
+            machine.step_with_io(&mut &input[..], &mut out),
+            Err(MachineError::InvalidNumber { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_load_imm32() {
+    // 0: loadimm32 r1, 0x12345678
+    // 6:
+    let mut machine = Machine::new(&[36, 1, 0x78, 0x56, 0x34, 0x12]);
+    expect(&mut machine, false, 6);
+    assert_eq!(0x1234_5678, machine.regs()[1]);
+
+    // 0: loadimm32 r1, 0xfffe0000
+    // 6:
+    let mut machine = Machine::new(&[36, 1, 0x00, 0x00, 0xfe, 0xff]);
+    expect(&mut machine, false, 6);
+    assert_eq!(0xFFFE_0000, machine.regs()[1]);
+
+    // 0: loadimm32 r0, 0x00000100
+    // 6:
+    let mut machine = Machine::new(&[36, 0, 0x00, 0x01, 0x00, 0x00]);
+    expect(&mut machine, false, 0x100);
+}
+
+#[test]
+fn test_load_imm32_out_of_bounds() {
+    // 0: loadimm32 r100, 0
+    // 6:
+    let mut machine = Machine::new(&[36, 100, 0, 0, 0, 0]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_load_byte() {
+    // 0: loadb r1 <- [r3]
+    // 3: loadbs r2 <- [r3]
+    // 6: 0x85
+    let mut machine = Machine::new(&[37, 1, 3, 38, 2, 3, 0x85]);
+    machine.set_reg(3, 6).unwrap();
+    expect(&mut machine, false, 3);
+    assert_eq!(0x85, machine.regs()[1]);
+    expect(&mut machine, false, 6);
+    assert_eq!(0xFFFF_FF85, machine.regs()[2]);
+
+    // 0: loadbs r2 <- [r0]
+    // 3:
+    let mut machine = Machine::new(&[38, 2, 0, 0x7f]);
+    expect(&mut machine, false, 3);
+    assert_eq!(0x7f, machine.regs()[2]);
+}
+
+#[test]
+fn test_load_halfword() {
+    // 0: loadh r1 <- [r3]
+    // 3: loadhs r2 <- [r3]
+    // 6: 0x8034
+    let mut machine = Machine::new(&[40, 1, 3, 41, 2, 3, 0x34, 0x80]);
+    machine.set_reg(3, 6).unwrap();
+    expect(&mut machine, false, 3);
+    assert_eq!(0x8034, machine.regs()[1]);
+    expect(&mut machine, false, 6);
+    assert_eq!(0xFFFF_8034, machine.regs()[2]);
+
+    // 0: loadhs r2 <- [r0]
+    // 3:
+    let mut machine = Machine::new(&[41, 2, 0, 0x34, 0x70]);
+    expect(&mut machine, false, 3);
+    assert_eq!(0x7034, machine.regs()[2]);
+}
+
+#[test]
+fn test_store_byte_halfword() {
+    // 0: storeb [r3] <- r1
+    // 3: storeh [r2] <- r1
+    // 6:
+    let mut machine = Machine::new(&[39, 3, 1, 42, 2, 1]);
+    machine.set_reg(1, 0x01020304).unwrap();
+    machine.set_reg(2, 10).unwrap();
+    machine.set_reg(3, 20).unwrap();
+    expect(&mut machine, false, 3);
+    assert_eq!(&[4, 0], &machine.memory()[20..22]);
+    expect(&mut machine, false, 6);
+    assert_eq!(&[4, 3, 0], &machine.memory()[10..13]);
+}
+
+#[test]
+fn test_narrow_load_store_out_of_bounds() {
+    for opcode in 37..=42 {
+        // 0: op r100, r1
+        // 3:
+        let mut machine = Machine::new(&[opcode, 100, 1]);
+        assert!(machine.step().is_err());
+
+        // 0: op r1, r100
+        // 3:
+        let mut machine = Machine::new(&[opcode, 1, 100]);
+        assert!(machine.step().is_err());
+
+        // 0: op r1, r1 with r1 == 0xffffffff
+        // 3:
+        let mut machine = Machine::new(&[opcode, 1, 1]);
+        machine.set_reg(1, 0xFFFF_FFFF).unwrap();
+        assert!(matches!(
+            machine.step(),
+            Err(MachineError::NonExistingAddress { .. })
+        ));
+    }
+
+    // 0: loadh r1 <- [r1] / storeh [r1] <- r1 with r1 == memory_size - 1
+    // 3:
+    for opcode in [40, 41, 42] {
+        let mut machine = Machine::new(&[opcode, 1, 1]);
+        let memory_size = machine.memory().len();
+        machine.set_reg(1, (memory_size - 1) as u32).unwrap();
+        assert!(machine.step().is_err());
+    }
+}
+
+// Run a single `cmp r1, r2` instruction and return the resulting flags
+fn compare(left: u32, right: u32) -> Flags {
+    let mut machine = Machine::new(&[43, 1, 2]);
+    machine.set_reg(1, left).unwrap();
+    machine.set_reg(2, right).unwrap();
+    expect(&mut machine, false, 3);
+    machine.flags()
+}
+
+#[test]
+fn test_cmp() {
+    assert_eq!(Flags::default(), Machine::new(&[]).flags());
+
+    let flags = compare(42, 42);
+    assert!(flags.zero && !flags.negative && !flags.carry && !flags.overflow);
+
+    let flags = compare(1, 2);
+    assert!(!flags.zero && flags.negative && flags.carry && !flags.overflow);
+
+    let flags = compare(2, 1);
+    assert!(!flags.zero && !flags.negative && !flags.carry && !flags.overflow);
+
+    let flags = compare(-1i32 as u32, 1);
+    assert!(!flags.zero && flags.negative && !flags.carry && !flags.overflow);
+}
+
+#[test]
+fn cmp_with_overflow() {
+    let flags = compare(i32::MIN as u32, 1);
+    assert!(!flags.zero && !flags.negative && !flags.carry && flags.overflow);
+
+    let flags = compare(i32::MAX as u32, -1i32 as u32);
+    assert!(!flags.zero && flags.negative && flags.carry && flags.overflow);
+}
+
+#[test]
+fn test_cmp_out_of_bounds() {
+    // 0: cmp r1, r100
+    // 3:
+    let mut machine = Machine::new(&[43, 1, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_mov() {
+    // 0: mov r1 <- r2
+    // 3: mov r0 <- r1
+    let mut machine = Machine::new(&[45, 1, 2, 45, 0, 1]);
+    machine.set_reg(2, 42).unwrap();
+    expect(&mut machine, false, 3);
+    assert_eq!(42, machine.regs()[1]);
+    expect(&mut machine, false, 42);
+}
+
+#[test]
+fn test_mov_out_of_bounds() {
+    // 0: mov r100 <- r1
+    // 3:
+    let mut machine = Machine::new(&[45, 100, 1]);
+    assert!(machine.step().is_err());
+
+    // 0: mov r1 <- r100
+    // 3:
+    let mut machine = Machine::new(&[45, 1, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_load_indexed() {
+    // 0: load r1 <- [r2 + 4]
+    // 4: load r3 <- [r4 - 2]
+    // 8: 0x04030201
+    let mut machine = Machine::new(&[46, 1, 2, 4, 46, 3, 4, 0xfe, 1, 2, 3, 4]);
+    machine.set_reg(2, 4).unwrap();
+    machine.set_reg(4, 10).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(0x04030201, machine.regs()[1]);
+    expect(&mut machine, false, 8);
+    assert_eq!(0x04030201, machine.regs()[3]);
+}
+
+#[test]
+fn test_store_indexed() {
+    // 0: store [r1 + 127] <- r2
+    // 4: store [r1 - 128] <- r2
+    // 8:
+    let mut machine = Machine::new(&[47, 1, 0x7f, 2, 47, 1, 0x80, 2]);
+    machine.set_reg(1, 200).unwrap();
+    machine.set_reg(2, 0x01020304).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(&[4, 3, 2, 1], &machine.memory()[327..331]);
+    expect(&mut machine, false, 8);
+    assert_eq!(&[4, 3, 2, 1], &machine.memory()[72..76]);
+}
+
+#[test]
+fn test_indexed_out_of_bounds() {
+    // 0: load r1 <- [r2 - 1] with r2 == 0
+    // 4:
+    let mut machine = Machine::new(&[46, 1, 2, 0xff]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: store [r1 + 1] <- r1 with r1 == memory_size - 4
+    // 4:
+    let mut machine = Machine::new(&[47, 1, 1, 1]);
+    let memory_size = machine.memory().len();
+    machine.set_reg(1, (memory_size - 4) as u32).unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: load r1 <- [r1 + 1] with r1 == 0xffffffff
+    // 4:
+    let mut machine = Machine::new(&[46, 1, 1, 1]);
+    machine.set_reg(1, 0xFFFF_FFFF).unwrap();
+    assert!(machine.step().is_err());
+
+    // 0: load r100 <- [r1 + 0]
+    // 4:
+    let mut machine = Machine::new(&[46, 100, 1, 0]);
+    assert!(machine.step().is_err());
+
+    // 0: store [r1 + 0] <- r100
+    // 4:
+    let mut machine = Machine::new(&[47, 1, 0, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_syscall() {
+    // 0: syscall 3
+    // 2: syscall 4
+    // 4:
+    let mut machine = Machine::new(&[48, 3, 48, 4]);
+    machine.set_syscall_handler(|machine: &mut Machine, number: u8| match number {
+        3 => machine.set_reg(1, machine.regs()[0] + 100),
+        _ => Err(MachineError::UnhandledSyscall {
+            ip: 0,
+            opcode: 0,
+            number,
+        }),
+    });
+    expect(&mut machine, false, 2);
+    assert_eq!(102, machine.regs()[1]);
+    assert_eq!(
+        Err(MachineError::UnhandledSyscall {
+            ip: 2,
+            opcode: 48,
+            number: 4
+        }),
+        machine.step()
+    );
+}
+
+#[test]
+fn test_syscall_handler_state() {
+    struct Counter(Arc<Mutex<Vec<u8>>>);
+
+    impl SyscallHandler for Counter {
+        fn syscall(&mut self, _: &mut Machine, number: u8) -> Result<(), MachineError> {
+            self.0.lock().unwrap().push(number);
+            Ok(())
+        }
+    }
+
+    // 0: syscall 1
+    // 2: syscall 2
+    // 4: exit
+    // 5:
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let mut machine = Machine::new(&[48, 1, 48, 2, 7]);
+    machine.set_syscall_handler(Counter(calls.clone()));
+    machine.run().unwrap();
+    assert_eq!(&[1, 2], &calls.lock().unwrap()[..]);
+}
+
+#[test]
+fn test_step_hooks() {
+    // 0: loadimm r1, 1
+    // 4: add r1, r1, r1
+    // 8: add r1, r1, r1
+    // 12: exit
+    // 13:
+    let program = [4, 1, 1, 0, 9, 1, 1, 1, 9, 1, 1, 1, 7];
+
+    // The hook sees every instruction before it's executed, and can pause
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut machine = Machine::new(&program);
+    let hook_seen = seen.clone();
+    machine.set_hook(move |machine: &Machine, insn: &DecodedInsn| {
+        hook_seen
+            .lock()
+            .unwrap()
+            .push((insn.address, machine.regs()[1]));
+        match insn.info.map(|info| info.mnemonic) {
+            Some("add") if machine.regs()[1] == 2 => HookAction::Pause,
+            _ => HookAction::Continue,
+        }
+    });
+    assert_eq!(RunOutcome::Paused, machine.run_on(&mut io::sink()).unwrap());
+    assert_eq!(8, machine.regs()[0]);
+    assert_eq!(vec![(0, 0), (4, 1), (8, 2)], seen.lock().unwrap().clone());
+    machine.remove_hook();
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut io::sink()).unwrap());
+    assert_eq!(4, machine.regs()[1]);
+
+    // After the step, the instruction has been executed
+    struct Limit(u64);
+
+    impl StepHook for Limit {
+        fn before_step(&mut self, _: &Machine, _: &DecodedInsn) -> HookAction {
+            HookAction::Continue
+        }
+
+        fn after_step(&mut self, machine: &Machine, _: &DecodedInsn) -> HookAction {
+            match machine.instructions_retired() {
+                n if n == self.0 => HookAction::Pause,
+                n if n > self.0 => HookAction::Abort,
+                _ => HookAction::Continue,
+            }
+        }
+    }
+
+    let mut machine = Machine::new(&program);
+    machine.set_hook(Limit(2));
+    assert_eq!(RunOutcome::Paused, machine.run_on(&mut io::sink()).unwrap());
+    assert_eq!(8, machine.regs()[0]);
+    assert!(matches!(
+        machine.run_on(&mut io::sink()),
+        Err(MachineError::Aborted { .. })
+    ));
+    assert_eq!(12, machine.regs()[0]);
+    assert_eq!(4, machine.regs()[1]);
+}
+
+#[test]
+fn test_memory_observer() {
+    #[derive(Default)]
+    struct Accesses(Arc<Mutex<Vec<String>>>);
+
+    impl MemoryObserver for Accesses {
+        fn on_read(&mut self, address: u32, len: usize) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("read {} {}", address, len));
+        }
+
+        fn on_write(&mut self, address: u32, len: usize, bytes: &[u8]) {
+            let access = format!("write {} {} {:?}", address, len, bytes);
+            self.0.lock().unwrap().push(access);
+        }
+    }
+
+    // 0: loadimm r1, 0x100
+    // 4: loadimm r2, 42
+    // 8: store r1, r2
+    // 11: loadb r3, r1
+    // 14: push r2
+    // 16: load r4, r15
+    // 19: exit
+    // 20:
+    let program = [
+        4, 1, 0, 1, 4, 2, 42, 0, 2, 1, 2, 37, 3, 1, 32, 2, 3, 4, 15, 7,
+    ];
+    let mut machine = Machine::new(&program);
+    machine.set_reg(15, 0x200).unwrap();
+    let accesses = Accesses::default();
+    let seen = accesses.0.clone();
+    machine.set_memory_observer(accesses);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(
+        vec![
+            "write 256 4 [42, 0, 0, 0]",
+            "read 256 1",
+            "write 508 4 [42, 0, 0, 0]",
+            "read 508 4"
+        ],
+        *seen.lock().unwrap()
+    );
+}
+
+// Without the rayon feature, the handlers don't have to be Send
+#[cfg(not(feature = "rayon"))]
+#[test]
+fn syscall_handler_not_send() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let calls = Rc::new(Cell::new(0));
+    let seen = calls.clone();
+    // 0: syscall 3
+    // 2: exit
+    // 3:
+    let mut machine = Machine::new(&[48, 3, 7]);
+    machine.set_syscall_handler(move |_: &mut Machine, number: u8| {
+        calls.set(calls.get() + number);
+        Ok(())
+    });
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(3, seen.get());
+}
+
+#[test]
+fn syscall_without_handler() {
+    // 0: syscall 0
+    // 2:
+    let mut machine = Machine::new(&[48, 0]);
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::UnhandledSyscall { .. })
+    ));
+    assert_eq!(0, machine.regs()[0]);
+}
+
+// 200 reg_a: double the content of register reg_a
+fn double(
+    machine: &mut Machine,
+    _: &mut dyn io::Read,
+    _: &mut dyn Write,
+) -> Result<StepOutcome, MachineError> {
+    let ip = machine.regs()[0] as usize;
+    let Some(&reg) = machine.memory().get(ip + 1) else {
+        return Err(MachineError::NonExistingAddress {
+            ip: 0,
+            opcode: 0,
+            address: ip as u32 + 1,
+        });
+    };
+    let value = machine.regs().get(reg as usize).copied().unwrap_or(0);
+    machine.set_reg(reg as usize, value.wrapping_mul(2))?;
+    machine.ip_inc(2);
+    Ok(StepOutcome::Continue)
+}
+
+#[test]
+fn extension_instruction() {
+    // 0: loadimm r1, 21
+    // 4: double r1
+    // 6: out_number r1
+    // 8: double r16
+    let mut machine = Machine::new(&[4, 1, 21, 0, 200, 1, 8, 1, 200, 16]);
+    assert!(matches!(
+        machine.run_on(&mut io::sink()),
+        Err(MachineError::NonExistingInstruction { ip: 4, opcode: 200 })
+    ));
+    machine.set_instruction_handler(200, double);
+    let mut out = Vec::new();
+    assert!(matches!(
+        machine.run_on(&mut out),
+        Err(MachineError::NonExistingRegister {
+            ip: 8,
+            opcode: 200,
+            register: 16
+        })
+    ));
+    assert_eq!(b"42", &out[..]);
+    assert_eq!(8, machine.regs()[0]);
+
+    // The built-in instructions can be replaced too
+    let mut machine = Machine::new(&[4, 1, 21, 0]);
+    machine.set_instruction_handler(4, double);
+    machine.step_on(&mut io::sink()).unwrap();
+    assert_eq!((2, 0), (machine.regs()[0], machine.regs()[1]));
+}
+
+#[test]
+fn faulting_instruction_is_undone() {
+    // 0: syscall 1
+    // 2:
+    let mut machine = Machine::new(&[48, 1]);
+    machine.set_syscall_handler(|machine: &mut Machine, number: u8| {
+        machine.set_reg(1, 42)?;
+        machine.load_at(0x100, &[1, 2, 3])?;
+        Err(MachineError::UnhandledSyscall {
+            ip: 0,
+            opcode: 0,
+            number,
+        })
+    });
+    machine.set_journal_capacity(10);
+    assert!(machine.step_on(&mut io::sink()).is_err());
+    assert!(machine.regs().iter().all(|r| *r == 0));
+    assert!(machine.memory()[0x100..0x103].iter().all(|b| *b == 0));
+    assert_eq!(0, machine.instructions_retired());
+    assert_eq!(0, machine.journal_len());
+
+    // 0: loadimm r1, 0x0ffe
+    // 4: store r1, r1
+    // 7:
+    let mut machine = Machine::new(&[4, 1, 0xfe, 0x0f, 2, 1, 1]);
+    machine.step_on(&mut io::sink()).unwrap();
+    let regs = machine.regs().to_vec();
+    let memory = machine.memory().to_vec();
+    assert!(machine.step_on(&mut io::sink()).is_err());
+    assert_eq!(regs, machine.regs());
+    assert_eq!(memory, machine.memory());
+}
+
+#[test]
+fn test_brk() {
+    // 0: brk
+    // 1: exit
+    // 2:
+    let mut machine = Machine::new(&[49, 7]);
+    assert_eq!(StepOutcome::Breakpoint, machine.step().unwrap());
+    assert_eq!(1, machine.regs()[0]);
+    expect(&mut machine, true, 2);
+}
+
+#[test]
+fn run_stops_on_brk() {
+    // 0: out_number r0
+    // 2: brk
+    // 3: out_number r0
+    // 5: exit
+    // 6:
+    let mut machine = Machine::new(&[8, 0, 49, 8, 0, 7]);
+    let mut out = Vec::new();
+    assert_eq!(
+        RunOutcome::BreakInstruction,
+        machine.run_on(&mut out).unwrap()
+    );
+    assert_eq!(3, machine.regs()[0]);
+    assert_eq!(None, machine.exit_code());
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut out).unwrap());
+    assert_eq!("25".as_bytes(), &out[..]);
+    assert_eq!(Some(0), machine.exit_code());
+}
+
+#[test]
+fn run_stops_on_breakpoints() {
+    // 0: out_number r0
+    // 2: out_number r0
+    // 4: out_number r0
+    // 6: exit
+    // 7:
+    let mut machine = Machine::new(&[8, 0, 8, 0, 8, 0, 7]);
+    assert!(machine.add_breakpoint(2));
+    assert!(machine.add_breakpoint(4));
+    assert!(!machine.add_breakpoint(4));
+    assert_eq!(vec![2, 4], machine.breakpoints().collect::<Vec<_>>());
+    let mut out = Vec::new();
+    assert_eq!(RunOutcome::Breakpoint(2), machine.run_on(&mut out).unwrap());
+    assert_eq!("2".as_bytes(), &out[..]);
+
+    // Running again executes the instruction at the breakpoint
+    assert_eq!(RunOutcome::Breakpoint(4), machine.run_on(&mut out).unwrap());
+    assert!(machine.remove_breakpoint(2));
+    assert!(!machine.remove_breakpoint(2));
+    assert!(!machine.has_breakpoint(2));
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut out).unwrap());
+    assert_eq!("246".as_bytes(), &out[..]);
+
+    // Stepping ignores the breakpoints
+    let mut machine = Machine::new(&[8, 0, 7]);
+    machine.add_breakpoint(2);
+    assert_eq!(StepOutcome::Continue, machine.step_on(&mut out).unwrap());
+    assert_eq!(StepOutcome::Exited, machine.step_on(&mut out).unwrap());
+}
+
+#[test]
+fn run_stops_on_watchpoints() {
+    // 0: loadimm r1, 0x100
+    // 4: loadimm r2, 42
+    // 8: store r1, r2
+    // 11: storeb r1, r1
+    // 14: memset r1, r2, r2
+    // 18: exit
+    // 19:
+    let mut machine = Machine::new(&[4, 1, 0, 1, 4, 2, 42, 0, 2, 1, 2, 39, 1, 1, 53, 1, 2, 2, 7]);
+    machine.add_watchpoint(0x102..0x103);
+    assert_eq!(
+        vec![0x102..0x103],
+        machine.watchpoints().collect::<Vec<_>>()
+    );
+    let write = MemoryWrite {
+        instruction: 8,
+        address: 0x100,
+        old: vec![0, 0, 0, 0],
+        new: vec![42, 0, 0, 0],
+    };
+    assert_eq!(
+        RunOutcome::Watchpoint(write),
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(11, machine.regs()[0]);
+
+    // The byte store doesn't touch the watchpoint
+    let write = MemoryWrite {
+        instruction: 14,
+        address: 0x100,
+        old: vec![0; 42],
+        new: vec![42; 42],
+    };
+    assert_eq!(
+        StepOutcome::Continue,
+        machine.step_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(
+        StepOutcome::Watchpoint(write),
+        machine.step_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(&[42; 42], &machine.memory()[0x100..0x12a]);
+    assert!(machine.remove_watchpoint(0x102..0x103));
+    assert!(!machine.remove_watchpoint(0x102..0x103));
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut io::sink()).unwrap());
+}
+
+#[test]
+fn run_stops_on_read_watchpoints() {
+    // 0: loadimm r1, 0x100
+    // 4: loadimm r2, 42
+    // 8: store r1, r2
+    // 11: load r3, r1
+    // 14: loadb r4, r1
+    // 17: exit
+    // 18:
+    let program = [4, 1, 0, 1, 4, 2, 42, 0, 2, 1, 2, 3, 3, 1, 37, 4, 1, 7];
+    let mut machine = Machine::new(&program);
+    machine.add_read_watchpoint(0x103..0x104);
+    assert_eq!(
+        vec![0x103..0x104],
+        machine.read_watchpoints().collect::<Vec<_>>()
+    );
+    let read = MemoryRead {
+        instruction: 11,
+        address: 0x100,
+        bytes: vec![42, 0, 0, 0],
+    };
+    assert_eq!(
+        RunOutcome::ReadWatchpoint(read),
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(42, machine.regs()[3]);
+
+    // The byte load doesn't touch the watchpoint
+    assert_eq!(
+        StepOutcome::Continue,
+        machine.step_on(&mut io::sink()).unwrap()
+    );
+    assert!(machine.remove_read_watchpoint(0x103..0x104));
+    assert!(!machine.remove_read_watchpoint(0x103..0x104));
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut io::sink()).unwrap());
+}
+
+#[test]
+fn run_stops_on_opcodes() {
+    // 0: loadimm r1, 0x100
+    // 4: load r2, r1
+    // 7: load r3, r1
+    // 10: exit
+    // 11:
+    let mut machine = Machine::new(&[4, 1, 0, 1, 3, 2, 1, 3, 3, 1, 7]);
+    assert!(machine.add_opcode_breakpoint(3));
+    assert!(!machine.add_opcode_breakpoint(3));
+    assert_eq!(vec![3], machine.opcode_breakpoints().collect::<Vec<_>>());
+    assert_eq!(
+        RunOutcome::Breakpoint(4),
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(
+        RunOutcome::Breakpoint(7),
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    assert!(machine.remove_opcode_breakpoint(3));
+    assert!(!machine.remove_opcode_breakpoint(3));
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut io::sink()).unwrap());
+}
+
+#[test]
+fn step_back_through_the_journal() {
+    // 0: loadimm r1, 0x100
+    // 4: loadimm r2, 42
+    // 8: store r1, r2
+    // 11: exit
+    // 12:
+    let program = [4, 1, 0, 1, 4, 2, 42, 0, 2, 1, 2, 7];
+    let mut machine = Machine::new(&program);
+    machine.step_on(&mut io::sink()).unwrap();
+    assert!(!machine.step_back());
+
+    // Only the last two steps are kept
+    machine.set_journal_capacity(2);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(2, machine.journal_len());
+    assert!(machine.step_back());
+    assert_eq!(None, machine.exit_code());
+    assert_eq!(11, machine.regs()[0]);
+    assert_eq!(42, machine.memory()[0x100]);
+    assert!(machine.step_back());
+    assert_eq!(8, machine.regs()[0]);
+    assert_eq!(0, machine.memory()[0x100]);
+    assert_eq!(2, machine.instructions_retired());
+    assert!(!machine.step_back());
+
+    // Steps can be replayed once undone
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(42, machine.memory()[0x100]);
+    assert_eq!(Some(0), machine.exit_code());
+}
+
+#[test]
+fn test_backtrace() {
+    // 0: call 4
+    // 3: exit
+    // 4: mov r13, r14
+    // 7: call 14
+    // 10: mov r14, r13
+    // 13: ret
+    // 14: ret
+    // 15:
+    let program = [30, 4, 0, 7, 45, 13, 14, 30, 14, 0, 45, 14, 13, 31, 31];
+    let mut machine = Machine::new(&program);
+    machine.set_journal_capacity(10);
+    let outer = Frame {
+        function: 4,
+        return_address: 3,
+    };
+    let inner = Frame {
+        function: 14,
+        return_address: 10,
+    };
+    expect(&mut machine, false, 4);
+    assert_eq!(vec![outer], machine.backtrace());
+    expect(&mut machine, false, 7);
+    expect(&mut machine, false, 14);
+    assert_eq!(vec![inner, outer], machine.backtrace());
+    expect(&mut machine, false, 10);
+    assert_eq!(vec![outer], machine.backtrace());
+    expect(&mut machine, false, 13);
+    expect(&mut machine, false, 3);
+    assert!(machine.backtrace().is_empty());
+
+    // Stepping back restores the calls
+    assert!(machine.step_back());
+    assert_eq!(vec![outer], machine.backtrace());
+    assert!(machine.step_back());
+    assert!(machine.step_back());
+    assert_eq!(vec![inner, outer], machine.backtrace());
+
+    // A return to an address no call returns to keeps the calls
+    machine.set_reg(14, 0).unwrap();
+    expect(&mut machine, false, 0);
+    assert_eq!(vec![inner, outer], machine.backtrace());
+}
+
+// A machine running `program` for `steps` steps
+fn stepped(program: &[u8], steps: usize) -> Machine {
+    let mut machine = Machine::new(program);
+    for _ in 0..steps {
+        machine.step_on(&mut io::sink()).unwrap();
+    }
+    machine
+}
+
+#[test]
+fn execute_written_operands() {
+    // Every pass of the loop writes the immediate value of the loadimm at
+    // 12, whose operands have been decoded already
+    // 0: loadimm r3, 14
+    // 4: loadimm r2, 1
+    // 8: loadimm r5, 3
+    // 12: loadimm r1, 0
+    // 16: out_number r1
+    // 18: add r4, r4, r2
+    // 22: storeb r3, r4
+    // 25: blt r4, r5, 12
+    // 30: exit
+    // 31:
+    let program = [
+        4, 3, 14, 0, 4, 2, 1, 0, 4, 5, 3, 0, 4, 1, 0, 0, 8, 1, 9, 4, 4, 2, 39, 3, 4, 28, 4, 5, 12,
+        0, 7,
+    ];
+    let mut machine = Machine::new(&program);
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"012", &output[..]);
+
+    // Stepping executes the same operands
+    let mut stepped = Machine::new(&program);
+    let mut stepped_output = Vec::new();
+    while stepped.exit_code().is_none() {
+        stepped.step_on(&mut stepped_output).unwrap();
+    }
+    assert_eq!(output, stepped_output);
+
+    // So do the operands written by the host
+    machine.write_memory(17, &[5]).unwrap();
+    machine.set_reg(0, 12).unwrap();
+    machine.set_reg(5, 42).unwrap();
+    machine.step_on(&mut io::sink()).unwrap();
+    output.clear();
+    machine.step_on(&mut output).unwrap();
+    assert_eq!(b"42", &output[..]);
+}
+
+#[test]
+fn rewind_to_checkpoints() {
+    // 0: loadimm r4, 0x100
+    // 4: loadimm r1, 100
+    // 8: loadimm r2, 1
+    // 12: sub r1, r1, r2
+    // 16: store r4, r1
+    // 19: bne r1, r3, 12
+    // 24: exit
+    // 25:
+    let program = [
+        4, 4, 0, 1, 4, 1, 100, 0, 4, 2, 1, 0, 5, 1, 1, 2, 2, 4, 1, 27, 1, 3, 12, 0, 7,
+    ];
+    let mut machine = Machine::new(&program);
+    machine.set_checkpoints(10, 5);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(304, machine.instructions_retired());
+    assert_eq!(5, machine.checkpoints_len());
+
+    // Going back past the latest checkpoints drops them
+    assert!(machine.rewind(30));
+    assert_eq!(274, machine.instructions_retired());
+    assert_eq!(None, machine.exit_code());
+    let expected = stepped(&program, 274);
+    assert_eq!(expected.regs(), machine.regs());
+    assert_eq!(expected.memory(), machine.memory());
+    assert_eq!(2, machine.checkpoints_len());
+
+    // Only the last ones are kept
+    assert!(!machine.rewind(100));
+    assert!(!machine.rewind(1000));
+    assert_eq!(274, machine.instructions_retired());
+    assert!(machine.rewind(14));
+    assert_eq!(stepped(&program, 260).regs(), machine.regs());
+
+    // The program goes on from there
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(Some(0), machine.exit_code());
+    assert_eq!(304, machine.instructions_retired());
+    assert!(machine.rewind(0));
+    assert_eq!(Some(0), machine.exit_code());
+
+    machine.set_checkpoints(0, 5);
+    assert_eq!(0, machine.checkpoints_len());
+    assert!(!machine.rewind(0));
+}
+
+#[test]
+fn rewind_without_observer() {
+    #[derive(Default)]
+    struct Writes(Arc<Mutex<usize>>);
+
+    impl MemoryObserver for Writes {
+        fn on_write(&mut self, _address: u32, _len: usize, _bytes: &[u8]) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+
+    // Same program as rewind_to_checkpoints, storing once per iteration
+    let program = [
+        4, 4, 0, 1, 4, 1, 100, 0, 4, 2, 1, 0, 5, 1, 1, 2, 2, 4, 1, 27, 1, 3, 12, 0, 7,
+    ];
+    let mut machine = Machine::new(&program);
+    machine.set_checkpoints(10, 5);
+    let writes = Writes::default();
+    let seen = writes.0.clone();
+    machine.set_memory_observer(writes);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(100, *seen.lock().unwrap());
+
+    // The replay from the checkpoint at 270 stores again, unseen
+    assert!(machine.rewind(30));
+    assert_eq!(100, *seen.lock().unwrap());
+
+    // The observer is still there for the rest of the run
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(110, *seen.lock().unwrap());
+}
+
+#[test]
+fn snapshot_and_restore() {
+    // Same program as rewind_to_checkpoints
+    let program = [
+        4, 4, 0, 1, 4, 1, 100, 0, 4, 2, 1, 0, 5, 1, 1, 2, 2, 4, 1, 27, 1, 3, 12, 0, 7,
+    ];
+    let mut machine = stepped(&program, 50);
+    machine.set_journal_capacity(10);
+    machine.set_checkpoints(10, 5);
+    let snapshot = machine.snapshot();
+    assert_eq!(50, snapshot.instructions_retired());
+    assert_eq!(machine.regs(), snapshot.regs());
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(Some(0), machine.exit_code());
+
+    // The machine goes back to the snapshot and runs the same way again
+    machine.restore(&snapshot);
+    assert_eq!(stepped(&program, 50).regs(), machine.regs());
+    assert_eq!(stepped(&program, 50).memory(), machine.memory());
+    assert_eq!(None, machine.exit_code());
+    assert_eq!(0, machine.journal_len());
+    assert_eq!(0, machine.checkpoints_len());
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(304, machine.instructions_retired());
+
+    // A snapshot forks the execution in another machine
+    let mut fork = Machine::new(&[]);
+    fork.restore(&snapshot);
+    assert_eq!(snapshot, fork.snapshot());
+    fork.set_reg(1, 1).unwrap();
+    fork.run_on(&mut io::sink()).unwrap();
+    assert_eq!(55, fork.instructions_retired());
+}
+
+#[test]
+fn iterate_over_steps() {
+    // 0: loadimm r1, 3
+    // 4: out_number r1
+    // 6: brk
+    // 7: exit
+    // 8:
+    let mut machine = Machine::new(&[4, 1, 3, 0, 8, 1, 49, 7, 7]);
+    let mut out = Vec::new();
+    let steps: Vec<StepInfo> = machine.steps_on(&mut out).map(Result::unwrap).collect();
+    assert_eq!(
+        vec![
+            StepInfo {
+                address: 0,
+                opcode: 4,
+                outcome: StepOutcome::Continue
+            },
+            StepInfo {
+                address: 4,
+                opcode: 8,
+                outcome: StepOutcome::Continue
+            },
+            StepInfo {
+                address: 6,
+                opcode: 49,
+                outcome: StepOutcome::Breakpoint
+            },
+            StepInfo {
+                address: 7,
+                opcode: 7,
+                outcome: StepOutcome::Exited
+            },
+        ],
+        steps
+    );
+    assert_eq!(b"3", &out[..]);
+
+    // The iterator adapters bound the execution
+    // 0: jrel -3
+    let mut machine = Machine::new(&[25, 0xfd, 0xff]);
+    assert_eq!(100, machine.steps_on(&mut io::sink()).take(100).count());
+    assert_eq!(100, machine.instructions_retired());
+
+    // It ends on the first error
+    // 0: loadimm r1, 3
+    // 4: invalid opcode 255
+    let mut machine = Machine::new(&[4, 1, 3, 0, 255]);
+    let steps: Vec<_> = machine.steps_on(&mut io::sink()).collect();
+    assert_eq!(2, steps.len());
+    assert!(matches!(
+        steps[1],
+        Err(MachineError::NonExistingInstruction { ip: 4, .. })
+    ));
+}
+
+#[test]
+fn run_for_some_steps() {
+    // 0: loadimm r1, 5
+    // 4: loadimm r2, 1
+    // 8: sub r1, r1, r2
+    // 12: bne r1, r3, 8
+    // 17: out_number r1
+    // 19: exit
+    // 20:
+    let program = [4, 1, 5, 0, 4, 2, 1, 0, 5, 1, 1, 2, 27, 1, 3, 8, 0, 8, 1, 7];
+    let mut machine = Machine::new(&program);
+    let mut out = Vec::new();
+    assert_eq!(
+        RunOutcome::StepLimitReached,
+        machine.run_for_on(6, &mut out).unwrap()
+    );
+    assert_eq!(6, machine.instructions_retired());
+    assert_eq!(3, machine.regs()[1]);
+    assert_eq!(
+        RunOutcome::StepLimitReached,
+        machine.run_for_on(0, &mut out).unwrap()
+    );
+    assert_eq!(6, machine.instructions_retired());
+
+    // Running again resumes the program, which may stop before the limit
+    machine.add_breakpoint(17);
+    assert_eq!(
+        RunOutcome::Breakpoint(17),
+        machine.run_for_on(100, &mut out).unwrap()
+    );
+    assert_eq!(
+        RunOutcome::Exited,
+        machine.run_for_on(100, &mut out).unwrap()
+    );
+    assert_eq!(b"0", &out[..]);
+}
+
+#[test]
+fn run_out_of_fuel() {
+    // Same program as run_for_some_steps
+    let program = [4, 1, 5, 0, 4, 2, 1, 0, 5, 1, 1, 2, 27, 1, 3, 8, 0, 8, 1, 7];
+    let mut machine = Machine::new(&program);
+    assert_eq!(None, machine.fuel());
+    machine.set_fuel(Some(7));
+    machine.set_fuel_cost(5, 3);
+    assert_eq!(3, machine.fuel_cost(5));
+    let mut out = Vec::new();
+    assert_eq!(RunOutcome::OutOfFuel, machine.run_on(&mut out).unwrap());
+    assert_eq!(4, machine.instructions_retired());
+    assert_eq!((8, 4), (machine.regs()[0], machine.regs()[1]));
+    assert_eq!(Some(1), machine.fuel());
+    assert_eq!(StepOutcome::OutOfFuel, machine.step_on(&mut out).unwrap());
+    assert_eq!(4, machine.instructions_retired());
+
+    // Adding fuel resumes the program where it stopped
+    machine.add_fuel(2);
+    assert_eq!(StepOutcome::Continue, machine.step_on(&mut out).unwrap());
+    assert_eq!((Some(0), 3), (machine.fuel(), machine.regs()[1]));
+    machine.set_fuel(None);
+    machine.add_fuel(1);
+    assert_eq!(None, machine.fuel());
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut out).unwrap());
+    assert_eq!(b"0", &out[..]);
+}
+
+#[test]
+fn faulting_instruction_consumes_no_fuel() {
+    // 0: store r1, r16
+    let mut machine = Machine::new(&[2, 1, 16]);
+    machine.set_fuel(Some(1));
+    assert!(machine.step_on(&mut io::sink()).is_err());
+    assert_eq!(Some(1), machine.fuel());
+}
+
+#[test]
+fn run_with_timeout() {
+    // 0: jrel -3
+    let mut machine = Machine::new(&[25, 0xfd, 0xff]);
+    assert_eq!(
+        RunOutcome::TimedOut,
+        machine
+            .run_with_timeout_on(Duration::ZERO, &mut io::sink())
+            .unwrap()
+    );
+    assert_eq!(0, machine.instructions_retired());
+    assert_eq!(
+        RunOutcome::TimedOut,
+        machine
+            .run_with_timeout_on(Duration::from_millis(20), &mut io::sink())
+            .unwrap()
+    );
+    assert!(machine.instructions_retired() > 0);
+    assert_eq!(0, machine.regs()[0]);
+
+    // A run stopping before the deadline doesn't wait for it
+    let mut machine = Machine::new(&[7]);
+    let deadline = Instant::now() + Duration::from_secs(3600);
+    assert_eq!(
+        RunOutcome::Exited,
+        machine
+            .run_until_with_io(deadline, &mut io::empty(), &mut io::sink())
+            .unwrap()
+    );
+}
+
+#[test]
+fn run_and_capture_output() {
+    // 0: loadimm r1, 42
+    // 4: out_number r1
+    // 6: out r1
+    // 8: exit
+    let mut machine = Machine::new(&[4, 1, 42, 0, 8, 1, 6, 1, 7]);
+    let (outcome, output) = machine.run_capture().unwrap();
+    assert_eq!(RunOutcome::Exited, outcome);
+    assert_eq!(b"42*", &output[..]);
+}
+
+#[test]
+fn reset_and_run_again() {
+    // 0: loadimm r1, 42
+    // 4: store r2, r1
+    // 7: out_number r1
+    // 9: exit
+    let program = [4, 1, 42, 0, 2, 2, 1, 8, 1, 7];
+    let mut machine = Machine::new(&program);
+    machine.add_breakpoint(7);
+    assert_eq!(
+        RunOutcome::Breakpoint(7),
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(42, machine.memory()[0]);
+    machine.reset();
+    assert_eq!(Machine::new(&program), machine);
+
+    // The breakpoint is kept
+    assert_eq!(
+        RunOutcome::Breakpoint(7),
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    machine.reset();
+    machine.remove_breakpoint(7);
+    for _ in 0..2 {
+        assert_eq!(
+            (RunOutcome::Exited, b"42".to_vec()),
+            machine.run_capture().unwrap()
+        );
+        machine.reset();
+    }
+}
+
+#[test]
+fn clone_and_compare_machines() {
+    // 0: rand r1
+    // 2: syscall 1
+    // 4: exit
+    let mut machine = Machine::new(&[50, 1, 48, 1, 7]);
+    machine.seed_rng(3);
+    machine.set_syscall_handler(|_: &mut Machine, _: u8| Ok(()));
+    machine.step_on(&mut io::sink()).unwrap();
+    let mut clone = machine.clone();
+    assert_eq!(machine, clone);
+
+    // The clone goes on the same way, without the syscall handler
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_ne!(machine, clone);
+    assert!(matches!(
+        clone.run_on(&mut io::sink()),
+        Err(MachineError::UnhandledSyscall { .. })
+    ));
+    clone.set_reg(0, 4).unwrap();
+    clone.run_on(&mut io::sink()).unwrap();
+    assert_ne!(machine, clone);
+    assert_eq!(machine.regs(), clone.regs());
+
+    // The memory isn't dumped
+    let debug = format!("{:?}", Machine::new(&[50, 1, 48, 1, 7]));
+    assert!(debug.starts_with("Machine { regs: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "));
+    assert!(debug.contains("memory: 4096 bytes, 5 not zero"));
+}
+
+// Draw `n` numbers with the `rand` instruction from a machine seeded with `seed`
+fn random_numbers(seed: u64, n: usize) -> Vec<u32> {
+    // 0: rand r1
+    // 2:
+    let mut machine = Machine::new(&[50, 1]);
+    machine.seed_rng(seed);
+    (0..n)
+        .map(|_| {
+            machine.set_reg(0, 0).unwrap();
+            expect(&mut machine, false, 2);
+            machine.regs()[1]
+        })
+        .collect()
+}
+
+#[test]
+fn test_rand() {
+    let numbers = random_numbers(42, 100);
+    assert_eq!(numbers, random_numbers(42, 100));
+    assert_ne!(numbers, random_numbers(43, 100));
+    assert!(numbers.windows(2).all(|w| w[0] != w[1]));
+
+    // 0: rand r1
+    // 2:
+    let mut machine = Machine::new(&[50, 1]);
+    expect(&mut machine, false, 2);
+    assert_eq!(random_numbers(0, 1)[0], machine.regs()[1]);
+}
+
+#[test]
+fn test_rand_out_of_bounds() {
+    // 0: rand r100
+    // 2:
+    let mut machine = Machine::new(&[50, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_outs() {
+    // 0: outs r1
+    // 2: "Hello\0"
+    let mut machine = Machine::new(b"\x33\x01Hello\0World\0");
+    machine.set_reg(1, 2).unwrap();
+    let mut out = Vec::new();
+    expect_on(&mut machine, &mut out, false, 2);
+    assert_eq!("Hello".as_bytes(), &out[..]);
+
+    // 0: outs r1 with r1 pointing to an empty string
+    // 2:
+    let mut machine = Machine::new(&[51, 1]);
+    machine.set_reg(1, 100).unwrap();
+    let mut out = Vec::new();
+    expect_on(&mut machine, &mut out, false, 2);
+    assert!(out.is_empty());
+}
+
+#[test]
+fn outs_too_long() {
+    // 0: outs r1
+    // 2: 'x' * 2000
+    let mut memory = vec![51, 1];
+    memory.extend(std::iter::repeat_n(b'x', 2000));
+    let mut machine = Machine::new(&memory);
+    machine.set_reg(1, 2).unwrap();
+    let mut out = Vec::new();
+    assert!(matches!(
+        machine.step_on(&mut out),
+        Err(MachineError::StringTooLong { .. })
+    ));
+    assert!(out.is_empty());
+
+    // A string of exactly the maximum length is accepted
+    memory[2 + 1024] = 0;
+    let mut machine = Machine::new(&memory);
+    machine.set_reg(1, 2).unwrap();
+    expect_on(&mut machine, &mut out, false, 2);
+    assert_eq!(1024, out.len());
+}
+
+#[test]
+fn outs_near_end_of_memory() {
+    // 0: outs r1 with r1 pointing to an unterminated string at the end of memory
+    // 2:
+    let mut memory = Machine::new(&[]).memory().to_vec();
+    let memory_size = memory.len();
+    memory[0] = 51;
+    memory[1] = 1;
+    memory[memory_size - 3..].copy_from_slice(b"abc");
+    let mut machine = Machine::new(&memory);
+    machine.set_reg(1, (memory_size - 3) as u32).unwrap();
+    let mut out = Vec::new();
+    assert!(matches!(
+        machine.step_on(&mut out),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: outs r1 with r1 == 0xffffffff
+    // 2:
+    let mut machine = Machine::new(&[51, 1]);
+    machine.set_reg(1, 0xFFFF_FFFF).unwrap();
+    assert!(machine.step_on(&mut out).is_err());
+
+    // 0: outs r100
+    // 2:
+    let mut machine = Machine::new(&[51, 100]);
+    assert!(machine.step_on(&mut out).is_err());
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_memcpy() {
+    // 0: memcpy [r1] <- [r2] (r3 bytes)
+    // 4: 1 2 3 4 5 6
+    let mut machine = Machine::new(&[52, 1, 2, 3, 1, 2, 3, 4, 5, 6]);
+    machine.set_reg(1, 100).unwrap();
+    machine.set_reg(2, 4).unwrap();
+    machine.set_reg(3, 6).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(&[1, 2, 3, 4, 5, 6, 0], &machine.memory()[100..107]);
+
+    // Overlapping areas
+    machine.set_reg(0, 0).unwrap();
+    machine.set_reg(1, 6).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(&[1, 2, 1, 2, 3, 4, 5, 6], &machine.memory()[4..12]);
+}
+
+#[test]
+fn test_memset() {
+    // 0: memset [r1] <- r2 (r3 bytes)
+    // 4:
+    let mut machine = Machine::new(&[53, 1, 2, 3]);
+    machine.set_reg(1, 10).unwrap();
+    machine.set_reg(2, 0x1AB).unwrap();
+    machine.set_reg(3, 5).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(
+        &[0, 0xAB, 0xAB, 0xAB, 0xAB, 0xAB, 0],
+        &machine.memory()[9..16]
+    );
+
+    // Filling zero bytes does nothing, even at the end of memory
+    let memory_size = machine.memory().len();
+    machine.set_reg(0, 0).unwrap();
+    machine.set_reg(1, memory_size as u32).unwrap();
+    machine.set_reg(3, 0).unwrap();
+    expect(&mut machine, false, 4);
+}
+
+#[test]
+fn test_block_memory_out_of_bounds() {
+    for opcode in [52, 53] {
+        // 0: op r1, r2, r3 with r1 + r3 == memory_size + 1
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 2, 3]);
+        let memory_size = machine.memory().len();
+        machine.set_reg(1, (memory_size - 10) as u32).unwrap();
+        machine.set_reg(3, 11).unwrap();
+        assert!(matches!(
+            machine.step(),
+            Err(MachineError::NonExistingAddress { .. })
+        ));
+
+        // 0: op r1, r2, r3 with r3 == 0xffffffff
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 2, 3]);
+        machine.set_reg(3, 0xFFFF_FFFF).unwrap();
+        assert!(machine.step().is_err());
+
+        // 0: op r1, r2, r100
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 2, 100]);
+        assert!(machine.step().is_err());
+    }
+
+    // 0: memcpy [r1] <- [r2] (r3 bytes) with r2 + r3 == memory_size + 1
+    // 4:
+    let mut machine = Machine::new(&[52, 1, 2, 3]);
+    let memory_size = machine.memory().len();
+    machine.set_reg(2, (memory_size - 10) as u32).unwrap();
+    machine.set_reg(3, 11).unwrap();
+    assert!(machine.step().is_err());
+}
+
+// Run a single `op r2 <- r1` instruction and return r2
+fn unary(opcode: u8, value: u32) -> u32 {
+    let mut machine = Machine::new(&[opcode, 2, 1]);
+    machine.set_reg(1, value).unwrap();
+    expect(&mut machine, false, 3);
+    machine.regs()[2]
+}
+
+#[test]
+fn test_clz() {
+    assert_eq!(32, unary(54, 0));
+    assert_eq!(31, unary(54, 1));
+    assert_eq!(16, unary(54, 0xFFFF));
+    assert_eq!(0, unary(54, 0x8000_0000));
+}
+
+#[test]
+fn test_popcnt() {
+    assert_eq!(0, unary(55, 0));
+    assert_eq!(1, unary(55, 0x8000_0000));
+    assert_eq!(16, unary(55, 0xF0F0_F0F0));
+    assert_eq!(32, unary(55, 0xFFFF_FFFF));
+}
+
+#[test]
+fn test_bit_count_out_of_bounds() {
+    for opcode in [54, 55] {
+        // 0: op r1 <- r100
+        // 3:
+        let mut machine = Machine::new(&[opcode, 1, 100]);
+        assert!(machine.step().is_err());
+    }
+}
+
+#[test]
+fn test_adc() {
+    // 0: adc r3 <- r1 + r2 + carry
+    // 4: adc r4 <- r5 + r5 + carry
+    // 8:
+    let mut machine = Machine::new(&[56, 3, 1, 2, 56, 4, 5, 5]);
+    machine.set_reg(1, 0xFFFF_FFFF).unwrap();
+    machine.set_reg(2, 2).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(1, machine.regs()[3]);
+    assert!(machine.flags().carry);
+    expect(&mut machine, false, 8);
+    assert_eq!(1, machine.regs()[4]);
+    assert!(!machine.flags().carry);
+}
+
+#[test]
+fn adc_carry_from_carry_flag() {
+    // 0: cmp r1, r2 (sets carry as r1 < r2)
+    // 3: adc r3 <- r4 + r2 + carry
+    // 7:
+    let mut machine = Machine::new(&[43, 1, 2, 56, 3, 4, 2]);
+    machine.set_reg(2, 0xFFFF_FFFF).unwrap();
+    expect(&mut machine, false, 3);
+    assert!(machine.flags().carry);
+    expect(&mut machine, false, 7);
+    assert_eq!(0, machine.regs()[3]);
+    assert!(machine.flags().carry);
+}
+
+#[test]
+fn test_sbb() {
+    // 0: sbb r3 <- r1 - r2 - carry
+    // 4: sbb r4 <- r5 - r5 - carry
+    // 8:
+    let mut machine = Machine::new(&[57, 3, 1, 2, 57, 4, 5, 5]);
+    machine.set_reg(1, 1).unwrap();
+    machine.set_reg(2, 2).unwrap();
+    expect(&mut machine, false, 4);
+    assert_eq!(0xFFFF_FFFF, machine.regs()[3]);
+    assert!(machine.flags().carry);
+    expect(&mut machine, false, 8);
+    assert_eq!(0xFFFF_FFFF, machine.regs()[4]);
+    assert!(machine.flags().carry);
+}
+
+#[test]
+fn test_adc_sbb_out_of_bounds() {
+    for opcode in [56, 57] {
+        // 0: op r1 <- r1, r100
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 1, 100]);
+        assert!(machine.step().is_err());
+    }
+}
+
+#[test]
+#[cfg(not(feature = "fp"))]
+fn refuse_float_instructions_without_fp() {
+    for opcode in 58..=64 {
+        let mut machine = Machine::new(&[opcode, 0, 0, 0]);
+        assert!(matches!(
+            machine.step(),
+            Err(MachineError::NonExistingInstruction { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_bswap() {
+    // 0: bswap r1
+    // 2: bswap r1
+    // 4:
+    let mut machine = Machine::new(&[65, 1, 65, 1]);
+    machine.set_reg(1, 0x1234_ABCD).unwrap();
+    expect(&mut machine, false, 2);
+    assert_eq!(0xCDAB_3412, machine.regs()[1]);
+    expect(&mut machine, false, 4);
+    assert_eq!(0x1234_ABCD, machine.regs()[1]);
+
+    // 0: bswap r100
+    // 2:
+    let mut machine = Machine::new(&[65, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn load_big_endian_word() {
+    // 0: load r1 <- [r2]
+    // 3: bswap r1
+    // 5: 0x12 0x34 0x56 0x78
+    let mut machine = Machine::new(&[3, 1, 2, 65, 1, 0x12, 0x34, 0x56, 0x78]);
+    machine.set_reg(2, 5).unwrap();
+    expect(&mut machine, false, 3);
+    expect(&mut machine, false, 5);
+    assert_eq!(0x1234_5678, machine.regs()[1]);
+}
+
+// Run a single `op r3 <- r1, r2` instruction on signed values and return r3
+fn signed_binary(opcode: u8, left: i32, right: i32) -> i32 {
+    let mut machine = Machine::new(&[opcode, 3, 1, 2]);
+    machine.set_reg(1, left as u32).unwrap();
+    machine.set_reg(2, right as u32).unwrap();
+    expect(&mut machine, false, 4);
+    machine.regs()[3] as i32
+}
+
+#[test]
+fn test_min_max() {
+    assert_eq!(-5, signed_binary(66, 3, -5));
+    assert_eq!(3, signed_binary(67, 3, -5));
+    assert_eq!(7, signed_binary(66, 7, 7));
+    assert_eq!(i32::MIN, signed_binary(66, i32::MIN, i32::MAX));
+    assert_eq!(i32::MAX, signed_binary(67, i32::MIN, i32::MAX));
+    assert_eq!(-1, signed_binary(67, i32::MIN, -1));
+}
+
+#[test]
+fn test_abs() {
+    assert_eq!(5, unary(68, -5i32 as u32));
+    assert_eq!(5, unary(68, 5));
+    assert_eq!(0, unary(68, 0));
+    assert_eq!(i32::MAX as u32, unary(68, (i32::MIN + 1) as u32));
+    assert_eq!(i32::MIN as u32, unary(68, i32::MIN as u32));
+}
+
+#[test]
+fn test_min_max_abs_out_of_bounds() {
+    for opcode in [66, 67] {
+        // 0: op r1 <- r1, r100
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 1, 100]);
+        assert!(machine.step().is_err());
+    }
+
+    // 0: abs r100 <- r1
+    // 3:
+    let mut machine = Machine::new(&[68, 100, 1]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_cas() {
+    // 0: cas [r1], r2, r3
+    // 4: 0x2a
+    let mut machine = Machine::new(&[69, 1, 2, 3, 42, 0, 0, 0]);
+    machine.set_reg(1, 4).unwrap();
+    machine.set_reg(2, 42).unwrap();
+    machine.set_reg(3, 0x01020304).unwrap();
+    expect(&mut machine, false, 4);
+    assert!(machine.flags().zero);
+    assert_eq!(42, machine.regs()[2]);
+    assert_eq!(&[4, 3, 2, 1], &machine.memory()[4..8]);
+
+    // The memory does not contain 42 anymore, so nothing is stored
+    machine.set_reg(0, 0).unwrap();
+    machine.set_reg(3, 0).unwrap();
+    expect(&mut machine, false, 4);
+    assert!(!machine.flags().zero);
+    assert_eq!(0x01020304, machine.regs()[2]);
+    assert_eq!(&[4, 3, 2, 1], &machine.memory()[4..8]);
+}
+
+#[test]
+fn test_cas_out_of_bounds() {
+    // 0: cas [r1], r2, r3 with r1 == memory_size - 3
+    // 4:
+    let mut machine = Machine::new(&[69, 1, 2, 3]);
+    let memory_size = machine.memory().len();
+    machine.set_reg(1, (memory_size - 3) as u32).unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+
+    // 0: cas [r1], r2, r100
+    // 4:
+    let mut machine = Machine::new(&[69, 1, 2, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_instructions_retired() {
+    // 0: sub r1 <- r1 - r0
+    // 4: sub r1 <- r1 - r0
+    // 8: exit
+    // 9:
+    let mut machine = Machine::new(&[5, 1, 1, 0, 5, 1, 1, 0, 7]);
+    assert_eq!(0, machine.instructions_retired());
+    machine.run().unwrap();
+    assert_eq!(3, machine.instructions_retired());
+
+    // Faulting instructions are not counted
+    assert!(machine.step().is_err());
+    assert_eq!(3, machine.instructions_retired());
+}
+
+#[test]
+fn test_rdinsn() {
+    // 0: mov r3 <- r3
+    // 3: mov r3 <- r3
+    // 6: rdinsn r1, r2
+    // 9: rdinsn r3, r4
+    // 12:
+    let mut machine = Machine::new(&[45, 3, 3, 45, 3, 3, 70, 1, 2, 70, 3, 4]);
+    machine.set_reg(2, 42).unwrap();
+    expect(&mut machine, false, 3);
+    expect(&mut machine, false, 6);
+    expect(&mut machine, false, 9);
+    assert_eq!(2, machine.regs()[1]);
+    assert_eq!(0, machine.regs()[2]);
+    expect(&mut machine, false, 12);
+    assert_eq!(3, machine.regs()[3]);
+    assert_eq!(4, machine.instructions_retired());
+
+    // 0: rdinsn r1, r100
+    // 3:
+    let mut machine = Machine::new(&[70, 1, 100]);
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_hexdump() {
+    let mut machine = Machine::new(b"Hello, world!\n\x00\xffrvm");
+    assert_eq!(
+        "0x0000: 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 ff  Hello, world!...\n\
+         0x0010: 72 76 6d                                         rvm\n",
+        machine.hexdump(0..19)
+    );
+    assert_eq!(
+        "0x0007: 77 6f                                            wo\n",
+        machine.hexdump(7..9)
+    );
+
+    // The range is cut at the end of the memory
+    machine.load_at(4094, b"ok").unwrap();
+    assert_eq!(
+        "0x0ffe: 6f 6b                                            ok\n",
+        machine.hexdump(4094..5000)
+    );
+    assert_eq!("", machine.hexdump(5000..5001));
+    assert_eq!("", machine.hexdump(3..3));
+}
+
+#[test]
+fn test_error_messages() {
+    // 0: loadimm r2, 0x1000
+    // 4: add r1, r20, r2
+    let mut machine = Machine::new(&[4, 2, 0, 16, 9, 1, 20, 2]);
+    machine.step_on(&mut io::sink()).unwrap();
+    let error = machine.step_on(&mut io::sink()).unwrap_err();
+    assert_eq!(
+        MachineError::NonExistingRegister {
+            ip: 4,
+            opcode: 9,
+            register: 20
+        },
+        error
+    );
+    assert_eq!(
+        "non-existing register r20 in `add` at 0x0004",
+        error.to_string()
+    );
+
+    // 0: load r1, r2 with r2 == 0x1000
+    let mut machine = Machine::new(&[3, 1, 2]);
+    machine.set_reg(2, 0x1000).unwrap();
+    assert_eq!(
+        "address 0x1000 outside the memory in `load` at 0x0000",
+        machine.step_on(&mut io::sink()).unwrap_err().to_string()
+    );
+
+    // 0: invalid opcode 255
+    let mut machine = Machine::new(&[255]);
+    assert_eq!(
+        "invalid opcode 255 at 0x0000",
+        machine.step_on(&mut io::sink()).unwrap_err().to_string()
+    );
+
+    // The errors of the host calls don't come from an instruction
+    let error = machine.set_reg(16, 0).unwrap_err();
+    assert_eq!("non-existing register r16", error.to_string());
+    let error: Box<dyn std::error::Error> = Box::new(MachineError::CorruptImage);
+    assert_eq!("program image not matching its checksum", error.to_string());
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "rayon")]
+
+use interpreter::{assemble, run_all, DecodedInsn, HookAction, Machine, RunOutcome};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Prints r1 times r2, computed by repeated additions
+const PRODUCT: &str = "
+        loadimm r4, 1
+loop:   beq r2, r5, done
+        add r3, r3, r1
+        sub r2, r2, r4
+        jmp loop
+done:   out_number r3
+        exit
+";
+
+#[test]
+fn run_machines_in_parallel() {
+    let program = assemble(PRODUCT).unwrap();
+    let mut machines: Vec<Machine> = (0..64)
+        .map(|i| {
+            let mut machine = Machine::try_new(&program).unwrap();
+            machine.set_reg(1, i).unwrap();
+            machine.set_reg(2, 1000 + i).unwrap();
+            machine
+        })
+        .collect();
+    let mut expected = machines.clone();
+
+    let outcomes = run_all(&mut machines);
+    assert_eq!(64, outcomes.len());
+    for (i, (outcome, machine)) in outcomes.into_iter().zip(&machines).enumerate() {
+        let i = i as u32;
+        let (outcome, output) = outcome.unwrap();
+        assert_eq!(RunOutcome::Exited, outcome);
+        assert_eq!((i * (1000 + i)).to_string().into_bytes(), output);
+        assert_eq!(
+            expected[i as usize].run_capture().unwrap(),
+            (outcome, output)
+        );
+        assert_eq!(expected[i as usize].snapshot(), machine.snapshot());
+    }
+}
+
+#[test]
+fn report_every_outcome() {
+    let looping = Machine::try_new(&assemble("loop: jmp loop").unwrap()).unwrap();
+    let mut limited = looping.clone();
+    limited.set_fuel(Some(100));
+    let faulting = Machine::try_new(&assemble("loadimm r1, -1\nload r2, r1").unwrap()).unwrap();
+    let mut hooked = Machine::try_new(&assemble("loadimm r1, 1\nexit").unwrap()).unwrap();
+    let steps = Arc::new(AtomicU64::new(0));
+    let counted = steps.clone();
+    hooked.set_hook(move |_: &Machine, _: &DecodedInsn| {
+        counted.fetch_add(1, Ordering::Relaxed);
+        HookAction::Continue
+    });
+
+    let mut machines = vec![limited, faulting.clone(), hooked];
+    let outcomes = run_all(&mut machines);
+    assert_eq!(Ok((RunOutcome::OutOfFuel, Vec::new())), outcomes[0]);
+    assert_eq!(
+        Err(faulting.clone().run_capture().unwrap_err()),
+        outcomes[1]
+    );
+    assert_eq!(Ok((RunOutcome::Exited, Vec::new())), outcomes[2]);
+    assert_eq!(2, steps.load(Ordering::Relaxed));
+    assert!(run_all(&mut []).is_empty());
+}
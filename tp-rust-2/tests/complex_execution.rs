@@ -1,3 +1,6 @@
+// The tests build their programs, known to fit, with Machine::new
+#![allow(deprecated)]
+
 use interpreter::Machine;
 
 #[test]
@@ -15,6 +18,15 @@ fn test_function() {
     assert_eq!(42, machine.regs()[10]);
 }
 
+#[test]
+fn test_nested_calls() {
+    let mut machine = Machine::new(include_bytes!("nested_calls.bin"));
+    machine.run().unwrap();
+    assert_eq!(14, machine.regs()[10]);
+    assert_eq!(7, machine.regs()[14]);
+    assert_eq!(8, machine.regs()[0]);
+}
+
 // Multiplication
 #[test]
 fn test_mult() {
@@ -91,3 +103,49 @@ fn test_fibo() {
         assert_eq!(fibo(i), machine.regs()[11]);
     }
 }
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Greatest common divisor using conditional branches
+#[test]
+fn test_gcd() {
+    for a in 1..30 {
+        for b in &[1u32, 7, 12, 25, 48] {
+            let mut machine = Machine::new(include_bytes!("gcd.bin"));
+            machine.set_reg(10, a).unwrap();
+            machine.set_reg(11, *b).unwrap();
+            machine.run().unwrap();
+            assert_eq!(gcd(a, *b), machine.regs()[11]);
+        }
+    }
+}
+
+// 64-bit addition with r11:r10 <- r11:r10 + r13:r12
+#[test]
+fn test_add64() {
+    for left in &[
+        0u64,
+        1,
+        0xFFFF_FFFF,
+        0x1_0000_0000,
+        0x1234_5678_9ABC_DEF0,
+        u64::MAX,
+    ] {
+        for right in &[0u64, 1, 0xFFFF_FFFF, 0x8000_0000_8000_0000] {
+            let mut machine = Machine::new(include_bytes!("add64.bin"));
+            machine.set_reg(10, *left as u32).unwrap();
+            machine.set_reg(11, (*left >> 32) as u32).unwrap();
+            machine.set_reg(12, *right as u32).unwrap();
+            machine.set_reg(13, (*right >> 32) as u32).unwrap();
+            machine.run().unwrap();
+            let result = ((machine.regs()[11] as u64) << 32) | machine.regs()[10] as u64;
+            assert_eq!(left.wrapping_add(*right), result);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use interpreter::{assemble, disassemble, FormatOptions, Machine};
+use std::io;
+
+const PROGRAM: &str = "
+            loadimm r1, 2
+            loadimm r2, 1
+      loop: sub r1, r1, r2
+            bne r1, r3, loop
+            exit
+            out_number r1
+";
+
+#[test]
+fn count_the_executions() {
+    let mut machine = Machine::try_new(&assemble(PROGRAM).unwrap()).unwrap();
+    assert!(machine.coverage().is_none());
+    machine.enable_coverage();
+    machine.run_on(&mut io::sink()).unwrap();
+    let coverage = machine.coverage().unwrap();
+    assert_eq!(1, coverage.hits(0));
+    assert_eq!(2, coverage.hits(8));
+    assert_eq!(2, coverage.hits(12));
+    assert_eq!(0, coverage.hits(18));
+    assert!(!coverage.is_covered(1));
+    assert_eq!(vec![0, 4, 8, 12, 17], coverage.covered());
+    assert!(machine.disable_coverage().is_some());
+    assert!(machine.coverage().is_none());
+}
+
+#[test]
+fn report_the_executions() {
+    let program = assemble(PROGRAM).unwrap();
+    let mut machine = Machine::try_new(&program).unwrap();
+    machine.enable_coverage();
+    machine.run_on(&mut io::sink()).unwrap();
+    let instructions = disassemble(machine.memory(), 0, program.len());
+    assert_eq!(
+        "       1  0000  loadimm r1, 2\n       \
+                1  0004  loadimm r2, 1\n       \
+                2  0008  sub r1, r1, r2\n       \
+                2  000c  bne r1, r3, 0x0008\n       \
+                1  0011  exit\n       \
+                -  0012  out_number r1\n\
+         5 of 6 instructions executed (83.3%)\n",
+        machine
+            .coverage()
+            .unwrap()
+            .report(&instructions, &FormatOptions::default())
+    );
+}
+
+#[test]
+fn faults_are_not_counted() {
+    let mut machine =
+        Machine::try_new(&assemble("loadimm r2, -1\nload r1, r2\n").unwrap()).unwrap();
+    machine.enable_coverage();
+    assert!(machine.run_on(&mut io::sink()).is_err());
+    assert_eq!(vec![0], machine.coverage().unwrap().covered());
+}
@@ -0,0 +1,81 @@
+use interpreter::{
+    assemble_with_debug_info, Assembler, DebugInfo, DebugInfoError, DebugInfoErrorKind,
+    SourceLocation,
+};
+
+fn location(file: &str, line: usize) -> SourceLocation {
+    SourceLocation {
+        file: file.to_string(),
+        line,
+    }
+}
+
+#[test]
+fn assemble_debug_info() {
+    let source = "
+        start: loadimm r1, 3
+        loop:  sub r1, r1, r2
+               .byte 1, 2
+               bne r1, r3, loop
+               exit
+    ";
+    let (program, info) = assemble_with_debug_info("loop.s", source).unwrap();
+    assert_eq!(16, program.len());
+    assert_eq!(Some(&location("loop.s", 2)), info.location(0));
+    assert_eq!(Some(&location("loop.s", 3)), info.location(7));
+    assert_eq!(Some(&location("loop.s", 4)), info.location(9));
+    assert_eq!(Some(&location("loop.s", 6)), info.location(15));
+    assert_eq!(None, info.location(16));
+    assert_eq!("loop.s:5", info.describe(10));
+    assert_eq!("0x01a3", info.describe(0x1a3));
+    assert_eq!(Some(4), info.symbols().address("loop"));
+    assert_eq!(Some(("start", 3)), info.label_for(3));
+    assert_eq!(Some(("loop", 11)), info.label_for(15));
+
+    // The text format is parsed back
+    assert_eq!(info, DebugInfo::parse(&info.to_string()).unwrap());
+}
+
+#[test]
+fn debug_info_of_included_files() {
+    let mut assembler = Assembler::new();
+    assembler.set_include_loader(|name: &str| match name {
+        "exit.s" => Ok("\n  exit".to_string()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    });
+    let (_, info) = assembler
+        .assemble_with_debug_info("main.s", "out r1\n.include \"exit.s\"")
+        .unwrap();
+    assert_eq!(Some(&location("main.s", 1)), info.location(1));
+    assert_eq!(Some(&location("exit.s", 2)), info.location(2));
+}
+
+#[test]
+fn parse_debug_info() {
+    let info =
+        DebugInfo::parse("; comment\n\nline 0x10  4 my dir/loop.s:42\nlabel 16 loop ; the loop\n")
+            .unwrap();
+    assert_eq!(Some(&location("my dir/loop.s", 42)), info.location(0x13));
+    assert_eq!(None, info.location(0x14));
+    assert_eq!(Some(0x10), info.symbols().address("loop"));
+
+    let error = |text: &str| DebugInfo::parse(text).unwrap_err();
+    assert_eq!(
+        DebugInfoError {
+            line: 2,
+            kind: DebugInfoErrorKind::UnknownRecord("lines".to_string())
+        },
+        error("label 0 a\nlines 0 1 a.s:1")
+    );
+    assert_eq!(DebugInfoErrorKind::MissingField, error("line 0 1").kind);
+    assert_eq!(DebugInfoErrorKind::MissingField, error("label 0").kind);
+    assert_eq!(
+        DebugInfoErrorKind::InvalidNumber("0xg".to_string()),
+        error("label 0xg a").kind
+    );
+    assert_eq!(
+        DebugInfoErrorKind::InvalidLocation("a.s".to_string()),
+        error("line 0 1 a.s").kind
+    );
+    assert_eq!("line 1: missing field", error("label").to_string());
+}
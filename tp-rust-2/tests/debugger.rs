@@ -0,0 +1,305 @@
+use interpreter::{assemble, assemble_with_debug_info, Debugger, Machine};
+
+// The transcript of a debugging session running `commands` on `source`
+fn session(source: &str, commands: &str) -> String {
+    let mut debugger = Debugger::new(Machine::try_new(&assemble(source).unwrap()).unwrap());
+    let mut output: Vec<u8> = Vec::new();
+    debugger.run(commands.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+const COUNT: &str = "
+    loadimm r1, 3
+    loadimm r2, 1
+    loop: sub r1, r1, r2
+    out_number r1
+    bne r1, r3, loop
+    halt r2
+";
+
+#[test]
+fn step_and_print() {
+    let transcript = session(COUNT, "step\n\nregs\nmem 0 8\nquit\nstep\n");
+    assert_eq!(
+        "(rvm) 0x0004: loadimm r2, 1\n\
+         (rvm) 0x0008: sub r1, r1, r2\n\
+         (rvm) r0  0x00000008  r1  0x00000003  r2  0x00000001  r3  0x00000000\n\
+         r4  0x00000000  r5  0x00000000  r6  0x00000000  r7  0x00000000\n\
+         r8  0x00000000  r9  0x00000000  r10 0x00000000  r11 0x00000000\n\
+         r12 0x00000000  r13 0x00000000  r14 0x00000000  r15 0x00000000\n\
+         flags: zero=0 negative=0 carry=0 overflow=0\n\
+         (rvm) 0x0000: 04 01 03 00 04 02 01 00                          ........\n\
+         (rvm) ",
+        transcript
+    );
+}
+
+#[test]
+fn continue_to_breakpoints() {
+    let transcript = session(COUNT, "break 0x0c\nc\nc\ndelete 12\nb\nc\ns\n");
+    assert_eq!(
+        "(rvm) breakpoint at 0x000c\n\
+         (rvm) breakpoint at 0x000c\n\
+         0x000c: out_number r1\n\
+         (rvm) 2breakpoint at 0x000c\n\
+         0x000c: out_number r1\n\
+         (rvm) (rvm) no breakpoints\n\
+         (rvm) 10the program exited with code 1\n\
+         (rvm) the program is not running\n\
+         (rvm) \n",
+        transcript
+    );
+}
+
+#[test]
+fn report_faults_and_errors() {
+    let transcript = session(
+        "loadimm r1, -1\nload r2, r1\n",
+        "c\nfoo\nstep x\nmem\nb nowhere\n",
+    );
+    assert_eq!(
+        "(rvm) fault at 0x0004: address 0xffffffff outside the memory in `load` at 0x0004\n\
+         (rvm) unknown command `foo`, see `help`\n\
+         (rvm) invalid count `x`\n\
+         (rvm) invalid arguments for `mem`, see `help`\n\
+         (rvm) invalid address `nowhere`\n\
+         (rvm) \n",
+        transcript
+    );
+}
+
+#[test]
+fn use_debug_info() {
+    let (program, debug_info) = assemble_with_debug_info("count.s", COUNT).unwrap();
+    let mut debugger = Debugger::new(Machine::try_new(&program).unwrap());
+    debugger.set_debug_info(debug_info);
+    let mut output: Vec<u8> = Vec::new();
+    debugger
+        .run("break loop\ncontinue\n".as_bytes(), &mut output)
+        .unwrap();
+    assert_eq!(
+        "(rvm) breakpoint at 0x0008 <loop> (count.s:4)\n\
+         (rvm) breakpoint at 0x0008 <loop> (count.s:4)\n\
+         0x0008: sub r1, r1, r2 ; count.s:4\n\
+         (rvm) \n",
+        String::from_utf8(output).unwrap()
+    );
+    assert_eq!(
+        vec![8],
+        debugger.machine().breakpoints().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn watch_memory() {
+    let source = "
+        loadimm r1, 0x100
+        loadimm r2, 42
+        store r1, r2
+        push r2
+        exit
+    ";
+    let transcript = session(source, "watch 0x100\nw\nc\nunwatch 0x100\nw\nc\n");
+    assert_eq!(
+        "(rvm) watchpoint at 0x0100..0x0104\n\
+         (rvm) watchpoint at 0x0100..0x0104\n\
+         (rvm) watchpoint: 0x0008 wrote 0x0100: 00 00 00 00 -> 2a 00 00 00\n\
+         0x000b: push r2\n\
+         (rvm) (rvm) no watchpoints\n\
+         (rvm) fault at 0x000b: stack overflow or underflow with sp = 0x0000 in `push` at 0x000b\n\
+         (rvm) \n",
+        transcript
+    );
+}
+
+#[test]
+fn watch_reads_and_instructions() {
+    let source = "
+        loadimm r1, 0x100
+        loadimm r2, 42
+        store r1, r2
+        load r3, r1
+        out_number r3
+        exit
+    ";
+    let commands = "rwatch 0x100 1\nrwatch\nc\nunwatch 0x100\nrwatch\n\
+                    b insn exit\nb\nc\nd insn exit\nd insn exit\nb insn foo\nc\n";
+    assert_eq!(
+        "(rvm) read watchpoint at 0x0100..0x0101\n\
+         (rvm) read watchpoint at 0x0100..0x0101\n\
+         (rvm) read watchpoint: 0x000b read 0x0100: 2a 00 00 00\n\
+         0x000e: out_number r3\n\
+         (rvm) (rvm) no read watchpoints\n\
+         (rvm) breakpoint on exit\n\
+         (rvm) breakpoint on exit\n\
+         (rvm) 42breakpoint at 0x0010\n\
+         0x0010: exit\n\
+         (rvm) (rvm) no breakpoint on exit\n\
+         (rvm) unknown instruction `foo`\n\
+         (rvm) the program exited with code 0\n\
+         (rvm) \n",
+        session(source, commands)
+    );
+}
+
+#[test]
+fn conditional_breakpoints() {
+    let transcript = session(
+        COUNT,
+        "break 0x0c if r1 == 1\nb\nc\nregs\nb 8 if r1 +\nstep 2 if r1\n",
+    );
+    assert!(transcript.starts_with(
+        "(rvm) breakpoint at 0x000c if r1 == 1\n\
+         (rvm) breakpoint at 0x000c if r1 == 1\n\
+         (rvm) 2breakpoint at 0x000c\n\
+         0x000c: out_number r1\n\
+         (rvm) r0  0x0000000c  r1  0x00000001"
+    ));
+    assert!(transcript.ends_with(
+        "(rvm) invalid condition: column 5: unexpected end of expression\n\
+         (rvm) only breakpoints take a condition\n\
+         (rvm) \n"
+    ));
+}
+
+#[test]
+fn step_backwards() {
+    let source = "
+        loadimm r1, 0x100
+        loadimm r2, -1
+        store r1, r2
+        load r3, r2
+    ";
+    let transcript = session(source, "c\nrstep\nmem 0x100 4\nrstep 4\nstep\n");
+    assert_eq!(
+        "(rvm) fault at 0x000b: address 0xffffffff outside the memory in `load` at 0x000b\n\
+         (rvm) 0x0008: store r1, r2\n\
+         (rvm) 0x0100: 00 00 00 00                                      ....\n\
+         (rvm) no earlier step recorded\n\
+         0x0000: loadimm r1, 256\n\
+         (rvm) 0x0004: loadimm r2, 65535\n\
+         (rvm) \n",
+        transcript
+    );
+}
+
+#[test]
+fn run_scripts() {
+    let mut debugger = Debugger::new(Machine::try_new(&assemble(COUNT).unwrap()).unwrap());
+    let mut output: Vec<u8> = Vec::new();
+    let script = "# Stop in the loop\n\
+                  break 0x0e\n\
+                  \n\
+                  c\n\
+                  expect r1 == 2 && mem[0] == 0x30104\n\
+                  expect r1 == 3\n\
+                  expect mem[r1 - 3]\n\
+                  expect (\n\
+                  quit\n\
+                  step\n";
+    debugger.run_script(script, &mut output).unwrap();
+    assert_eq!(
+        "(rvm) break 0x0e\n\
+         breakpoint at 0x000e\n\
+         (rvm) c\n\
+         2breakpoint at 0x000e\n\
+         0x000e: bne r1, r3, 0x0008\n\
+         (rvm) expect r1 == 2 && mem[0] == 0x30104\n\
+         (rvm) expect r1 == 3\n\
+         expectation failed: r1 == 3\n\
+         (rvm) expect mem[r1 - 3]\n\
+         expectation failed: mem[r1 - 3]: address 0xffffffff outside the memory\n\
+         (rvm) expect (\n\
+         invalid condition: column 2: unexpected end of expression\n\
+         (rvm) quit\n",
+        String::from_utf8(output).unwrap()
+    );
+    assert_eq!(3, debugger.failures());
+}
+
+const CALLS: &str = "
+    main: loadimm sp, 4096
+          loadimm r1, 2
+          call double
+          out_number r1
+          halt r1
+  double: push lr
+          add r1, r1, r1
+          call inc
+          pop lr
+          ret
+     inc: loadimm r2, 1
+          add r1, r1, r2
+          ret
+";
+
+#[test]
+fn step_over_and_out_of_calls() {
+    let transcript = session(CALLS, "s 2\nnext\nn\n");
+    assert_eq!(
+        "(rvm) 0x0008: call 0x000f\n\
+         (rvm) 0x000b: out_number r1\n\
+         (rvm) 50x000d: halt r1\n\
+         (rvm) \n",
+        transcript
+    );
+    let transcript = session(CALLS, "s 4\nfinish\nfin\n");
+    assert_eq!(
+        "(rvm) 0x0011: add r1, r1, r1\n\
+         (rvm) 0x000b: out_number r1\n\
+         (rvm) 5the program exited with code 5\n\
+         (rvm) \n",
+        transcript
+    );
+
+    // The breakpoints in the called functions stop them
+    let transcript = session(CALLS, "b 0x1f\ns 2\nn 2\nfinish\nn\n");
+    assert_eq!(
+        "(rvm) breakpoint at 0x001f\n\
+         (rvm) 0x0008: call 0x000f\n\
+         (rvm) breakpoint at 0x001f\n\
+         0x001f: add r1, r1, r2\n\
+         (rvm) 0x0018: pop r14\n\
+         (rvm) 0x001a: ret\n\
+         (rvm) \n",
+        transcript
+    );
+}
+
+#[test]
+fn print_backtraces() {
+    let (program, debug_info) = assemble_with_debug_info("calls.s", CALLS).unwrap();
+    let mut debugger = Debugger::new(Machine::try_new(&program).unwrap());
+    debugger.set_debug_info(debug_info);
+    let mut output: Vec<u8> = Vec::new();
+    debugger
+        .run("bt\nbreak inc\nc\nbt\nfinish\nbt\n".as_bytes(), &mut output)
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("(rvm) #0 0x0000 <main> (calls.s:2)\n(rvm) "));
+    assert!(output.contains(
+        "(rvm) #0 0x001b <inc> (calls.s:12)\n\
+         #1 0x0018 <double+9> (calls.s:10)\n\
+         #2 0x000b <main+11> (calls.s:5)\n"
+    ));
+    assert!(output.ends_with(
+        "(rvm) #0 0x0018 <double+9> (calls.s:10)\n\
+         #1 0x000b <main+11> (calls.s:5)\n\
+         (rvm) \n"
+    ));
+}
+
+#[test]
+fn rewind_instructions() {
+    let transcript = session(COUNT, "s 5\nrewind 2\nrewind 10\nc\nrewind 3\n");
+    assert_eq!(
+        "(rvm) 20x0008: sub r1, r1, r2\n\
+         (rvm) 0x000c: out_number r1\n\
+         (rvm) cannot rewind 10 instructions\n\
+         0x000c: out_number r1\n\
+         (rvm) 210the program exited with code 1\n\
+         (rvm) 0x000c: out_number r1\n\
+         (rvm) \n",
+        transcript
+    );
+}
@@ -0,0 +1,180 @@
+use interpreter::{
+    assemble, assemble_with_symbols, disassemble, format_listing, format_listing_as,
+    format_listing_with, instruction_info, FormatOptions, SymbolTable, INSTRUCTIONS,
+};
+
+#[test]
+fn disassemble_gcd() {
+    let program = include_bytes!("gcd.bin");
+    let listing = format_listing(&disassemble(program, 0, program.len()));
+    assert_eq!(
+        "\
+0000  1a 0a 0b 18 00     beq r10, r11, 0x0018
+0005  1d 0a 0b 11 00     bge r10, r11, 0x0011
+000a  05 0b 0b 0a        sub r11, r11, r10
+000e  18 00 00           jmp 0x0000
+0011  05 0a 0a 0b        sub r10, r10, r11
+0015  18 00 00           jmp 0x0000
+0018  07                 exit
+",
+        listing
+    );
+}
+
+#[test]
+fn disassemble_operands() {
+    let program = assemble(
+        "
+        loadimm32 r1, -1
+        jrel -3
+        loadx r1, r2, 0x80
+        fcvt f7, r3
+    ",
+    )
+    .unwrap();
+    let text: Vec<String> = disassemble(&program, 0, program.len())
+        .iter()
+        .map(|insn| insn.to_string())
+        .collect();
+    assert_eq!(
+        vec![
+            "loadimm32 r1, 4294967295",
+            "jrel -3",
+            "loadx r1, r2, 128",
+            "fcvt f7, r3"
+        ],
+        text
+    );
+}
+
+#[test]
+fn disassemble_range() {
+    let program = include_bytes!("gcd.bin");
+    let instructions = disassemble(program, 10, 7);
+    assert_eq!(2, instructions.len());
+    assert_eq!(10, instructions[0].address);
+    assert_eq!(vec![5, 11, 11, 10], instructions[0].bytes);
+    assert_eq!(instruction_info(5), instructions[0].info);
+    assert_eq!(vec![11, 11, 10], instructions[0].operands);
+    assert_eq!(14, instructions[1].address);
+
+    // The range is clipped to the end of the memory
+    assert_eq!(1, disassemble(program, 24, 100).len());
+    assert!(disassemble(program, 100, 10).is_empty());
+}
+
+#[test]
+fn disassemble_invalid() {
+    // Unknown opcode, then an instruction truncated by the end of the range
+    let instructions = disassemble(&[0, 255, 4, 1, 2], 0, 5);
+    let text: Vec<String> = instructions.iter().map(|insn| insn.to_string()).collect();
+    assert_eq!(
+        vec![
+            ".byte 0x00",
+            ".byte 0xff",
+            ".byte 0x04",
+            ".byte 0x01",
+            ".byte 0x02"
+        ],
+        text
+    );
+    assert!(instructions.iter().all(|insn| insn.info.is_none()));
+}
+
+#[test]
+fn disassemble_reassembles() {
+    // The listing of every instruction is accepted by the assembler
+    let mut program = Vec::new();
+    for info in INSTRUCTIONS {
+        program.push(info.opcode);
+        for (i, kind) in info.operands.iter().enumerate() {
+            program.extend(std::iter::repeat_n(i as u8 + 1, kind.size()));
+        }
+    }
+    let source: Vec<String> = disassemble(&program, 0, program.len())
+        .iter()
+        .map(|insn| insn.to_string())
+        .collect();
+    assert_eq!(program, assemble(&source.join("\n")).unwrap());
+}
+
+#[test]
+fn disassemble_with_symbols() {
+    let source = "
+        loop:    beq r10, r11, done
+                 bge r10, r11, greater
+                 sub r11, r11, r10
+                 jmp loop
+        greater: sub r10, r10, r11
+                 jrel loop
+        done:    loadimm r1, message
+        message: exit
+    ";
+    let (program, symbols) = assemble_with_symbols(source).unwrap();
+    let listing = format_listing_with(&disassemble(&program, 0, program.len()), &symbols);
+    assert_eq!(
+        "\
+loop:
+0000  1a 0a 0b 18 00     beq r10, r11, done
+0005  1d 0a 0b 11 00     bge r10, r11, greater
+000a  05 0b 0b 0a        sub r11, r11, r10
+000e  18 00 00           jmp loop
+greater:
+0011  05 0a 0a 0b        sub r10, r10, r11
+0015  19 e8 ff           jrel loop
+done:
+0018  04 01 1c 00        loadimm r1, 28 ; message
+message:
+001c  07                 exit
+",
+        listing
+    );
+
+    // Addresses without a label are left as numbers
+    let mut symbols = SymbolTable::new();
+    symbols.insert("greater", 0x11);
+    let instructions = disassemble(&program, 0, 5);
+    assert_eq!(
+        "beq r10, r11, 0x0018",
+        instructions[0].format_with(&symbols)
+    );
+}
+
+#[test]
+fn disassemble_register_aliases() {
+    let source = "
+        start: push r14
+               add r1, r2, r5
+               mov r0, r15
+               call start
+    ";
+    let (program, symbols) = assemble_with_symbols(source).unwrap();
+    let instructions = disassemble(&program, 0, program.len());
+    assert_eq!("push r14", instructions[0].to_string());
+    let options = FormatOptions {
+        symbols: Some(&symbols),
+        aliases: true,
+    };
+    assert_eq!(
+        "\
+start:
+0000  20 0e              push lr
+0002  09 01 02 05        add a0, a1, r5
+0006  2d 00 0f           mov ip, sp
+0009  1e 00 00           call start
+",
+        format_listing_as(&instructions, &options)
+    );
+
+    // The aliases are accepted by the assembler
+    let source: Vec<String> = instructions
+        .iter()
+        .map(|insn| {
+            insn.format(&FormatOptions {
+                aliases: true,
+                ..FormatOptions::default()
+            })
+        })
+        .collect();
+    assert_eq!(program, assemble(&source.join("\n")).unwrap());
+}
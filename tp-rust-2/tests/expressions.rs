@@ -0,0 +1,95 @@
+use interpreter::{assemble, Expr, ExprError, ExprErrorKind, Machine, MachineError, RunOutcome};
+
+fn eval(source: &str, machine: &Machine) -> Result<u32, MachineError> {
+    Expr::parse(source).unwrap().eval(machine)
+}
+
+fn parse_error(source: &str) -> ExprError {
+    Expr::parse(source).unwrap_err()
+}
+
+#[test]
+fn evaluate_expressions() {
+    let mut machine = Machine::try_new(&[0x2a, 0, 1, 0]).unwrap();
+    machine.set_reg(3, 10).unwrap();
+    machine.set_reg(15, 0x100).unwrap();
+    assert_eq!(1, eval("r3 == 10 && mem[0] != 0", &machine).unwrap());
+    assert_eq!(0x1002a, eval("mem[r3 - 10]", &machine).unwrap());
+    assert_eq!(7, eval("1 + 2 * 3", &machine).unwrap());
+    assert_eq!(9, eval("(1 + 2) * 3", &machine).unwrap());
+    assert_eq!(1, eval("sp > 0xff && 0b11 % 2 == 1", &machine).unwrap());
+    assert_eq!(u32::MAX, eval("-1", &machine).unwrap());
+    assert_eq!(1, eval("!r4 || mem[5000]", &machine).unwrap());
+    assert_eq!(0, eval("10 - 11 < 0", &machine).unwrap());
+    assert_eq!("r3==10", Expr::parse(" r3==10 ").unwrap().to_string());
+    assert!(matches!(
+        eval("mem[4094]", &machine),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+    assert!(matches!(
+        eval("r3 / r4", &machine),
+        Err(MachineError::DivisionByZero { .. })
+    ));
+}
+
+#[test]
+fn report_expression_errors() {
+    assert_eq!(
+        ExprError {
+            position: 7,
+            kind: ExprErrorKind::UnexpectedEnd
+        },
+        parse_error("r3 == (")
+    );
+    assert_eq!(
+        ExprErrorKind::UnknownName("r16".to_string()),
+        parse_error("r16 == 1").kind
+    );
+    assert_eq!(
+        ExprErrorKind::InvalidNumber("0x100000000".to_string()),
+        parse_error("0x100000000").kind
+    );
+    assert_eq!(
+        ExprErrorKind::UnexpectedCharacter('$'),
+        parse_error("r1 + $").kind
+    );
+    assert_eq!("column 4: unexpected `2`", parse_error("r1 2").to_string());
+    assert_eq!(
+        ExprErrorKind::UnexpectedCharacter(']'),
+        parse_error("mem[1]]").kind
+    );
+}
+
+#[test]
+fn stop_on_conditional_breakpoints() {
+    let program = assemble(
+        "
+            loadimm r1, 5
+            loadimm r2, 1
+      loop: sub r1, r1, r2
+            bne r1, r3, loop
+            exit
+        ",
+    )
+    .unwrap();
+    let mut machine = Machine::try_new(&program).unwrap();
+    let condition = Expr::parse("r1 == 2").unwrap();
+    assert!(machine.add_conditional_breakpoint(12, condition.clone()));
+    assert_eq!(Some(&condition), machine.breakpoint_condition(12));
+    assert_eq!(
+        RunOutcome::Breakpoint(12),
+        machine.run_on(&mut Vec::new()).unwrap()
+    );
+    assert_eq!(2, machine.regs()[1]);
+    assert_eq!(RunOutcome::Exited, machine.run_on(&mut Vec::new()).unwrap());
+
+    // A condition that can't be evaluated stops too
+    let mut machine = Machine::try_new(&program).unwrap();
+    machine.add_conditional_breakpoint(8, Expr::parse("mem[0x1000]").unwrap());
+    assert_eq!(
+        RunOutcome::Breakpoint(8),
+        machine.run_on(&mut Vec::new()).unwrap()
+    );
+    assert!(!machine.add_breakpoint(8));
+    assert_eq!(None, machine.breakpoint_condition(8));
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "fp")]
+
+use interpreter::{Machine, MachineError};
+
+#[test]
+fn test_float_arithmetic() {
+    // 0: fadd f2 <- f0 + f1
+    // 4: fsub f3 <- f0 - f1
+    // 8: fmul f4 <- f0 * f1
+    // 12: fdiv f5 <- f0 / f1
+    // 16:
+    let mut machine =
+        Machine::try_new(&[58, 2, 0, 1, 59, 3, 0, 1, 60, 4, 0, 1, 61, 5, 0, 1]).unwrap();
+    machine.set_freg(0, 1.5).unwrap();
+    machine.set_freg(1, -0.5).unwrap();
+    for _ in 0..4 {
+        machine.step().unwrap();
+    }
+    assert_eq!(&[1.5, -0.5, 1.0, 2.0, -0.75, -3.0], &machine.fregs()[..6]);
+}
+
+#[test]
+fn float_division_by_zero() {
+    // 0: fdiv f2 <- f0 / f1
+    // 4:
+    let mut machine = Machine::try_new(&[61, 2, 0, 1]).unwrap();
+    machine.set_freg(0, 1.0).unwrap();
+    machine.step().unwrap();
+    assert_eq!(f32::INFINITY, machine.fregs()[2]);
+}
+
+#[test]
+fn test_float_conversions() {
+    // 0: fcvt f1 <- r1
+    // 3: fcvti r2 <- f1
+    // 6: fcvti r3 <- f2
+    // 9:
+    let mut machine = Machine::try_new(&[62, 1, 1, 63, 2, 1, 63, 3, 2]).unwrap();
+    machine.set_reg(1, -7i32 as u32).unwrap();
+    machine.set_freg(2, 1e20).unwrap();
+    for _ in 0..3 {
+        machine.step().unwrap();
+    }
+    assert_eq!(-7.0, machine.fregs()[1]);
+    assert_eq!(-7, machine.regs()[2] as i32);
+    assert_eq!(i32::MAX, machine.regs()[3] as i32);
+
+    // 0: fcvti r1 <- f0
+    // 3:
+    let mut machine = Machine::try_new(&[63, 1, 0]).unwrap();
+    machine.set_freg(0, -2.9).unwrap();
+    machine.step().unwrap();
+    assert_eq!(-2, machine.regs()[1] as i32);
+}
+
+#[test]
+fn test_fout() {
+    // 0: fout f0
+    // 2: fout f1
+    // 4: exit
+    // 5:
+    let mut machine = Machine::try_new(&[64, 0, 64, 1, 7]).unwrap();
+    machine.set_freg(0, 2.25).unwrap();
+    machine.set_freg(1, -3.0).unwrap();
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!("2.25-3".as_bytes(), &out[..]);
+}
+
+#[test]
+fn test_float_out_of_bounds() {
+    assert!(Machine::try_new(&[]).unwrap().set_freg(8, 0.0).is_err());
+    for program in [
+        &[58, 8, 0, 0][..],
+        &[62, 8, 0],
+        &[62, 0, 16],
+        &[63, 16, 0],
+        &[63, 0, 8],
+        &[64, 8],
+    ] {
+        let mut machine = Machine::try_new(program).unwrap();
+        assert!(matches!(
+            machine.step(),
+            Err(MachineError::NonExistingRegister { .. })
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+use interpreter::{assemble, Heatmap, Machine};
+use std::io;
+
+fn heatmap(source: &str) -> Heatmap {
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    machine.enable_heatmap();
+    machine.run_on(&mut io::sink()).unwrap();
+    machine.disable_heatmap().unwrap()
+}
+
+#[test]
+fn count_the_accesses() {
+    let heatmap = heatmap(
+        "
+            loadimm r1, 0x100
+            loadimm r2, 42
+            store r1, r2
+            loadb r3, r1
+            load r4, r1
+            exit
+        ",
+    );
+    assert_eq!(2, heatmap.reads(0x100));
+    assert_eq!(1, heatmap.reads(0x103));
+    assert_eq!(1, heatmap.writes(0x103));
+    assert_eq!(0, heatmap.writes(0x104));
+    assert_eq!(0, heatmap.reads(0));
+    assert_eq!(0, heatmap.reads(5000));
+
+    let mut csv = Vec::new();
+    heatmap.write_csv(&mut csv).unwrap();
+    assert_eq!(
+        "address,reads,writes\n256,2,1\n257,1,1\n258,1,1\n259,1,1\n",
+        String::from_utf8(csv).unwrap()
+    );
+}
+
+#[test]
+fn write_ppm_images() {
+    let mut heatmap = Heatmap::new(128);
+    heatmap.record_write(0, 1);
+    heatmap.record_write(0, 1);
+    heatmap.record_read(65, 2);
+    heatmap.record_read(127, 10);
+    let mut image = Vec::new();
+    heatmap.write_ppm(&mut image).unwrap();
+    let header = b"P6\n64 2\n255\n";
+    assert_eq!(header, &image[..header.len()]);
+    let pixels = &image[header.len()..];
+    assert_eq!(128 * 3, pixels.len());
+    assert_eq!([255, 0, 0], pixels[0..3]);
+    assert_eq!([0, 0, 0], pixels[3..6]);
+    assert_eq!([0, 127, 0], pixels[65 * 3..66 * 3]);
+    assert_eq!([0, 127, 0], pixels[127 * 3..]);
+}
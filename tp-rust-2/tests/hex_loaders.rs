@@ -0,0 +1,100 @@
+use interpreter::{HexError, HexErrorKind, Machine};
+
+// The exit code of the program run by `machine`
+fn exit_code(mut machine: Machine) -> Option<u32> {
+    machine.run_on(&mut Vec::new()).unwrap();
+    machine.exit_code()
+}
+
+#[test]
+fn load_ihex() {
+    // loadimm r1, 42 ; halt r1
+    let machine = Machine::from_ihex(":0600000004012A002C019E\n:00000001FF\n").unwrap();
+    assert_eq!(&[4, 1, 42, 0, 44, 1, 0], &machine.memory()[..7]);
+    assert_eq!(Some(42), exit_code(machine));
+
+    // The same program at 0x10, started by a start linear address record
+    let text = "
+        :0600100004012A002C018E
+        :0400000500000010E7
+        :00000001FF
+    ";
+    let machine = Machine::from_ihex(text).unwrap();
+    assert_eq!(0x10, machine.regs()[0]);
+    assert_eq!(&[0, 4, 1, 42], &machine.memory()[0x0f..0x13]);
+    assert_eq!(Some(42), exit_code(machine));
+
+    // An extended segment address record moves the following data
+    let text = ":020000020001FB\n:0100000007F8\n:00000001FF";
+    let machine = Machine::from_ihex(text).unwrap();
+    assert_eq!(7, machine.memory()[0x10]);
+}
+
+#[test]
+fn ihex_errors() {
+    let error = |text: &str| Machine::from_ihex(text).err().unwrap();
+    assert_eq!(
+        HexError {
+            line: 2,
+            kind: HexErrorKind::InvalidChecksum(0x9e, 0x9f)
+        },
+        Machine::from_ihex("\n:0600000004012A002C019F\n:00000001FF")
+            .err()
+            .unwrap()
+    );
+    assert_eq!(HexErrorKind::MissingStartCode, error("00000001FF").kind);
+    assert_eq!(
+        HexErrorKind::InvalidHex("0000001FG".to_string()),
+        error(":0000001FG").kind
+    );
+    assert_eq!(HexErrorKind::InvalidLength, error(":0200000001FD").kind);
+    assert_eq!(
+        HexErrorKind::UnknownRecordType(6),
+        error(":00000006FA").kind
+    );
+    assert_eq!(HexErrorKind::InvalidRecord(4), error(":0100000401FA").kind);
+    assert_eq!(
+        HexError {
+            line: 1,
+            kind: HexErrorKind::MissingEndOfFile
+        },
+        error(":0100000007F8")
+    );
+    assert_eq!(
+        HexErrorKind::AddressOutOfRange(0x1000),
+        error(":01100000FFF0\n:00000001FF").kind
+    );
+    assert_eq!(
+        "line 3: record after the end-of-file one",
+        error(":00000001FF\n\n:00000001FF").to_string()
+    );
+}
+
+#[test]
+fn load_hex_text() {
+    let text = "
+        ; loadimm r1, 42
+        04 01 2a 00
+        2C01 ; halt r1
+    ";
+    let machine = Machine::from_hex_text(text).unwrap();
+    assert_eq!(&[4, 1, 42, 0, 44, 1, 0], &machine.memory()[..7]);
+    assert_eq!(Some(42), exit_code(machine));
+
+    let error = |text: &str| Machine::from_hex_text(text).err().unwrap();
+    assert_eq!(
+        HexError {
+            line: 2,
+            kind: HexErrorKind::InvalidHex("7".to_string())
+        },
+        error("07\n7")
+    );
+    assert_eq!(
+        HexErrorKind::InvalidHex("0x07".to_string()),
+        error("0x07").kind
+    );
+    assert_eq!(
+        HexErrorKind::AddressOutOfRange(4096),
+        error(&"00 ".repeat(4097)).kind
+    );
+}
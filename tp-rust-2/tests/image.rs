@@ -0,0 +1,211 @@
+use interpreter::{assemble, Image, LoadError, Machine, MachineError, Segment};
+use std::io::{self, Read};
+
+fn image() -> Image {
+    Image {
+        entry: 0x100,
+        segments: vec![
+            Segment {
+                address: 0x100,
+                data: assemble("loadimm r2, 0x200\nload r1, r2\nhalt r1").unwrap(),
+            },
+            Segment {
+                address: 0x200,
+                data: vec![42, 0, 0, 0],
+            },
+        ],
+        crc: false,
+    }
+}
+
+#[test]
+fn encode_image() {
+    let bytes = image().to_bytes();
+    assert_eq!(b"RVMI\x01\x00\x02\x00\x00\x01\x00\x00", &bytes[..12]);
+    assert_eq!(b"\x00\x01\x00\x00\x09\x00\x00\x00\x04\x02", &bytes[12..22]);
+    assert_eq!(12 + 8 + 9 + 8 + 4, bytes.len());
+    assert!(Image::is_image(&bytes));
+    assert_eq!(image(), Image::parse(&bytes).unwrap());
+
+    assert_eq!(
+        Image {
+            entry: 0,
+            segments: vec![Segment {
+                address: 0,
+                data: vec![7]
+            }],
+            crc: false
+        },
+        Image::from_program(&[7])
+    );
+}
+
+#[test]
+fn load_image() {
+    let mut machine = Machine::load_image(&image().to_bytes()).unwrap();
+    assert_eq!(0x100, machine.regs()[0]);
+    assert_eq!(&[0, 0], &machine.memory()[..2]);
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(Some(42), machine.exit_code());
+}
+
+#[test]
+fn reset_to_loaded_image() {
+    let mut machine = Machine::load_image(&image().to_bytes()).unwrap();
+    machine.run_on(&mut Vec::new()).unwrap();
+    machine.reset();
+    assert_eq!(None, machine.exit_code());
+    assert_eq!(&[0x100, 0, 0], &machine.regs()[..3]);
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(Some(42), machine.exit_code());
+}
+
+#[test]
+fn load_invalid_images() {
+    let bytes = image().to_bytes();
+    let load = |bytes: &[u8]| Machine::load_image(bytes).err().unwrap();
+    assert!(matches!(load(&bytes[1..]), MachineError::InvalidImage));
+    assert!(matches!(load(&bytes[..8]), MachineError::InvalidImage));
+    assert!(matches!(
+        load(&bytes[..bytes.len() - 1]),
+        MachineError::InvalidImage
+    ));
+    assert!(matches!(
+        load(&[&bytes[..], &[0]].concat()),
+        MachineError::InvalidImage
+    ));
+    let mut version = bytes.clone();
+    version[4] = 2;
+    assert!(matches!(load(&version), MachineError::InvalidImage));
+
+    let beyond = Image {
+        entry: 0,
+        segments: vec![Segment {
+            address: 4095,
+            data: vec![1, 2],
+        }],
+        crc: false,
+    };
+    assert!(matches!(
+        load(&beyond.to_bytes()),
+        MachineError::NonExistingAddress { .. }
+    ));
+}
+
+#[test]
+fn check_image_crc() {
+    let mut image = Image::from_program(&[7]);
+    image.crc = true;
+    let bytes = image.to_bytes();
+    assert_eq!(b"RVMI\x01\x01", &bytes[..6]);
+    assert_eq!(&CRC.to_le_bytes(), &bytes[12..16]);
+    assert_eq!(image, Image::parse(&bytes).unwrap());
+
+    // The image of the load_image test, with a CRC
+    let mut machine = Machine::load_image(&image_with_crc().to_bytes()).unwrap();
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(Some(42), machine.exit_code());
+
+    // Any change of the content is detected
+    let mut bytes = image_with_crc().to_bytes();
+    let corrupt =
+        |bytes: &[u8]| matches!(Machine::load_image(bytes), Err(MachineError::CorruptImage));
+    for index in [8, 12, 20, bytes.len() - 1] {
+        bytes[index] ^= 0x10;
+        assert!(corrupt(&bytes), "{}", index);
+        bytes[index] ^= 0x10;
+    }
+    assert!(corrupt(&bytes[..bytes.len() - 1]));
+
+    // The flags other than the CRC one are reserved
+    bytes[5] = 3;
+    assert!(matches!(
+        Machine::load_image(&bytes),
+        Err(MachineError::InvalidImage)
+    ));
+}
+
+fn image_with_crc() -> Image {
+    Image {
+        crc: true,
+        ..image()
+    }
+}
+
+// The CRC32 of the image of `exit` loaded at 0
+const CRC: u32 = 0x404424be;
+
+#[test]
+fn load_at_offset() {
+    // loadimm r2, 0x800 ; load r1, r2 ; halt r1
+    let mut machine = Machine::try_new(&[4, 2, 0, 8, 3, 1, 2, 44, 1]).unwrap();
+    machine.load_at(0x800, &[7, 0, 0, 0]).unwrap();
+    machine.load_at(0x7ff, &[5]).unwrap();
+    assert_eq!(&[5, 7, 0], &machine.memory()[0x7ff..0x802]);
+    assert_eq!(&[4, 2], &machine.memory()[..2]);
+    machine.load_at(4096, &[]).unwrap();
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(Some(7), machine.exit_code());
+
+    // Nothing is copied when the bytes don't fit
+    let mut machine = Machine::try_new(&[]).unwrap();
+    assert!(matches!(
+        machine.load_at(4094, &[1, 2, 3]),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+    assert!(matches!(
+        machine.load_at(usize::MAX, &[1]),
+        Err(MachineError::NonExistingAddress { .. })
+    ));
+    assert!(machine.memory().iter().all(|b| *b == 0));
+}
+
+// A reader returning one byte at a time, interrupted before every byte
+struct Trickle<'a> {
+    bytes: &'a [u8],
+    interrupted: bool,
+}
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.interrupted = !self.interrupted;
+        if self.interrupted {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let Some((first, rest)) = self.bytes.split_first() else {
+            return Ok(0);
+        };
+        buffer[0] = *first;
+        self.bytes = rest;
+        Ok(1)
+    }
+}
+
+#[test]
+fn load_from_reader() {
+    let program = assemble("loadimm r1, 9\nhalt r1").unwrap();
+    let reader = Trickle {
+        bytes: &program,
+        interrupted: false,
+    };
+    let mut machine = Machine::from_reader(reader).unwrap();
+    assert_eq!(&program[..], &machine.memory()[..program.len()]);
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(Some(9), machine.exit_code());
+
+    // The program may fill the memory, but not more
+    let machine = Machine::from_reader(io::repeat(1).take(4096)).unwrap();
+    assert!(machine.memory().iter().all(|b| *b == 1));
+    assert!(matches!(
+        Machine::from_reader(io::repeat(1).take(4097)),
+        Err(LoadError::TooLarge)
+    ));
+    assert!(matches!(
+        Machine::from_reader(io::repeat(1)),
+        Err(LoadError::TooLarge)
+    ));
+
+    // A directory can't be read
+    let error = Machine::from_reader(std::fs::File::open("tests").unwrap());
+    assert!(matches!(error, Err(LoadError::Io(_))));
+}
@@ -0,0 +1,65 @@
+use interpreter::{assemble, IoEvent, IoLog, Machine, RecordingReader, RecordingWriter};
+use std::cell::RefCell;
+use std::io;
+
+const DOUBLE: &str = "
+      loop: in_number r1
+            add r1, r1, r1
+            out_number r1
+            bne r1, r2, loop
+            exit
+";
+
+#[test]
+fn record_the_input_and_the_output() {
+    let log = RefCell::new(IoLog::new());
+    let mut machine = Machine::try_new(&assemble(DOUBLE).unwrap()).unwrap();
+    let mut input = RecordingReader::new(&b"21 4\n0 7"[..], &log);
+    let mut output = RecordingWriter::new(Vec::new(), &log);
+    machine.run_with_io(&mut input, &mut output).unwrap();
+    let log = log.into_inner();
+    assert_eq!(
+        &[
+            IoEvent::Input(b"21 ".to_vec()),
+            IoEvent::Output(b"42".to_vec()),
+            IoEvent::Input(b"4\n".to_vec()),
+            IoEvent::Output(b"8".to_vec()),
+            IoEvent::Input(b"0 ".to_vec()),
+            IoEvent::Output(b"0".to_vec()),
+        ],
+        log.events()
+    );
+    assert_eq!(b"21 4\n0 ", &log.input()[..]);
+    assert_eq!(b"4280", &log.output()[..]);
+}
+
+#[test]
+fn replay_the_input() {
+    let log = RefCell::new(IoLog::new());
+    let mut machine = Machine::try_new(&assemble(DOUBLE).unwrap()).unwrap();
+    let mut input = RecordingReader::new(&b"3 0 1"[..], &log);
+    machine
+        .run_with_io(&mut input, &mut RecordingWriter::new(io::sink(), &log))
+        .unwrap();
+    let recorded = IoLog::parse(&log.borrow().to_bytes()).unwrap();
+    assert_eq!(*log.borrow(), recorded);
+
+    let mut machine = Machine::try_new(&assemble(DOUBLE).unwrap()).unwrap();
+    let mut output = Vec::new();
+    machine
+        .run_with_io(&mut recorded.replay_input(), &mut output)
+        .unwrap();
+    assert_eq!(recorded.output(), output);
+}
+
+#[test]
+fn refuse_invalid_logs() {
+    let mut bytes = IoLog::new().to_bytes();
+    assert_eq!(IoLog::new(), IoLog::parse(&bytes).unwrap());
+    bytes.extend_from_slice(&[b'I', 4, 0, 0, 0, b'1']);
+    assert_eq!(
+        io::ErrorKind::InvalidData,
+        IoLog::parse(&bytes).unwrap_err().kind()
+    );
+    assert!(IoLog::parse(b"RVMT").is_err());
+}
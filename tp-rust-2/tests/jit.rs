@@ -0,0 +1,150 @@
+#![cfg(feature = "jit")]
+
+use interpreter::{assemble, Machine, MachineError, RunOutcome, StepOutcome};
+use std::io::{self, Read, Write};
+
+// Mixes the operations compiled in a loop, reading the IP too, and prints
+// the result
+const MIX: &str = "
+        loadimm r1, 1000
+        loadimm r2, 1
+        loadimm r3, -7
+        loadimm32 r4, 0x12345678
+        loadimm r5, 0
+        loadimm sp, 0x800
+loop:   add r5, r5, r1
+        mul r6, r5, r3
+        xor r5, r5, r6
+        rol r7, r4, r1
+        ror r8, r7, r3
+        and r9, r8, r4
+        or r5, r5, r9
+        shl r6, r1, r2
+        shr r7, r5, r2
+        sar r8, r3, r2
+        min r9, r7, r8
+        max r10, r9, r6
+        not r11, r10
+        mov r12, r11
+        add r12, r12, r0
+        move_if r5, r12, r2
+        sub r1, r1, r2
+        blt r1, r13, done
+        bne r1, r13, loop
+done:   call check
+        out_number r5
+        exit
+check:  bge r5, r13, positive
+        jmp negative
+positive: ret
+negative: ret
+";
+
+// The state of a machine running `source` until it stops, with or without
+// the JIT, and what it printed
+fn run(source: &str, jit: bool) -> (Machine, Vec<u8>) {
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    assert!(!jit || machine.enable_jit());
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    (machine, output)
+}
+
+#[test]
+fn compile_hot_blocks() {
+    let (interpreted, expected) = run(MIX, false);
+    let (compiled, output) = run(MIX, true);
+    assert!(compiled.compiled_blocks() > 0);
+    assert_eq!(expected, output);
+    assert_eq!(interpreted.snapshot(), compiled.snapshot());
+    assert_eq!(interpreted.stats(), compiled.stats());
+    assert_eq!(0, interpreted.compiled_blocks());
+}
+
+#[test]
+fn recompile_modified_blocks() {
+    // Every pass of the outer loop writes the next value over the
+    // immediate value of the first instruction of the inner loop, at 18
+    let source = "
+        loadimm r3, 18
+        loadimm r4, 1
+        loadimm r6, 20
+outer:  loadimm r1, 40
+inner:  loadimm r2, 5
+        add r5, r5, r2
+        sub r1, r1, r4
+        bne r1, r13, inner
+        add r7, r7, r4
+        storeb r3, r7
+        sub r6, r6, r4
+        bne r6, r13, outer
+        out_number r5
+        exit
+    ";
+    let (interpreted, expected) = run(source, false);
+    let (compiled, output) = run(source, true);
+    assert_eq!(expected, output);
+    assert_eq!(interpreted.snapshot(), compiled.snapshot());
+}
+
+#[test]
+fn run_compiled_blocks_for_some_steps() {
+    let program = assemble(MIX).unwrap();
+    let mut interpreted = Machine::try_new(&program).unwrap();
+    let mut compiled = Machine::try_new(&program).unwrap();
+    assert!(compiled.enable_jit());
+    for steps in [1, 1000, 7, 5000, 3] {
+        assert_eq!(
+            RunOutcome::StepLimitReached,
+            compiled.run_for_on(steps, &mut io::sink()).unwrap()
+        );
+        interpreted.run_for_on(steps, &mut io::sink()).unwrap();
+        assert_eq!(interpreted.snapshot(), compiled.snapshot());
+    }
+}
+
+#[test]
+fn interpret_observed_runs() {
+    let program = assemble(MIX).unwrap();
+    let mut machine = Machine::try_new(&program).unwrap();
+    assert!(machine.enable_jit());
+    machine.add_breakpoint(0x1000);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(0, machine.compiled_blocks());
+
+    // Stepping doesn't use the compiled blocks either
+    let mut machine = Machine::try_new(&program).unwrap();
+    assert!(machine.enable_jit());
+    while machine.step_on(&mut io::sink()).unwrap() == StepOutcome::Continue {}
+    assert_eq!(0, machine.compiled_blocks());
+    machine.disable_jit();
+}
+
+// 9 reg_a reg_b reg_c: add 1 to register reg_a instead of the sum
+fn increment(
+    machine: &mut Machine,
+    _: &mut dyn Read,
+    _: &mut dyn Write,
+) -> Result<StepOutcome, MachineError> {
+    let ip = machine.regs()[0] as usize;
+    let reg = machine.memory()[ip + 1] as usize;
+    machine.set_reg(reg, machine.regs()[reg] + 1)?;
+    machine.ip_inc(4);
+    Ok(StepOutcome::Continue)
+}
+
+#[test]
+fn interpret_replaced_instructions() {
+    let mut machine = Machine::try_new(&assemble(MIX).unwrap()).unwrap();
+    machine.set_instruction_handler(9, increment);
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+
+    let mut compiled = Machine::try_new(&assemble(MIX).unwrap()).unwrap();
+    compiled.set_instruction_handler(9, increment);
+    assert!(compiled.enable_jit());
+    let mut compiled_output = Vec::new();
+    compiled.run_on(&mut compiled_output).unwrap();
+    assert_eq!(output, compiled_output);
+    assert_eq!(machine.snapshot(), compiled.snapshot());
+}
@@ -0,0 +1,172 @@
+use interpreter::{
+    assemble, assemble_object, link, link_with_symbols, AsmErrorKind, LinkError, Machine, Operand,
+    Relocation, RelocationTarget,
+};
+
+const MAIN: &str = "
+        .global start
+    start:
+        loadimm r1, greeting
+        call print
+        loadimm r1, name
+        call print
+        jrel done
+        exit
+    done:
+        exit
+        .section .data
+    greeting:
+        .asciiz \"Hello, \"
+";
+
+const LIBRARY: &str = "
+        .global print, name
+    print:
+        outs r1
+        ret
+        .section .data
+    name:
+        .asciiz \"World!\"
+";
+
+#[test]
+fn link_two_objects() {
+    let main = assemble_object(MAIN).unwrap();
+    let library = assemble_object(LIBRARY).unwrap();
+    let (program, symbols) = link_with_symbols(&[main, library]).unwrap();
+
+    // The text sections come first, then the data ones
+    assert_eq!(
+        vec![("name", 30), ("print", 19), ("start", 0)],
+        symbols.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(b"Hello, \0World!\0", &program[22..]);
+
+    let mut machine = Machine::try_new(&program).unwrap();
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!(b"Hello, World!", &out[..]);
+}
+
+#[test]
+fn object_contents() {
+    let object = assemble_object(MAIN).unwrap();
+    assert_eq!(["start"], object.globals.iter().collect::<Vec<_>>()[..]);
+    assert_eq!(2, object.sections.len());
+    let (text, data) = (&object.sections[0], &object.sections[1]);
+    assert_eq!(".text", text.name);
+    assert_eq!(".data", data.name);
+    assert_eq!(Some(&0), text.symbols.get("start"));
+    assert_eq!(Some(&18), text.symbols.get("done"));
+    assert_eq!(Some(&0), data.symbols.get("greeting"));
+    assert_eq!(b"Hello, \0", &data.data[..]);
+
+    // The jump within the section is already resolved
+    assert_eq!(vec![25, 1, 0], text.data[14..17]);
+    assert_eq!(
+        vec![
+            Relocation {
+                offset: 2,
+                kind: Operand::Imm16,
+                target: RelocationTarget::Section(1),
+                addend: 0
+            },
+            Relocation {
+                offset: 5,
+                kind: Operand::Addr16,
+                target: RelocationTarget::Symbol("print".to_string()),
+                addend: 0
+            },
+            Relocation {
+                offset: 9,
+                kind: Operand::Imm16,
+                target: RelocationTarget::Symbol("name".to_string()),
+                addend: 0
+            },
+            Relocation {
+                offset: 12,
+                kind: Operand::Addr16,
+                target: RelocationTarget::Symbol("print".to_string()),
+                addend: 0
+            },
+        ],
+        text.relocations
+    );
+}
+
+#[test]
+fn link_relative_and_offsets() {
+    let main = assemble_object(
+        "
+            jrel far + 1
+            loadimm32 r1, table + 8
+            exit
+            .section .data
+        table: .word far - 4, table
+    ",
+    )
+    .unwrap();
+    let library = assemble_object(".global far\nexit\nfar: exit").unwrap();
+    let program = link(&[main, library]).unwrap();
+    assert_eq!(
+        vec![25, 9, 0, 36, 1, 20, 0, 0, 0, 7, 7, 7, 7, 0, 0, 0, 12, 0, 0, 0][..],
+        program[..20]
+    );
+}
+
+#[test]
+fn assemble_sections() {
+    // Without linking, the sections are also laid out one after the other
+    let source = "
+            loadimm r1, data
+            .section .data
+        data: .byte 1, 2
+            .section .text
+            exit
+    ";
+    assert_eq!(vec![4, 1, 5, 0, 7, 1, 2], assemble(source).unwrap());
+}
+
+#[test]
+fn link_errors() {
+    let user = assemble_object("call missing").unwrap();
+    assert_eq!(
+        Err(LinkError::UndefinedSymbol("missing".to_string())),
+        link(&[user])
+    );
+
+    let first = assemble_object(".global f\nf: exit").unwrap();
+    let second = assemble_object(".global f\nf: ret").unwrap();
+    assert_eq!(
+        Err(LinkError::DuplicateSymbol("f".to_string())),
+        link(&[first.clone(), second])
+    );
+
+    let byte = assemble_object(".byte f + 300").unwrap();
+    assert_eq!(
+        Err(LinkError::RelocationOutOfRange("f".to_string(), 301)),
+        link(&[byte, first])
+    );
+}
+
+#[test]
+fn object_errors() {
+    let error = |source| assemble_object(source).unwrap_err().kind;
+    assert_eq!(
+        AsmErrorKind::UndefinedLabel("nowhere".to_string()),
+        error(".global nowhere")
+    );
+    assert_eq!(
+        AsmErrorKind::UnrelocatableExpression("2 * f".to_string()),
+        error("loadimm r1, 2 * f")
+    );
+    assert_eq!(
+        AsmErrorKind::UnrelocatableExpression("f - g".to_string()),
+        error("loadimm r1, f - g")
+    );
+    assert_eq!(
+        AsmErrorKind::UnrelocatableExpression("a - b".to_string()),
+        error("a: exit\n.section .data\nb: loadimm r1, a - b")
+    );
+    assert_eq!(AsmErrorKind::WrongOperandCount(1), error(".section"));
+}
@@ -0,0 +1,131 @@
+use interpreter::{
+    assemble, DecodedInsn, HookAction, LoadError, Machine, MachineBuilder, MachineError, Register,
+    RunOutcome,
+};
+use std::io;
+
+#[test]
+fn build_machine() {
+    let program = assemble("syscall 1\nrand r2\nout_number r1\nexit").unwrap();
+    let mut machine = MachineBuilder::new()
+        .memory_size(0x200)
+        .load_at(0x100, &program)
+        .entry(0x100)
+        .register(Register::R1, 20)
+        .seed_rng(7)
+        .syscall_handler(|machine: &mut Machine, number: u8| {
+            machine.set_reg(1, machine.regs()[1] + number as u32)
+        })
+        .build()
+        .unwrap();
+    assert_eq!(0x200, machine.memory().len());
+    assert_eq!(
+        &program[..],
+        &machine.memory()[0x100..0x100 + program.len()]
+    );
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"21", &output[..]);
+
+    // The same seed gives the same numbers
+    let mut expected = Machine::try_new(&assemble("rand r2").unwrap()).unwrap();
+    expected.seed_rng(7);
+    expected.step_on(&mut io::sink()).unwrap();
+    assert_eq!(expected.regs()[2], machine.regs()[2]);
+}
+
+#[test]
+fn build_machine_like_new() {
+    let program = [4, 1, 42, 0, 7];
+    let built = MachineBuilder::new().program(&program).build().unwrap();
+    let created = Machine::try_new(&program).unwrap();
+    assert_eq!(created.memory(), built.memory());
+    assert_eq!(created.regs(), built.regs());
+}
+
+#[test]
+fn reset_built_machine() {
+    let mut machine = MachineBuilder::new()
+        .program(&assemble("rand r2\nexit").unwrap())
+        .register(Register::R1, 20)
+        .seed_rng(7)
+        .build()
+        .unwrap();
+    machine.run_on(&mut io::sink()).unwrap();
+    let random = machine.regs()[2];
+    machine.reset();
+    assert_eq!(&[0, 20, 0], &machine.regs()[..3]);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(random, machine.regs()[2]);
+}
+
+#[test]
+fn refuse_bytes_outside_memory() {
+    let builder = MachineBuilder::new().memory_size(16).load_at(12, &[0; 5]);
+    assert!(matches!(builder.build(), Err(LoadError::TooLarge)));
+    let builder = MachineBuilder::new().program(&[0; 4097]);
+    assert!(matches!(builder.build(), Err(LoadError::TooLarge)));
+}
+
+#[test]
+fn build_machine_with_hook() {
+    let mut machine = MachineBuilder::new()
+        .program(&[7])
+        .hook(|_: &Machine, _: &DecodedInsn| HookAction::Abort)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        machine.step_on(&mut io::sink()),
+        Err(MachineError::Aborted { .. })
+    ));
+}
+
+#[test]
+fn run_built_session() {
+    let program = assemble("in r1\nout r1\njmp 0").unwrap();
+    let mut output = Vec::new();
+    let mut session = MachineBuilder::new()
+        .program(&program)
+        .input(&b"abc"[..])
+        .output(&mut output)
+        .step_limit(3)
+        .build_session()
+        .unwrap();
+    assert_eq!(RunOutcome::StepLimitReached, session.run().unwrap());
+    assert_eq!(3, session.machine().instructions_retired());
+
+    // Every run executes the steps again
+    assert_eq!(RunOutcome::StepLimitReached, session.run().unwrap());
+    session.machine_mut().set_reg(2, 7).unwrap();
+    let machine = session.into_machine();
+    assert_eq!(6, machine.instructions_retired());
+    assert_eq!(7, machine.regs()[2]);
+    assert_eq!(b"ab", &output[..]);
+}
+
+#[test]
+fn build_machine_with_fuel() {
+    let mut machine = MachineBuilder::new()
+        .program(&assemble("jmp 0").unwrap())
+        .fuel(5)
+        .build()
+        .unwrap();
+    assert_eq!(Some(5), machine.fuel());
+    assert_eq!(
+        RunOutcome::OutOfFuel,
+        machine.run_on(&mut io::sink()).unwrap()
+    );
+    assert_eq!(5, machine.instructions_retired());
+
+    // Without a step limit, a session runs until the program stops
+    let mut output = Vec::new();
+    let mut session = MachineBuilder::new()
+        .program(&assemble("loadimm r1, 42\nout_number r1\nexit").unwrap())
+        .input(io::empty())
+        .output(&mut output)
+        .build_session()
+        .unwrap();
+    assert_eq!(RunOutcome::Exited, session.run().unwrap());
+    drop(session);
+    assert_eq!(b"42", &output[..]);
+}
@@ -0,0 +1,156 @@
+use interpreter::{assemble, optimize, optimize_with_stats, Machine, OptimizeStats};
+
+// The output of `program` run until it exits
+fn run(program: &[u8]) -> String {
+    let mut machine = Machine::try_new(program).unwrap();
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn remove_redundant_moves() {
+    let program = assemble(
+        "
+        loadimm r1, 5
+        mov r1, r1
+        mov r2, r1
+        mov r1, r2
+        out_number r2
+        exit
+    ",
+    )
+    .unwrap();
+    let (optimized, stats) = optimize_with_stats(program);
+    assert_eq!(
+        assemble("loadimm r1, 5\nmov r2, r1\nout_number r2\nexit").unwrap(),
+        optimized
+    );
+    assert_eq!(
+        OptimizeStats {
+            size_before: 16,
+            size_after: 10,
+            instructions_before: 6,
+            instructions_after: 4,
+            moves_removed: 2,
+            constants_folded: 0,
+            unreachable_bytes_removed: 0,
+            skipped: false,
+        },
+        stats
+    );
+
+    // A move the execution can jump to is kept
+    let program = assemble("mov r1, r2\nloop: mov r2, r1\njmp loop").unwrap();
+    assert_eq!(program, optimize(program.clone()));
+}
+
+#[test]
+fn fold_constants() {
+    let source = |sub: &str| format!("loadimm r1, 10\nloadimm r2, 3\n{}\nexit", sub);
+    let fold = |sub: &str| optimize_with_stats(assemble(&source(sub)).unwrap());
+    let (optimized, stats) = fold("sub r1, r1, r2");
+    assert_eq!(
+        assemble("loadimm r2, 3\nloadimm r1, 7\nexit").unwrap(),
+        optimized
+    );
+    assert_eq!(1, stats.constants_folded);
+    assert_eq!(
+        assemble("loadimm r1, 10\nloadimm r2, 7\nexit").unwrap(),
+        fold("sub r2, r1, r2").0
+    );
+    assert_eq!(
+        assemble("loadimm r1, 10\nloadimm r2, 3\nloadimm r3, 7\nexit").unwrap(),
+        fold("sub r3, r1, r2").0
+    );
+
+    // The operands must be the loaded registers, in this order
+    let program = assemble(&source("sub r1, r2, r1")).unwrap();
+    assert_eq!(program, optimize(program.clone()));
+
+    // The result must fit in a loadimm
+    let program = assemble("loadimm r1, 0x7fff\nloadimm r2, -1\nsub r1, r1, r2\nexit").unwrap();
+    assert_eq!(program, optimize(program.clone()));
+}
+
+#[test]
+fn remove_unreachable_code() {
+    let program = assemble(
+        "
+                jmp start
+                .byte 1, 2, 3
+        start:  exit
+                loadimm r1, 3
+    ",
+    )
+    .unwrap();
+    let (optimized, stats) = optimize_with_stats(program);
+    assert_eq!(vec![24, 3, 0, 7], optimized);
+    assert_eq!(7, stats.unreachable_bytes_removed);
+    assert_eq!(
+        "11 -> 4 bytes, 2 -> 2 instructions (0 moves removed, 0 constants folded, 7 unreachable bytes removed)",
+        stats.to_string()
+    );
+}
+
+#[test]
+fn relocate_targets() {
+    let source = "
+                 loadimm sp, 4096
+                 loadimm r10, 84
+                 loadimm r11, 36
+        loop:    mov r10, r10
+                 beq r10, r11, done
+                 bge r10, r11, greater
+                 sub r11, r11, r10
+                 jmp loop
+                 exit
+        greater: sub r10, r10, r11
+                 jrel loop
+        done:    call print
+                 exit
+        print:   push lr
+                 mov r1, r1
+                 out_number r10
+                 pop lr
+                 ret
+    ";
+    let program = assemble(source).unwrap();
+    let (optimized, stats) = optimize_with_stats(program.clone());
+    assert_eq!(2, stats.moves_removed);
+    assert_eq!(1, stats.unreachable_bytes_removed);
+    assert_eq!(program.len() - 7, optimized.len());
+    assert_eq!("12", run(&optimized));
+    assert_eq!(run(&program), run(&optimized));
+}
+
+#[test]
+fn keep_unsafe_programs() {
+    // The memory accesses may use addresses of the program
+    let program = assemble("loadimm r1, message\nouts r1\nexit\nmessage: .asciiz \"hi\"").unwrap();
+    let (optimized, stats) = optimize_with_stats(program.clone());
+    assert_eq!(program, optimized);
+    assert_eq!(
+        OptimizeStats {
+            size_before: program.len(),
+            size_after: program.len(),
+            skipped: true,
+            ..Default::default()
+        },
+        stats
+    );
+    assert_eq!(
+        format!("{} bytes, not optimized safely", program.len()),
+        stats.to_string()
+    );
+
+    // So may the instruction pointer and the link register
+    for source in [
+        "mov r1, ip\nexit",
+        "loadimm lr, 5\nret\nexit",
+        "mov r1, r1\n.byte 0",
+    ] {
+        let program = assemble(source).unwrap();
+        assert_eq!(program, optimize(program.clone()));
+    }
+}
@@ -0,0 +1,49 @@
+use interpreter::{assemble, Machine};
+use rvm_asm_macro::rvm_asm;
+
+#[test]
+fn assemble_at_compile_time() {
+    const PROGRAM: &[u8] = rvm_asm! {
+        // Print the message
+                  loadimm r1, message
+        loop:     loadb r2, r1
+                  beq r2, r3, done
+                  out r2
+                  loadimm r4, (2 - 1)
+                  add r1, r1, r4
+                  jmp loop
+        done:     exit
+        message:  .asciiz "hi\n"
+    };
+    let source = "
+                  loadimm r1, message
+        loop:     loadb r2, r1
+                  beq r2, r3, done
+                  out r2
+                  loadimm r4, (2 - 1)
+                  add r1, r1, r4
+                  jmp loop
+        done:     exit
+        message:  .asciiz \"hi\\n\"
+    ";
+    assert_eq!(assemble(source).unwrap(), PROGRAM);
+
+    let mut machine = Machine::try_new(PROGRAM).unwrap();
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"hi\n", &output[..]);
+}
+
+#[test]
+fn assemble_string_at_compile_time() {
+    let program: &'static [u8] = rvm_asm!(
+        "loadimm r1, 'A' ; the character
+         .macro set register, value
+         loadimm \\register, \\value
+         .endm
+         set r2, -2"
+    );
+    assert_eq!(&[4, 1, 65, 0, 4, 2, 0xfe, 0xff], program);
+    assert_eq!(&[7, 7, 0], rvm_asm!(r#".byte 7, 7, 0"#));
+    assert_eq!(&[6, 1], rvm_asm! { out r1 });
+}
@@ -0,0 +1,59 @@
+use interpreter::{assemble_with_debug_info, Machine, Profiler};
+use std::io;
+
+const CALLS: &str = "
+    main: loadimm sp, 4096
+          loadimm r1, 2
+          call double
+          out_number r1
+          halt r1
+  double: push lr
+          add r1, r1, r1
+          call inc
+          pop lr
+          ret
+     inc: loadimm r2, 1
+          add r1, r1, r2
+          ret
+";
+
+fn profiled(profiler: Profiler) -> Profiler {
+    let (program, _) = assemble_with_debug_info("calls.s", CALLS).unwrap();
+    let mut machine = Machine::try_new(&program).unwrap();
+    machine.attach_profiler(profiler);
+    machine.run_on(&mut io::sink()).unwrap();
+    machine.detach_profiler().unwrap()
+}
+
+#[test]
+fn sample_the_call_stacks() {
+    let profiler = profiled(Profiler::new());
+    let samples: Vec<(&[u32], u64)> = profiler.samples().collect();
+    assert_eq!(13, samples.iter().map(|(_, count)| count).sum::<u64>());
+    assert!(samples.contains(&(&[0x0b, 0x0f][..], 1)));
+    assert!(samples.contains(&(&[0x0b, 0x18, 0x1b][..], 1)));
+
+    let profiler = profiled(Profiler::with_period(4));
+    assert_eq!(4, profiler.samples().map(|(_, count)| count).sum::<u64>());
+}
+
+#[test]
+fn write_folded_stacks() {
+    let (_, debug_info) = assemble_with_debug_info("calls.s", CALLS).unwrap();
+    let mut folded = Vec::new();
+    profiled(Profiler::new())
+        .write_folded(Some(&debug_info), &mut folded)
+        .unwrap();
+    assert_eq!(
+        "main 5\nmain;double 5\nmain;double;inc 3\n",
+        String::from_utf8(folded).unwrap()
+    );
+
+    let mut folded = Vec::new();
+    profiled(Profiler::new())
+        .write_folded(None, &mut folded)
+        .unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert_eq!(13, folded.lines().count());
+    assert!(folded.starts_with("0x0000 1\n0x0004 1\n0x0008 1\n0x000a;0x000f 1\n"));
+}
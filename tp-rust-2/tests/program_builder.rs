@@ -0,0 +1,129 @@
+use interpreter::registers::*;
+use interpreter::{assemble, AsmErrorKind, Machine, Program};
+
+#[test]
+fn build_gcd() {
+    let program = Program::new()
+        .loadimm(r10, 84)
+        .loadimm(r11, 36)
+        .label("loop")
+        .beq(r10, r11, "done")
+        .bge(r10, r11, "greater")
+        .sub(r11, r11, r10)
+        .jmp("loop")
+        .label("greater")
+        .sub(r10, r10, r11)
+        .jrel("loop")
+        .label("done")
+        .out_number(r10)
+        .exit()
+        .build()
+        .unwrap();
+    let source = "
+                 loadimm r10, 84
+                 loadimm r11, 36
+        loop:    beq r10, r11, done
+                 bge r10, r11, greater
+                 sub r11, r11, r10
+                 jmp loop
+        greater: sub r10, r10, r11
+                 jrel loop
+        done:    out_number r10
+                 exit
+    ";
+    assert_eq!(assemble(source).unwrap(), program);
+
+    let mut machine = Machine::try_new(&program).unwrap();
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"12", &output[..]);
+}
+
+#[test]
+fn build_operands() {
+    let program = Program::new()
+        .loadimm(a0, "message")
+        .outs(a0)
+        .storex(sp, -4, lr)
+        .r#mod(r1, r2, r3)
+        .fadd(f7, f0, f1)
+        .jrel(-3)
+        .exit()
+        .label("message")
+        .asciiz("hi")
+        .bytes(&[1, 2])
+        .build()
+        .unwrap();
+    let source = "
+                 loadimm r1, message
+                 outs r1
+                 storex r15, -4, r14
+                 mod r1, r2, r3
+                 fadd f7, f0, f1
+                 jrel -3
+                 exit
+        message: .asciiz \"hi\"
+                 .byte 1, 2
+    ";
+    assert_eq!(assemble(source).unwrap(), program);
+}
+
+#[test]
+fn build_errors() {
+    assert_eq!(
+        Err(AsmErrorKind::UndefinedLabel("end".to_string())),
+        Program::new().jmp("end").build()
+    );
+    assert_eq!(
+        Err(AsmErrorKind::DuplicateLabel("a".to_string())),
+        Program::new().label("a").exit().label("a").build()
+    );
+    assert_eq!(
+        Err(AsmErrorKind::ImmediateOutOfRange(65536)),
+        Program::new().loadimm(r1, 65536).build()
+    );
+    assert_eq!(
+        Err(AsmErrorKind::InvalidRegister("r16".to_string())),
+        Program::new().out(16).build()
+    );
+    assert_eq!(
+        Err(AsmErrorKind::InvalidRegister("f8".to_string())),
+        Program::new().fout(8).build()
+    );
+}
+
+#[test]
+fn program_macro() {
+    let program = interpreter::program! {
+        loadimm r10, 84;
+        loadimm r11, 36;
+        again:
+        beq r10, r11, "done";
+        bge r10, r11, "greater";
+        sub r11, r11, r10;
+        jmp "again";
+        greater:
+        sub r10, r10, r11;
+        jrel "again";
+        done:
+        out_number r10;
+        exit;
+        asciiz "data"
+    };
+    let expected = Program::new()
+        .loadimm(r10, 84)
+        .loadimm(r11, 36)
+        .label("again")
+        .beq(r10, r11, "done")
+        .bge(r10, r11, "greater")
+        .sub(r11, r11, r10)
+        .jmp("again")
+        .label("greater")
+        .sub(r10, r10, r11)
+        .jrel("again")
+        .label("done")
+        .out_number(r10)
+        .exit()
+        .asciiz("data");
+    assert_eq!(expected.build(), program.build());
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "async")]
+
+use interpreter::{assemble, Machine, RunOutcome};
+use std::future::Future;
+use std::io;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+// Prints the sum of the numbers from 1 to 1000
+const SUM: &str = "
+        loadimm r1, 1000
+        loadimm r2, 1
+loop:   add r3, r3, r1
+        sub r1, r1, r2
+        bne r1, r4, loop
+        out_number r3
+        exit
+";
+
+// A waker counting the times it's woken
+struct Wakes(AtomicUsize);
+
+impl Wake for Wakes {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Poll `future` until it's ready, at most `polls` times, returning its
+// output if it got ready, and the number of times it woke its task
+fn poll_for<F: Future>(future: F, polls: usize) -> (Option<F::Output>, usize) {
+    let mut future = pin!(future);
+    let wakes = Arc::new(Wakes(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+    let mut context = Context::from_waker(&waker);
+    for _ in 0..polls {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return (Some(output), wakes.0.load(Ordering::Relaxed));
+        }
+    }
+    (None, wakes.0.load(Ordering::Relaxed))
+}
+
+#[test]
+fn yield_periodically() {
+    let program = assemble(SUM).unwrap();
+    let mut expected = Machine::try_new(&program).unwrap();
+    let (outcome, output) = expected.run_capture().unwrap();
+
+    let mut machine = Machine::try_new(&program).unwrap();
+    let mut async_output = Vec::new();
+    let mut input = io::empty();
+    let future = machine.run_async(100, &mut input, &mut async_output);
+    let (result, wakes) = poll_for(future, usize::MAX);
+    assert_eq!(Some(Ok(outcome)), result);
+    assert_eq!(output, async_output);
+    assert_eq!(b"500500", &output[..]);
+    assert_eq!(expected.snapshot(), machine.snapshot());
+    // The last chunk of steps ends with the exit
+    let retired = machine.instructions_retired();
+    assert_eq!((retired - 1) / 100, wakes as u64);
+}
+
+#[test]
+fn cancel_by_dropping() {
+    let program = assemble(SUM).unwrap();
+    let mut machine = Machine::try_new(&program).unwrap();
+    let (result, wakes) = poll_for(machine.run_async(100, &mut io::empty(), &mut io::sink()), 3);
+    assert!(result.is_none());
+    assert_eq!(3, wakes);
+    assert_eq!(300, machine.instructions_retired());
+
+    // The program resumes where it was cancelled
+    let (outcome, output) = machine.run_capture().unwrap();
+    assert_eq!(RunOutcome::Exited, outcome);
+    assert_eq!(b"500500", &output[..]);
+    let mut expected = Machine::try_new(&program).unwrap();
+    expected.run_capture().unwrap();
+    assert_eq!(expected.snapshot(), machine.snapshot());
+}
+
+#[test]
+fn fail_in_the_future() {
+    let mut machine = Machine::try_new(&assemble("loadimm r1, -1\nload r2, r1").unwrap()).unwrap();
+    let expected = machine.clone().run_capture().unwrap_err();
+    let (result, _) = poll_for(
+        machine.run_async(1, &mut io::empty(), &mut io::sink()),
+        usize::MAX,
+    );
+    assert_eq!(Some(Err(expected)), result);
+}
@@ -0,0 +1,484 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+// A fresh directory for the files of a test
+fn directory(test: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("rvm-asm-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn rvm_asm(arguments: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_rvm-asm"))
+        .args(arguments)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn assemble_file() {
+    let directory = directory("assemble");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 42\nexit\n").unwrap();
+    let listing = directory.join("prog.lst");
+
+    let output = rvm_asm(&[source.to_str().unwrap(), "-l", listing.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        vec![4, 1, 42, 0, 7],
+        std::fs::read(directory.join("prog.bin")).unwrap()
+    );
+    assert!(std::fs::read_to_string(&listing)
+        .unwrap()
+        .starts_with("0000  04 01 2a 00"));
+
+    let output = directory.join("other.bin");
+    assert!(
+        rvm_asm(&["-o", output.to_str().unwrap(), source.to_str().unwrap()])
+            .status
+            .success()
+    );
+    assert_eq!(vec![4, 1, 42, 0, 7], std::fs::read(output).unwrap());
+}
+
+#[test]
+fn link_files() {
+    let directory = directory("link");
+    let main = directory.join("main.s");
+    std::fs::write(&main, "call f\nexit\n").unwrap();
+    let library = directory.join("library.s");
+    std::fs::write(&library, ".global f\nf: ret\n").unwrap();
+    let program = directory.join("prog.bin");
+
+    let status = rvm_asm(&[
+        main.to_str().unwrap(),
+        library.to_str().unwrap(),
+        "-o",
+        program.to_str().unwrap(),
+    ])
+    .status;
+    assert!(status.success());
+    assert_eq!(vec![30, 4, 0, 7, 31], std::fs::read(program).unwrap());
+}
+
+#[test]
+fn report_errors() {
+    let directory = directory("errors");
+    let source = directory.join("bad.s");
+    std::fs::write(&source, "exit\n  loadimm r1, r16 ; oops\n").unwrap();
+
+    let output = rvm_asm(&[source.to_str().unwrap()]);
+    assert_eq!(Some(65), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        format!(
+            "{}:2:15: error: label `r16` is not defined\n    2 |   loadimm r1, r16 ; oops\n      |               ^\n",
+            source.display()
+        ),
+        stderr
+    );
+    assert!(!directory.join("bad.bin").exists());
+
+    assert_eq!(Some(66), rvm_asm(&["missing.s"]).status.code());
+    assert_eq!(Some(64), rvm_asm(&[]).status.code());
+    assert_eq!(Some(64), rvm_asm(&["-x", "prog.s"]).status.code());
+    assert_eq!(
+        Some(64),
+        rvm_asm(&["a.s", "b.s", "-l", "a.lst"]).status.code()
+    );
+    assert_eq!(Some(0), rvm_asm(&["-h"]).status.code());
+}
+
+#[test]
+fn include_files() {
+    let directory = directory("include");
+    let library = directory.join("lib");
+    std::fs::create_dir_all(&library).unwrap();
+    std::fs::write(library.join("exit.s"), "done: exit\n").unwrap();
+    std::fs::write(directory.join("local.s"), ".include \"exit.s\"\n").unwrap();
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "jmp done\n.include \"local.s\"\n").unwrap();
+
+    // exit.s is only found with -I
+    let output = rvm_asm(&[source.to_str().unwrap()]);
+    assert_eq!(Some(65), output.status.code());
+    let output = rvm_asm(&["-I", library.to_str().unwrap(), source.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        vec![24, 3, 0, 7],
+        std::fs::read(directory.join("prog.bin")).unwrap()
+    );
+
+    // Errors are reported in the included file
+    std::fs::write(library.join("exit.s"), "\ndone: exit r1\n").unwrap();
+    let output = rvm_asm(&["-I", library.to_str().unwrap(), source.to_str().unwrap()]);
+    assert_eq!(Some(65), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let exit = library.join("exit.s").canonicalize().unwrap();
+    let local = directory.join("local.s").canonicalize().unwrap();
+    assert_eq!(
+        format!(
+            "{}:2:1: error: expected 0 operand(s)\n    \
+             2 | done: exit r1\n      | ^\n\
+             note: included from {}:1\n\
+             note: included from {}:2\n",
+            exit.display(),
+            local.display(),
+            source.display()
+        ),
+        stderr
+    );
+}
+
+#[test]
+fn debug_info_file() {
+    let directory = directory("debug");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 0\nloop:\n  div r2, r1, r1\nexit\n").unwrap();
+    let program = directory.join("prog.bin");
+    let debug_info = directory.join("prog.dbg");
+
+    let output = rvm_asm(&[source.to_str().unwrap(), "-g", debug_info.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        format!(
+            "; rvm debug info\nline 0x0000 4 {0}:1\nline 0x0004 4 {0}:3\nline 0x0008 1 {0}:4\nlabel 0x0004 loop\n",
+            source.display()
+        ),
+        std::fs::read_to_string(&debug_info).unwrap()
+    );
+
+    // The machine reports the line of the faulting instruction
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg(&program)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(&format!("fault at {}:3\n", source.display())));
+}
+
+#[test]
+fn optimize_program() {
+    let directory = directory("optimize");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "mov r1, r1\nexit\nexit\n").unwrap();
+
+    assert!(rvm_asm(&["-O", source.to_str().unwrap()]).status.success());
+    assert_eq!(vec![7], std::fs::read(directory.join("prog.bin")).unwrap());
+
+    let listing = directory.join("prog.lst");
+    let output = rvm_asm(&[
+        "-O",
+        "-l",
+        listing.to_str().unwrap(),
+        source.to_str().unwrap(),
+    ]);
+    assert_eq!(Some(64), output.status.code());
+}
+
+#[test]
+fn refuse_invalid_programs() {
+    let directory = directory("verify");
+    let program = directory.join("prog.bin");
+    std::fs::write(&program, [45, 1, 16, 0]).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg(&program)
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "invalid program: 0x0000: invalid register 16\ninvalid program: 0x0003: invalid opcode 0\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn write_image() {
+    let directory = directory("image");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 3\nhalt r1\n").unwrap();
+
+    assert!(rvm_asm(&["-i", source.to_str().unwrap()]).status.success());
+    let program = directory.join("prog.bin");
+    let image = std::fs::read(&program).unwrap();
+    assert!(image.starts_with(b"RVMI"));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+}
+
+#[test]
+fn run_debugger_scripts() {
+    let directory = directory("script");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 3\nloop: sub r1, r1, r2\nhalt r1\n").unwrap();
+    let debug_info = directory.join("prog.dbg");
+    let arguments = ["-g", debug_info.to_str().unwrap(), source.to_str().unwrap()];
+    assert!(rvm_asm(&arguments).status.success());
+    let program = directory.join("prog.bin");
+
+    let script = directory.join("ok.txt");
+    std::fs::write(&script, "break loop\nc\nexpect r1 == 3\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .args(["dbg", "--script", script.to_str().unwrap()])
+        .arg(&program)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("(rvm) expect r1 == 3\n"));
+
+    // A failed expectation makes the debugger fail
+    std::fs::write(&script, "s\nexpect r1 == 4\nexpect r2 == 0\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .args(["dbg", "--script", script.to_str().unwrap()])
+        .arg(&program)
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("expectation failed: r1 == 4\n"));
+    assert_eq!(
+        "1 of the script commands failed\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn write_trace_files() {
+    let directory = directory("trace");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 3\nhalt r1\n").unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let program = directory.join("prog.bin");
+
+    let trace = directory.join("trace.jsonl");
+    let status = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg("--trace-file")
+        .arg(&trace)
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+    assert_eq!(
+        "{\"address\":0,\"opcode\":4,\"mnemonic\":\"loadimm\",\"operands\":[1,3],\"registers\":{\"r1\":3}}\n\
+         {\"address\":4,\"opcode\":44,\"mnemonic\":\"halt\",\"operands\":[1],\"registers\":{}}\n",
+        std::fs::read_to_string(&trace).unwrap()
+    );
+
+    let trace = directory.join("trace.bin");
+    let status = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg("--trace-file")
+        .arg(&trace)
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+    assert!(std::fs::read(&trace).unwrap().starts_with(b"RVMT"));
+}
+
+#[test]
+fn record_and_replay_io() {
+    let directory = directory("replay");
+    let source = directory.join("prog.s");
+    std::fs::write(
+        &source,
+        "in_number r1\nadd r1, r1, r1\nout_number r1\nexit\n",
+    )
+    .unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let program = directory.join("prog.bin");
+    let log = directory.join("io.log");
+    let run = |option: &str, input: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+            .arg(option)
+            .arg(&log)
+            .arg(&program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // The replays may exit without reading their input
+        let _ = child.stdin.take().unwrap().write_all(input);
+        child.wait_with_output().unwrap()
+    };
+
+    let output = run("--record", b"21\n");
+    assert!(output.status.success());
+    assert_eq!(b"42", &output.stdout[..]);
+
+    // The recorded input is replayed, the standard input being ignored
+    let output = run("--replay", b"5\n");
+    assert!(output.status.success());
+    assert_eq!(b"42", &output.stdout[..]);
+
+    // A program printing something else fails the replay
+    std::fs::write(&source, "in_number r1\nout_number r1\nexit\n").unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let output = run("--replay", b"");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "the output differs from the recorded one\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn write_coverage_reports() {
+    let directory = directory("coverage");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "start: loadimm r1, 3\nhalt r1\nexit\n").unwrap();
+    let debug_info = directory.join("prog.dbg");
+    let arguments = [source.to_str().unwrap(), "-g", debug_info.to_str().unwrap()];
+    assert!(rvm_asm(&arguments).status.success());
+    let program = directory.join("prog.bin");
+
+    let report = directory.join("report.txt");
+    let status = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg("--coverage")
+        .arg(&report)
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+    assert_eq!(
+        "start:\n       \
+                1  0000  loadimm r1, 3\n       \
+                1  0004  halt r1\n       \
+                -  0006  exit\n\
+         2 of 3 instructions executed (66.7%)\n",
+        std::fs::read_to_string(&report).unwrap()
+    );
+}
+
+#[test]
+fn print_execution_statistics() {
+    let directory = directory("stats");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 3\nhalt r1\n").unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg("--stats")
+        .arg(directory.join("prog.bin"))
+        .output()
+        .unwrap();
+    assert_eq!(Some(3), output.status.code());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("opcode            count   share\n"));
+    assert!(stderr.contains("halt                  1   50.0%\n"));
+    assert!(stderr.contains("total                 2\n"));
+}
+
+#[test]
+fn print_fusion_and_block_statistics() {
+    let directory = directory("fusion");
+    let source = directory.join("prog.s");
+    let program = "loadimm r1, 3\nloop: loadimm r2, 1\nsub r1, r1, r2\nbne r1, r3, loop\nexit\n";
+    std::fs::write(&source, program).unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg("--stats")
+        .arg(directory.join("prog.bin"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("total                11\n"));
+    assert!(stderr.contains("\nfused                     count   share\n"));
+    assert!(stderr.contains("loadimm+sub                   3   54.5%\n"));
+
+    // The block of the loop is built by the first pass, then found
+    assert!(stderr.contains("\nblocks                    count\n"));
+    assert!(stderr.contains("\nhits                          1\n"));
+    assert!(stderr.contains("\nmisses                        3\n"));
+}
+
+#[test]
+fn run_with_the_jit() {
+    let directory = directory("jit");
+    let source = directory.join("prog.s");
+    let program = "loadimm r1, 1000\nloadimm r2, 1\nloop: add r3, r3, r1\nsub r1, r1, r2\nbne r1, r4, loop\nout_number r3\nexit\n";
+    std::fs::write(&source, program).unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let output = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .args(["--jit", "--stats"])
+        .arg(directory.join("prog.bin"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if cfg!(feature = "jit") {
+        assert!(output.status.success());
+        assert_eq!(b"500500", &output.stdout[..]);
+        assert!(stderr.contains("total              3004\n"), "{}", stderr);
+        assert!(
+            stderr.contains("\ncompiled blocks               1\n"),
+            "{}",
+            stderr
+        );
+    } else {
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!("error: --jit needs the jit feature\n", stderr);
+    }
+}
+
+#[test]
+fn write_profiles() {
+    let directory = directory("profile");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "main: call f\nhalt r1\nf: loadimm r1, 3\nret\n").unwrap();
+    let debug_info = directory.join("prog.dbg");
+    let arguments = [source.to_str().unwrap(), "-g", debug_info.to_str().unwrap()];
+    assert!(rvm_asm(&arguments).status.success());
+
+    let profile = directory.join("stacks.folded");
+    let status = Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+        .arg("--profile")
+        .arg(&profile)
+        .arg(directory.join("prog.bin"))
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+    assert_eq!(
+        "main 2\nmain;f 2\n",
+        std::fs::read_to_string(&profile).unwrap()
+    );
+}
+
+#[test]
+fn write_heatmaps() {
+    let directory = directory("heatmap");
+    let source = directory.join("prog.s");
+    std::fs::write(&source, "loadimm r1, 0x100\nstore r1, r1\nexit\n").unwrap();
+    assert!(rvm_asm(&[source.to_str().unwrap()]).status.success());
+    let run = |heatmap: &PathBuf| {
+        Command::new(env!("CARGO_BIN_EXE_tp-rust-2"))
+            .arg("--heatmap")
+            .arg(heatmap)
+            .arg(directory.join("prog.bin"))
+            .status()
+            .unwrap()
+    };
+
+    let heatmap = directory.join("heatmap.csv");
+    assert!(run(&heatmap).success());
+    assert_eq!(
+        "address,reads,writes\n256,0,1\n257,0,1\n258,0,1\n259,0,1\n",
+        std::fs::read_to_string(&heatmap).unwrap()
+    );
+
+    let heatmap = directory.join("heatmap.ppm");
+    assert!(run(&heatmap).success());
+    assert!(std::fs::read(&heatmap)
+        .unwrap()
+        .starts_with(b"P6\n64 64\n255\n"));
+}
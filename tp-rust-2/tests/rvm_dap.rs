@@ -0,0 +1,177 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+// A client of the debug adapter, talking to it with JSON texts
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: u32,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rvm-dap"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Client {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+            seq: 0,
+        }
+    }
+
+    fn request(&mut self, command: &str, arguments: &str) {
+        self.seq += 1;
+        let content = format!(
+            r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#,
+            self.seq, command, arguments
+        );
+        write!(
+            self.stdin,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+        .unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    // The next message containing `text`, skipping the others
+    fn wait_for(&mut self, text: &str) -> String {
+        loop {
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                assert_ne!(0, self.stdout.read_line(&mut header).unwrap());
+                match header.trim().strip_prefix("Content-Length: ") {
+                    Some(value) => length = value.parse().unwrap(),
+                    None if header.trim().is_empty() => break,
+                    None => {}
+                }
+            }
+            let mut content = vec![0; length];
+            self.stdout.read_exact(&mut content).unwrap();
+            let content = String::from_utf8(content).unwrap();
+            if content.contains(text) {
+                return content;
+            }
+        }
+    }
+}
+
+#[test]
+fn debug_with_the_adapter() {
+    let directory = std::env::temp_dir().join(format!("rvm-dap-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let source = directory.join("count.s");
+    std::fs::write(
+        &source,
+        "loadimm r1, 3\n\
+         loadimm r2, 1\n\
+         loop: sub r1, r1, r2\n\
+         out_number r1\n\
+         bne r1, r3, loop\n\
+         halt r2\n",
+    )
+    .unwrap();
+    let program = directory.join("count.bin");
+    let status = Command::new(env!("CARGO_BIN_EXE_rvm-asm"))
+        .current_dir(&directory)
+        .args(["count.s", "-g", "count.dbg"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut client = Client::start();
+    client.request("initialize", r#"{"adapterID":"rvm"}"#);
+    assert!(client
+        .wait_for(r#""command":"initialize""#)
+        .contains(r#""supportsStepBack":true"#));
+    client.request(
+        "launch",
+        &format!(
+            r#"{{"program":"{}","cwd":"{}"}}"#,
+            program.display(),
+            directory.display()
+        ),
+    );
+    client.wait_for(r#""event":"initialized""#);
+
+    // Breakpoints are set by line, moved to the next line holding code
+    let source = source.display().to_string();
+    client.request(
+        "setBreakpoints",
+        &format!(
+            r#"{{"source":{{"path":"{}"}},"breakpoints":[{{"line":4}},{{"line":9}}]}}"#,
+            source
+        ),
+    );
+    let response = client.wait_for(r#""command":"setBreakpoints""#);
+    assert!(response.contains(r#"{"verified":true,"line":4,"instructionReference":"0x000c"}"#));
+    assert!(response.contains(r#"{"verified":false,"message":"no code at this line"}"#));
+    client.request("configurationDone", "{}");
+    assert!(client
+        .wait_for(r#""event":"stopped""#)
+        .contains(r#""reason":"breakpoint""#));
+
+    // The state of the machine at the breakpoint
+    client.request("stackTrace", r#"{"threadId":1}"#);
+    let response = client.wait_for(r#""command":"stackTrace""#);
+    assert!(response.contains(r#""name":"loop+4""#));
+    assert!(response.contains(r#""line":4"#));
+    assert!(response.contains(r#""name":"count.s""#));
+    client.request("variables", r#"{"variablesReference":1}"#);
+    assert!(client
+        .wait_for(r#""command":"variables""#)
+        .contains(r#"{"name":"r1 (a0)","value":"0x00000002 (2)","variablesReference":0,"memoryReference":"0x2"}"#));
+    client.request("evaluate", r#"{"expression":"r1 + mem[0]"}"#);
+    assert!(client
+        .wait_for(r#""command":"evaluate""#)
+        .contains(r#""result":"0x00030106 (196870)""#));
+    client.request("readMemory", r#"{"memoryReference":"0x0","count":4}"#);
+    assert!(client
+        .wait_for(r#""command":"readMemory""#)
+        .contains(r#""data":"BAEDAA==""#));
+    client.request(
+        "readMemory",
+        r#"{"memoryReference":"0x7fffffffffffffff","offset":0,"count":16}"#,
+    );
+    let response = client.wait_for(r#""command":"readMemory""#);
+    assert!(response.contains(r#""success":false"#));
+    assert!(response.contains(r#""message":"invalid memory range""#));
+    client.request(
+        "readMemory",
+        r#"{"memoryReference":"0x0","count":18446744073709551615}"#,
+    );
+    assert!(client
+        .wait_for(r#""command":"readMemory""#)
+        .contains(r#""message":"invalid memory range""#));
+
+    // Stepping back to the previous line, then running to the end
+    client.request("stepBack", r#"{"threadId":1}"#);
+    client.wait_for(r#""event":"stopped""#);
+    client.request("stackTrace", r#"{"threadId":1}"#);
+    assert!(client
+        .wait_for(r#""command":"stackTrace""#)
+        .contains(r#""line":3"#));
+    client.request(
+        "setBreakpoints",
+        &format!(r#"{{"source":{{"path":"{}"}},"breakpoints":[]}}"#, source),
+    );
+    client.request("continue", r#"{"threadId":1}"#);
+    assert!(client
+        .wait_for(r#""event":"output""#)
+        .contains(r#""output":"2""#));
+    assert!(client
+        .wait_for(r#""event":"exited""#)
+        .contains(r#""exitCode":1"#));
+    client.wait_for(r#""event":"terminated""#);
+    client.request("disconnect", "{}");
+    client.wait_for(r#""command":"disconnect""#);
+    assert!(client.child.wait().unwrap().success());
+}
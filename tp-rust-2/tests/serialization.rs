@@ -0,0 +1,60 @@
+#![cfg(feature = "serde")]
+
+use interpreter::{assemble, Machine, MachineBuilder, Snapshot};
+use std::io;
+
+const COUNT: &str = "
+        loadimm r1, 0
+        loadimm r2, 1
+        loadimm r3, 5
+        loadimm sp, 0x200
+loop:   call inc
+        bne r1, r3, loop
+        out_number r1
+        exit
+inc:    add r1, r1, r2
+        ret
+";
+
+// A machine running `COUNT` in a memory of 0x200 bytes for `steps` steps
+fn stepped(steps: usize) -> Machine {
+    let program = assemble(COUNT).unwrap();
+    let mut machine = MachineBuilder::new()
+        .memory_size(0x200)
+        .program(&program)
+        .build()
+        .unwrap();
+    for _ in 0..steps {
+        machine.step_on(&mut io::sink()).unwrap();
+    }
+    machine
+}
+
+#[test]
+fn serialize_snapshots() {
+    let snapshot = stepped(9).snapshot();
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let loaded: Snapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(snapshot, loaded);
+}
+
+#[test]
+fn serialize_machines() {
+    let machine = stepped(9);
+    let json = serde_json::to_string(&machine).unwrap();
+    let mut loaded: Machine = serde_json::from_str(&json).unwrap();
+    assert_eq!(machine.snapshot(), loaded.snapshot());
+    assert_eq!(0x200, loaded.memory().len());
+    assert_eq!(1, loaded.backtrace().len());
+
+    // The loaded machine goes on where the other one was
+    let mut output = Vec::new();
+    loaded.run_on(&mut output).unwrap();
+    assert_eq!(b"5", &output[..]);
+    assert_eq!(Some(0), loaded.exit_code());
+}
+
+#[test]
+fn refuse_malformed_state() {
+    assert!(serde_json::from_str::<Machine>("{\"memory\": [1, 2]}").is_err());
+}
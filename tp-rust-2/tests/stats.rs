@@ -0,0 +1,262 @@
+use interpreter::{assemble, BlockStats, Machine, RunOutcome};
+use std::io;
+
+const PROGRAM: &str = "
+            loadimm r1, 2
+            loadimm r2, 1
+      loop: sub r1, r1, r2
+            bne r1, r3, loop
+            exit
+";
+
+fn run(source: &str) -> Machine {
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    let _ = machine.run_on(&mut io::sink());
+    machine
+}
+
+#[test]
+fn count_by_opcode_and_address() {
+    let machine = run(PROGRAM);
+    let stats = machine.stats();
+    assert_eq!(7, stats.total());
+    assert_eq!(2, stats.opcode_count(4));
+    assert_eq!(2, stats.opcode_count(5));
+    assert_eq!(0, stats.opcode_count(1));
+    assert_eq!(2, stats.address_count(8));
+    assert_eq!(0, stats.address_count(9));
+    assert_eq!(vec![(4, 2), (5, 2)], stats.opcodes()[..2]);
+    assert_eq!(
+        vec![(8, 2), (12, 2), (0, 1), (4, 1), (17, 1)],
+        stats.addresses()
+    );
+}
+
+#[test]
+fn faults_are_not_counted() {
+    let machine = run("loadimm r2, -1\nload r1, r2\n");
+    assert_eq!(1, machine.stats().total());
+    assert_eq!(0, machine.stats().address_count(4));
+}
+
+#[test]
+fn reset_the_stats() {
+    let mut machine = run(PROGRAM);
+    machine.reset_stats();
+    assert_eq!(0, machine.stats().total());
+    assert!(machine.stats().addresses().is_empty());
+}
+
+#[test]
+fn print_a_summary() {
+    let machine = run("loadimm r1, 1\nloadimm r2, 2\nexit\n");
+    assert_eq!(
+        "opcode            count   share\n\
+         loadimm               2   66.7%\n\
+         exit                  1   33.3%\n\
+         total                 3\n\
+         address           count   share\n\
+         0x0000                1   33.3%\n\
+         0x0004                1   33.3%\n\
+         0x0008                1   33.3%\n",
+        machine.stats().summary()
+    );
+}
+
+// The state of a machine running `source` with the pairs fused, which must
+// be the one of a machine executing every instruction by itself
+fn run_fused(source: &str) -> Machine {
+    let fused = run(source);
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    machine.disable_fusion();
+    let _ = machine.run_on(&mut io::sink());
+    assert_eq!(0, machine.fusion_stats().hits());
+    assert_eq!(machine.snapshot(), fused.snapshot());
+    assert_eq!(machine.stats(), fused.stats());
+    fused
+}
+
+#[test]
+fn fuse_frequent_pairs() {
+    let machine = run_fused(PROGRAM);
+    let fusion = machine.fusion_stats();
+    assert_eq!(2, fusion.hits());
+    assert_eq!(1, fusion.pair_count(5, 27));
+    assert_eq!(0, fusion.pair_count(4, 4));
+    assert_eq!(vec![((4, 5), 1), ((5, 27), 1)], fusion.pairs());
+
+    let machine = run_fused(
+        "
+            loadimm r1, 3
+            loadimm r3, loop
+      loop: loadimm r2, 1
+            add r4, r4, r2
+            sub r1, r1, r2
+            move_if r0, r3, r1
+            exit
+        ",
+    );
+    assert_eq!(3, machine.fusion_stats().pair_count(4, 9));
+    assert_eq!(3, machine.fusion_stats().pair_count(5, 1));
+}
+
+#[test]
+fn fuse_modified_pairs_again() {
+    // Every pass of the outer loop writes the next value over the
+    // immediate value of the loadimm fused with a sub, at 14
+    let machine = run_fused(
+        "
+            loadimm r3, 14
+            loadimm r4, 1
+            loadimm r6, 20
+    outer:  loadimm r2, 5
+            sub r5, r5, r2
+            add r7, r7, r4
+            storeb r3, r7
+            sub r6, r6, r4
+            bne r6, r8, outer
+            exit
+        ",
+    );
+    assert_eq!(20, machine.fusion_stats().pair_count(4, 5));
+}
+
+#[test]
+fn fuse_only_pairs_which_cannot_fault() {
+    // The first instruction writes the IP, the register of the second one
+    // doesn't exist, or its target isn't in the memory
+    for source in [
+        "loadimm r0, 8\nsub r1, r1, r2\nexit",
+        "loadimm r1, 1\n.byte 9, 1, 1, 16\nexit",
+        "sub r1, r1, r1\nbeq r1, r2, 0x2000\nexit",
+    ] {
+        let machine = run_fused(source);
+        assert_eq!(0, machine.fusion_stats().hits(), "{}", source);
+    }
+}
+
+#[test]
+fn fuse_only_unobserved_runs() {
+    let program = assemble(PROGRAM).unwrap();
+    let mut unfused = Machine::try_new(&program).unwrap();
+    unfused.disable_fusion();
+    let mut fused = Machine::try_new(&program).unwrap();
+    for steps in [1, 3, 1] {
+        assert_eq!(
+            RunOutcome::StepLimitReached,
+            fused.run_for_on(steps, &mut io::sink()).unwrap()
+        );
+        unfused.run_for_on(steps, &mut io::sink()).unwrap();
+        assert_eq!(unfused.snapshot(), fused.snapshot());
+    }
+    assert_eq!(1, fused.fusion_stats().hits());
+
+    let mut machine = Machine::try_new(&program).unwrap();
+    machine.add_breakpoint(0x1000);
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(0, machine.fusion_stats().hits());
+    machine.reset_stats();
+    machine.remove_breakpoint(0x1000);
+}
+
+#[test]
+fn print_a_fusion_summary() {
+    let machine = run(PROGRAM);
+    assert_eq!(
+        "fused                     count   share\n\
+         loadimm+sub                   1   28.6%\n\
+         sub+bne                       1   28.6%\n\
+         total                         2   57.1%\n",
+        machine.fusion_stats().summary(machine.stats())
+    );
+}
+
+// The state of a machine running `source` with the basic blocks cached,
+// which must be the one of a machine stepping every instruction
+fn run_blocks(source: &str) -> Machine {
+    let cached = run(source);
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    machine.disable_block_cache();
+    let _ = machine.run_on(&mut io::sink());
+    assert_eq!(BlockStats::default(), machine.block_stats());
+    assert_eq!(machine.snapshot(), cached.snapshot());
+    assert_eq!(machine.stats(), cached.stats());
+    cached
+}
+
+#[test]
+fn cache_the_blocks() {
+    // The blocks start at 0, at the loop and at the exit
+    let mut machine = run_blocks(&PROGRAM.replace("r1, 2", "r1, 5"));
+    let expected = BlockStats {
+        hits: 3,
+        misses: 3,
+        invalidations: 0,
+    };
+    assert_eq!(expected, machine.block_stats());
+    machine.reset_stats();
+    assert_eq!(BlockStats::default(), machine.block_stats());
+
+    // The faults are located at the instruction executed in the block
+    let machine = run_blocks("loadimm r2, -1\nloadimm r3, 1\nload r1, r2\nexit");
+    assert_eq!(2, machine.stats().total());
+}
+
+#[test]
+fn invalidate_the_written_blocks() {
+    // Every pass of the loop writes the block it's executing, which stops
+    // after the storeb
+    let machine = run_blocks(
+        "
+            loadimm r3, 14
+            loadimm r4, 1
+            loadimm r6, 20
+    outer:  loadimm r2, 5
+            sub r5, r5, r2
+            add r7, r7, r4
+            storeb r3, r7
+            sub r6, r6, r4
+            bne r6, r8, outer
+            exit
+        ",
+    );
+    let expected = BlockStats {
+        hits: 19,
+        misses: 22,
+        invalidations: 20,
+    };
+    assert_eq!(expected, machine.block_stats());
+}
+
+#[test]
+fn print_a_block_summary() {
+    let stats = BlockStats {
+        hits: 3,
+        misses: 2,
+        invalidations: 1,
+    };
+    assert_eq!(
+        "blocks                    count\n\
+         hits                          3\n\
+         misses                        2\n\
+         invalidations                 1\n",
+        stats.summary()
+    );
+}
+
+#[test]
+fn run_blocks_for_some_steps() {
+    let program = assemble(PROGRAM).unwrap();
+    let mut stepped = Machine::try_new(&program).unwrap();
+    stepped.disable_block_cache();
+    stepped.disable_fusion();
+    let mut cached = Machine::try_new(&program).unwrap();
+    for steps in [2, 1, 3] {
+        assert_eq!(
+            RunOutcome::StepLimitReached,
+            cached.run_for_on(steps, &mut io::sink()).unwrap()
+        );
+        stepped.run_for_on(steps, &mut io::sink()).unwrap();
+        assert_eq!(stepped.snapshot(), cached.snapshot());
+    }
+}
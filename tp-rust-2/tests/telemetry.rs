@@ -0,0 +1,111 @@
+#![cfg(feature = "tracing")]
+
+use interpreter::{assemble, Machine};
+use std::fmt::Debug;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// A subscriber keeping the events as `level name=value...` lines
+struct Events(Arc<Mutex<Vec<String>>>);
+
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0 += &format!(" {}={:?}", field.name(), value);
+    }
+}
+
+impl Subscriber for Events {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        let mut fields = Fields(format!("span {}", span.metadata().name()));
+        span.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0);
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _: &Id, _: &Record) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut fields = Fields(event.metadata().level().to_string());
+        event.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+fn events(source: &str, input: &[u8]) -> Vec<String> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    tracing::subscriber::with_default(Events(events.clone()), || {
+        let _ = machine.run_with_io(&mut &input[..], &mut io::sink());
+    });
+    let events = events.lock().unwrap().clone();
+    events
+}
+
+#[test]
+fn trace_runs_and_instructions() {
+    assert_eq!(
+        vec![
+            "span run ip=0",
+            "INFO message=run started",
+            "TRACE address=0 instruction=loadimm r1, 7",
+            "TRACE address=4 instruction=out_number r1",
+            "DEBUG message=output bytes=[55]",
+            "TRACE address=6 instruction=exit",
+            "INFO message=run stopped ip=7 result=Ok(Exited)",
+        ],
+        events("loadimm r1, 7\nout_number r1\nexit\n", b"")
+    );
+}
+
+#[test]
+fn trace_faults_and_input() {
+    let events = events("in r1\nloadimm r2, -1\nload r1, r2\n", b"A");
+    assert!(events.contains(&String::from("DEBUG message=input bytes=[65]")));
+    assert!(events.contains(&String::from(
+        "WARN message=fault address=6 error=address 0xffffffff outside the memory in `load` at 0x0006"
+    )));
+    assert_eq!(
+        "INFO message=run stopped ip=6 \
+         result=Err(NonExistingAddress { ip: 6, opcode: 3, address: 4294967295 })",
+        events.last().unwrap()
+    );
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn trace_instructions_with_the_jit() {
+    let source = "loadimm r1, 100\nloop: sub r1, r1, r2\nadd r3, r3, r1\nbne r1, r4, loop\nexit\n";
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    machine.set_reg(2, 1).unwrap();
+    assert!(machine.enable_jit());
+    tracing::subscriber::with_default(Events(events.clone()), || {
+        machine
+            .run_with_io(&mut io::empty(), &mut io::sink())
+            .unwrap();
+    });
+    let traced = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|event| event.starts_with("TRACE"))
+        .count();
+    assert_eq!(machine.instructions_retired(), traced as u64);
+    assert_eq!(302, traced);
+    assert_eq!(0, machine.compiled_blocks());
+}
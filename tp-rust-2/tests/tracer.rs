@@ -0,0 +1,100 @@
+use interpreter::{assemble, Machine, TraceEntry, Tracer};
+use std::io;
+
+fn traced(source: &str) -> Tracer {
+    let mut machine = Machine::try_new(&assemble(source).unwrap()).unwrap();
+    machine.attach_tracer(Tracer::new());
+    let _ = machine.run_on(&mut io::sink());
+    machine.detach_tracer().unwrap()
+}
+
+#[test]
+fn record_instructions() {
+    let tracer = traced(
+        "
+            loadimm r1, 2
+            loadimm r2, 1
+      loop: sub r1, r1, r2
+            bne r1, r3, loop
+            exit
+        ",
+    );
+    assert_eq!(7, tracer.entries().len());
+    assert_eq!(
+        TraceEntry {
+            address: 0,
+            opcode: 4,
+            operands: vec![1, 2],
+            registers: vec![(1, 2)],
+        },
+        tracer.entries()[0]
+    );
+    assert_eq!(
+        TraceEntry {
+            address: 12,
+            opcode: 27,
+            operands: vec![1, 3, 8],
+            registers: vec![],
+        },
+        tracer.entries()[3]
+    );
+
+    let mut output: Vec<u8> = Vec::new();
+    tracer.write_json_lines(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(7, lines.len());
+    assert_eq!(
+        r#"{"address":8,"opcode":5,"mnemonic":"sub","operands":[1,1,2],"registers":{"r1":1}}"#,
+        lines[2]
+    );
+    assert_eq!(
+        r#"{"address":17,"opcode":7,"mnemonic":"exit","operands":[],"registers":{}}"#,
+        lines[6]
+    );
+}
+
+#[test]
+fn faults_are_not_recorded() {
+    let tracer = traced("loadimm r2, -1\nload r1, r2\n");
+    assert_eq!(1, tracer.entries().len());
+}
+
+#[test]
+fn record_modified_instructions() {
+    // The first pass writes 9 over the immediate value of the first
+    // instruction, which the second pass executes
+    let tracer = traced(
+        "
+      loop: loadimm r1, 5
+            loadimm r2, 9
+            loadimm r3, 2
+            storeb r3, r2
+            bne r1, r2, loop
+            exit
+        ",
+    );
+    assert_eq!(11, tracer.entries().len());
+    assert_eq!(vec![1, 5], tracer.entries()[0].operands);
+    assert_eq!(vec![1, 9], tracer.entries()[5].operands);
+    assert_eq!(vec![(1, 9)], tracer.entries()[5].registers);
+}
+
+#[test]
+fn write_binary_traces() {
+    let tracer = traced("loadimm r1, 300\nadd r2, r1, r1\nexit\n");
+    let mut output: Vec<u8> = Vec::new();
+    tracer.write_binary(&mut output).unwrap();
+    assert_eq!(
+        b"RVMT\
+          \x00\x00\x00\x00\x04\x02\x01\x00\x00\x00\x2c\x01\x00\x00\x01\x01\x2c\x01\x00\x00"[..],
+        output[..24]
+    );
+    assert_eq!(tracer, Tracer::read_binary(&mut &output[..]).unwrap());
+
+    // Truncated and foreign traces are refused
+    for bytes in [&output[..output.len() - 1], &output[1..]] {
+        let error = Tracer::read_binary(&mut &bytes[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+}
@@ -0,0 +1,304 @@
+use interpreter::{assemble, transpile, Image, Machine, RunOutcome};
+use std::path::PathBuf;
+use std::process::Command;
+
+// The input read by every program
+const INPUT: &[u8] = b"  -42 x";
+
+// Runs through most instructions, printing what they compute
+const MIX: &str = "
+        loadimm sp, 0x800
+        in_number r1
+        in r2
+        in r3
+        out_number r1
+        out r2
+        loadimm32 r4, 0x89abcdef
+        loadimm r5, 7
+        add r6, r4, r5
+        sub r6, r6, r1
+        mul r6, r6, r5
+        div r7, r6, r1
+        divu r8, r6, r5
+        mod r9, r6, r1
+        modu r10, r6, r5
+        xor r6, r7, r8
+        and r6, r6, r9
+        or r6, r6, r10
+        not r7, r6
+        shl r8, r7, r5
+        shr r9, r7, r5
+        sar r10, r7, r5
+        rol r11, r4, r5
+        ror r12, r4, r5
+        min r13, r11, r12
+        max r13, r13, r1
+        clz r7, r13
+        popcnt r8, r13
+        abs r9, r1
+        bswap r4
+        cmp r4, r1
+        adc r10, r4, r4
+        sbb r11, r1, r4
+        out_number r10
+        out_number r11
+        loadimm r3, data
+        store r3, r4
+        loadb r5, r3
+        loadbs r6, r3
+        loadh r7, r3
+        loadhs r8, r3
+        load r9, r3
+        storeb r3, r1
+        storeh r3, r2
+        loadx r10, r3, -4
+        storex r3, 8, r10
+        cas r3, r10, r1
+        push r4
+        push r1
+        pop r5
+        pop r6
+        loadimm r7, 8
+        loadimm r8, 0x41
+        memset r3, r8, r7
+        loadimm r9, 0x800
+        memcpy r9, r3, r7
+        storeb r9, r7
+        loadimm r9, 0x800
+        outs r9
+        rand r1
+        rand r2
+        rdinsn r3, r4
+        move_if r5, r1, r2
+        mov r6, r0
+        call square
+        out_number r11
+        jrel skip
+        exit
+skip:   loadimm r12, 40
+        loadimm r13, 8
+loop:   sub r12, r12, r13
+        bge r12, r13, loop
+        beq r12, r12, end
+        exit
+end:    bne r12, r13, done
+        exit
+done:   bge r1, r2, large
+        halt r9
+large:  halt r6
+square: mul r11, r5, r5
+        ret
+data:   .word 0x11223344, 0, 0
+";
+
+// A program to run, starting with the registers `regs` set. The name is the
+// one of its module.
+struct Program {
+    name: &'static str,
+    image: Image,
+    regs: &'static [(usize, u32)],
+}
+
+fn programs() -> Vec<Program> {
+    let program = |name, source: &str| {
+        let image = Image::from_program(&assemble(source).unwrap());
+        Program {
+            name,
+            image,
+            regs: &[],
+        }
+    };
+    let file = |name, bytes: &[u8], regs| {
+        let image = Image::from_program(bytes);
+        Program { name, image, regs }
+    };
+    // Started at 0x100, where it's loaded
+    let mut image = Image::from_program(&assemble("out_number r0\nbrk\nhalt r0").unwrap());
+    image.segments[0].address = 0x100;
+    image.entry = 0x100;
+    vec![
+        program("mix", MIX),
+        program("division", "loadimm r1, 4\ndiv r2, r1, r3\nexit"),
+        program("register", "loadimm r1, 4\n.byte 9, 1, 2, 20\nexit"),
+        program("stack", "loadimm sp, 2\npush r1"),
+        program("address", "loadimm r1, -1\nload r2, r1"),
+        program("invalid", "jmp 0x200\nexit"),
+        program("long", "loadimm32 r1, 0x1234\nouts r2"),
+        file("fact", include_bytes!("fact.bin"), &[(10, 6)]),
+        file("gcd", include_bytes!("gcd.bin"), &[(10, 12), (11, 18)]),
+        file("fibo", include_bytes!("fibo.bin"), &[(10, 9)]),
+        file("rfact", include_bytes!("rfact.bin"), &[(10, 5)]),
+        file("nested_calls", include_bytes!("nested_calls.bin"), &[]),
+        Program {
+            name: "image",
+            image,
+            regs: &[],
+        },
+    ]
+}
+
+// What a run of the machine did, formatted like the transpiled programs
+// print it
+fn expected(program: &Program) -> String {
+    let mut machine = Machine::load_image(&program.image.to_bytes()).unwrap();
+    for &(reg, value) in program.regs {
+        machine.set_reg(reg, value).unwrap();
+    }
+    let mut output = Vec::new();
+    let mut text = String::new();
+    loop {
+        let stop = match machine.run_with_io(&mut &INPUT[..], &mut output) {
+            Ok(RunOutcome::Exited) => format!("Ok(Exited({}))", machine.exit_code().unwrap()),
+            Ok(RunOutcome::BreakInstruction) => "Ok(Break)".to_string(),
+            Ok(outcome) => panic!("unexpected {:?}", outcome),
+            Err(error) => format!("Err({:?})", error),
+        };
+        text.push_str(&format!("{}\n", stop));
+        if stop != "Ok(Break)" {
+            break;
+        }
+    }
+    format!(
+        "{}\n{}{:?}\n{:?}\n{}\n{:?}\n{:?}\n",
+        program.name,
+        text,
+        machine.regs(),
+        machine.flags(),
+        machine.instructions_retired(),
+        output,
+        non_zero(machine.memory())
+    )
+}
+
+// The address and the value of the bytes of `memory` which aren't zero
+fn non_zero(memory: &[u8]) -> Vec<(usize, &u8)> {
+    memory
+        .iter()
+        .enumerate()
+        .filter(|&(_, &byte)| byte != 0)
+        .collect()
+}
+
+// Compile the transpiled programs into a binary running them all, in the
+// directory `test`, and return what it printed
+fn run_transpiled(test: &str, programs: &[Program]) -> String {
+    let name = format!("rvm-aot-{}-{}", std::process::id(), test);
+    let directory = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut main = String::new();
+    let mut calls = String::new();
+    for program in programs {
+        let path: PathBuf = directory.join(format!("{}.rs", program.name));
+        std::fs::write(&path, transpile(&program.image).unwrap()).unwrap();
+        main.push_str(&format!("mod {};\n", program.name));
+        calls.push_str(&format!(
+            "    run!({}, {:?});\n",
+            program.name, program.regs
+        ));
+    }
+    main.push_str(
+        "
+macro_rules! run {
+    ($program:ident, $regs:expr) => {{
+        let mut state = $program::State::new();
+        let regs: &[(usize, u32)] = &$regs;
+        for &(reg, value) in regs {
+            state.regs[reg] = value;
+        }
+        let mut input = &INPUT[..];
+        let mut output = Vec::new();
+        println!(\"{}\", stringify!($program));
+        loop {
+            let stop = $program::run(&mut state, &mut input, &mut output);
+            println!(\"{:?}\", stop);
+            if stop != Ok($program::Stop::Break) {
+                break;
+            }
+        }
+        println!(\"{:?}\", state.regs);
+        println!(\"{:?}\", state.flags);
+        println!(\"{}\", state.instructions_retired);
+        println!(\"{:?}\", output);
+        let memory = state.memory.iter().enumerate().filter(|&(_, &byte)| byte != 0);
+        println!(\"{:?}\", memory.collect::<Vec<_>>());
+    }};
+}
+",
+    );
+    main.push_str(&format!("\nconst INPUT: &[u8] = &{:?};\n", INPUT));
+    main.push_str(&format!("\nfn main() {{\n{}}}\n", calls));
+    std::fs::write(directory.join("main.rs"), main).unwrap();
+
+    let binary = directory.join("main");
+    let compiled = Command::new("rustc")
+        .args(["--edition", "2021", "-o"])
+        .arg(&binary)
+        .arg(directory.join("main.rs"))
+        .output()
+        .unwrap();
+    let errors = String::from_utf8_lossy(&compiled.stderr);
+    assert!(compiled.status.success() && errors.is_empty(), "{}", errors);
+    let output = Command::new(&binary).output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn run_transpiled_programs() {
+    let programs = programs();
+    let expected: String = programs.iter().map(expected).collect();
+    let output = run_transpiled("programs", &programs);
+    for (output, expected) in output.lines().zip(expected.lines()) {
+        assert_eq!(expected, output);
+    }
+    assert_eq!(expected, output);
+}
+
+#[test]
+fn stop_on_modified_code() {
+    // Writes an exit where there was no instruction when transpiled
+    let source = "loadimm r1, 7\nloadimm r2, 11\nstoreb r2, r1\n.byte 0";
+    let image = Image::from_program(&assemble(source).unwrap());
+    let programs = [Program {
+        name: "modified",
+        image,
+        regs: &[],
+    }];
+    let output = run_transpiled("modified", &programs);
+    assert_eq!(
+        Some("Err(NotTranspiled { ip: 11, opcode: 7 })"),
+        output.lines().nth(1)
+    );
+}
+
+#[test]
+fn transpile_file() {
+    let directory = std::env::temp_dir().join(format!("rvm-aot-file-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let program = directory.join("prog.bin");
+    std::fs::write(&program, [4, 1, 42, 0, 7]).unwrap();
+    let rvm_aot = |arguments: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rvm-aot"))
+            .args(arguments)
+            .output()
+            .unwrap()
+    };
+
+    assert!(rvm_aot(&[program.to_str().unwrap()]).status.success());
+    let source = std::fs::read_to_string(directory.join("prog.rs")).unwrap();
+    assert!(source.contains("0x0000 => {"));
+    assert!(source.contains("0x0004 => {"));
+
+    let output = rvm_aot(&[directory.join("missing.bin").to_str().unwrap()]);
+    assert_eq!(Some(66), output.status.code());
+    assert_eq!(Some(64), rvm_aot(&[]).status.code());
+}
+
+#[test]
+fn refuse_segments_outside_memory() {
+    let mut image = Image::from_program(&[7]);
+    image.segments[0].address = 0x1000;
+    assert!(transpile(&image).is_err());
+}
@@ -0,0 +1,82 @@
+use interpreter::{assemble, assemble_with_debug_info, Debugger, Machine, Tui};
+
+const COUNT: &str = "
+    loadimm r1, 3
+    loadimm r2, 1
+    loop: sub r1, r1, r2
+    out_number r1
+    bne r1, r3, loop
+    halt r2
+";
+
+fn tui(source: &str) -> Tui {
+    Tui::new(Debugger::new(
+        Machine::try_new(&assemble(source).unwrap()).unwrap(),
+    ))
+}
+
+#[test]
+fn render_the_panes() {
+    let mut tui = tui(COUNT);
+    let screen = tui.render();
+    assert!(screen.starts_with("\x1b[H\x1b[2J"));
+    assert!(screen.contains("\x1b[7m> 0x0000: loadimm r1, 3"));
+    assert!(screen.contains("  0x0008: sub r1, r1, r2"));
+    assert!(screen.contains("r0  0x00000000  r8  0x00000000"));
+    assert!(screen.contains("zero=0 negative=0 carry=0 overflow=0"));
+    assert!(screen.contains("0x0000: 04 01 03 00 04 02 01 00"));
+
+    // The program output goes to its own pane, the IP moving along
+    assert!(tui.execute("break 0x0e").unwrap());
+    assert!(tui.execute("c").unwrap());
+    let screen = tui.render();
+    assert!(screen.contains("Output\x1b[0m\n2\n"));
+    assert!(screen.contains("Debugger\x1b[0m\nbreakpoint at 0x000e\n"));
+    assert!(screen.contains("  0x0008: sub r1, r1, r2"));
+    assert!(screen.contains("\x1b[7m> 0x000e: bne r1, r3, 0x0008"));
+    assert!(screen.contains("r1  0x00000002"));
+    assert!(!tui.execute("quit").unwrap());
+}
+
+#[test]
+fn scroll_the_memory_pane() {
+    let mut tui = tui(COUNT);
+    assert!(tui.execute("view 0x10").unwrap());
+    let screen = tui.render();
+    assert!(screen.contains("Memory\x1b[0m\n0x0010: "));
+    assert!(!screen.contains("0x0000: 04"));
+    assert!(tui.execute("view nowhere").unwrap());
+    assert!(tui.render().contains("invalid address `nowhere`"));
+
+    // With debug info, labels are shown and can be used
+    let (program, debug_info) = assemble_with_debug_info("count.s", COUNT).unwrap();
+    let mut debugger = Debugger::new(Machine::try_new(&program).unwrap());
+    debugger.set_debug_info(debug_info);
+    let mut tui = Tui::new(debugger);
+    assert!(tui.execute("break loop").unwrap());
+    assert!(tui.render().contains("* 0x0008: sub r1, r1, r2"));
+    assert!(tui.execute("view loop").unwrap());
+    assert!(tui.render().contains("Memory\x1b[0m\n0x0008: "));
+}
+
+#[test]
+fn run_commands() {
+    let mut output: Vec<u8> = Vec::new();
+    tui(COUNT).run("s\nq\n".as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(2, output.matches("(rvm) ").count());
+    assert!(output.contains("\x1b[7m> 0x0004: loadimm r2, 1"));
+}
+
+#[test]
+fn render_the_registers_beside_a_short_program() {
+    let program = assemble("exit\n").unwrap();
+    let machine = Machine::with_memory_size(program.len(), &program).unwrap();
+    let screen = Tui::new(Debugger::new(machine)).render();
+    assert!(screen.contains("\x1b[7m> 0x0000: exit"));
+    assert!(screen.contains("r7  0x00000000  r15 0x00000000"));
+    assert!(screen.contains(&format!(
+        "\n{} zero=0 negative=0 carry=0 overflow=0\n",
+        " ".repeat(44)
+    )));
+}
@@ -0,0 +1,87 @@
+use interpreter::{assemble, verify, Machine, VerifyError};
+
+#[test]
+fn verify_sample_programs() {
+    for name in [
+        "add64",
+        "afact",
+        "fact",
+        "fibo",
+        "function",
+        "gcd",
+        "multiply",
+        "nested_calls",
+        "push_pop",
+        "rfact",
+        "rfact_tr",
+    ] {
+        let program = std::fs::read(format!("tests/{}.bin", name)).unwrap();
+        assert_eq!(Ok(()), verify(&program), "{}", name);
+    }
+}
+
+#[test]
+fn verify_reachable_instructions() {
+    // The unreachable bytes are not checked
+    let program = assemble("jmp start\n.byte 0, 99\nstart: exit\n.byte 0xff").unwrap();
+    assert_eq!(Ok(()), verify(&program));
+    assert_eq!(Ok(()), verify(&[]));
+
+    // Both ways of a branch are followed
+    let program = assemble("beq r1, r2, other\n.byte 0\nother: .byte 0xff").unwrap();
+    assert_eq!(
+        Err(vec![
+            VerifyError::InvalidOpcode(5, 0),
+            VerifyError::InvalidOpcode(6, 0xff)
+        ]),
+        verify(&program)
+    );
+
+    // So are both the called function and the return address
+    let program = assemble("call f\n.byte 0\nf: ret").unwrap();
+    assert_eq!(
+        Err(vec![VerifyError::InvalidOpcode(3, 0)]),
+        verify(&program)
+    );
+}
+
+#[test]
+fn verify_errors() {
+    assert_eq!(
+        Err(vec![
+            VerifyError::InvalidRegister(0, 16),
+            VerifyError::InvalidRegister(0, 20)
+        ]),
+        verify(&[45, 16, 20, 7])
+    );
+    assert_eq!(Err(vec![VerifyError::Truncated(2)]), verify(&[6, 1, 4, 1]));
+    assert_eq!(
+        Err(vec![VerifyError::OutsideProgram(0, 100)]),
+        verify(&assemble("jmp 100").unwrap())
+    );
+    assert_eq!(
+        Err(vec![VerifyError::OutsideProgram(0, -2)]),
+        verify(&assemble("jrel -5").unwrap())
+    );
+    assert_eq!(
+        Err(vec![VerifyError::OutsideProgram(0, 2)]),
+        verify(&assemble("out r1").unwrap())
+    );
+    assert_eq!(
+        "0x0000: invalid register 16",
+        VerifyError::InvalidRegister(0, 16).to_string()
+    );
+}
+
+#[test]
+fn new_verified_machine() {
+    let program = assemble("loadimm r1, 42\nhalt r1").unwrap();
+    let mut machine = Machine::new_verified(&program).unwrap();
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!(Some(42), machine.exit_code());
+
+    assert_eq!(
+        vec![VerifyError::InvalidOpcode(0, 0)],
+        Machine::new_verified(&[0]).err().unwrap()
+    );
+}